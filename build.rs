@@ -0,0 +1,29 @@
+use std::{env, path::Path, process::Command, time::SystemTime};
+
+// Embeds the commit and build time reported by GET /about. Builds without a
+// git checkout (e.g. a container build that copies only the sources) can pass
+// INDEXPAGE_GIT_COMMIT; SOURCE_DATE_EPOCH pins the build time for
+// reproducible builds.
+fn main() {
+    let commit = env::var("INDEXPAGE_GIT_COMMIT")
+        .ok()
+        .or_else(|| {
+            let output = Command::new("git").args(["rev-parse", "--short=12", "HEAD"]).output().ok()?;
+            output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".into());
+    let built = env::var("SOURCE_DATE_EPOCH").ok().unwrap_or_else(|| {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        now.as_secs().to_string()
+    });
+    println!("cargo:rustc-env=INDEXPAGE_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=INDEXPAGE_BUILD_TIME={}", built);
+    println!("cargo:rerun-if-env-changed=INDEXPAGE_GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    // A new commit moves HEAD or the branch it points to.
+    for path in [".git/HEAD", ".git/refs/heads"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+}
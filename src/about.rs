@@ -0,0 +1,93 @@
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::{env, fs, path::Path, sync::LazyLock};
+
+// What build is running where, for fleet dashboards and the UI footer.
+static STARTED_AT: LazyLock<DateTime<Utc>> = LazyLock::new(Utc::now);
+
+// Cargo features compiled into this binary.
+const FEATURES: &[(&str, bool)] = &[
+    ("checks", cfg!(feature = "checks")),
+    ("tls", cfg!(feature = "tls")),
+    ("plugins", cfg!(feature = "plugins")),
+    ("discovery", cfg!(feature = "discovery")),
+];
+
+pub fn features() -> Vec<&'static str> {
+    FEATURES.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| *name).collect()
+}
+
+// Called at startup so uptime counts from then, not from the first request.
+pub fn init() {
+    LazyLock::force(&STARTED_AT);
+    tracing::info!(
+        "indexpage {} ({}), features: {}",
+        env!("CARGO_PKG_VERSION"),
+        env!("INDEXPAGE_GIT_COMMIT"),
+        features().join(", ")
+    );
+}
+
+#[derive(Debug, Serialize)]
+pub struct About {
+    version: &'static str,
+    commit: &'static str,
+    built_at: Option<DateTime<Utc>>,
+    features: Vec<&'static str>,
+    storage: Storage,
+    started_at: DateTime<Utc>,
+    uptime_secs: i64,
+    host: Host,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Storage {
+    backend: &'static str,
+    version: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Host {
+    hostname: Option<String>,
+    // "kubernetes", "docker" or "podman" when running in a container.
+    container: Option<&'static str>,
+}
+
+fn hostname() -> Option<String> {
+    let name = env::var("HOSTNAME").ok().or_else(|| fs::read_to_string("/etc/hostname").ok())?;
+    Some(name.trim().to_string()).filter(|name| !name.is_empty())
+}
+
+fn container() -> Option<&'static str> {
+    if env::var_os("KUBERNETES_SERVICE_HOST").is_some() {
+        Some("kubernetes")
+    } else if Path::new("/.dockerenv").exists() {
+        Some("docker")
+    } else if Path::new("/run/.containerenv").exists() {
+        Some("podman")
+    } else {
+        None
+    }
+}
+
+// GET /about
+pub async fn about(State(pool): State<PgPool>) -> Result<Json<About>, (StatusCode, String)> {
+    let version = sqlx::query_scalar!(r#"SELECT current_setting('server_version') AS "version!""#)
+        .fetch_one(&pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let built_at = env!("INDEXPAGE_BUILD_TIME").parse().ok().and_then(|secs| DateTime::from_timestamp(secs, 0));
+    Ok(Json(About {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: env!("INDEXPAGE_GIT_COMMIT"),
+        built_at,
+        features: features(),
+        storage: Storage { backend: "postgres", version },
+        started_at: *STARTED_AT,
+        uptime_secs: (Utc::now() - *STARTED_AT).num_seconds(),
+        host: Host { hostname: hostname(), container: container() },
+    }))
+}
@@ -0,0 +1,76 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http::{HeaderMap, Method, StatusCode};
+use ipnet::IpNet;
+use std::{net::{IpAddr, SocketAddr}, sync::Arc};
+
+use crate::config::{AccessConfig, IpRules};
+
+#[derive(Clone)]
+pub struct Access {
+    config: Arc<AccessConfig>,
+}
+
+impl Access {
+    pub fn new(config: &AccessConfig) -> Self {
+        Self { config: Arc::new(config.clone()) }
+    }
+
+    pub fn is_trusted_proxy(&self, peer: IpAddr) -> bool {
+        self.config.trusted_proxies.iter().any(|net| net.contains(&peer))
+    }
+
+    // The address the request really came from: the TCP peer, unless that is
+    // a trusted proxy, in which case X-Forwarded-For is walked from the right
+    // past any further trusted hops.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let trusted = |ip: &IpAddr| self.is_trusted_proxy(*ip);
+        if !trusted(&peer) {
+            return peer;
+        }
+        let forwarded = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|hop| hop.trim().parse::<IpAddr>().ok())
+            .collect::<Vec<_>>();
+        forwarded.into_iter().rev().find(|ip| !trusted(ip)).unwrap_or(peer)
+    }
+}
+
+// Safe methods, plus the Grafana datasource endpoints, which only read but
+// are queried with POST. GET /quick-add creates a service.
+pub fn is_read(method: &Method, path: &str) -> bool {
+    (method.is_safe() && path != crate::quick_add::PATH) || path == "/grafana" || path.starts_with("/grafana/")
+}
+
+fn permits(rules: &IpRules, ip: IpAddr) -> bool {
+    let matches = |nets: &[IpNet]| nets.iter().any(|net| net.contains(&ip));
+    !matches(&rules.deny) && (rules.allow.is_empty() || matches(&rules.allow))
+}
+
+pub async fn enforce(
+    State(access): State<Access>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, String)> {
+    let ip = access.client_ip(peer.ip(), request.headers());
+    let config = &access.config;
+    let path = request.uri().path();
+
+    let mut groups = vec![if is_read(request.method(), path) { &config.read } else { &config.mutations }];
+    if path == "/admin" || path.starts_with("/admin/") {
+        groups.push(&config.admin);
+    }
+    if groups.into_iter().all(|rules| permits(rules, ip)) {
+        Ok(next.run(request).await)
+    } else {
+        tracing::info!("rejected {} {} from {}", request.method(), path, ip);
+        Err((StatusCode::FORBIDDEN, format!("Access from {} is not allowed", ip)))
+    }
+}
@@ -0,0 +1,253 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::PgPool;
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{fs, io::AsyncWriteExt, sync::mpsc};
+
+use crate::{
+    access::Access,
+    auth::Principal,
+    config::{AccessLogConfig, AccessLogField, AccessLogSink},
+    scheduler::{Schedule, Scheduler},
+};
+
+// A request log for reviewing traffic without a log pipeline. Requests are
+// handed to a background writer, so a slow disk or database never holds up a
+// response; if the writer falls this far behind, entries are dropped and
+// counted instead.
+const QUEUE: usize = 4096;
+// Entries written per file write or INSERT.
+const BATCH: usize = 256;
+const PRUNE_INTERVAL_SECS: u64 = 3600;
+
+struct Entry {
+    at: DateTime<Utc>,
+    method: String,
+    path: String,
+    status: u16,
+    latency_ms: i32,
+    ip: Option<String>,
+    user: Option<String>,
+}
+
+#[derive(Clone, Default)]
+pub struct AccessLog {
+    tx: Option<mpsc::Sender<Entry>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl AccessLog {
+    // Starts the writer for the configured sink; without one, requests are
+    // not logged.
+    pub async fn start(config: &AccessLogConfig, pool: PgPool) -> anyhow::Result<Self> {
+        let Some(sink) = config.sink else { return Ok(Self::default()) };
+        let sink = match sink {
+            AccessLogSink::File => Sink::File(LogFile::open(config).await?),
+            AccessLogSink::Database => Sink::Database(pool),
+        };
+        let (tx, rx) = mpsc::channel(QUEUE);
+        let log = Self { tx: Some(tx), dropped: Arc::default() };
+        tokio::spawn(write(rx, sink, config.fields.clone(), log.dropped.clone()));
+        Ok(log)
+    }
+
+    fn enabled(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    fn push(&self, entry: Entry) {
+        if let Some(tx) = &self.tx
+            && tx.try_send(entry).is_err()
+        {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// Outermost layer, so requests turned away by access rules or auth are
+// logged too. Latency is measured to the response headers; streamed bodies
+// may take longer.
+pub async fn record(
+    State(log): State<AccessLog>,
+    State(access): State<Access>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !log.enabled() {
+        return next.run(request).await;
+    }
+    let started = Instant::now();
+    let (at, method, path) = (Utc::now(), request.method().to_string(), request.uri().path().to_string());
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| access.client_ip(peer.ip(), request.headers()).to_string());
+    let response = next.run(request).await;
+    log.push(Entry {
+        at,
+        method,
+        path,
+        status: response.status().as_u16(),
+        latency_ms: started.elapsed().as_millis().min(i32::MAX as u128) as i32,
+        ip,
+        // Set by auth::enforce.
+        user: response.extensions().get::<Principal>().map(|principal| principal.name.clone()),
+    });
+    response
+}
+
+enum Sink {
+    File(LogFile),
+    Database(PgPool),
+}
+
+async fn write(mut rx: mpsc::Receiver<Entry>, mut sink: Sink, fields: Vec<AccessLogField>, dropped: Arc<AtomicU64>) {
+    let mut batch = Vec::with_capacity(BATCH);
+    while rx.recv_many(&mut batch, BATCH).await > 0 {
+        let result = match &mut sink {
+            Sink::File(file) => file.append(&batch, &fields).await.map_err(|e| e.to_string()),
+            Sink::Database(pool) => insert(pool, &batch, &fields).await.map_err(|e| e.to_string()),
+        };
+        if let Err(e) = result {
+            tracing::warn!("writing {} access log entries failed: {}", batch.len(), e);
+        }
+        batch.clear();
+        let missed = dropped.swap(0, Ordering::Relaxed);
+        if missed > 0 {
+            tracing::warn!("access log fell behind; dropped {} entries", missed);
+        }
+    }
+}
+
+// One tab-separated line per request, "-" for missing values.
+fn line(entry: &Entry, fields: &[AccessLogField]) -> String {
+    let values: Vec<String> = fields
+        .iter()
+        .map(|field| match field {
+            AccessLogField::Time => entry.at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            AccessLogField::Method => entry.method.clone(),
+            AccessLogField::Path => entry.path.clone(),
+            AccessLogField::Status => entry.status.to_string(),
+            AccessLogField::LatencyMs => entry.latency_ms.to_string(),
+            AccessLogField::Ip => entry.ip.clone().unwrap_or_else(|| "-".into()),
+            AccessLogField::User => entry.user.as_deref().unwrap_or("-").replace(['\t', '\n', '\r'], " "),
+        })
+        .collect();
+    values.join("\t") + "\n"
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: fs::File,
+    size: u64,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl LogFile {
+    async fn open(config: &AccessLogConfig) -> anyhow::Result<Self> {
+        let (file, size) = Self::open_path(&config.path)
+            .await
+            .map_err(|e| anyhow::anyhow!("access_log.path: opening {}: {}", config.path.display(), e))?;
+        Ok(Self { path: config.path.clone(), file, size, max_bytes: config.max_bytes, keep: config.keep })
+    }
+
+    async fn open_path(path: &Path) -> std::io::Result<(fs::File, u64)> {
+        let file = fs::OpenOptions::new().create(true).append(true).open(path).await?;
+        let size = file.metadata().await?.len();
+        Ok((file, size))
+    }
+
+    async fn append(&mut self, entries: &[Entry], fields: &[AccessLogField]) -> std::io::Result<()> {
+        let text: String = entries.iter().map(|entry| line(entry, fields)).collect();
+        if self.max_bytes > 0 && self.size > 0 && self.size + text.len() as u64 > self.max_bytes {
+            self.rotate().await?;
+        }
+        self.file.write_all(text.as_bytes()).await?;
+        self.file.flush().await?;
+        self.size += text.len() as u64;
+        Ok(())
+    }
+
+    // path.(keep-1) -> path.keep, ..., path -> path.1; the oldest is
+    // overwritten.
+    async fn rotate(&mut self) -> std::io::Result<()> {
+        let numbered = |n: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        };
+        for n in (1..self.keep).rev() {
+            match fs::rename(numbered(n), numbered(n + 1)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        if self.keep > 0 {
+            fs::rename(&self.path, numbered(1)).await?;
+        } else {
+            fs::remove_file(&self.path).await?;
+        }
+        (self.file, self.size) = Self::open_path(&self.path).await?;
+        Ok(())
+    }
+}
+
+async fn insert(pool: &PgPool, entries: &[Entry], fields: &[AccessLogField]) -> sqlx::Result<()> {
+    // Fields left out of the configuration are stored as NULL.
+    let column = |field, value: &dyn Fn(&Entry) -> Option<String>| -> Vec<Option<String>> {
+        entries.iter().map(|entry| if fields.contains(&field) { value(entry) } else { None }).collect()
+    };
+    let number = |field, value: &dyn Fn(&Entry) -> i32| -> Vec<Option<i32>> {
+        entries.iter().map(|entry| fields.contains(&field).then(|| value(entry))).collect()
+    };
+    let at: Vec<DateTime<Utc>> = entries.iter().map(|entry| entry.at).collect();
+    sqlx::query!(
+        "INSERT INTO access_log (at, method, path, status, latency_ms, ip, user_name) \
+         SELECT * FROM UNNEST($1::timestamptz[], $2::text[], $3::text[], $4::int[], $5::int[], $6::text[], $7::text[])",
+        &at,
+        &column(AccessLogField::Method, &|entry| Some(entry.method.clone())) as _,
+        &column(AccessLogField::Path, &|entry| Some(entry.path.clone())) as _,
+        &number(AccessLogField::Status, &|entry| entry.status as i32) as _,
+        &number(AccessLogField::LatencyMs, &|entry| entry.latency_ms) as _,
+        &column(AccessLogField::Ip, &|entry| entry.ip.clone()) as _,
+        &column(AccessLogField::User, &|entry| entry.user.clone()) as _,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// Deletes table rows past `retention_days`; the file sink rotates instead.
+pub fn register(scheduler: &Scheduler, pool: PgPool, config: &AccessLogConfig) -> anyhow::Result<()> {
+    if config.sink != Some(AccessLogSink::Database) || config.retention_days <= 0 {
+        return Ok(());
+    }
+    let days = config.retention_days;
+    let interval = Schedule::Every(Duration::from_secs(PRUNE_INTERVAL_SECS));
+    scheduler.register("access_log", interval, move || {
+        let pool = pool.clone();
+        async move {
+            let deleted = sqlx::query!("DELETE FROM access_log WHERE at < now() - make_interval(days => $1)", days)
+                .execute(&pool)
+                .await?
+                .rows_affected();
+            if deleted > 0 {
+                tracing::info!("pruned {} access log entries", deleted);
+            }
+            Ok(())
+        }
+    })
+}
@@ -0,0 +1,136 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashSet;
+
+use crate::{
+    audit::{Actor, Audit},
+    categories::Visibility,
+    services::SERVICE_ID_BY_NAME,
+};
+
+// Acknowledging a service that is down or degraded silences the checker's
+// notifications about it until it recovers (the ack is then dropped) or the
+// ack's `until` passes, whichever comes first. Recovery notices still go out.
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Ack {
+    service: String,
+    acked_by: String,
+    acked_at: DateTime<Utc>,
+    // None: until recovery.
+    until: Option<DateTime<Utc>>,
+    note: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AckRequest {
+    until: Option<DateTime<Utc>>,
+    note: Option<String>,
+}
+
+// Ids of services whose notifications are silenced right now.
+#[cfg_attr(not(feature = "checks"), allow(dead_code))]
+pub async fn silenced(pool: &PgPool) -> sqlx::Result<HashSet<i32>> {
+    let ids = sqlx::query_scalar!("SELECT service_id FROM alert_acks WHERE until IS NULL OR until > now()")
+        .fetch_all(pool)
+        .await?;
+    Ok(ids.into_iter().collect())
+}
+
+// Called once a service is up and not degraded again.
+#[cfg_attr(not(feature = "checks"), allow(dead_code))]
+pub async fn recovered(pool: &PgPool, service_id: i32) -> sqlx::Result<()> {
+    sqlx::query!("DELETE FROM alert_acks WHERE service_id = $1", service_id).execute(pool).await?;
+    Ok(())
+}
+
+// POST /services/:name/ack
+// Body (optional): {"until": RFC 3339, "note": "..."}. Acking again replaces
+// the previous ack.
+pub async fn ack(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(name): Path<String>,
+    payload: Option<Json<AckRequest>>,
+) -> Result<Json<Ack>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let Json(payload) = payload.unwrap_or_default();
+    if payload.until.is_some_and(|until| until <= Utc::now()) {
+        return Err((StatusCode::BAD_REQUEST, "until must be in the future".into()));
+    }
+    let note = payload.note.as_deref().map(str::trim).filter(|note| !note.is_empty());
+    let internal = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+    // Down means an open incident; degraded, that the last check was.
+    let outage = sqlx::query_as::<_, (i32, bool)>(&format!(
+        "SELECT s.id, EXISTS (SELECT 1 FROM incidents WHERE service_id = s.id AND resolved_at IS NULL) \
+         OR coalesce((SELECT degraded FROM check_results WHERE service_id = s.id ORDER BY checked_at DESC LIMIT 1), \
+         false) FROM services s WHERE s.id = {}",
+        SERVICE_ID_BY_NAME
+    ))
+    .bind(&name)
+    .fetch_optional(&pool)
+    .await
+    .map_err(internal)?;
+    let (id, down) = outage.ok_or((StatusCode::NOT_FOUND, "Service not found".into()))?;
+    if !down {
+        return Err((StatusCode::CONFLICT, format!("'{}' is neither down nor degraded", name)));
+    }
+
+    let ack = sqlx::query_as!(
+        Ack,
+        r#"WITH ack AS (
+               INSERT INTO alert_acks (service_id, acked_by, until, note) VALUES ($1, $2, $3, $4)
+               ON CONFLICT (service_id) DO UPDATE
+               SET acked_by = EXCLUDED.acked_by, acked_at = now(), until = EXCLUDED.until, note = EXCLUDED.note
+               RETURNING *
+           )
+           SELECT s.name AS service, ack.acked_by, ack.acked_at, ack.until, ack.note
+           FROM ack JOIN services s ON s.id = ack.service_id"#,
+        id,
+        actor.name,
+        payload.until,
+        note,
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(internal)?;
+    let detail = match ack.until {
+        Some(until) => format!("until {}", until.to_rfc3339()),
+        None => "until recovery".into(),
+    };
+    audit.record(&actor, "service.ack", Some(&ack.service), Some(detail)).await;
+    Ok(Json(ack))
+}
+
+// DELETE /services/:name/ack
+// Returns the removed ack.
+pub async fn unack(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(name): Path<String>,
+) -> Result<Json<Ack>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let removed = sqlx::query_as::<_, Ack>(&format!(
+        "DELETE FROM alert_acks a USING services s WHERE a.service_id = s.id AND s.id = {} \
+         RETURNING s.name AS service, a.acked_by, a.acked_at, a.until, a.note",
+        SERVICE_ID_BY_NAME
+    ))
+    .bind(&name)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, format!("'{}' is not acknowledged", name)))?;
+    audit.record(&actor, "service.unack", Some(&removed.service), None).await;
+    Ok(Json(removed))
+}
@@ -0,0 +1,228 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use http::{HeaderName, HeaderValue, Method, StatusCode};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    audit::{Actor, Audit},
+    auth::{Principal, Role},
+    categories::Visibility,
+    config::ActionConfig,
+    links::{LinkContext, Links},
+    secrets::Secrets,
+    services::SERVICE_ID_BY_NAME,
+};
+
+// Per-service actions: `[[actions]]` entries are named HTTP calls, such as
+// "Restart container" against Portainer's API, offered on the services they
+// list and run with POST /services/:name/actions/:action by anyone with the
+// entry's role. The url, header values and body may use {service},
+// {link} (expanded as for checks), {action} and {actor}; {secret:NAME} is
+// resolved once at startup like other secrets (see secrets.rs).
+
+// Characters of the target's response returned to the caller.
+const MAX_RESULT_CHARS: usize = 4096;
+
+struct Action {
+    name: String,
+    label: String,
+    // Lowercased.
+    services: Vec<String>,
+    method: Method,
+    url: String,
+    headers: Vec<(HeaderName, String)>,
+    body: Option<String>,
+    role: Role,
+    timeout: Duration,
+}
+
+#[derive(Clone)]
+pub struct Actions {
+    actions: Arc<Vec<Action>>,
+    links: LinkContext,
+    http: reqwest::Client,
+}
+
+// Replaces the {secret:NAME} placeholders in `template`.
+fn resolve_secrets(template: &str, secrets: &Secrets) -> anyhow::Result<String> {
+    let mut resolved = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{secret:") {
+        let end = rest[start..].find('}').ok_or_else(|| anyhow::anyhow!("unclosed {{secret:"))? + start;
+        let name = &rest[start + "{secret:".len()..end];
+        let value = secrets.get(name)?.ok_or_else(|| anyhow::anyhow!("secret {} is not set", name))?;
+        resolved.push_str(&rest[..start]);
+        resolved.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    resolved.push_str(rest);
+    Ok(resolved)
+}
+
+impl Actions {
+    pub fn new(configs: &[ActionConfig], secrets: &Secrets, links: &Links) -> anyhow::Result<Self> {
+        let mut actions: Vec<Action> = Vec::new();
+        for (index, config) in configs.iter().enumerate() {
+            let context = |e: anyhow::Error| anyhow::anyhow!("actions[{}] ({}): {}", index, config.name, e);
+            let valid_name = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+            if config.name.is_empty() || !config.name.chars().all(valid_name) {
+                anyhow::bail!("actions[{}]: name must be letters, digits, '-' and '_'", index);
+            }
+            if config.services.is_empty() {
+                return Err(context(anyhow::anyhow!("list at least one service")));
+            }
+            let services: Vec<String> = config.services.iter().map(|service| service.to_lowercase()).collect();
+            let clash = |other: &Action| other.services.iter().any(|service| services.contains(service));
+            if actions.iter().any(|other| other.name == config.name && clash(other)) {
+                return Err(context(anyhow::anyhow!("a service has two actions named {}", config.name)));
+            }
+            let method = Method::from_bytes(config.method.to_uppercase().as_bytes())
+                .map_err(|_| context(anyhow::anyhow!("invalid method {}", config.method)))?;
+            let url = resolve_secrets(&config.url, secrets).map_err(context)?;
+            let mut headers = Vec::new();
+            for (name, value) in &config.headers {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| context(anyhow::anyhow!("invalid header name {}", name)))?;
+                headers.push((name, resolve_secrets(value, secrets).map_err(context)?));
+            }
+            let body = config.body.as_deref().map(|body| resolve_secrets(body, secrets)).transpose().map_err(context)?;
+            actions.push(Action {
+                name: config.name.clone(),
+                label: config.label.clone().unwrap_or_else(|| config.name.clone()),
+                services,
+                method,
+                url,
+                headers,
+                body,
+                role: config.role,
+                timeout: Duration::from_secs(config.timeout_secs.max(1)),
+            });
+        }
+        Ok(Self { actions: Arc::new(actions), links: links.for_checks(), http: reqwest::Client::new() })
+    }
+
+    fn for_service<'a>(&'a self, service: &str) -> impl Iterator<Item = &'a Action> {
+        let service = service.to_lowercase();
+        self.actions.iter().filter(move |action| action.services.contains(&service))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActionInfo {
+    name: String,
+    label: String,
+    method: String,
+    role: Role,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActionResult {
+    action: String,
+    // The target's status code and body.
+    status: u16,
+    body: String,
+}
+
+// The service's name and link.
+async fn load(pool: &PgPool, name: &str) -> Result<(String, String), (StatusCode, String)> {
+    sqlx::query_as::<_, (String, String)>(&format!(
+        "SELECT name, link FROM services WHERE id = {}",
+        SERVICE_ID_BY_NAME
+    ))
+    .bind(name)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Service not found".into()))
+}
+
+// GET /services/:name/actions
+pub async fn get_actions(
+    State(pool): State<PgPool>,
+    State(actions): State<Actions>,
+    visibility: Visibility,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<ActionInfo>>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let (service, _) = load(&pool, &name).await?;
+    let listed = actions
+        .for_service(&service)
+        .map(|action| ActionInfo {
+            name: action.name.clone(),
+            label: action.label.clone(),
+            method: action.method.to_string(),
+            role: action.role,
+        })
+        .collect();
+    Ok(Json(listed))
+}
+
+// POST /services/:name/actions/:action
+// 200 with the target's response when it answers 2xx, otherwise 502.
+pub async fn run(
+    State(pool): State<PgPool>,
+    State(actions): State<Actions>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    principal: Option<Extension<Principal>>,
+    Path((name, action_name)): Path<(String, String)>,
+) -> Result<(StatusCode, Json<ActionResult>), (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let (service, link) = load(&pool, &name).await?;
+    let action = actions
+        .for_service(&service)
+        .find(|action| action.name == action_name)
+        .ok_or((StatusCode::NOT_FOUND, format!("'{}' has no action '{}'", service, action_name)))?;
+    // Without auth everyone may run everything, as with the other endpoints.
+    if let Some(Extension(principal)) = &principal
+        && principal.role < action.role
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("'{}' lacks the {} role", principal.name, action.role.as_str()),
+        ));
+    }
+
+    let link = actions.links.expand(&link);
+    let fill = |template: &str| {
+        template
+            .replace("{service}", &service)
+            .replace("{link}", &link)
+            .replace("{action}", &action.name)
+            .replace("{actor}", &actor.name)
+    };
+    let mut request = actions.http.request(action.method.clone(), fill(&action.url)).timeout(action.timeout);
+    for (header, value) in &action.headers {
+        let value = HeaderValue::from_str(&fill(value))
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, format!("Invalid value for header {}", header)))?;
+        request = request.header(header, value);
+    }
+    if let Some(body) = &action.body {
+        request = request.body(fill(body));
+    }
+    // Runs that never got an answer are audited too.
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            let (status, outcome) = match e.is_timeout() {
+                true => (StatusCode::GATEWAY_TIMEOUT, "timed out".to_string()),
+                false => (StatusCode::BAD_GATEWAY, format!("failed: {}", e)),
+            };
+            let detail = format!("{} -> {}", action.name, outcome);
+            audit.record(&actor, "service.action", Some(&service), Some(detail)).await;
+            return Err((status, format!("Action '{}' failed: {}", action.name, e)));
+        }
+    };
+    let status = response.status();
+    let body: String = response.text().await.unwrap_or_default().chars().take(MAX_RESULT_CHARS).collect();
+
+    let detail = format!("{} -> HTTP {}", action.name, status.as_u16());
+    audit.record(&actor, "service.action", Some(&service), Some(detail)).await;
+    let result = ActionResult { action: action.name.clone(), status: status.as_u16(), body };
+    Ok((if status.is_success() { StatusCode::OK } else { StatusCode::BAD_GATEWAY }, Json(result)))
+}
@@ -0,0 +1,144 @@
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{categories::Visibility, config::AlertmanagerConfig};
+
+// Label and severity mapping for POST /integrations/alertmanager.
+#[derive(Clone)]
+pub struct Alertmanager {
+    config: Arc<AlertmanagerConfig>,
+}
+
+impl Alertmanager {
+    pub fn new(config: &AlertmanagerConfig) -> Self {
+        Self { config: Arc::new(config.clone()) }
+    }
+
+    // The first configured label the alert carries names its service.
+    fn service_of<'a>(&self, alert: &'a Alert) -> Option<&'a str> {
+        self.config
+            .service_labels
+            .iter()
+            .find_map(|label| alert.labels.get(label))
+            .map(String::as_str)
+    }
+
+    fn is_down(&self, alert: &Alert) -> bool {
+        alert
+            .labels
+            .get("severity")
+            .is_some_and(|severity| self.config.down_severities.contains(severity))
+    }
+}
+
+// The parts of Alertmanager's webhook payload (version 4) used here.
+#[derive(Debug, Deserialize)]
+pub struct Payload {
+    alerts: Vec<Alert>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Alert {
+    status: String,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+    starts_at: DateTime<Utc>,
+    // Missing before Alertmanager 0.19; the labels identify the alert then.
+    #[serde(default)]
+    fingerprint: Option<String>,
+}
+
+impl Alert {
+    fn fingerprint(&self) -> String {
+        self.fingerprint
+            .clone()
+            .unwrap_or_else(|| serde_json::to_string(&self.labels).unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Ingested {
+    firing: usize,
+    resolved: usize,
+    // Alerts without a service label, or naming a service that doesn't exist
+    // or that the caller can't see.
+    ignored: usize,
+}
+
+// POST /integrations/alertmanager
+// Firing alerts are kept against the service they name until Alertmanager
+// reports them resolved (so `send_resolved` must stay on). While one is
+// active the service shows as down, or degraded when no alert has one of
+// the `down_severities`, whatever its own checks say.
+pub async fn ingest(
+    State(pool): State<PgPool>,
+    State(alertmanager): State<Alertmanager>,
+    visibility: Visibility,
+    Json(payload): Json<Payload>,
+) -> Result<Json<Ingested>, (StatusCode, String)> {
+    let internal = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let mut ingested = Ingested { firing: 0, resolved: 0, ignored: 0 };
+    for alert in &payload.alerts {
+        let Some(name) = alertmanager.service_of(alert) else {
+            ingested.ignored += 1;
+            continue;
+        };
+        match visibility.check_service(&pool, name).await {
+            Ok(()) => {}
+            Err((StatusCode::NOT_FOUND, _)) => {
+                ingested.ignored += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        }
+
+        if alert.status == "resolved" {
+            sqlx::query(&format!(
+                "DELETE FROM external_alerts WHERE service_id = {} AND fingerprint = $2",
+                crate::services::SERVICE_ID_BY_NAME
+            ))
+            .bind(name)
+            .bind(alert.fingerprint())
+            .execute(&pool)
+            .await
+            .map_err(internal)?;
+            ingested.resolved += 1;
+            continue;
+        }
+
+        let matched = sqlx::query(&format!(
+            r#"
+            INSERT INTO external_alerts (service_id, fingerprint, alertname, severity, down, summary, starts_at)
+            SELECT id, $2, $3, $4, $5, $6, $7 FROM services WHERE id = {}
+            ON CONFLICT (service_id, fingerprint) DO UPDATE SET
+                severity = excluded.severity, down = excluded.down, summary = excluded.summary,
+                updated_at = now()
+            "#,
+            crate::services::SERVICE_ID_BY_NAME
+        ))
+        .bind(name)
+        .bind(alert.fingerprint())
+        .bind(alert.labels.get("alertname"))
+        .bind(alert.labels.get("severity"))
+        .bind(alertmanager.is_down(alert))
+        .bind(alert.annotations.get("summary").or(alert.annotations.get("description")))
+        .bind(alert.starts_at)
+        .execute(&pool)
+        .await
+        .map_err(internal)?
+        .rows_affected();
+        if matched > 0 {
+            ingested.firing += 1;
+        } else {
+            ingested.ignored += 1;
+        }
+    }
+    Ok(Json(ingested))
+}
@@ -0,0 +1,261 @@
+use axum::{
+    extract::{ConnectInfo, FromRef, FromRequestParts, Query, RawQuery, State},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Json,
+};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use futures_util::{Stream, StreamExt};
+use http::{header, request::Parts, HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use std::{convert::Infallible, net::SocketAddr};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+
+use crate::{
+    access::Access,
+    auth::Principal,
+    pagination::{self, Cursor, Page, PageQuery},
+    streaming::{self, Writer},
+    tz::Timezone,
+};
+
+// Who performed a mutation: the authenticated principal's name, or
+// "anonymous" when auth is off, plus the client address.
+#[derive(Debug, Clone)]
+pub struct Actor {
+    pub name: String,
+    pub ip: Option<String>,
+}
+
+impl Actor {
+    pub fn system() -> Self {
+        Self { name: "system".into(), ip: None }
+    }
+}
+
+impl<S> FromRequestParts<S> for Actor
+where
+    S: Send + Sync,
+    Access: FromRef<S>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let name = parts
+            .extensions
+            .get::<Principal>()
+            .map_or_else(|| "anonymous".to_string(), |principal| principal.name.clone());
+        let ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(peer)| Access::from_ref(state).client_ip(peer.ip(), &parts.headers).to_string());
+        Ok(Self { name, ip })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub at: DateTime<Utc>,
+    pub actor: String,
+    pub ip: Option<String>,
+    pub action: String,
+    pub service: Option<String>,
+    pub detail: Option<String>,
+}
+
+// Entries buffered per slow subscriber before it starts missing some.
+const EVENT_BUFFER: usize = 256;
+
+#[derive(Clone)]
+pub struct Audit {
+    pool: PgPool,
+    events: broadcast::Sender<AuditEntry>,
+}
+
+impl Audit {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool, events: broadcast::channel(EVENT_BUFFER).0 }
+    }
+
+    // Records a completed mutation and publishes it to /events/audit
+    // subscribers. A failure to write the entry is logged rather than
+    // failing a change that has already been made.
+    pub async fn record(&self, actor: &Actor, action: &str, service: Option<&str>, detail: Option<String>) {
+        let result = sqlx::query_as!(
+            AuditEntry,
+            "INSERT INTO audit_log (actor, ip, action, service, detail) VALUES ($1, $2, $3, $4, $5) \
+             RETURNING id, at, actor, ip, action, service, detail",
+            actor.name,
+            actor.ip,
+            action,
+            service,
+            detail,
+        )
+        .fetch_one(&self.pool)
+        .await;
+        match result {
+            // No subscribers is not an error.
+            Ok(entry) => drop(self.events.send(entry)),
+            Err(e) => tracing::warn!("writing audit entry for {}: {}", action, e),
+        }
+    }
+
+    // New entries as they are recorded, as for /events/audit.
+    pub fn subscribe(&self) -> broadcast::Receiver<AuditEntry> {
+        self.events.subscribe()
+    }
+}
+
+// GET /events/audit
+// Server-sent events, one `audit` event per new entry with the entry as JSON
+// and its id as the event id. A subscriber that falls too far behind gets a
+// `lagged` event with the number of entries it missed; GET /audit fills gaps.
+pub async fn events(State(audit): State<Audit>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = BroadcastStream::new(audit.events.subscribe()).map(|message| {
+        Ok(match message {
+            Ok(entry) => Event::default()
+                .event("audit")
+                .id(entry.id.to_string())
+                .json_data(&entry)
+                .unwrap_or_else(|_| Event::default().comment("unserializable entry")),
+            Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                Event::default().event("lagged").data(missed.to_string())
+            }
+        })
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditFilter {
+    actor: Option<String>,
+    action: Option<String>,
+    service: Option<String>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    format: Option<String>,
+}
+
+// GET /audit
+// Newest first. Filters: ?actor=&action=&service=&since=&until= (RFC 3339),
+// paging as for /services; ?format=csv or Accept: text/csv for a CSV export,
+// which is streamed when unpaged and has times in the caller's timezone.
+pub async fn get_audit(
+    State(audit): State<Audit>,
+    Query(query): Query<PageQuery>,
+    Query(filter): Query<AuditFilter>,
+    RawQuery(raw_query): RawQuery,
+    Timezone(tz): Timezone,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let page = query.page()?;
+    let wants_csv = match filter.format.as_deref() {
+        Some(format) => format == "csv",
+        None => headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/csv")),
+    };
+    let csv_headers = [
+        (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+        (header::CONTENT_DISPOSITION, "attachment; filename=\"audit.csv\""),
+    ];
+    if wants_csv && matches!(page, Page::All) {
+        let body = streaming::body("audit export", move |out| write_csv(audit.pool, filter, tz, out));
+        return Ok((csv_headers, body).into_response());
+    }
+
+    let mut entries = build(&filter, &page)
+        .build_query_as::<AuditEntry>()
+        .fetch_all(&audit.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let base = pagination::base_url("/audit", raw_query.as_deref());
+    let response_headers = pagination::paginate(&base, &page, &mut entries, |entry| Cursor {
+        created_at: entry.at,
+        id: entry.id,
+    });
+
+    if !wants_csv {
+        return Ok((response_headers, Json(entries)).into_response());
+    }
+    let mut csv = String::from(CSV_HEADER);
+    for entry in &entries {
+        csv.push_str(&csv_row(entry, tz));
+    }
+    Ok((response_headers, csv_headers, csv).into_response())
+}
+
+fn build<'a>(filter: &'a AuditFilter, page: &'a Page) -> QueryBuilder<'a, Postgres> {
+    let mut sql = QueryBuilder::new("SELECT * FROM audit_log WHERE true");
+    if let Some(actor) = &filter.actor {
+        sql.push(" AND lower(actor) = lower(").push_bind(actor).push(")");
+    }
+    if let Some(action) = &filter.action {
+        sql.push(" AND action = ").push_bind(action);
+    }
+    if let Some(service) = &filter.service {
+        sql.push(" AND lower(service) = lower(").push_bind(service).push(")");
+    }
+    if let Some(since) = filter.since {
+        sql.push(" AND at >= ").push_bind(since);
+    }
+    if let Some(until) = filter.until {
+        sql.push(" AND at < ").push_bind(until);
+    }
+    if let Page::Keyset { after: Some(cursor), .. } = page {
+        sql.push(" AND (at, id) < (")
+            .push_bind(cursor.created_at)
+            .push(", ")
+            .push_bind(cursor.id)
+            .push(")");
+    }
+    sql.push(" ORDER BY at DESC, id DESC");
+    if let Some(limit) = page.fetch_limit() {
+        sql.push(" LIMIT ").push_bind(limit);
+    }
+    if let Page::Offset { offset, .. } = page {
+        sql.push(" OFFSET ").push_bind(*offset);
+    }
+    sql
+}
+
+const CSV_HEADER: &str = "id,at,actor,ip,action,service,detail\r\n";
+
+fn csv_row(entry: &AuditEntry, tz: Tz) -> String {
+    let fields = [
+        entry.id.to_string(),
+        entry.at.with_timezone(&tz).to_rfc3339(),
+        entry.actor.clone(),
+        entry.ip.clone().unwrap_or_default(),
+        entry.action.clone(),
+        entry.service.clone().unwrap_or_default(),
+        entry.detail.clone().unwrap_or_default(),
+    ];
+    let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    format!("{}\r\n", row.join(","))
+}
+
+async fn write_csv(pool: PgPool, filter: AuditFilter, tz: Tz, mut out: Writer) -> anyhow::Result<()> {
+    out.write(CSV_HEADER.as_bytes()).await?;
+    let mut sql = build(&filter, &Page::All);
+    let mut entries = sql.build_query_as::<AuditEntry>().fetch(&pool);
+    while let Some(entry) = entries.next().await {
+        out.write(csv_row(&entry?, tz).as_bytes()).await?;
+    }
+    out.finish().await
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
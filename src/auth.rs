@@ -0,0 +1,341 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http::{header, HeaderMap, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use chrono::Duration;
+use sqlx::PgPool;
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use crate::{
+    access::{self, Access},
+    audit::{Actor, Audit},
+    config::{AuthConfig, ServerConfig},
+    lockout::{self, Lockout},
+    quick_add, sessions, tokens,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "viewer" => Some(Role::Viewer),
+            "editor" => Some(Role::Editor),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+// Who made a request. Inserted into the request extensions by `enforce`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Principal {
+    pub name: String,
+    pub role: Role,
+    pub groups: Vec<String>,
+    // Set when signed in with a session from POST /login.
+    #[serde(skip)]
+    pub session_id: Option<i32>,
+}
+
+// Identities of a verified client certificate (subject CN first, then SANs),
+// attached to every request on the connection by the TLS listener.
+#[derive(Debug, Clone, Default)]
+pub struct ClientCert {
+    pub names: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct Auth {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    pool: PgPool,
+    api_key: Option<String>,
+    required: bool,
+    public_dashboard: bool,
+    mtls: bool,
+    secure_cookies: bool,
+    session_ttl: Duration,
+    session_idle: Duration,
+    client_certs: HashMap<String, Role>,
+    default_role: Option<Role>,
+    groups: HashMap<String, Vec<String>>,
+    group_roles: HashMap<String, Role>,
+    lockout: Lockout,
+    totp_issuer: String,
+}
+
+impl Auth {
+    // `api_key` is INDEXPAGE_API_KEY; it authenticates as an admin.
+    pub fn new(config: &AuthConfig, server: &ServerConfig, api_key: Option<String>, pool: PgPool) -> Self {
+        let mtls = server.tls.as_ref().is_some_and(|tls| tls.client_ca_file.is_some());
+        Self {
+            inner: Arc::new(Inner {
+                pool,
+                api_key: api_key.filter(|key| !key.is_empty()),
+                required: config.required,
+                public_dashboard: config.public_dashboard,
+                mtls,
+                secure_cookies: server.tls.is_some(),
+                session_ttl: Duration::hours(config.session_ttl_hours.max(1)),
+                session_idle: Duration::hours(config.session_idle_hours.max(1)),
+                client_certs: config.client_certs.clone(),
+                default_role: config.client_cert_default_role,
+                groups: config.groups.clone(),
+                group_roles: config.group_roles.clone(),
+                lockout: Lockout::new(&config.lockout),
+                totp_issuer: config.totp_issuer.clone(),
+            }),
+        }
+    }
+
+    // With no API key, mutual TLS, `auth.required` or `auth.public_dashboard`,
+    // every request is allowed as before.
+    pub fn enabled(&self) -> bool {
+        self.inner.api_key.is_some() || self.inner.mtls || self.inner.required || self.inner.public_dashboard
+    }
+
+    pub fn session_ttl(&self) -> Duration {
+        self.inner.session_ttl
+    }
+
+    pub fn session_idle(&self) -> Duration {
+        self.inner.session_idle
+    }
+
+    pub fn secure_cookies(&self) -> bool {
+        self.inner.secure_cookies
+    }
+
+    pub fn lockout(&self) -> &Lockout {
+        &self.inner.lockout
+    }
+
+    pub fn totp_issuer(&self) -> &str {
+        &self.inner.totp_issuer
+    }
+
+    async fn principal(
+        &self,
+        headers: &HeaderMap,
+        cert: Option<&ClientCert>,
+    ) -> Result<Option<Principal>, (StatusCode, String)> {
+        // A stale cookie just means signed out; a stale bearer token is an error.
+        let bearer = presented_key(headers).filter(|key| key.starts_with(sessions::TOKEN_PREFIX));
+        if let Some(token) = bearer.or_else(|| sessions::from_cookie(headers)) {
+            match sessions::authenticate(&self.inner.pool, token, self.inner.session_idle).await {
+                Ok(Some((name, role, id))) => {
+                    return Ok(Some(Principal { session_id: Some(id), ..self.member_for(&name, role) }));
+                }
+                Ok(None) if bearer.is_some() => {
+                    return Err((StatusCode::UNAUTHORIZED, "Session expired; sign in again".into()));
+                }
+                Ok(None) => {}
+                Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+            }
+        }
+        if let Some(key) = presented_key(headers) {
+            if let Some(expected) = &self.inner.api_key
+                && keys_match(expected, key)
+            {
+                return Ok(Some(self.principal_for("api-key", Role::Admin)));
+            }
+            return match tokens::authenticate(&self.inner.pool, key).await {
+                Ok(Some((owner, role))) => Ok(Some(self.principal_for(&owner, role))),
+                Ok(None) => Err((StatusCode::UNAUTHORIZED, "Invalid API key".into())),
+                Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+            };
+        }
+        let Some(cert) = cert else { return Ok(None) };
+        let mapped = cert
+            .names
+            .iter()
+            .find_map(|name| self.inner.client_certs.get(name).map(|role| (name, *role)));
+        Ok(match (mapped, self.inner.default_role, cert.names.first()) {
+            (Some((name, role)), _, _) => Some(self.member_for(name, role)),
+            (None, Some(role), Some(name)) => Some(self.member_for(name, role)),
+            _ => None,
+        })
+    }
+
+    fn principal_for(&self, name: &str, role: Role) -> Principal {
+        let mut groups: Vec<String> = self
+            .inner
+            .groups
+            .iter()
+            .filter(|(_, members)| members.iter().any(|member| member == name))
+            .map(|(group, _)| group.clone())
+            .collect();
+        groups.sort();
+        Principal { name: name.to_string(), role, groups, session_id: None }
+    }
+
+    // As `principal_for`, raised to the roles of its groups. Not for tokens,
+    // whose scope is a deliberate limit.
+    fn member_for(&self, name: &str, role: Role) -> Principal {
+        let mut principal = self.principal_for(name, role);
+        let granted = principal.groups.iter().filter_map(|group| self.inner.group_roles.get(group));
+        principal.role = granted.fold(principal.role, |role, granted| role.max(*granted));
+        principal
+    }
+}
+
+fn presented_key(headers: &HeaderMap) -> Option<&str> {
+    if let Some(key) = headers.get("x-api-key") {
+        return key.to_str().ok();
+    }
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+pub fn keys_match(expected: &str, presented: &str) -> bool {
+    expected.len() == presented.len() && openssl::memcmp::eq(expected.as_bytes(), presented.as_bytes())
+}
+
+// Reads are open (only the dashboard's with `auth.public_dashboard`); anything
+// that changes state needs an editor, and /admin, the audit log and its event
+// stream, category access rules, waking machines, proxy syncs, result
+// webhooks and embed tokens need an admin. /me is about the caller, so it
+// needs someone signed in; /embed is authorized by its token.
+fn required_role(method: &Method, path: &str, public_dashboard: bool) -> Option<Role> {
+    let under = |prefix: &str| path == prefix || path.starts_with(&format!("{}/", prefix));
+    let category_access = under("/categories") && path.ends_with("/access");
+    // Anyone may propose a service; only admins see and decide on proposals.
+    let proposing = method == Method::POST && path == "/proposals";
+    let proposals = (under("/proposals") && !proposing) || (under("/services") && path.ends_with("/approve"));
+    // Waking a machine is admin-only; setting its MAC address is an edit.
+    let waking = method == Method::POST && under("/services") && path.ends_with("/wake");
+    let admin_only = under("/admin")
+        || under("/audit")
+        || under("/events/audit")
+        || under("/changes")
+        || under("/integrations/proxies")
+        || under("/result-webhooks")
+        || under("/embed/tokens")
+        || waking;
+    if admin_only || category_access || proposals {
+        Some(Role::Admin)
+    } else if path == "/login" || path == "/logout" || path == "/embed" || under("/password-reset") || proposing {
+        None
+    } else if under("/me") || is_action(method, path) {
+        // An action's own role is checked when it runs (see actions.rs).
+        Some(Role::Viewer)
+    } else if under("/discovery") {
+        // What is on the LAN is only of interest to those adding services.
+        Some(Role::Editor)
+    } else if access::is_read(method, path) {
+        (public_dashboard && !is_dashboard(path)).then_some(Role::Viewer)
+    } else if under("/invites") {
+        // Anyone signed in may join an org they were invited to.
+        Some(Role::Viewer)
+    } else {
+        Some(Role::Editor)
+    }
+}
+
+// POST /services/:name/actions/:action
+fn is_action(method: &Method, path: &str) -> bool {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    method == Method::POST && matches!(segments.as_slice(), ["services", _, "actions", _])
+}
+
+// What an anonymous visitor sees of the start page in `auth.public_dashboard`
+// mode.
+fn is_dashboard(path: &str) -> bool {
+    const PATHS: [&str; 8] = [
+        "/services",
+        "/services/suggest",
+        "/categories",
+        "/tags",
+        "/status",
+        "/manifest.webmanifest",
+        "/icon.svg",
+        "/sw.js",
+    ];
+    if PATHS.contains(&path) || path.starts_with("/icons/") {
+        return true;
+    }
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    matches!(
+        segments.as_slice(),
+        ["services", _, "status" | "history" | "icon" | "badge"] | ["categories", _, "status"] | ["go", _]
+    )
+}
+
+pub async fn enforce(
+    State(auth): State<Auth>,
+    State(access): State<Access>,
+    State(audit): State<Audit>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, String)> {
+    if !auth.enabled() || request.method() == Method::OPTIONS {
+        return Ok(next.run(request).await);
+    }
+    let uri = request.uri().clone();
+    quick_add::with_query_token(&uri, request.headers_mut(), auth.inner.api_key.as_deref())?;
+    let principal = match auth
+        .principal(request.headers(), request.extensions().get::<ClientCert>())
+        .await
+    {
+        Ok(principal) => principal,
+        // Bad keys and tokens count towards the client's lockout, like bad
+        // passwords. It only refuses keys that fail, so a client locked out
+        // by bad passwords can still use a valid key.
+        Err((StatusCode::UNAUTHORIZED, message)) => {
+            let ip = request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(peer)| access.client_ip(peer.ip(), request.headers()).to_string());
+            let ip_key = lockout::ip_key(ip.as_deref());
+            if let Some(wait) = auth.inner.lockout.locked(&[&ip_key]) {
+                return Ok(lockout::too_many(wait));
+            }
+            let actor = Actor { name: "anonymous".into(), ip };
+            auth.inner.lockout.record_failure(&audit, &actor, &[&ip_key], &message).await;
+            return Err((StatusCode::UNAUTHORIZED, message));
+        }
+        Err(e) => return Err(e),
+    };
+
+    let public_dashboard = auth.inner.public_dashboard;
+    if let Some(required) = required_role(request.method(), request.uri().path(), public_dashboard) {
+        match &principal {
+            None => return Err((StatusCode::UNAUTHORIZED, "Authentication required".into())),
+            Some(principal) if principal.role < required => {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    format!("'{}' lacks the {} role", principal.name, required.as_str()),
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+    let Some(principal) = principal else { return Ok(next.run(request).await) };
+    request.extensions_mut().insert(principal.clone());
+    let mut response = next.run(request).await;
+    // For the access log, which sees only the response.
+    response.extensions_mut().insert(principal);
+    Ok(response)
+}
@@ -0,0 +1,119 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::Response,
+};
+use bytes::Bytes;
+use http::{HeaderMap, StatusCode};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::time::Duration;
+
+use crate::{
+    caching::{self, Version},
+    categories::Visibility,
+    checks,
+};
+
+// Shields.io-style SVG badges of a service's state, for embedding in wikis
+// and READMEs. Browsers and image proxies (e.g. GitHub's) keep one for a
+// minute, then revalidate against its ETag.
+const MAX_AGE: Duration = Duration::from_secs(60);
+const MAX_LABEL_CHARS: usize = 64;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Show {
+    #[default]
+    Status,
+    // The last check's latency, or the state when there is none.
+    Latency,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BadgeQuery {
+    // Left-hand text; the service's name by default.
+    label: Option<String>,
+    #[serde(default)]
+    show: Show,
+}
+
+fn color(state: &str) -> &'static str {
+    match state {
+        "up" => "#4c1",
+        "degraded" | "flapping" => "#dfb317",
+        "down" => "#e05d44",
+        _ => "#9f9f9f",
+    }
+}
+
+// Approximate width of `text` in 11px Verdana.
+fn text_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c {
+            'i' | 'j' | 'l' | 'f' | 't' | 'r' | 'I' | '.' | ',' | ':' | ';' | '|' | '!' | '\'' | ' ' => 4,
+            'm' | 'w' | 'M' | 'W' => 10,
+            c if c.is_uppercase() => 8,
+            _ => 7,
+        })
+        .sum()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render(label: &str, message: &str, color: &str) -> String {
+    let label_width = text_width(label) + 10;
+    let message_width = text_width(message) + 10;
+    let width = label_width + message_width;
+    let (label, message) = (escape_xml(label), escape_xml(message));
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img"
+ aria-label="{label}: {message}">
+<title>{label}: {message}</title>
+<linearGradient id="s" x2="0" y2="100%">
+<stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/>
+</linearGradient>
+<clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath>
+<g clip-path="url(#r)">
+<rect width="{label_width}" height="20" fill="#555"/>
+<rect x="{label_width}" width="{message_width}" height="20" fill="{color}"/>
+<rect width="{width}" height="20" fill="url(#s)"/>
+</g>
+<g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+<text x="{label_x}" y="14">{label}</text><text x="{message_x}" y="14">{message}</text>
+</g>
+</svg>"##,
+        label_x = label_width / 2,
+        message_x = label_width + message_width / 2,
+    )
+}
+
+// GET /services/:name/badge
+// ?show=status (default) or latency; ?label= replaces the service's name.
+pub async fn badge(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    Path(name): Path<String>,
+    Query(query): Query<BadgeQuery>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let status = checks::fetch_status(&pool, &name)
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, "Service not found".into()))?;
+    let label = query.label.filter(|label| !label.trim().is_empty()).unwrap_or(name);
+    let label: String = label.chars().take(MAX_LABEL_CHARS).collect();
+    let message = match (query.show, status.latency_ms) {
+        (Show::Latency, Some(latency_ms)) if status.state != "down" => format!("{} ms", latency_ms),
+        _ => status.state.to_string(),
+    };
+
+    let body = render(&label, &message, color(status.state));
+    let fingerprint = caching::fingerprint(body.as_bytes());
+    let body = Bytes::from(body);
+    Ok(caching::serve(&headers, &Version::default(), MAX_AGE, "image/svg+xml", &fingerprint, body))
+}
@@ -0,0 +1,138 @@
+use axum::{extract::State, Json};
+use http::StatusCode;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    audit::{Actor, Audit},
+    categories::Visibility,
+    hooks::{HookEvent, Hooks},
+    links::LinkContext,
+    repository::ServiceRepository,
+    services::Service,
+};
+
+// Applies one set of changes to every service matching a filter, in a single
+// transaction: either all of them change or none do. pre_update hooks see
+// every service before anything is written, so one rejection stops them all.
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BulkFilter {
+    pub ids: Option<Vec<i32>>,
+    pub tag: Option<String>,
+    pub category_id: Option<i32>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BulkChanges {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub add_tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub remove_tags: Vec<String>,
+    // Some(None), from an explicit null, takes the services out of their
+    // category.
+    #[serde(deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<Option<i32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public: Option<bool>,
+}
+
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<i32>>, D::Error> {
+    Option::<i32>::deserialize(deserializer).map(Some)
+}
+
+impl BulkChanges {
+    fn is_empty(&self) -> bool {
+        self.add_tags.is_empty() && self.remove_tags.is_empty() && self.category_id.is_none() && self.public.is_none()
+    }
+
+    // For the audit log, e.g. "+tags media; category 3; public false".
+    fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.add_tags.is_empty() {
+            parts.push(format!("+tags {}", self.add_tags.join(",")));
+        }
+        if !self.remove_tags.is_empty() {
+            parts.push(format!("-tags {}", self.remove_tags.join(",")));
+        }
+        match self.category_id {
+            Some(Some(id)) => parts.push(format!("category {}", id)),
+            Some(None) => parts.push("no category".into()),
+            None => {}
+        }
+        if let Some(public) = self.public {
+            parts.push(format!("public {}", public));
+        }
+        parts.join("; ")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BulkUpdate {
+    filter: BulkFilter,
+    changes: BulkChanges,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkReport {
+    updated: usize,
+    services: Vec<Service>,
+}
+
+// POST /services/bulk/update
+// {"filter": {"ids": [..], "tag": "..", "category_id": ..}, "changes":
+// {"add_tags": [..], "remove_tags": [..], "category_id": ..|null, "public": ..}}.
+// Filters combine; only services the caller can see are matched.
+#[allow(clippy::too_many_arguments)]
+pub async fn bulk_update<R: ServiceRepository>(
+    State(repository): State<R>,
+    State(audit): State<Audit>,
+    State(hooks): State<Hooks>,
+    actor: Actor,
+    links: LinkContext,
+    visibility: Visibility,
+    Json(payload): Json<BulkUpdate>,
+) -> Result<Json<BulkReport>, (StatusCode, String)> {
+    let BulkUpdate { filter, mut changes } = payload;
+    if filter.ids.is_none() && filter.tag.is_none() && filter.category_id.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "filter needs at least one of ids, tag or category_id".into()));
+    }
+    for tags in [&mut changes.add_tags, &mut changes.remove_tags] {
+        tags.retain(|tag| !tag.trim().is_empty());
+        tags.sort();
+        tags.dedup();
+    }
+    if changes.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "changes must change something".into()));
+    }
+    if changes.add_tags.iter().any(|tag| changes.remove_tags.contains(tag)) {
+        return Err((StatusCode::BAD_REQUEST, "a tag can't be both added and removed".into()));
+    }
+    let matched = repository.bulk_matches(&filter, &visibility).await?;
+    if matched.is_empty() {
+        return Ok(Json(BulkReport { updated: 0, services: Vec::new() }));
+    }
+
+    let change = serde_json::to_value(&changes).unwrap_or_default();
+    for (_, name) in &matched {
+        let proposed = serde_json::json!({ "name": name, "changes": change });
+        hooks.before(HookEvent::PreUpdate, &actor, &proposed).await?;
+    }
+
+    let ids: Vec<i32> = matched.iter().map(|(id, _)| *id).collect();
+    let updated = repository.bulk_update(&ids, &changes, &visibility).await?;
+
+    let detail = changes.describe();
+    let mut services = Vec::with_capacity(updated.len());
+    for (id, name) in matched.iter().filter(|(id, _)| updated.contains(id)) {
+        audit.record(&actor, "service.bulk_update", Some(name), Some(detail.clone())).await;
+        if let Some(mut service) = repository.get(*id).await? {
+            hooks.after(HookEvent::PostUpdate, &actor, serde_json::to_value(&service).unwrap_or_default());
+            service.expand_links(&links);
+            services.push(service);
+        }
+    }
+    Ok(Json(BulkReport { updated: services.len(), services }))
+}
@@ -0,0 +1,67 @@
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use serde::Deserialize;
+use std::time::Duration;
+
+// Browser caching for icons, badges and static assets. Each response carries
+// an ETag derived from its content, so once max-age runs out the browser
+// revalidates and gets a bodiless 304 if nothing changed. URLs fingerprinted
+// with that value (?v=...) name one version of the content for good and are
+// cached as immutable.
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Version {
+    // The fingerprint the URL was built with, if any.
+    pub v: Option<String>,
+}
+
+// A short hash of `body`, used as its ETag and in fingerprinted URLs.
+pub fn fingerprint(body: &[u8]) -> String {
+    openssl::sha::sha256(body)[..8].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// `path` with `fingerprint` appended as ?v=.
+pub fn versioned(path: &str, fingerprint: &str) -> String {
+    format!("{}?v={}", path, fingerprint)
+}
+
+// Whether If-None-Match lists the ETag; weak tags compare equal too.
+fn not_modified(request: &HeaderMap, etag: &str) -> bool {
+    request
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+// Serves `body`, or 304 Not Modified if the browser's copy is current. With
+// a ?v= naming this content the response can be kept forever; with a stale
+// one it must be revalidated every time, so the old URL never pins the new
+// content; without one it is fresh for `max_age`.
+pub fn serve(
+    request: &HeaderMap,
+    version: &Version,
+    max_age: Duration,
+    content_type: &'static str,
+    fingerprint: &str,
+    body: Bytes,
+) -> Response {
+    let etag = format!("\"{}\"", fingerprint);
+    let cache_control = match version.v.as_deref() {
+        Some(v) if v == fingerprint => IMMUTABLE.to_string(),
+        Some(_) => "no-cache".to_string(),
+        None => format!("public, max-age={}", max_age.as_secs()),
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_str(&cache_control).expect("ASCII"));
+    headers.insert(header::ETAG, HeaderValue::from_str(&etag).expect("hex digits"));
+    if not_modified(request, &etag) {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    (headers, body).into_response()
+}
@@ -0,0 +1,381 @@
+use axum::{
+    extract::{FromRef, FromRequestParts, Path, State},
+    Json,
+};
+use http::{request::Parts, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use std::convert::Infallible;
+
+use crate::{
+    audit::{Actor, Audit},
+    auth::{Auth, Principal, Role},
+    checks,
+    colors::Colors,
+    health, orgs,
+};
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Category {
+    pub id: i32,
+    pub name: String,
+    // Restricted categories are only visible to principals with at least
+    // `min_role` or in one of `groups` (and always to admins).
+    pub min_role: Option<String>,
+    pub groups: Vec<String>,
+    pub org_id: Option<i32>,
+    // Tile colors for the category's services; see colors.rs.
+    pub color: Option<String>,
+    pub accent: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCategory {
+    name: String,
+    #[serde(default)]
+    org_id: Option<i32>,
+    #[serde(flatten)]
+    access: CategoryAccess,
+    #[serde(flatten)]
+    colors: Colors,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CategoryAccess {
+    #[serde(default)]
+    min_role: Option<Role>,
+    #[serde(default)]
+    groups: Vec<String>,
+}
+
+// Which restricted categories and org-scoped services the caller may see:
+// everything for admins and when auth is off, otherwise categories matching
+// its role or groups and things in orgs it belongs to. Hidden services are
+// left out of listings and answer 404.
+#[derive(Debug, Clone)]
+pub struct Visibility {
+    // None when nothing is hidden.
+    roles: Option<Vec<String>>,
+    groups: Vec<String>,
+    member: Option<String>,
+}
+
+impl Visibility {
+    // What an admin sees; also for tests.
+    pub fn everything() -> Self {
+        Self { roles: None, groups: Vec::new(), member: None }
+    }
+
+    fn of(principal: Option<&Principal>) -> Self {
+        let Some(principal) = principal else {
+            return Self { roles: Some(Vec::new()), groups: Vec::new(), member: None };
+        };
+        if principal.role == Role::Admin {
+            return Self::everything();
+        }
+        let roles = [Role::Viewer, Role::Editor]
+            .into_iter()
+            .filter(|role| *role <= principal.role)
+            .map(|role| role.as_str().to_string())
+            .collect();
+        Self { roles: Some(roles), groups: principal.groups.clone(), member: Some(principal.name.clone()) }
+    }
+
+    pub fn is_unrestricted(&self) -> bool {
+        self.roles.is_none()
+    }
+
+    // The principal name org membership is checked against.
+    pub fn member(&self) -> Option<&str> {
+        self.member.as_deref()
+    }
+
+    // Appends " AND <category visible>" for the `categories` table joined
+    // (LEFT JOIN for services) under that name.
+    pub fn restrict(&self, sql: &mut QueryBuilder<'_, Postgres>) {
+        let Some(roles) = &self.roles else { return };
+        sql.push(
+            " AND (categories.id IS NULL OR (((categories.min_role IS NULL AND categories.groups = '{}') \
+             OR categories.min_role = ANY(",
+        )
+        .push_bind(roles.clone())
+        .push(") OR categories.groups && ")
+        .push_bind(self.groups.clone())
+        .push(") AND ");
+        self.push_org(sql, "categories.org_id");
+        sql.push("))");
+    }
+
+    // As `restrict`, plus the service's own org.
+    pub fn restrict_services(&self, sql: &mut QueryBuilder<'_, Postgres>) {
+        if self.is_unrestricted() {
+            return;
+        }
+        self.restrict(sql);
+        sql.push(" AND ");
+        self.push_org(sql, "services.org_id");
+    }
+
+    // What `restrict_services` checks, for a service already in memory:
+    // `category` is its category's (min_role, groups, org_id), `org_id` its
+    // own org and `orgs` the orgs the caller is a member of.
+    pub fn allows(
+        &self,
+        category: Option<(Option<&str>, &[String], Option<i32>)>,
+        org_id: Option<i32>,
+        orgs: &[i32],
+    ) -> bool {
+        let Some(roles) = &self.roles else { return true };
+        let in_org = |org_id: Option<i32>| org_id.is_none_or(|org_id| orgs.contains(&org_id));
+        let category_visible = category.is_none_or(|(min_role, groups, category_org)| {
+            let open = min_role.is_none() && groups.is_empty();
+            let by_role = min_role.is_some_and(|min_role| roles.iter().any(|role| role == min_role));
+            let by_group = groups.iter().any(|group| self.groups.contains(group));
+            (open || by_role || by_group) && in_org(category_org)
+        });
+        category_visible && in_org(org_id)
+    }
+
+    fn push_org(&self, sql: &mut QueryBuilder<'_, Postgres>, column: &str) {
+        sql.push(format!("({0} IS NULL OR {0} IN (SELECT org_id FROM org_members WHERE member = ", column))
+            .push_bind(self.member.clone())
+            .push("))");
+    }
+
+    // 404s for a service the caller may not see, as if it did not exist.
+    pub async fn check_service(&self, pool: &PgPool, name: &str) -> Result<(), (StatusCode, String)> {
+        if self.roles.is_none() {
+            return Ok(());
+        }
+        let mut sql = QueryBuilder::new(
+            "SELECT services.id FROM services LEFT JOIN categories ON categories.id = services.category_id \
+             WHERE services.deleted_at IS NULL AND lower(services.name) = lower(",
+        );
+        sql.push_bind(name).push(")");
+        self.restrict_services(&mut sql);
+        let visible = sql
+            .build_query_scalar::<i32>()
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        match visible {
+            Some(_) => Ok(()),
+            None => Err((StatusCode::NOT_FOUND, "Service not found".into())),
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for Visibility
+where
+    S: Send + Sync,
+    Auth: FromRef<S>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if !Auth::from_ref(state).enabled() {
+            return Ok(Self::everything());
+        }
+        Ok(Self::of(parts.extensions.get::<Principal>()))
+    }
+}
+
+// GET /categories
+pub async fn get_categories(State(pool): State<PgPool>, visibility: Visibility) -> Json<Vec<Category>> {
+    let mut sql = QueryBuilder::new("SELECT * FROM categories WHERE true");
+    visibility.restrict(&mut sql);
+    sql.push(" ORDER BY name");
+    let categories = sql
+        .build_query_as::<Category>()
+        .fetch_all(&pool)
+        .await
+        .unwrap_or_else(|_| vec![]);
+    Json(categories)
+}
+
+// POST /categories
+pub async fn create_category(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Json(payload): Json<CreateCategory>,
+) -> Result<Json<Category>, (StatusCode, String)> {
+    if let Some(org_id) = payload.org_id {
+        orgs::check_member(&pool, &visibility, org_id).await?;
+    }
+    let colors = payload.colors.validated()?;
+    let result = sqlx::query_as!(
+        Category,
+        "INSERT INTO categories (name, min_role, groups, org_id, color, accent) VALUES ($1, $2, $3, $4, $5, $6) \
+         RETURNING id, name, min_role, groups, org_id, color, accent",
+        payload.name,
+        payload.access.min_role.map(Role::as_str),
+        &payload.access.groups,
+        payload.org_id,
+        colors.color,
+        colors.accent,
+    )
+    .fetch_one(&pool)
+    .await;
+
+    match result {
+        Ok(category) => {
+            audit.record(&actor, "category.create", None, Some(category.name.clone())).await;
+            Ok(Json(category))
+        }
+        Err(e) => Err((StatusCode::BAD_REQUEST, format!("Failed to insert: {}", e))),
+    }
+}
+
+// POST /categories/:id/access
+// Replaces the category's restriction; an empty body makes it visible to all.
+pub async fn set_access(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(id): Path<i32>,
+    Json(access): Json<CategoryAccess>,
+) -> Result<Json<Category>, (StatusCode, String)> {
+    let category = sqlx::query_as!(
+        Category,
+        "UPDATE categories SET min_role = $2, groups = $3 WHERE id = $1 \
+         RETURNING id, name, min_role, groups, org_id, color, accent",
+        id,
+        access.min_role.map(Role::as_str),
+        &access.groups,
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Category not found".into()))?;
+
+    let detail = format!(
+        "{}: min_role={}, groups=[{}]",
+        category.name,
+        category.min_role.as_deref().unwrap_or("-"),
+        category.groups.join(", ")
+    );
+    audit.record(&actor, "category.access", None, Some(detail)).await;
+    Ok(Json(category))
+}
+
+// DELETE /categories/:id
+// Member services are kept and become uncategorized. Returns the deleted
+// category.
+pub async fn delete_category(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    visibility: Visibility,
+    Path(id): Path<i32>,
+) -> Result<Json<Category>, (StatusCode, String)> {
+    let mut sql = QueryBuilder::new("DELETE FROM categories WHERE categories.id = ");
+    sql.push_bind(id);
+    visibility.restrict(&mut sql);
+    sql.push(" RETURNING id, name, min_role, groups, org_id, color, accent");
+    let result = sql.build_query_as::<Category>().fetch_optional(&pool).await;
+
+    match result {
+        Ok(Some(category)) => {
+            audit.record(&actor, "category.delete", None, Some(category.name.clone())).await;
+            Ok(Json(category))
+        }
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Category not found".into())),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct MemberState {
+    health: Option<String>,
+    ok: Option<bool>,
+    degraded: Option<bool>,
+    alert_down: Option<bool>,
+    latency_ms: Option<i32>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct StateCounts {
+    up: usize,
+    degraded: usize,
+    down: usize,
+    flapping: usize,
+    maintenance: usize,
+    unknown: usize,
+    total: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategoryStatus {
+    id: i32,
+    name: String,
+    // "up" when every member is, "down" when every checked member is,
+    // "partial" for anything in between and "unknown" before any checks.
+    state: &'static str,
+    counts: StateCounts,
+    // Slowest latest check among the members.
+    worst_latency_ms: Option<i32>,
+}
+
+// GET /categories/:id/status
+// One summary of the latest state of the category's visible services, for
+// section headers.
+pub async fn category_status(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    Path(id): Path<i32>,
+) -> Result<Json<CategoryStatus>, (StatusCode, String)> {
+    let internal = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let mut sql = QueryBuilder::new("SELECT name FROM categories WHERE id = ");
+    sql.push_bind(id);
+    visibility.restrict(&mut sql);
+    let name = sql
+        .build_query_scalar::<String>()
+        .fetch_optional(&pool)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "Category not found".into()))?;
+
+    let mut sql = QueryBuilder::new(format!(
+        "SELECT r.ok, r.degraded, r.latency_ms, {}, {} FROM (
+            SELECT services.id, services.maintenance_until FROM services
+            LEFT JOIN categories ON categories.id = services.category_id
+            WHERE services.deleted_at IS NULL AND services.archived_at IS NULL AND services.category_id = ",
+        checks::ALERT_DOWN_COLUMN,
+        health::HEALTH_COLUMN
+    ));
+    sql.push_bind(id);
+    visibility.restrict_services(&mut sql);
+    sql.push(
+        ") s LEFT JOIN LATERAL (
+            SELECT ok, degraded, latency_ms FROM check_results
+            WHERE service_id = s.id ORDER BY checked_at DESC LIMIT 1
+        ) r ON true",
+    );
+    let members = sql.build_query_as::<MemberState>().fetch_all(&pool).await.map_err(internal)?;
+
+    let mut counts = StateCounts { total: members.len(), ..Default::default() };
+    for member in &members {
+        match checks::state_of(member.health.as_deref(), member.ok, member.degraded, member.alert_down) {
+            "up" => counts.up += 1,
+            "degraded" => counts.degraded += 1,
+            "down" => counts.down += 1,
+            "flapping" => counts.flapping += 1,
+            "maintenance" => counts.maintenance += 1,
+            _ => counts.unknown += 1,
+        }
+    }
+    let checked = counts.total - counts.unknown;
+    let state = if checked == 0 {
+        "unknown"
+    } else if counts.up == counts.total {
+        "up"
+    } else if counts.down == checked {
+        "down"
+    } else {
+        "partial"
+    };
+    let worst_latency_ms = members.iter().filter_map(|member| member.latency_ms).max();
+    Ok(Json(CategoryStatus { id, name, state, counts, worst_latency_ms }))
+}
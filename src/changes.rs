@@ -0,0 +1,384 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Path, Query, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use http::{header, HeaderValue, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, OnceLock},
+};
+use tower::ServiceExt;
+
+use crate::{
+    access,
+    audit::{Actor, Audit},
+    auth::{ClientCert, Principal, Role},
+    quick_add,
+};
+
+// Review mode (`auth.review_changes`). Edits and deletes by editors (see
+// `reviewed`) are not carried out but stored as pending changes: the request
+// as it was sent, which an admin approves or rejects under /changes.
+// Approving replays it through the router with the admin's credentials, so
+// it is checked and audited like any request of theirs; the change.* audit
+// entries name both the editor and the admin. Creating things, and anything
+// admins do, is never held back.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+// Of the replayed response, kept as the change's result.
+const MAX_RESULT_CHARS: usize = 4096;
+
+#[derive(Clone)]
+pub struct Changes {
+    pool: PgPool,
+    enabled: bool,
+    // The whole app, for replaying approved changes; set once it is built.
+    app: Arc<OnceLock<Router>>,
+}
+
+impl Changes {
+    pub fn new(pool: PgPool, enabled: bool) -> Self {
+        Self { pool, enabled, app: Arc::default() }
+    }
+
+    pub fn attach(&self, app: Router) {
+        let _ = self.app.set(app);
+    }
+}
+
+// Every change needs review except creating things, the caller's own
+// account, and operating services without editing them (acks, maintenance
+// windows, actions, reviewing page changes), so new routes are held unless
+// they are listed here.
+fn reviewed(method: &Method, path: &str) -> bool {
+    if access::is_read(method, path) {
+        return false;
+    }
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let creating = path == quick_add::PATH
+        || (*method == Method::POST
+            && matches!(
+                segments.as_slice(),
+                ["services"]
+                    | ["services", "import", _]
+                    | ["services", _, "clone"]
+                    | ["categories"]
+                    | ["orgs"]
+                    | ["orgs", _, "invites"]
+                    | ["incidents", _, "notes"]
+                    | ["proposals"]
+            ));
+    let operating = matches!(
+        segments.as_slice(),
+        ["services", _, "ack" | "maintenance"]
+            | ["services", _, "actions", _]
+            | ["services", _, "snapshots", "review"]
+            | ["integrations", "alertmanager"]
+            | ["admin", "backups"]
+    );
+    let own = matches!(
+        segments.as_slice(),
+        ["me", ..] | ["login"] | ["logout"] | ["password-reset", ..] | ["invites", _, "accept"]
+    );
+    !(creating || operating || own)
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Change {
+    id: i32,
+    method: String,
+    // With the query string.
+    path: String,
+    content_type: Option<String>,
+    body: String,
+    requested_by: String,
+    requested_ip: Option<String>,
+    requested_at: DateTime<Utc>,
+    // "pending", "approved", "failed" (approved, but the replay was refused)
+    // or "rejected".
+    status: String,
+    decided_by: Option<String>,
+    decided_at: Option<DateTime<Utc>>,
+    // The replayed response's status code and body.
+    result_status: Option<i32>,
+    result: Option<String>,
+}
+
+impl Change {
+    fn summary(&self) -> String {
+        format!("{} {} requested by {}", self.method, self.path, self.requested_by)
+    }
+}
+
+fn internal(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+// Middleware, inside auth::enforce: holds back editors' edits and deletes
+// and answers 202 with the pending change.
+pub async fn review(
+    State(changes): State<Changes>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    request: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, String)> {
+    let editor = request.extensions().get::<Principal>().is_some_and(|principal| principal.role == Role::Editor);
+    if !changes.enabled || !editor || !reviewed(request.method(), request.uri().path()) {
+        return Ok(next.run(request).await);
+    }
+    let (parts, body) = request.into_parts();
+    let body = axum::body::to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|e| (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()))?;
+    let body = String::from_utf8(body.to_vec())
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Changes for review must have a text body".to_string()))?;
+    let path = parts.uri.path_and_query().map_or_else(|| parts.uri.path().to_string(), |path| path.to_string());
+    let content_type = parts.headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+
+    let change = sqlx::query_as::<_, Change>(
+        "INSERT INTO pending_changes (method, path, content_type, body, requested_by, requested_ip) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+    )
+    .bind(parts.method.as_str())
+    .bind(&path)
+    .bind(content_type)
+    .bind(&body)
+    .bind(&actor.name)
+    .bind(&actor.ip)
+    .fetch_one(&changes.pool)
+    .await
+    .map_err(internal)?;
+    let detail = format!("#{} {} {}", change.id, change.method, change.path);
+    audit.record(&actor, "change.request", None, Some(detail)).await;
+    Ok((StatusCode::ACCEPTED, Json(change)).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangesQuery {
+    // pending (default), approved, failed, rejected or all.
+    status: Option<String>,
+}
+
+// GET /changes
+// Oldest first.
+pub async fn get_changes(
+    State(pool): State<PgPool>,
+    Query(query): Query<ChangesQuery>,
+) -> Result<Json<Vec<Change>>, (StatusCode, String)> {
+    let status = query.status.as_deref().unwrap_or("pending");
+    if !matches!(status, "pending" | "approved" | "failed" | "rejected" | "all") {
+        return Err((StatusCode::BAD_REQUEST, format!("Unknown status '{}'", status)));
+    }
+    let changes = sqlx::query_as::<_, Change>(
+        "SELECT * FROM pending_changes WHERE $1 = 'all' OR status = $1 ORDER BY requested_at, id",
+    )
+    .bind(status)
+    .fetch_all(&pool)
+    .await
+    .map_err(internal)?;
+    Ok(Json(changes))
+}
+
+// GET /changes/:id
+pub async fn get_change(State(pool): State<PgPool>, Path(id): Path<i32>) -> Result<Json<Change>, (StatusCode, String)> {
+    sqlx::query_as::<_, Change>("SELECT * FROM pending_changes WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(internal)?
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "Change not found".into()))
+}
+
+// Marks a pending change as decided; 409 if someone else already did.
+async fn decide(pool: &PgPool, id: i32, status: &str, actor: &Actor) -> Result<Change, (StatusCode, String)> {
+    let decided = sqlx::query_as::<_, Change>(
+        "UPDATE pending_changes SET status = $2, decided_by = $3, decided_at = now() \
+         WHERE id = $1 AND status = 'pending' RETURNING *",
+    )
+    .bind(id)
+    .bind(status)
+    .bind(&actor.name)
+    .fetch_optional(pool)
+    .await
+    .map_err(internal)?;
+    if let Some(change) = decided {
+        return Ok(change);
+    }
+    let status = sqlx::query_scalar::<_, String>("SELECT status FROM pending_changes WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "Change not found".into()))?;
+    Err((StatusCode::CONFLICT, format!("Change #{} is already {}", id, status)))
+}
+
+// POST /changes/:id/approve
+// Replays the change as the approving admin. Its result is stored either
+// way; a refused replay leaves the change "failed".
+pub async fn approve(
+    State(pool): State<PgPool>,
+    State(changes): State<Changes>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(id): Path<i32>,
+    approval: Request,
+) -> Result<Json<Change>, (StatusCode, String)> {
+    let app = changes
+        .app
+        .get()
+        .cloned()
+        .ok_or((StatusCode::SERVICE_UNAVAILABLE, "Not ready to replay changes".to_string()))?;
+    let mut change = decide(&pool, id, "approved", &actor).await?;
+
+    // The admin's credentials and connection, the editor's request.
+    let mut replay = Request::new(Body::from(change.body.clone()));
+    let stored = |what: &str| (StatusCode::INTERNAL_SERVER_ERROR, format!("Change #{} has an invalid {}", id, what));
+    *replay.method_mut() = change.method.parse().map_err(|_| stored("method"))?;
+    *replay.uri_mut() = change.path.parse().map_err(|_| stored("path"))?;
+    *replay.headers_mut() = approval.headers().clone();
+    replay.headers_mut().remove(header::CONTENT_LENGTH);
+    replay.headers_mut().remove(header::CONTENT_TYPE);
+    if let Some(content_type) = change.content_type.as_deref().and_then(|value| HeaderValue::from_str(value).ok()) {
+        replay.headers_mut().insert(header::CONTENT_TYPE, content_type);
+    }
+    if let Some(peer) = approval.extensions().get::<ConnectInfo<SocketAddr>>() {
+        replay.extensions_mut().insert(*peer);
+    }
+    if let Some(cert) = approval.extensions().get::<ClientCert>() {
+        replay.extensions_mut().insert(cert.clone());
+    }
+
+    let response = app.oneshot(replay).await.unwrap_or_else(|never| match never {});
+    let result_status = response.status();
+    let result = axum::body::to_bytes(response.into_body(), MAX_BODY_BYTES).await.unwrap_or_default();
+    let result: String = String::from_utf8_lossy(&result).chars().take(MAX_RESULT_CHARS).collect();
+    change = sqlx::query_as::<_, Change>(
+        "UPDATE pending_changes SET status = $2, result_status = $3, result = $4 WHERE id = $1 RETURNING *",
+    )
+    .bind(id)
+    .bind(if result_status.is_success() { "approved" } else { "failed" })
+    .bind(i32::from(result_status.as_u16()))
+    .bind(result)
+    .fetch_one(&pool)
+    .await
+    .map_err(internal)?;
+    let action = if result_status.is_success() { "change.approve" } else { "change.fail" };
+    audit.record(&actor, action, None, Some(format!("#{} {}", change.id, change.summary()))).await;
+    Ok(Json(change))
+}
+
+// POST /changes/:id/reject
+pub async fn reject(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<Change>, (StatusCode, String)> {
+    let change = decide(&pool, id, "rejected", &actor).await?;
+    audit.record(&actor, "change.reject", None, Some(format!("#{} {}", change.id, change.summary()))).await;
+    Ok(Json(change))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What editors may do without review besides their own account (/me),
+    // as (method, path) in routes.rs.
+    const NOT_REVIEWED: [(&str, &str); 26] = [
+        ("POST", "/services"),
+        ("POST", "/services/import/csv"),
+        ("POST", "/services/import/homer"),
+        ("POST", "/services/import/heimdall"),
+        ("POST", "/services/import/homepage"),
+        ("POST", "/services/{name}/clone"),
+        ("POST", "/services/{name}/ack"),
+        ("DELETE", "/services/{name}/ack"),
+        ("POST", "/services/{name}/maintenance"),
+        ("DELETE", "/services/{name}/maintenance"),
+        ("POST", "/services/{name}/snapshots/review"),
+        ("POST", "/services/{name}/actions/{action}"),
+        ("POST", "/proposals"),
+        ("GET", "quick_add::PATH"),
+        ("POST", "quick_add::PATH"),
+        ("POST", "/categories"),
+        ("POST", "/orgs"),
+        ("POST", "/orgs/{id}/invites"),
+        ("POST", "/invites/{token}/accept"),
+        ("POST", "/login"),
+        ("POST", "/password-reset"),
+        ("POST", "/password-reset/{token}"),
+        ("POST", "/logout"),
+        ("POST", "/integrations/alertmanager"),
+        ("POST", "/admin/backups"),
+        ("POST", "/incidents/{id}/notes"),
+    ];
+
+    // (method, path) for every handler in routes.rs, paths as written there.
+    fn routes() -> Vec<(String, String)> {
+        let source = include_str!("routes.rs");
+        let mut routes = Vec::new();
+        for (start, _) in source.match_indices(".route(") {
+            let rest = &source[start + ".route(".len()..];
+            let mut depth = 1;
+            let end = rest
+                .char_indices()
+                .find(|(_, c)| {
+                    depth += match c {
+                        '(' => 1,
+                        ')' => -1,
+                        _ => 0,
+                    };
+                    depth == 0
+                })
+                .map(|(end, _)| end)
+                .unwrap();
+            let (path, handlers) = rest[..end].split_once(',').unwrap();
+            let path = path.trim().trim_matches('"');
+            for method in ["get", "post", "put", "delete"] {
+                let called = handlers.match_indices(&format!("{}(", method)).any(|(at, _)| {
+                    !handlers[..at].ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == ':')
+                });
+                if called {
+                    routes.push((method.to_uppercase(), path.to_string()));
+                }
+            }
+        }
+        routes
+    }
+
+    #[test]
+    fn holds_every_editor_change_not_listed() {
+        let routes = routes();
+        assert!(routes.len() > 100, "found only {} routes", routes.len());
+        for (method, path) in &routes {
+            let concrete = match path.as_str() {
+                "quick_add::PATH" => quick_add::PATH.to_string(),
+                path => {
+                    let parts: Vec<&str> =
+                        path.split('/').map(|part| if part.starts_with('{') { "x" } else { part }).collect();
+                    parts.join("/")
+                }
+            };
+            let method: Method = method.parse().unwrap();
+            let exempt = path.starts_with("/me/") || NOT_REVIEWED.contains(&(method.as_str(), path.as_str()));
+            let read = access::is_read(&method, &concrete);
+            assert_eq!(reviewed(&method, &concrete), !read && !exempt, "{} {}", method, path);
+        }
+        for (method, path) in NOT_REVIEWED {
+            let routed = routes.iter().any(|route| (route.0.as_str(), route.1.as_str()) == (method, path));
+            assert!(routed, "{} {} is not in routes.rs", method, path);
+        }
+        for held in ["/services/x/archive", "/services/x/colors", "/categories/1/colors", "/services/x/sla"] {
+            assert!(reviewed(&Method::PUT, held) || reviewed(&Method::POST, held), "{}", held);
+        }
+    }
+}
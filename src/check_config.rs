@@ -0,0 +1,199 @@
+use sqlx::postgres::PgPoolOptions;
+use std::{path::Path, time::Duration};
+
+use crate::{
+    config::{AccessLogSink, Config},
+    actions, crypto, email, hooks, links, notify, plugins, proxy_sync, scheduler::Schedule, secrets, server, storage,
+};
+
+// `indexpage check-config [PATH] [--database]`
+// Loads the configuration the way startup does and reports every problem
+// found, so a bad edit is caught before a restart takes the dashboard down.
+// PATH defaults to INDEXPAGE_CONFIG or the default path; --database also
+// connects with DATABASE_URL.
+const USAGE: &str = "usage: indexpage check-config [PATH] [--database]";
+const CONNECT_TIMEOUT_SECS: u64 = 5;
+
+// Returns the process exit code: 0 when the configuration is valid.
+pub async fn run(args: &[String]) -> i32 {
+    let (mut path, mut database) = (None, false);
+    for arg in args {
+        match arg.as_str() {
+            "--database" => database = true,
+            _ if arg.starts_with('-') || path.is_some() => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+            _ => path = Some(arg.as_str()),
+        }
+    }
+
+    let loaded = match path {
+        Some(path) => Config::from_file(Path::new(path)),
+        None => Config::load(),
+    };
+    let config = match loaded {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 1;
+        }
+    };
+
+    let errors = check(&config, database).await;
+    for error in &errors {
+        eprintln!("error: {}", error);
+    }
+    if !errors.is_empty() {
+        return 1;
+    }
+    println!("configuration OK{}", if database { " (database reachable)" } else { "" });
+    0
+}
+
+async fn check(config: &Config, database: bool) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut report = |result: anyhow::Result<()>| {
+        if let Err(e) = result {
+            errors.push(e.to_string());
+        }
+    };
+
+    let notification_urls =
+        [("notifications.webhooks", &config.notifications.webhooks), ("notifications.slack", &config.notifications.slack)];
+    for (setting, urls) in notification_urls {
+        for (index, url) in urls.iter().enumerate() {
+            report(check_url(&format!("{}[{}]", setting, index), url));
+        }
+    }
+    for (index, hook) in config.hooks.iter().enumerate() {
+        if let Some(url) = &hook.url {
+            report(check_url(&format!("hooks[{}].url", index), url));
+        }
+    }
+    if let Some(url) = &config.email.base_url {
+        report(check_url("email.base_url", url));
+    }
+    if let Some(url) = &config.vault.address {
+        report(check_url("vault.address", url));
+    }
+    if config.icons.enabled {
+        report(check_url("icons.base_url", &config.icons.base_url));
+    }
+    if config.server.admin_listen == Some(config.server.listen) {
+        report(Err(anyhow::anyhow!("server.admin_listen: must differ from server.listen")));
+    }
+    for path in config.server.admin_paths.iter().filter(|path| !path.starts_with('/')) {
+        report(Err(anyhow::anyhow!("server.admin_paths: '{}' must start with /", path)));
+    }
+    if let Some(tls) = &config.server.tls {
+        let files = [
+            ("cert_file", Some(&tls.cert_file)),
+            ("key_file", Some(&tls.key_file)),
+            ("client_ca_file", tls.client_ca_file.as_ref()),
+        ];
+        let mut missing = false;
+        for (setting, file) in files {
+            if let Some(file) = file.filter(|file| !file.is_file()) {
+                report(Err(anyhow::anyhow!("server.tls.{}: {} does not exist", setting, file.display())));
+                missing = true;
+            }
+        }
+        // Also catches unreadable files and a key that doesn't match.
+        if !missing {
+            report(server::acceptor(tls).map(drop).map_err(|e| anyhow::anyhow!("server.tls: {}", e)));
+        }
+    }
+    if config.access_log.sink == Some(AccessLogSink::File) {
+        let path = &config.access_log.path;
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        if !dir.is_dir() {
+            report(Err(anyhow::anyhow!("access_log.path: directory {} does not exist", dir.display())));
+        }
+    }
+    let mut group_roles: Vec<_> = config.auth.group_roles.keys().collect();
+    group_roles.sort();
+    for group in group_roles.into_iter().filter(|group| !config.auth.groups.contains_key(*group)) {
+        report(Err(anyhow::anyhow!("auth.group_roles.{}: no such group in auth.groups", group)));
+    }
+    report(hooks::Hooks::new(&config.hooks).map(drop));
+    report(plugins::Plugins::load(&config.plugins).map(drop));
+    let mut jobs: Vec<_> = config.jobs.iter().collect();
+    jobs.sort();
+    for (name, spec) in jobs {
+        report(check_job(config, name, spec));
+    }
+
+    // The rest needs secrets, which may come from Vault.
+    let secrets = match secrets::Secrets::load(&config.vault).await {
+        Ok(secrets) => secrets,
+        Err(e) => {
+            report(Err(anyhow::anyhow!("vault: {}", e)));
+            return errors;
+        }
+    };
+    report(secrets.get("INDEXPAGE_SECRET_KEY").and_then(|key| crypto::Cipher::load(key, &config.secrets)).map(drop));
+    let mailer = secrets
+        .get("INDEXPAGE_SMTP_PASSWORD")
+        .and_then(|password| email::Mailer::load(&config.email, password));
+    match mailer {
+        Ok(mailer) => {
+            let notifier = notify::Notifier::new(&config.notifications, mailer);
+            match (notifier, &config.digest.recipients) {
+                (Ok(notifier), Some(recipients)) if config.digest.enabled => {
+                    report(notifier.check_recipients(recipients, "digest.recipients"))
+                }
+                (result, _) => report(result.map(drop)),
+            }
+        }
+        Err(e) => report(Err(e)),
+    }
+    let links = links::Links::new(&config.links, config.server.tls.is_some());
+    report(actions::Actions::new(&config.actions, &secrets, &links).map(drop));
+    report(proxy_sync::ProxySync::new(&config.proxy_sync, &secrets).map(drop));
+    report(storage::Storage::new(&config.storage, &secrets).map(drop));
+    if database {
+        report(check_database(&secrets).await);
+    }
+    errors
+}
+
+fn check_url(setting: &str, value: &str) -> anyhow::Result<()> {
+    let url = url::Url::parse(value).map_err(|e| anyhow::anyhow!("{}: invalid URL '{}': {}", setting, value, e))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+        anyhow::bail!("{}: '{}' is not an http(s) URL", setting, value);
+    }
+    Ok(())
+}
+
+// Mirrors the jobs registered at startup (see the `register` functions)
+// without starting them.
+fn check_job(config: &Config, name: &str, spec: &str) -> anyhow::Result<()> {
+    let enabled = match name {
+        "checks" => cfg!(feature = "checks") && config.checks.enabled,
+        "retention" => config.retention.enabled,
+        "digest" => config.digest.enabled,
+        "trash_purge" => true,
+        "access_log" => config.access_log.sink == Some(AccessLogSink::Database) && config.access_log.retention_days > 0,
+        "idempotency" => true,
+        "discovery" => config.discovery.enabled,
+        _ => false,
+    };
+    if !enabled {
+        anyhow::bail!("jobs.{}: no such job (or it is disabled)", name);
+    }
+    Schedule::parse(spec).map(drop).map_err(|e| anyhow::anyhow!("jobs.{}: {}", name, e))
+}
+
+async fn check_database(secrets: &secrets::Secrets) -> anyhow::Result<()> {
+    let url = secrets.get("DATABASE_URL")?.ok_or_else(|| anyhow::anyhow!("DATABASE_URL is not set"))?;
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+        .connect(&url)
+        .await
+        .map_err(|e| anyhow::anyhow!("DATABASE_URL: {}", e))?;
+    sqlx::query!("SELECT 1 AS one").fetch_one(&pool).await.map_err(|e| anyhow::anyhow!("DATABASE_URL: {}", e))?;
+    pool.close().await;
+    Ok(())
+}
@@ -0,0 +1,461 @@
+use chrono::{DateTime, Utc};
+use hickory_resolver::{
+    config::{NameServerConfig, ResolverConfig},
+    net::runtime::TokioRuntimeProvider,
+    TokioResolver,
+};
+use sqlx::PgPool;
+use std::{collections::{HashMap, VecDeque}, net::IpAddr, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use tokio::{
+    sync::{mpsc, Semaphore},
+    task::JoinSet,
+};
+
+use crate::{
+    acks,
+    checks::{cert_expiry, CheckType},
+    config::ChecksConfig,
+    crypto::Cipher,
+    health::{self, Health, Rules, Transition},
+    incidents,
+    links::LinkContext,
+    notify::{Notification, Notifier},
+    plugins::Plugins,
+    result_webhooks::{self, CheckResult},
+    scheduler::{Schedule, Scheduler},
+};
+
+// The scheduled availability checks behind the `checks` feature: each round
+// checks every service that has a check type and records the result.
+
+// Notifications waiting to go out. They are delivered in order by a task of
+// their own, so slow receivers never hold up a round; should it fall this
+// far behind, further notifications are logged and dropped.
+const OUTBOX: usize = 1024;
+
+#[derive(Debug)]
+struct Target {
+    id: i32,
+    name: String,
+    link: String,
+    check_type: String,
+    expected_ip: Option<String>,
+    latency_threshold_ms: Option<i32>,
+    check_token_encrypted: Option<String>,
+    in_maintenance: bool,
+}
+
+struct Outcome {
+    ok: bool,
+    latency_ms: i32,
+    error: Option<String>,
+    cert_expires_at: Option<DateTime<Utc>>,
+}
+
+// Per-service memory carried between rounds.
+#[derive(Default)]
+struct Tracker {
+    // Expiry date we last warned about, so each certificate is reported once
+    // rather than on every round.
+    expiry_warned: Option<DateTime<Utc>>,
+    latencies: VecDeque<i32>,
+    breached_windows: u32,
+    degraded: bool,
+}
+
+pub struct Checker {
+    http: reqwest::Client,
+    resolver: TokioResolver,
+    outbox: mpsc::Sender<Notification>,
+    cipher: Option<Cipher>,
+    plugins: Plugins,
+    cert_expiry_warn_days: i64,
+    latency_window: usize,
+    latency_breach_windows: u32,
+    rules: Rules,
+    trackers: Mutex<HashMap<i32, Tracker>>,
+    permits: Semaphore,
+    spread: Duration,
+}
+
+impl Checker {
+    pub fn new(
+        config: &ChecksConfig,
+        notifier: Notifier,
+        cipher: Option<Cipher>,
+        plugins: Plugins,
+    ) -> anyhow::Result<Self> {
+        // Idle connections are kept across rounds so each check reuses its
+        // host's connection instead of handshaking again.
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .user_agent(concat!("indexpage/", env!("CARGO_PKG_VERSION")))
+            .tls_info(true)
+            .pool_idle_timeout(Duration::from_secs(config.interval_secs.max(1) + config.timeout_secs + 30))
+            .pool_max_idle_per_host(2)
+            .tcp_keepalive(Duration::from_secs(60))
+            .build()?;
+
+        let mut resolver = match config.dns_resolver {
+            Some(addr) => {
+                let mut server = NameServerConfig::udp_and_tcp(addr.ip());
+                for connection in &mut server.connections {
+                    connection.port = addr.port();
+                }
+                TokioResolver::builder_with_config(
+                    ResolverConfig::from_name_servers(vec![server]),
+                    TokioRuntimeProvider::default(),
+                )
+            }
+            None => TokioResolver::builder_tokio()?,
+        };
+        resolver.options_mut().timeout = Duration::from_secs(config.timeout_secs);
+
+        let (outbox, mut queued) = mpsc::channel::<Notification>(OUTBOX);
+        tokio::spawn(async move {
+            while let Some(notification) = queued.recv().await {
+                notifier.send(notification).await;
+            }
+        });
+
+        Ok(Self {
+            http,
+            resolver: resolver.build()?,
+            outbox,
+            cipher,
+            plugins,
+            cert_expiry_warn_days: config.cert_expiry_warn_days,
+            latency_window: config.latency_window.max(1),
+            latency_breach_windows: config.latency_breach_windows.max(1),
+            rules: Rules::new(config),
+            trackers: Mutex::new(HashMap::new()),
+            permits: Semaphore::new(config.concurrency.max(1)),
+            spread: Duration::from_secs(config.spread_secs.min(config.interval_secs.max(1))),
+        })
+    }
+
+    // Where in the round the service's check starts. Derived from the id so
+    // every service keeps a steady cadence from one round to the next.
+    fn offset(&self, id: i32) -> Duration {
+        let spread = self.spread.as_millis() as u64;
+        if spread == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_millis((id as u32 as u64).wrapping_mul(2_654_435_761) % spread)
+    }
+
+    async fn run(&self, target: &Target) -> Outcome {
+        let started = Instant::now();
+        let result = match CheckType::parse(&target.check_type) {
+            Some(CheckType::Http) => match self.check_token(target) {
+                Ok(token) => self.check_http(&target.link, token.as_deref()).await,
+                Err(e) => Err(e),
+            },
+            Some(CheckType::Dns) => self
+                .check_dns(&target.link, target.expected_ip.as_deref())
+                .await
+                .map(|_| None),
+            Some(CheckType::None) => Ok(None),
+            None if self.plugins.has_check(&target.check_type) => self
+                .plugins
+                .check(&target.check_type, &target.name, &target.link)
+                .await
+                .map(|_| None),
+            None => Err(format!("unknown check type '{}'", target.check_type)),
+        };
+        let latency_ms = started.elapsed().as_millis().min(i32::MAX as u128) as i32;
+        match result {
+            Ok(cert_expires_at) => Outcome { ok: true, latency_ms, error: None, cert_expires_at },
+            Err(error) => Outcome { ok: false, latency_ms, error: Some(error), cert_expires_at: None },
+        }
+    }
+
+    // Anything below 500 counts as up: a 401/403 still means the service answered.
+    // Returns the peer certificate's expiry for HTTPS links.
+    async fn check_http(&self, link: &str, token: Option<&str>) -> Result<Option<DateTime<Utc>>, String> {
+        let mut request = self.http.get(link);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if response.status().is_server_error() {
+            return Err(format!("HTTP {}", response.status()));
+        }
+        Ok(response
+            .extensions()
+            .get::<reqwest::tls::TlsInfo>()
+            .and_then(|info| info.peer_certificate())
+            .and_then(cert_expiry))
+    }
+
+    fn check_token(&self, target: &Target) -> Result<Option<String>, String> {
+        let Some(stored) = &target.check_token_encrypted else { return Ok(None) };
+        let cipher = self
+            .cipher
+            .as_ref()
+            .ok_or("check token is set but no encryption key is configured")?;
+        cipher.decrypt(stored).map(Some).map_err(|e| e.to_string())
+    }
+
+    async fn check_dns(&self, link: &str, expected_ip: Option<&str>) -> Result<(), String> {
+        let host = host_of(link).ok_or_else(|| format!("no hostname in '{}'", link))?;
+        let lookup = self
+            .resolver
+            .lookup_ip(host.as_str())
+            .await
+            .map_err(|e| format!("resolving {}: {}", host, e))?;
+        let addresses: Vec<IpAddr> = lookup.iter().collect();
+        if addresses.is_empty() {
+            return Err(format!("{} has no addresses", host));
+        }
+
+        if let Some(expected) = expected_ip {
+            let expected: IpAddr = expected
+                .parse()
+                .map_err(|_| format!("invalid expected_ip '{}'", expected))?;
+            if !addresses.contains(&expected) {
+                return Err(format!("{} resolved to {:?}, expected {}", host, addresses, expected));
+            }
+        }
+        Ok(())
+    }
+
+    // Updates the service's tracker with this round's outcome, sends any
+    // notifications it triggers (only recoveries while `silenced`, see
+    // acks.rs) and returns whether the service is degraded.
+    fn observe(&self, target: &Target, outcome: &Outcome, silenced: bool) -> bool {
+        let mut notifications = Vec::new();
+        let degraded = {
+            let mut trackers = self.trackers.lock().unwrap();
+            let tracker = trackers.entry(target.id).or_default();
+
+            if let Some(expires_at) = outcome.cert_expires_at {
+                let days_left = (expires_at - Utc::now()).num_days();
+                if days_left < self.cert_expiry_warn_days
+                    && tracker.expiry_warned.replace(expires_at) != Some(expires_at)
+                {
+                    notifications.push(Notification {
+                        event: "cert_expiring",
+                        service: target.name.clone(),
+                        message: format!(
+                            "TLS certificate for {} expires in {} days ({})",
+                            target.name, days_left, expires_at
+                        ),
+                    });
+                }
+            }
+
+            match target.latency_threshold_ms {
+                Some(threshold) if outcome.ok => {
+                    if tracker.latencies.len() == self.latency_window {
+                        tracker.latencies.pop_front();
+                    }
+                    tracker.latencies.push_back(outcome.latency_ms);
+                    if tracker.latencies.len() == self.latency_window {
+                        let p95 = p95(&tracker.latencies);
+                        if p95 > threshold {
+                            tracker.breached_windows += 1;
+                        } else {
+                            tracker.breached_windows = 0;
+                        }
+                        let degraded = tracker.breached_windows >= self.latency_breach_windows;
+                        if degraded != tracker.degraded {
+                            tracker.degraded = degraded;
+                            notifications.push(Notification {
+                                event: if degraded { "degraded" } else { "latency_recovered" },
+                                service: target.name.clone(),
+                                message: format!(
+                                    "{} p95 latency is {} ms (threshold {} ms)",
+                                    target.name, p95, threshold
+                                ),
+                            });
+                        }
+                    }
+                    tracker.degraded
+                }
+                // Hard downtime is reported separately; keep the latency
+                // history so a single failed check doesn't reset the SLO.
+                Some(_) => false,
+                None => {
+                    tracker.latencies.clear();
+                    tracker.breached_windows = 0;
+                    tracker.degraded = false;
+                    false
+                }
+            }
+        };
+
+        for notification in notifications {
+            if silenced && notification.event != "latency_recovered" {
+                tracing::info!("{} (silenced): {}", notification.event, notification.message);
+                continue;
+            }
+            self.notify(notification);
+        }
+        degraded
+    }
+
+    // Notifies about a service going down, flapping or recovering; while
+    // `silenced` only recoveries go out.
+    fn announce(&self, target: &Target, transition: &Transition, error: Option<&str>, silenced: bool) {
+        let (event, message) = match (transition.from, transition.to) {
+            (_, Health::Down) => (
+                "down",
+                format!("{} is down: {}", target.name, error.unwrap_or("check failed")),
+            ),
+            (_, Health::Flapping) => ("flapping", format!("{} keeps switching between up and down", target.name)),
+            (Health::Down | Health::Flapping, Health::Up | Health::Degraded) => {
+                ("recovered", format!("{} is up again", target.name))
+            }
+            _ => return,
+        };
+        let notification = Notification { event, service: target.name.clone(), message };
+        if silenced && event != "recovered" {
+            tracing::info!("{} (silenced): {}", notification.event, notification.message);
+            return;
+        }
+        self.notify(notification);
+    }
+
+    // Queues the notification for delivery; see OUTBOX.
+    fn notify(&self, notification: Notification) {
+        if let Err(e) = self.outbox.try_send(notification) {
+            let notification = e.into_inner();
+            tracing::warn!("notification queue is full, dropping {}: {}", notification.event, notification.message);
+        }
+    }
+}
+
+fn p95(latencies: &VecDeque<i32>) -> i32 {
+    let mut sorted: Vec<i32> = latencies.iter().copied().collect();
+    sorted.sort_unstable();
+    let rank = (sorted.len() * 95).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+// Accepts both full URLs and bare "host[:port][/path]" links.
+fn host_of(link: &str) -> Option<String> {
+    if let Ok(url) = url::Url::parse(link)
+        && let Some(host) = url.host_str()
+    {
+        return Some(host.trim_matches(|c| c == '[' || c == ']').to_string());
+    }
+    let host = link.split('/').next()?.rsplit_once(':').map_or(link, |(host, _)| host);
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+pub fn register(
+    scheduler: &Scheduler,
+    pool: PgPool,
+    config: ChecksConfig,
+    notifier: Notifier,
+    cipher: Option<Cipher>,
+    plugins: Plugins,
+    links: LinkContext,
+) -> anyhow::Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let checker = Arc::new(Checker::new(&config, notifier, cipher, plugins)?);
+    let interval = Schedule::Every(Duration::from_secs(config.interval_secs.max(1)));
+
+    scheduler.register("checks", interval, move || {
+        let (pool, checker, links) = (pool.clone(), checker.clone(), links.clone());
+        async move { Ok(run_round(&pool, &checker, &links).await?) }
+    })
+}
+
+async fn targets(pool: &PgPool, links: &LinkContext) -> sqlx::Result<Vec<Target>> {
+    let mut targets = sqlx::query_as!(
+        Target,
+        r#"SELECT id, name, link, check_type, expected_ip, latency_threshold_ms, check_token_encrypted,
+                  coalesce(maintenance_until > now(), false) AS "in_maintenance!"
+           FROM services WHERE check_type <> 'none' AND deleted_at IS NULL AND archived_at IS NULL"#,
+    )
+    .fetch_all(pool)
+    .await?;
+    for target in &mut targets {
+        target.link = links.expand(&target.link);
+    }
+    Ok(targets)
+}
+
+async fn run_round(pool: &PgPool, checker: &Arc<Checker>, links: &LinkContext) -> sqlx::Result<()> {
+    let silenced = acks::silenced(pool).await?;
+    let mut webhooks = result_webhooks::Round::start(pool).await?;
+    let mut running = JoinSet::new();
+    for target in targets(pool, links).await? {
+        let checker = Arc::clone(checker);
+        running.spawn(async move {
+            tokio::time::sleep(checker.offset(target.id)).await;
+            let outcome = {
+                let _permit = checker.permits.acquire().await;
+                checker.run(&target).await
+            };
+            (target, outcome)
+        });
+    }
+
+    while let Some(joined) = running.join_next().await {
+        let Ok((target, outcome)) = joined else { continue };
+        if let Some(error) = &outcome.error {
+            tracing::debug!("{} is down: {}", target.name, error);
+        }
+        let silenced = silenced.contains(&target.id) || target.in_maintenance;
+        let degraded = checker.observe(&target, &outcome, silenced);
+        sqlx::query!(
+            "INSERT INTO check_results (service_id, ok, latency_ms, error, cert_expires_at, degraded) VALUES ($1, $2, $3, $4, $5, $6)",
+            target.id,
+            outcome.ok,
+            outcome.latency_ms,
+            outcome.error,
+            outcome.cert_expires_at,
+            degraded,
+        )
+        .execute(pool)
+        .await?;
+        let (state, transition) =
+            health::observe(pool, target.id, outcome.ok, degraded, target.in_maintenance, &checker.rules).await?;
+        incidents::track(pool, target.id, state, outcome.ok, outcome.error.as_deref()).await?;
+        if let Some(transition) = transition {
+            checker.announce(&target, &transition, outcome.error.as_deref(), silenced);
+        }
+        if state == Health::Up && silenced {
+            acks::recovered(pool, target.id).await?;
+        }
+        webhooks.deliver(&CheckResult {
+            service: target.name,
+            service_id: target.id,
+            ok: outcome.ok,
+            latency_ms: outcome.latency_ms,
+            error: outcome.error,
+            degraded,
+            cert_expires_at: outcome.cert_expires_at,
+            checked_at: Utc::now(),
+        });
+    }
+    webhooks.finish();
+    Ok(())
+}
+
+// One round that is neither recorded nor alerted on, for `indexpage doctor`:
+// each checked service's name, latency and, when down, the error.
+pub async fn dry_run(
+    pool: &PgPool,
+    checker: Checker,
+    links: &LinkContext,
+) -> sqlx::Result<Vec<(String, i32, Option<String>)>> {
+    let checker = Arc::new(checker);
+    let mut running = JoinSet::new();
+    for target in targets(pool, links).await? {
+        let checker = Arc::clone(&checker);
+        running.spawn(async move {
+            let _permit = checker.permits.acquire().await;
+            let outcome = checker.run(&target).await;
+            (target.name, outcome.latency_ms, outcome.error)
+        });
+    }
+    let mut results: Vec<_> = running.join_all().await;
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(results)
+}
@@ -0,0 +1,236 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use http::{header, HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{
+    categories::Visibility,
+    health::{Health, HEALTH_COLUMN},
+    streaming::{self, Writer},
+};
+
+#[cfg(feature = "checks")]
+pub use crate::checker::register;
+
+// When a DER certificate (a TLS peer's, from reqwest's TlsInfo) expires.
+pub fn cert_expiry(der: &[u8]) -> Option<DateTime<Utc>> {
+    let cert = openssl::x509::X509::from_der(der).ok()?;
+    let epoch = openssl::asn1::Asn1Time::from_unix(0).ok()?;
+    let since_epoch = epoch.diff(cert.not_after()).ok()?;
+    DateTime::from_timestamp(since_epoch.days as i64 * 86_400 + since_epoch.secs as i64, 0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckType {
+    Http,
+    Dns,
+    None,
+}
+
+impl CheckType {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "http" => Some(Self::Http),
+            "dns" => Some(Self::Dns),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+}
+
+// Without the `checks` feature services are never checked, so their state
+// comes from Alertmanager alone.
+#[cfg(not(feature = "checks"))]
+pub fn register(
+    _scheduler: &crate::scheduler::Scheduler,
+    _pool: PgPool,
+    config: crate::config::ChecksConfig,
+    _notifier: crate::notify::Notifier,
+    _cipher: Option<crate::crypto::Cipher>,
+    _plugins: crate::plugins::Plugins,
+    _links: crate::links::LinkContext,
+) -> anyhow::Result<()> {
+    if config.enabled {
+        tracing::warn!("[checks] is enabled but this build lacks the `checks` feature; services are not checked");
+    }
+    Ok(())
+}
+
+// Selects whether a service has active Alertmanager alerts: NULL without
+// any, true when one of them marks it down; used inside service queries.
+pub const ALERT_DOWN_COLUMN: &str =
+    "(SELECT bool_or(down) FROM external_alerts WHERE service_id = s.id) AS alert_down";
+
+// Collapses a service's health (see health.rs), or its latest check result
+// before the checker has settled one, and any active alerts (see
+// alertmanager.rs) into the state shown by the API.
+pub fn state_of(
+    health: Option<&str>,
+    ok: Option<bool>,
+    degraded: Option<bool>,
+    alert_down: Option<bool>,
+) -> &'static str {
+    let checked = health.and_then(Health::parse).unwrap_or(match ok {
+        Some(true) if degraded == Some(true) => Health::Degraded,
+        Some(true) => Health::Up,
+        Some(false) => Health::Down,
+        None => Health::Unknown,
+    });
+    match alert_down {
+        _ if checked == Health::Maintenance => checked.as_str(),
+        Some(true) => "down",
+        Some(false) if checked != Health::Down => "degraded",
+        _ => checked.as_str(),
+    }
+}
+
+// What `state_of` gives without alerts, in SQL; for filtering service queries
+// that join the latest check result as `latest` and service_health as `health`.
+pub const STATE_SQL: &str = "coalesce(CASE WHEN services.maintenance_until > now() THEN 'maintenance' END, \
+     health.state, CASE WHEN latest.ok IS NULL THEN 'unknown' WHEN NOT latest.ok THEN 'down' \
+     WHEN latest.degraded THEN 'degraded' ELSE 'up' END)";
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ServiceStatus {
+    name: String,
+    check_type: String,
+    #[sqlx(skip)]
+    pub state: &'static str,
+    // Since when the service has been in its health state.
+    state_since: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    health: Option<String>,
+    #[serde(skip)]
+    ok: Option<bool>,
+    #[serde(skip)]
+    degraded: Option<bool>,
+    #[serde(skip)]
+    alert_down: Option<bool>,
+    // Summaries of active Alertmanager alerts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alerts: Option<Vec<String>>,
+    checked_at: Option<DateTime<Utc>>,
+    pub latency_ms: Option<i32>,
+    error: Option<String>,
+    cert_expires_at: Option<DateTime<Utc>>,
+    #[sqlx(skip)]
+    cert_days_remaining: Option<i64>,
+}
+
+// The latest check result and alerts of a service, whoever may see it.
+pub async fn fetch_status(pool: &PgPool, name: &str) -> Result<Option<ServiceStatus>, (StatusCode, String)> {
+    let status = sqlx::query_as::<_, ServiceStatus>(&format!(
+        r#"
+        SELECT s.name, s.check_type, r.ok, r.degraded, r.checked_at, r.latency_ms, r.error, r.cert_expires_at,
+               {}, {},
+               (SELECT since FROM service_health WHERE service_id = s.id) AS state_since,
+               (SELECT array_agg(coalesce(summary, alertname, fingerprint) ORDER BY starts_at)
+                FROM external_alerts WHERE service_id = s.id) AS alerts
+        FROM services s
+        LEFT JOIN LATERAL (
+            SELECT ok, degraded, checked_at, latency_ms, error, cert_expires_at FROM check_results
+            WHERE service_id = s.id ORDER BY checked_at DESC LIMIT 1
+        ) r ON true
+        WHERE s.id = {}
+        "#,
+        ALERT_DOWN_COLUMN,
+        HEALTH_COLUMN,
+        crate::services::SERVICE_ID_BY_NAME
+    ))
+    .bind(name)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(status.map(|mut status| {
+        status.state = state_of(status.health.as_deref(), status.ok, status.degraded, status.alert_down);
+        status.cert_days_remaining = status
+            .cert_expires_at
+            .map(|expires_at| (expires_at - Utc::now()).num_days());
+        status
+    }))
+}
+
+// GET /services/:name/status
+pub async fn service_status(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    Path(name): Path<String>,
+) -> Result<Json<ServiceStatus>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    match fetch_status(&pool, &name).await? {
+        Some(status) => Ok(Json(status)),
+        None => Err((StatusCode::NOT_FOUND, "Service not found".into())),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryEntry {
+    checked_at: DateTime<Utc>,
+    ok: bool,
+    degraded: bool,
+    latency_ms: Option<i32>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    format: Option<String>,
+}
+
+async fn write_history(
+    pool: PgPool,
+    id: i32,
+    query: HistoryQuery,
+    ndjson: bool,
+    mut out: Writer,
+) -> anyhow::Result<()> {
+    let rows = sqlx::query_as!(
+        HistoryEntry,
+        "SELECT checked_at, ok, degraded, latency_ms, error FROM check_results WHERE service_id = $1 \
+         AND checked_at >= coalesce($2::timestamptz, '-infinity') AND checked_at < coalesce($3::timestamptz, 'infinity') \
+         ORDER BY checked_at",
+        id,
+        query.since,
+        query.until,
+    )
+    .fetch(&pool);
+    if ndjson {
+        out.json_lines(rows).await?;
+    } else {
+        out.json_array(rows).await?;
+    }
+    out.finish().await
+}
+
+// GET /services/:name/history
+// Raw check results, oldest first, optionally within ?since=&until= (RFC
+// 3339). Only results not yet folded into rollups by retention are kept.
+// Streamed, as a busy service accumulates a lot of them; NDJSON as for
+// /admin/export.
+pub async fn service_history(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    Path(name): Path<String>,
+    Query(query): Query<HistoryQuery>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let id = sqlx::query_scalar::<_, i32>(&format!("SELECT id FROM services WHERE id = {}", crate::services::SERVICE_ID_BY_NAME))
+        .bind(&name)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Service not found".into()))?;
+    let ndjson = streaming::wants_ndjson(&headers, query.format.as_deref());
+    let content_type = if ndjson { streaming::NDJSON } else { "application/json" };
+    let body = streaming::body("history", move |out| write_history(pool, id, query, ndjson, out));
+    Ok(([(header::CONTENT_TYPE, content_type)], body).into_response())
+}
@@ -0,0 +1,132 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, QueryBuilder};
+
+use crate::{
+    audit::{Actor, Audit},
+    categories::Visibility,
+    repository::ServiceRepository,
+};
+
+// Optional tile colors for services and categories, so environments can be
+// told apart at a glance (prod red, lab blue): `color` for the tile and
+// `accent` for its details. Both are hex values, stored as #rrggbb; a
+// service without its own takes its category's on the status page.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Colors {
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub accent: Option<String>,
+}
+
+// "#rgb" or "#rrggbb", the # optional, as lowercase "#rrggbb".
+fn parse_hex(value: &str) -> Option<String> {
+    let hex = value.trim().trim_start_matches('#');
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hex.len() {
+        3 => Some(hex.chars().flat_map(|c| [c, c]).collect()),
+        6 => Some(hex.to_string()),
+        _ => None,
+    }
+    .map(|hex| format!("#{}", hex.to_lowercase()))
+}
+
+// Normalizes `value`, treating blank as unset.
+pub fn validate(field: &str, value: Option<&str>) -> Result<Option<String>, (StatusCode, String)> {
+    match value.map(str::trim).filter(|value| !value.is_empty()) {
+        Some(value) => parse_hex(value)
+            .map(Some)
+            .ok_or((StatusCode::BAD_REQUEST, format!("{} '{}' is not a hex color such as #c62828", field, value))),
+        None => Ok(None),
+    }
+}
+
+impl Colors {
+    pub fn validated(&self) -> Result<Self, (StatusCode, String)> {
+        Ok(Self {
+            color: validate("color", self.color.as_deref())?,
+            accent: validate("accent", self.accent.as_deref())?,
+        })
+    }
+
+    fn describe(&self) -> String {
+        format!("color={}, accent={}", self.color.as_deref().unwrap_or("-"), self.accent.as_deref().unwrap_or("-"))
+    }
+}
+
+// PUT /services/:name/colors
+// Body: {"color": "#c62828", "accent": "#ffcdd2"}; omitted or null clears.
+pub async fn set_service_colors<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    State(services): State<R>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(name): Path<String>,
+    Json(payload): Json<Colors>,
+) -> Result<Json<Colors>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let colors = payload.validated()?;
+    let service =
+        services.set_colors(&name, &colors).await?.ok_or((StatusCode::NOT_FOUND, "Service not found".into()))?;
+    audit.record(&actor, "service.colors", Some(&service), Some(colors.describe())).await;
+    Ok(Json(colors))
+}
+
+// PUT /categories/:id/colors
+// Same body as for services.
+pub async fn set_category_colors(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    visibility: Visibility,
+    Path(id): Path<i32>,
+    Json(payload): Json<Colors>,
+) -> Result<Json<Colors>, (StatusCode, String)> {
+    let colors = payload.validated()?;
+    let mut sql = QueryBuilder::new("UPDATE categories SET color = ");
+    sql.push_bind(&colors.color).push(", accent = ").push_bind(&colors.accent);
+    sql.push(" WHERE categories.id = ").push_bind(id);
+    visibility.restrict(&mut sql);
+    sql.push(" RETURNING name");
+    let category = sql
+        .build_query_scalar::<String>()
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Category not found".into()))?;
+    audit.record(&actor, "category.colors", None, Some(format!("{}: {}", category, colors.describe()))).await;
+    Ok(Json(colors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_short_and_long_hex() {
+        assert_eq!(parse_hex("#ABC").as_deref(), Some("#aabbcc"));
+        assert_eq!(parse_hex(" c62828 ").as_deref(), Some("#c62828"));
+        for bad in ["#12345", "#ggg", "", "#c628289"] {
+            assert_eq!(parse_hex(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn validates_blank_as_unset() {
+        assert_eq!(validate("color", Some("  ")).unwrap(), None);
+        assert_eq!(validate("color", None).unwrap(), None);
+        let (status, message) = validate("accent", Some("red")).unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(message, "accent 'red' is not a hex color such as #c62828");
+        let colors = Colors { color: Some("#FFF".into()), accent: None }.validated().unwrap();
+        assert_eq!((colors.color.as_deref(), colors.accent), (Some("#ffffff"), None));
+    }
+}
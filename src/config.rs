@@ -0,0 +1,818 @@
+use serde::Deserialize;
+use ipnet::IpNet;
+use std::{collections::HashMap, env, fs, net::SocketAddr, path::{Path, PathBuf}};
+
+use crate::{auth::Role, hooks::HookEvent};
+
+const DEFAULT_CONFIG_PATH: &str = "indexpage.toml";
+
+// Settings read from the optional TOML file (path in INDEXPAGE_CONFIG,
+// default ./indexpage.toml). Every field has a default so an empty or
+// missing file keeps the previous behaviour.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+    pub access: AccessConfig,
+    pub checks: ChecksConfig,
+    pub notifications: NotificationsConfig,
+    pub email: EmailConfig,
+    pub digest: DigestConfig,
+    pub retention: RetentionConfig,
+    pub trash: TrashConfig,
+    pub secrets: SecretsConfig,
+    pub vault: VaultConfig,
+    // IANA name ("Europe/Berlin") for cron schedules and the times shown on
+    // the status page, in digests and exports; UTC when unset (see tz.rs).
+    pub timezone: chrono_tz::Tz,
+    // Schedule overrides by job name, e.g. { retention = "0 3 * * *",
+    // checks = "@every 30s" }. Unlisted jobs keep their *_interval_secs.
+    pub jobs: HashMap<String, String>,
+    pub plugins: Vec<PluginConfig>,
+    pub hooks: Vec<HookConfig>,
+    pub static_links: Vec<StaticLinkConfig>,
+    pub actions: Vec<ActionConfig>,
+    pub links: LinksConfig,
+    pub alertmanager: AlertmanagerConfig,
+    pub access_log: AccessLogConfig,
+    pub icons: IconsConfig,
+    pub page_snapshots: PageSnapshotsConfig,
+    pub proposals: ProposalsConfig,
+    pub wake_on_lan: WakeOnLanConfig,
+    pub discovery: DiscoveryConfig,
+    pub proxy_sync: ProxySyncConfig,
+    pub storage: StorageConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen: SocketAddr,
+    // Serve HTTPS instead of plain HTTP when set.
+    pub tls: Option<TlsConfig>,
+    // Reuse connections for further requests. Idle HTTP/1 connections are
+    // closed after `keep_alive_timeout_secs`; HTTP/2 ones are pinged that
+    // often and closed when a ping goes unanswered as long.
+    pub keep_alive: bool,
+    pub keep_alive_timeout_secs: u64,
+    // Further connections wait to be accepted; unset means no limit.
+    pub max_connections: Option<usize>,
+    // Request line plus headers (at least 8192).
+    pub max_header_bytes: usize,
+    // Request bodies; POST /admin/import allows 64 MiB regardless.
+    pub max_body_bytes: usize,
+    // Offer HTTP/2 (h2c, or over TLS) next to HTTP/1.1.
+    pub http2: bool,
+    // Seconds a handler may take to produce a response before the client
+    // gets a 504; the request's queries are cancelled at the same point.
+    // 0 disables it. Streamed bodies may take longer once started.
+    pub request_timeout_secs: u64,
+    // Overrides by path prefix, longest first, e.g.
+    // { "/admin/import" = 600, "/services/import/csv" = 0 }.
+    pub route_timeouts: HashMap<String, u64>,
+    // A second listener, e.g. "127.0.0.1:3001", that alone serves the
+    // `admin_paths`; `listen` then answers them with 404, so they can be
+    // firewalled apart. Same TLS settings as `listen`.
+    pub admin_listen: Option<SocketAddr>,
+    // Path prefixes kept to `admin_listen`.
+    pub admin_paths: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([0, 0, 0, 0], 3000)),
+            tls: None,
+            keep_alive: true,
+            keep_alive_timeout_secs: 75,
+            max_connections: None,
+            max_header_bytes: 64 * 1024,
+            max_body_bytes: 2 * 1024 * 1024,
+            http2: true,
+            request_timeout_secs: 0,
+            route_timeouts: HashMap::new(),
+            admin_listen: None,
+            admin_paths: ["/admin", "/audit", "/events/audit", "/integrations/prometheus"].map(String::from).to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "tls"), allow(dead_code))]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    // PEM certificate chain and private key.
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+    // PEM bundle of CAs trusted to sign client certificates. Setting it
+    // enables mutual TLS.
+    pub client_ca_file: Option<PathBuf>,
+    // Reject handshakes without a client certificate; when false a missing
+    // certificate is allowed and the request is simply unauthenticated.
+    #[serde(default = "default_true")]
+    pub require_client_cert: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    // Client certificate identity (the subject CN or any DNS/email/URI SAN)
+    // to role, e.g. { "backup-bot" = "editor", "alice@example.com" = "admin" }.
+    pub client_certs: HashMap<String, Role>,
+    // Role for verified certificates not listed above; unset means they get none.
+    pub client_cert_default_role: Option<Role>,
+    // Named groups of principals, e.g. { family = ["alice", "bob"] }, that
+    // restricted categories can be opened to.
+    pub groups: HashMap<String, Vec<String>>,
+    // Roles granted through the groups above, e.g. { homelab-admins =
+    // "admin" }. Members get the highest of their own role and their
+    // groups'. Membership is only what `groups` lists; no identity
+    // provider's groups or claims are read.
+    pub group_roles: HashMap<String, Role>,
+    // Require authentication even without INDEXPAGE_API_KEY or client
+    // certificates, i.e. personal tokens from /me/tokens and password
+    // sign-in only. Create the first token or user with the API key, then
+    // unset it.
+    pub required: bool,
+    // Start-page mode: authentication is on (as with `required`), anonymous
+    // visitors may read the dashboard (services, their status and icons,
+    // categories, tags and the status page) and everything else, reads of
+    // the trash, orgs or incidents included, needs signing in. Restricted
+    // categories stay hidden until then.
+    pub public_dashboard: bool,
+    // Review mode: edits and deletes by editors wait as pending changes
+    // until an admin approves them under /changes (see changes.rs).
+    pub review_changes: bool,
+    // Sessions from POST /login end this long after sign-in, or earlier
+    // when unused for `session_idle_hours`.
+    pub session_ttl_hours: i64,
+    pub session_idle_hours: i64,
+    pub lockout: LockoutConfig,
+    // Shown as the account's issuer in authenticator apps (see totp.rs).
+    pub totp_issuer: String,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            client_certs: HashMap::new(),
+            client_cert_default_role: None,
+            groups: HashMap::new(),
+            group_roles: HashMap::new(),
+            required: false,
+            public_dashboard: false,
+            review_changes: false,
+            session_ttl_hours: 30 * 24,
+            session_idle_hours: 7 * 24,
+            lockout: LockoutConfig::default(),
+            totp_issuer: "indexpage".into(),
+        }
+    }
+}
+
+// Brute-force protection for POST /login and API keys; see lockout.rs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockoutConfig {
+    pub enabled: bool,
+    pub max_attempts: u32,
+    pub base_lockout_secs: u64,
+    pub max_lockout_secs: u64,
+    pub forget_after_secs: u64,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_attempts: 5,
+            base_lockout_secs: 30,
+            max_lockout_secs: 15 * 60,
+            forget_after_secs: 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessConfig {
+    // Peers whose X-Forwarded-For header is believed, e.g. ["127.0.0.1/32"].
+    pub trusted_proxies: Vec<IpNet>,
+    // Safe methods (GET, HEAD, OPTIONS).
+    pub read: IpRules,
+    // Every other method.
+    pub mutations: IpRules,
+    // Anything under /admin, in addition to the two above.
+    pub admin: IpRules,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IpRules {
+    // When non-empty, only these networks are let through.
+    pub allow: Vec<IpNet>,
+    // Checked first; a match is always rejected.
+    pub deny: Vec<IpNet>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChecksConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+    // Nameserver used by `dns` checks, e.g. "192.168.1.1:53". Falls back to
+    // the system resolver (/etc/resolv.conf) when unset.
+    pub dns_resolver: Option<SocketAddr>,
+    // Notify when an HTTPS link's certificate expires within this many days.
+    pub cert_expiry_warn_days: i64,
+    // Latency SLO: p95 over the last `latency_window` successful checks is
+    // compared to the service's latency_threshold_ms after every round, and
+    // `latency_breach_windows` consecutive breaches mark it degraded.
+    pub latency_window: usize,
+    pub latency_breach_windows: u32,
+    // At most this many checks run at once.
+    pub concurrency: usize,
+    // Each service's check starts at a fixed offset within this many seconds
+    // (capped at the interval) of the round, so they don't all fire at once.
+    pub spread_secs: u64,
+    // Hysteresis and flap detection for the health state (see health.rs):
+    // failed or successful checks in a row before a service counts as down
+    // or up again, and how many up/down switches within the last
+    // `flap_window` results make it flapping (0 turns that off).
+    pub failures_before_down: u32,
+    pub successes_before_up: u32,
+    pub flap_window: usize,
+    pub flap_threshold: usize,
+}
+
+impl Default for ChecksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 60,
+            timeout_secs: 10,
+            dns_resolver: None,
+            cert_expiry_warn_days: 14,
+            latency_window: 10,
+            latency_breach_windows: 3,
+            concurrency: 16,
+            spread_secs: 10,
+            failures_before_down: 2,
+            successes_before_up: 2,
+            flap_window: 10,
+            flap_threshold: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationsConfig {
+    // Each URL receives a JSON POST of {event, service, message}.
+    pub webhooks: Vec<String>,
+    // Slack incoming webhook URLs; each receives a formatted message.
+    pub slack: Vec<String>,
+    // Addresses that get each notification by email; needs `[email]`.
+    pub emails: Vec<String>,
+}
+
+// A periodic availability report (see digest.rs), sent through the
+// notification channels above. Runs at 08:00 UTC, daily or on Mondays;
+// `[jobs] digest` moves it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DigestConfig {
+    pub enabled: bool,
+    pub period: DigestPeriod,
+    // Who gets it by email, instead of `notifications.emails`.
+    pub recipients: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestPeriod {
+    Daily,
+    #[default]
+    Weekly,
+}
+
+// Outgoing mail over SMTP (see email.rs), for password resets, org invites
+// and notifications. Disabled without `smtp_host`. The password is the
+// INDEXPAGE_SMTP_PASSWORD secret.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailConfig {
+    pub smtp_host: Option<String>,
+    // Defaults to 465 for `tls`, 587 for `starttls` and 25 for `none`.
+    pub smtp_port: Option<u16>,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub from: String,
+    // Where this instance is reachable, e.g. "https://home.example.com",
+    // for links in emails. Without it emails carry the bare token.
+    pub base_url: Option<String>,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            smtp_host: None,
+            smtp_port: None,
+            security: SmtpSecurity::Starttls,
+            username: None,
+            from: "indexpage <indexpage@localhost>".into(),
+            base_url: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    // Implicit TLS from the start of the connection.
+    Tls,
+    Starttls,
+    // Plain text; only for a relay on localhost or a trusted network.
+    None,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    // Raw check results are kept this long, then folded into hourly rollups.
+    pub raw_days: i32,
+    // Hourly rollups are kept this long, then folded into daily rollups.
+    pub hourly_days: i32,
+    // Daily rollups are deleted after this many days; 0 keeps them forever.
+    pub daily_days: i32,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 3600,
+            raw_days: 7,
+            hourly_days: 90,
+            daily_days: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrashConfig {
+    // Deleted services stay restorable this long before being purged.
+    pub ttl_days: i32,
+    pub purge_interval_secs: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self { ttl_days: 30, purge_interval_secs: 3600 }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecretsConfig {
+    // File holding the base64 encryption key; INDEXPAGE_SECRET_KEY wins if set.
+    pub key_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VaultConfig {
+    // Vault server, e.g. "https://vault:8200"; VAULT_ADDR wins if set.
+    pub address: Option<String>,
+    // Secret to read at startup, e.g. "secret/data/indexpage" for KV v2. Its
+    // keys (DATABASE_URL, INDEXPAGE_SECRET_KEY, ...) fill in for unset env vars.
+    pub path: Option<String>,
+    // Used when neither VAULT_TOKEN nor VAULT_TOKEN_FILE is set.
+    pub token_file: Option<PathBuf>,
+    // Periodically renew the token so it outlives its initial TTL.
+    pub renew: bool,
+}
+
+impl Default for VaultConfig {
+    fn default() -> Self {
+        Self { address: None, path: None, token_file: None, renew: true }
+    }
+}
+
+// One `[[plugins]]` entry; see plugins.rs for the module interface.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    // Also the check_type name when the module implements a check.
+    pub name: String,
+    pub path: PathBuf,
+    // Passed to every call as `settings`.
+    #[serde(default)]
+    pub settings: toml::Table,
+    // Instruction budget per call.
+    #[serde(default = "default_plugin_fuel")]
+    pub fuel: u64,
+    #[serde(default = "default_plugin_memory_mb")]
+    pub max_memory_mb: usize,
+}
+
+fn default_plugin_fuel() -> u64 {
+    1_000_000_000
+}
+
+fn default_plugin_memory_mb() -> usize {
+    64
+}
+
+// Placeholders expanded in links when they are returned, so one stored link
+// like "{scheme}://{host}:8096" works from the LAN and through a proxy.
+// {host} and {scheme} come from the request; `variables` adds fixed ones,
+// e.g. { domain = "home.example.com" } for "https://jellyfin.{domain}".
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinksConfig {
+    pub variables: HashMap<String, String>,
+    // Stand-ins for {host} and {scheme} in health checks, which have no request.
+    pub check_host: String,
+    pub check_scheme: String,
+    // Clients in these networks get a service's `internal_link` (when it has
+    // one) as its link; `?view=internal|external` overrides the choice.
+    pub internal_networks: Vec<IpNet>,
+}
+
+impl Default for LinksConfig {
+    fn default() -> Self {
+        Self {
+            variables: HashMap::new(),
+            check_host: "localhost".into(),
+            check_scheme: "http".into(),
+            internal_networks: Vec::new(),
+        }
+    }
+}
+
+// Maps alerts POSTed to /integrations/alertmanager onto services (see
+// alertmanager.rs).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertmanagerConfig {
+    // Alert labels holding the service name, tried in order.
+    pub service_labels: Vec<String>,
+    // `severity` label values that mark the service down; any other firing
+    // alert marks it degraded.
+    pub down_severities: Vec<String>,
+}
+
+impl Default for AlertmanagerConfig {
+    fn default() -> Self {
+        Self { service_labels: vec!["service".into()], down_severities: vec!["critical".into()] }
+    }
+}
+
+// One line (or row) per request, kept apart from the tracing output (see
+// access_log.rs). Disabled without `sink`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessLogConfig {
+    pub sink: Option<AccessLogSink>,
+    // For the file sink.
+    pub path: PathBuf,
+    // Written in this order; unlisted fields are left out (NULL in the table).
+    pub fields: Vec<AccessLogField>,
+    // The file is rotated to path.1, path.2, ... once it would grow past
+    // this, keeping `keep` old files; 0 never rotates.
+    pub max_bytes: u64,
+    pub keep: usize,
+    // Table rows are deleted after this many days; 0 keeps them forever.
+    pub retention_days: i32,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            sink: None,
+            path: PathBuf::from("access.log"),
+            fields: vec![
+                AccessLogField::Time,
+                AccessLogField::Ip,
+                AccessLogField::User,
+                AccessLogField::Method,
+                AccessLogField::Path,
+                AccessLogField::Status,
+                AccessLogField::LatencyMs,
+            ],
+            max_bytes: 10 * 1024 * 1024,
+            keep: 5,
+            retention_days: 30,
+        }
+    }
+}
+
+// Logos from the walkxcode/dashboard-icons collection, proxied through
+// /icons (see icons.rs).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IconsConfig {
+    pub enabled: bool,
+    // A mirror of the repository: tree.json and the svg/, png/ and webp/
+    // directories are fetched from under it.
+    pub base_url: String,
+    // How long the icon list and fetched icons are kept before fetching
+    // them again.
+    pub cache_ttl_secs: u64,
+}
+
+impl Default for IconsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            base_url: "https://cdn.jsdelivr.net/gh/walkxcode/dashboard-icons@main".into(),
+            cache_ttl_secs: 86400,
+        }
+    }
+}
+
+// Scheduled snapshots of each service's landing page (status code, final
+// URL, title and a hash of the HTML), to notice a service that silently
+// changed; see page_snapshots.rs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PageSnapshotsConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+}
+
+impl Default for PageSnapshotsConfig {
+    fn default() -> Self {
+        Self { enabled: false, interval_secs: 6 * 3600 }
+    }
+}
+
+// The suggestion box: anyone may propose a service with POST /proposals,
+// and it is only listed once an admin approves it; see proposals.rs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProposalsConfig {
+    pub enabled: bool,
+    // Proposals accepted per client IP in any hour.
+    pub max_per_hour: usize,
+    // Once this many await review, new ones are refused.
+    pub max_pending: i64,
+}
+
+impl Default for ProposalsConfig {
+    fn default() -> Self {
+        Self { enabled: false, max_per_hour: 5, max_pending: 100 }
+    }
+}
+
+// Lets admins boot a service's machine with POST /services/:name/wake; see
+// wol.rs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WakeOnLanConfig {
+    pub enabled: bool,
+    // Where magic packets go: the broadcast address of the machines'
+    // network, usually port 9.
+    pub broadcast: SocketAddr,
+}
+
+impl Default for WakeOnLanConfig {
+    fn default() -> Self {
+        Self { enabled: false, broadcast: SocketAddr::from(([255, 255, 255, 255], 9)) }
+    }
+}
+
+// Browses the LAN for web UIs to add, listed by GET /discovery/found; see
+// discovery.rs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscoveryConfig {
+    pub enabled: bool,
+    // Between scans (at least 60).
+    pub interval_secs: u64,
+    // How long each scan waits for answers.
+    pub listen_secs: u64,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self { enabled: false, interval_secs: 300, listen_secs: 3 }
+    }
+}
+
+// Keeps services in step with a reverse proxy's hosts; see proxy_sync.rs.
+// Nothing is synced without sources.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxySyncConfig {
+    pub interval_secs: u64,
+    pub sources: Vec<ProxySourceConfig>,
+}
+
+impl Default for ProxySyncConfig {
+    fn default() -> Self {
+        Self { interval_secs: 900, sources: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyKind {
+    NginxProxyManager,
+    Caddy,
+}
+
+// One `[[proxy_sync.sources]]` entry.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProxySourceConfig {
+    // Recorded on the services it creates.
+    pub name: String,
+    pub kind: ProxyKind,
+    // Nginx Proxy Manager's, e.g. http://npm:81, or Caddy's admin API,
+    // e.g. http://caddy:2019.
+    pub url: String,
+    // Nginx Proxy Manager's login; the password is read like other secrets
+    // (see secrets.rs) under the name given here.
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub password_secret: Option<String>,
+    // Host names to skip; `*` matches any run of characters.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    // For the services created, made when missing.
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // Moves services this source created to the trash once their host is
+    // gone from the proxy.
+    #[serde(default)]
+    pub remove_missing: bool,
+}
+
+// Where binary assets are kept across restarts; see storage.rs. Nothing is
+// kept without `backend`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: Option<StorageBackend>,
+    // For the local backend; made when missing.
+    pub path: PathBuf,
+    pub s3: S3Config,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self { backend: None, path: PathBuf::from("data"), s3: S3Config::default() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    Local,
+    S3,
+}
+
+// The key pair is read like other secrets, as INDEXPAGE_S3_ACCESS_KEY_ID and
+// INDEXPAGE_S3_SECRET_ACCESS_KEY.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3Config {
+    // e.g. https://s3.eu-central-1.amazonaws.com or http://minio:9000.
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    // Keys are stored under it, e.g. "indexpage".
+    pub prefix: String,
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        Self { endpoint: String::new(), bucket: String::new(), region: "us-east-1".into(), prefix: String::new() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogSink {
+    File,
+    Database,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessLogField {
+    Time,
+    Method,
+    Path,
+    Status,
+    LatencyMs,
+    Ip,
+    User,
+}
+
+// One `[[hooks]]` entry: a command (payload on stdin) or a URL (payload
+// POSTed as JSON) run on `event`, e.g. "pre_create" or "post_delete".
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookConfig {
+    pub event: HookEvent,
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default = "default_hook_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_hook_timeout() -> u64 {
+    10
+}
+
+// One `[[static_links]]` entry: a service listed by GET /services without
+// being stored, so it survives database resets. It can't be changed or
+// deleted through the API; see static_links.rs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StaticLinkConfig {
+    pub name: String,
+    pub link: String,
+    #[serde(default)]
+    pub internal_link: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub public: bool,
+}
+
+// One `[[actions]]` entry: a named HTTP call offered on the listed
+// services, run with POST /services/:name/actions/:action; see actions.rs.
+// The url, header values and body are templates.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ActionConfig {
+    pub name: String,
+    // Shown on the tile; `name` when unset.
+    #[serde(default)]
+    pub label: Option<String>,
+    // Service names, any case.
+    pub services: Vec<String>,
+    #[serde(default = "default_action_method")]
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub body: Option<String>,
+    // Who may run it.
+    #[serde(default = "default_action_role")]
+    pub role: Role,
+    #[serde(default = "default_action_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_action_method() -> String {
+    "POST".into()
+}
+
+fn default_action_role() -> Role {
+    Role::Editor
+}
+
+fn default_action_timeout() -> u64 {
+    30
+}
+
+impl Config {
+    pub fn load() -> anyhow::Result<Self> {
+        match env::var("INDEXPAGE_CONFIG") {
+            Ok(path) => Self::from_file(Path::new(&path)),
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_PATH))
+            }
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let raw = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("reading {}: {}", path.display(), e))?;
+        toml::from_str(&raw).map_err(|e| anyhow::anyhow!("parsing {}: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,122 @@
+use aes_gcm::{
+    aead::{Aead, Generate, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use std::{fs, sync::Arc};
+
+use crate::config::SecretsConfig;
+
+const PREFIX: &str = "v1:";
+const NONCE_LEN: usize = 12;
+
+// AES-256-GCM for secrets stored in the database. The key is 32 bytes,
+// base64-encoded, from INDEXPAGE_SECRET_KEY (see secrets.rs) or the file
+// named by `secrets.key_file`. Stored values look like "v1:<base64(nonce || ciphertext)>".
+#[derive(Clone)]
+pub struct Cipher {
+    aead: Arc<Aes256Gcm>,
+}
+
+impl Cipher {
+    pub fn load(key: Option<String>, config: &SecretsConfig) -> anyhow::Result<Option<Self>> {
+        let encoded = match (key, &config.key_file) {
+            (Some(key), _) => key,
+            (None, Some(path)) => fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("reading {}: {}", path.display(), e))?,
+            (None, None) => return Ok(None),
+        };
+        let key = STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| anyhow::anyhow!("secret key is not valid base64: {}", e))?;
+        let aead = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| anyhow::anyhow!("secret key must be 32 bytes, got {}", key.len()))?;
+        Ok(Some(Self { aead: Arc::new(aead) }))
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let nonce = Nonce::generate();
+        let ciphertext = self
+            .aead
+            .encrypt(&nonce, plaintext.as_bytes())
+            .expect("AES-GCM encryption of an in-memory buffer cannot fail");
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        format!("{}{}", PREFIX, STANDARD.encode(sealed))
+    }
+
+    pub fn decrypt(&self, stored: &str) -> anyhow::Result<String> {
+        let encoded = stored
+            .strip_prefix(PREFIX)
+            .ok_or_else(|| anyhow::anyhow!("unrecognised secret format"))?;
+        let sealed = STANDARD.decode(encoded)?;
+        if sealed.len() < NONCE_LEN {
+            anyhow::bail!("secret is truncated");
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::try_from(nonce).map_err(|_| anyhow::anyhow!("invalid nonce"))?;
+        let plaintext = self
+            .aead
+            .decrypt(&nonce, ciphertext)
+            .map_err(|_| anyhow::anyhow!("secret cannot be decrypted with the configured key"))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+// A random secret handed out once (invites, API tokens). Only its hash is
+// stored, so a leaked database does not leak usable tokens.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    openssl::rand::rand_bytes(&mut bytes).expect("the system RNG is available");
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+pub fn hash_token(token: &str) -> String {
+    openssl::sha::sha256(token.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(byte: u8) -> Cipher {
+        Cipher::load(Some(STANDARD.encode([byte; 32])), &SecretsConfig::default()).unwrap().unwrap()
+    }
+
+    #[test]
+    fn decrypts_what_it_encrypted() {
+        let cipher = cipher(0);
+        let sealed = cipher.encrypt("hunter2");
+        assert!(sealed.starts_with(PREFIX));
+        assert_ne!(sealed, cipher.encrypt("hunter2"));
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), "hunter2");
+    }
+
+    #[test]
+    fn refuses_other_keys_and_tampering() {
+        let sealed = cipher(0).encrypt("hunter2");
+        assert!(cipher(1).decrypt(&sealed).is_err());
+        let mut tampered = STANDARD.decode(&sealed[PREFIX.len()..]).unwrap();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(cipher(0).decrypt(&format!("{}{}", PREFIX, STANDARD.encode(tampered))).is_err());
+        assert!(cipher(0).decrypt("hunter2").is_err());
+        assert!(cipher(0).decrypt("v1:AAAA").is_err());
+    }
+
+    #[test]
+    fn loads_only_32_byte_keys() {
+        assert!(Cipher::load(None, &SecretsConfig::default()).unwrap().is_none());
+        assert!(Cipher::load(Some(STANDARD.encode([0; 16])), &SecretsConfig::default()).is_err());
+        assert!(Cipher::load(Some("not base64!".into()), &SecretsConfig::default()).is_err());
+    }
+
+    #[test]
+    fn tokens_are_random_and_hashed_with_sha256() {
+        assert_ne!(random_token(), random_token());
+        assert_eq!(random_token().len(), 43);
+        assert_eq!(hash_token("abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+}
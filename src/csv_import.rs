@@ -0,0 +1,219 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{
+    audit::{Actor, Audit},
+    hooks::{HookEvent, Hooks},
+    repository::ServiceRepository,
+};
+
+// Bulk-creates services from a spreadsheet export. The header row names the
+// columns; each field is read from the column of the same name unless the
+// query maps it to another header, e.g. ?name=Title&link=URL.
+const FIELDS: [&str; 5] = ["name", "link", "category", "tags", "description"];
+
+#[derive(Debug, Deserialize)]
+pub struct CsvImportQuery {
+    // Validates and reports every row without saving anything.
+    #[serde(default)]
+    dry_run: bool,
+    // Imports links that are near-duplicates of existing ones (see
+    // duplicates.rs); identical links are always refused.
+    #[serde(default)]
+    force: bool,
+    name: Option<String>,
+    link: Option<String>,
+    category: Option<String>,
+    tags: Option<String>,
+    description: Option<String>,
+}
+
+impl CsvImportQuery {
+    fn header_for<'a>(&'a self, field: &'a str) -> &'a str {
+        let mapped = match field {
+            "name" => &self.name,
+            "link" => &self.link,
+            "category" => &self.category,
+            "tags" => &self.tags,
+            _ => &self.description,
+        };
+        mapped.as_deref().unwrap_or(field)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RowError {
+    // 1-based, counting the header, as spreadsheets number rows.
+    row: usize,
+    name: Option<String>,
+    error: String,
+}
+
+impl RowError {
+    pub fn new(row: usize, name: Option<String>, error: String) -> Self {
+        Self { row, name, error }
+    }
+
+    pub fn row(&self) -> usize {
+        self.row
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CsvImportReport {
+    dry_run: bool,
+    imported: usize,
+    created_categories: Vec<String>,
+    ignored_columns: Vec<String>,
+    errors: Vec<RowError>,
+}
+
+// Splits RFC 4180 CSV into records: comma separated, with double-quoted
+// fields that may contain commas, newlines and "" for a quote.
+fn parse(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let (mut records, mut record, mut field) = (Vec::new(), Vec::new(), String::new());
+    let (mut quoted, mut chars) = (false, text.chars().peekable());
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".into());
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+// A service to import; the link is saved as given.
+#[derive(Debug, Clone, Serialize)]
+pub struct Row {
+    pub name: String,
+    pub link: String,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    // A dashboard-icons slug (see icons.rs).
+    pub icon: Option<String>,
+}
+
+// Runs the pre_create hooks on each row, as POST /services does, also on a
+// dry run; a rejected row is added to `errors` and left out.
+pub async fn check_hooks(
+    hooks: &Hooks,
+    actor: &Actor,
+    rows: Vec<(usize, Row)>,
+    errors: &mut Vec<RowError>,
+) -> Vec<(usize, Row)> {
+    let mut accepted = Vec::with_capacity(rows.len());
+    for (number, row) in rows {
+        let proposed = serde_json::to_value(&row).unwrap_or_default();
+        match hooks.before(HookEvent::PreCreate, actor, &proposed).await {
+            Ok(()) => accepted.push((number, row)),
+            Err((_, error)) => errors.push(RowError::new(number, Some(row.name), error)),
+        }
+    }
+    accepted
+}
+
+// Runs the post_create hooks for the rows that were saved, those without
+// an error.
+pub fn saved_hooks(hooks: &Hooks, actor: &Actor, rows: &[(usize, Row)], errors: &[RowError]) {
+    for (number, row) in rows {
+        if !errors.iter().any(|error| error.row == *number) {
+            hooks.after(HookEvent::PostCreate, actor, serde_json::to_value(row).unwrap_or_default());
+        }
+    }
+}
+
+// POST /services/import/csv
+// Rows are imported independently: a bad row is reported with its number
+// and skipped, the rest are saved, rows rejected by a pre_create hook
+// included. Tags are separated by commas or semicolons; unknown categories
+// are created.
+pub async fn import_csv<R: ServiceRepository>(
+    State(services): State<R>,
+    State(audit): State<Audit>,
+    State(hooks): State<Hooks>,
+    actor: Actor,
+    Query(query): Query<CsvImportQuery>,
+    body: String,
+) -> Result<Json<CsvImportReport>, (StatusCode, String)> {
+    let records = parse(&body).map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid CSV: {}", e)))?;
+    let Some((header, rows)) = records.split_first() else {
+        return Err((StatusCode::BAD_REQUEST, "The CSV has no header row".into()));
+    };
+    let header: Vec<String> = header.iter().map(|column| column.trim().to_lowercase()).collect();
+    let mut columns = HashMap::new();
+    for field in FIELDS {
+        let wanted = query.header_for(field).to_lowercase();
+        if let Some(index) = header.iter().position(|column| *column == wanted) {
+            columns.insert(field, index);
+        } else if field == "name" || field == "link" {
+            return Err((StatusCode::BAD_REQUEST, format!("Missing column '{}' for {}", wanted, field)));
+        }
+    }
+    let ignored_columns = header
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !columns.values().any(|used| used == index))
+        .map(|(_, column)| column.clone())
+        .collect();
+
+    let mut errors = Vec::new();
+    let mut valid = Vec::new();
+    for (index, record) in rows.iter().enumerate() {
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        let get = |field: &str| {
+            let value = columns.get(field).and_then(|index| record.get(*index)).map(|value| value.trim());
+            value.filter(|value| !value.is_empty()).map(str::to_string)
+        };
+        let (Some(name), Some(link)) = (get("name"), get("link")) else {
+            errors.push(RowError::new(index + 2, get("name"), "name and link are required".into()));
+            continue;
+        };
+        let tags = get("tags")
+            .unwrap_or_default()
+            .split([',', ';'])
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        let row = Row { name, link, category: get("category"), tags, description: get("description"), icon: None };
+        valid.push((index + 2, row));
+    }
+
+    let valid = check_hooks(&hooks, &actor, valid, &mut errors).await;
+    let (imported, created_categories) =
+        services.import(valid.clone(), query.force, query.dry_run, &mut errors).await?;
+    errors.sort_by_key(|error| error.row);
+    if !query.dry_run {
+        saved_hooks(&hooks, &actor, &valid, &errors);
+        let detail = format!("{} services, {} rows failed", imported, errors.len());
+        audit.record(&actor, "service.import_csv", None, Some(detail)).await;
+    }
+    Ok(Json(CsvImportReport { dry_run: query.dry_run, imported, created_categories, ignored_columns, errors }))
+}
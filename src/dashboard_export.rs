@@ -0,0 +1,117 @@
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Response},
+};
+use http::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use crate::{categories::Visibility, icons::Icons, links::LinkContext, tags};
+
+// The services as another start page's config, so this database can stay
+// the source of truth while something else renders the dashboard. Only
+// Homer's config.yml for now; its groups are the categories, in name order,
+// with uncategorized services last.
+const UNCATEGORIZED: &str = "Other";
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    format: String,
+    // This instance's external URL, e.g. https://dash.example. Icons are
+    // only exported with it, as absolute URLs the other page can load.
+    base: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct HomerConfig {
+    title: &'static str,
+    services: Vec<HomerGroup>,
+}
+
+#[derive(Debug, Serialize)]
+struct HomerGroup {
+    name: String,
+    items: Vec<HomerItem>,
+}
+
+#[derive(Debug, Serialize)]
+struct HomerItem {
+    name: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subtitle: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logo: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct Exported {
+    name: String,
+    link: String,
+    internal_link: Option<String>,
+    description: Option<String>,
+    icon: Option<String>,
+    category: Option<String>,
+    tags: Vec<String>,
+}
+
+// GET /services/export?format=homer
+// Links are the ones this client would see (see links.rs).
+pub async fn export(
+    State(pool): State<PgPool>,
+    State(icons): State<Icons>,
+    visibility: Visibility,
+    links: LinkContext,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    if query.format != "homer" {
+        return Err((StatusCode::BAD_REQUEST, format!("Unknown format '{}' (expected homer)", query.format)));
+    }
+    let mut sql = QueryBuilder::<Postgres>::new(format!(
+        "SELECT services.name, services.link, services.internal_link, services.description, services.icon, \
+         categories.name AS category, {} FROM services LEFT JOIN categories ON categories.id = services.category_id \
+         WHERE services.deleted_at IS NULL AND services.archived_at IS NULL",
+        tags::TAGS_COLUMN
+    ));
+    visibility.restrict_services(&mut sql);
+    sql.push(" ORDER BY categories.name IS NULL, lower(categories.name), lower(services.name)");
+    let services = sql
+        .build_query_as::<Exported>()
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let base = query.base.as_deref().map(|base| base.trim_end_matches('/')).filter(|base| !base.is_empty());
+    let logos = match base {
+        Some(base) => {
+            let lookup: Vec<(&str, &str, Option<&str>)> = services
+                .iter()
+                .map(|service| (service.name.as_str(), service.link.as_str(), service.icon.as_deref()))
+                .collect();
+            let urls = icons.urls(&lookup).await;
+            urls.into_iter().map(|url| url.map(|url| format!("{}{}", base, url))).collect()
+        }
+        None => vec![None; services.len()],
+    };
+
+    let mut groups: Vec<HomerGroup> = Vec::new();
+    for (service, logo) in services.into_iter().zip(logos) {
+        let group = service.category.unwrap_or_else(|| UNCATEGORIZED.to_string());
+        let item = HomerItem {
+            url: links.choose(&service.link, service.internal_link.as_deref()),
+            name: service.name,
+            subtitle: service.description,
+            tag: service.tags.into_iter().next(),
+            logo,
+        };
+        match groups.last_mut() {
+            Some(last) if last.name == group => last.items.push(item),
+            _ => groups.push(HomerGroup { name: group, items: vec![item] }),
+        }
+    }
+    let yaml = serde_yaml_ng::to_string(&HomerConfig { title: "indexpage", services: groups })
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(([(header::CONTENT_TYPE, "application/yaml")], yaml).into_response())
+}
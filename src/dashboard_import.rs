@@ -0,0 +1,256 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_yaml_ng::Value;
+
+use crate::{
+    audit::{Actor, Audit},
+    csv_import::{self, Row, RowError},
+    hooks::Hooks,
+    repository::ServiceRepository,
+};
+
+// Imports the service lists of other start pages, for moving over to this
+// one: Homer's config.yml, a Heimdall JSON export and gethomepage's
+// services.yaml. Their groups become categories (created when missing) and
+// their icons, when they name an image file, the services' icon slugs. Each
+// entry is saved on its own, hooks included, as with the CSV import; an
+// entry's number is its position among the file's services, counting from 1.
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    // Validates and reports every entry without saving anything.
+    #[serde(default)]
+    dry_run: bool,
+    // Imports links that are near-duplicates of existing ones (see
+    // duplicates.rs); identical links are always refused.
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    dry_run: bool,
+    imported: usize,
+    created_categories: Vec<String>,
+    errors: Vec<RowError>,
+}
+
+// An entry's name and link, or why it has none; then its other fields.
+type Entry = (Option<String>, Option<String>, Row);
+
+fn text(value: Option<&str>) -> Option<String> {
+    value.map(str::trim).filter(|value| !value.is_empty()).map(str::to_string)
+}
+
+// "assets/tools/plex.png" or "https://host/icons/plex.svg" -> "plex". Font
+// icons ("fas fa-cloud", "mdi-home", "si-github") have no slug.
+fn icon_slug(icon: &str) -> Option<String> {
+    let icon = icon.trim();
+    if icon.contains(' ') || icon.starts_with("mdi-") || icon.starts_with("si-") {
+        return None;
+    }
+    let file = icon.split(['?', '#']).next()?.rsplit('/').next()?;
+    let (stem, extension) = file.rsplit_once('.').unwrap_or((file, ""));
+    if !matches!(extension, "" | "png" | "svg" | "webp" | "jpg" | "jpeg" | "ico") {
+        return None;
+    }
+    let slug = stem.to_lowercase();
+    let valid = !slug.is_empty() && slug.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| slug.replace('_', "-"))
+}
+
+fn entry(name: Option<&str>, link: Option<&str>, category: Option<&str>, description: Option<&str>) -> Entry {
+    let (name, link) = (text(name), text(link));
+    let row = Row {
+        name: name.clone().unwrap_or_default(),
+        link: link.clone().unwrap_or_default(),
+        category: text(category),
+        tags: Vec::new(),
+        description: text(description),
+        icon: None,
+    };
+    (name, link, row)
+}
+
+#[derive(Debug, Deserialize)]
+struct HomerConfig {
+    #[serde(default)]
+    services: Vec<HomerGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HomerGroup {
+    name: Option<String>,
+    #[serde(default)]
+    items: Vec<HomerItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HomerItem {
+    name: Option<String>,
+    url: Option<String>,
+    subtitle: Option<String>,
+    tag: Option<String>,
+    logo: Option<String>,
+}
+
+fn homer(body: &str) -> Result<Vec<Entry>, String> {
+    let config: HomerConfig = serde_yaml_ng::from_str(body).map_err(|e| e.to_string())?;
+    let mut entries = Vec::new();
+    for group in &config.services {
+        for item in &group.items {
+            let (name, link, mut row) =
+                entry(item.name.as_deref(), item.url.as_deref(), group.name.as_deref(), item.subtitle.as_deref());
+            row.tags.extend(text(item.tag.as_deref()));
+            row.icon = item.logo.as_deref().and_then(icon_slug);
+            entries.push((name, link, row));
+        }
+    }
+    Ok(entries)
+}
+
+#[derive(Debug, Deserialize)]
+struct HeimdallItem {
+    title: Option<String>,
+    url: Option<String>,
+    description: Option<String>,
+    appdescription: Option<String>,
+    icon: Option<String>,
+    // Heimdall's tags are the folders items are grouped in.
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum HeimdallExport {
+    Items(Vec<HeimdallItem>),
+    Wrapped { items: Vec<HeimdallItem> },
+}
+
+fn heimdall(body: &str) -> Result<Vec<Entry>, String> {
+    let items = match serde_json::from_str(body).map_err(|e| e.to_string())? {
+        HeimdallExport::Items(items) | HeimdallExport::Wrapped { items } => items,
+    };
+    Ok(items
+        .iter()
+        .map(|item| {
+            let description = item.description.as_deref().or(item.appdescription.as_deref());
+            let (name, link, mut row) =
+                entry(item.title.as_deref(), item.url.as_deref(), item.tags.first().map(String::as_str), description);
+            row.tags = item.tags.iter().skip(1).filter_map(|tag| text(Some(tag))).collect();
+            row.icon = item.icon.as_deref().and_then(icon_slug);
+            (name, link, row)
+        })
+        .collect())
+}
+
+// services.yaml is a list of one-key maps, group name to a list of one-key
+// maps, service name to its settings or, for a nested group, to another
+// such list.
+fn homepage_group(group: &str, services: &Value, entries: &mut Vec<Entry>) -> Result<(), String> {
+    let services = services.as_sequence().ok_or(format!("group '{}' is not a list", group))?;
+    for service in services {
+        let Some(service) = service.as_mapping() else { continue };
+        for (name, settings) in service {
+            let name = name.as_str().unwrap_or_default();
+            if settings.is_sequence() {
+                homepage_group(name, settings, entries)?;
+                continue;
+            }
+            let field = |key: &str| settings.get(key).and_then(Value::as_str);
+            let (name, link, mut row) = entry(Some(name), field("href"), Some(group), field("description"));
+            row.icon = field("icon").and_then(icon_slug);
+            entries.push((name, link, row));
+        }
+    }
+    Ok(())
+}
+
+fn homepage(body: &str) -> Result<Vec<Entry>, String> {
+    let groups: Vec<Value> = serde_yaml_ng::from_str(body).map_err(|e| e.to_string())?;
+    let mut entries = Vec::new();
+    for group in &groups {
+        let Some(group) = group.as_mapping() else { continue };
+        for (name, services) in group {
+            homepage_group(name.as_str().unwrap_or_default(), services, &mut entries)?;
+        }
+    }
+    Ok(entries)
+}
+
+async fn import<R: ServiceRepository>(
+    services: &R,
+    audit: &Audit,
+    hooks: &Hooks,
+    actor: &Actor,
+    query: &ImportQuery,
+    source: &str,
+    entries: Result<Vec<Entry>, String>,
+) -> Result<Json<ImportReport>, (StatusCode, String)> {
+    let entries = entries.map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid {} config: {}", source, e)))?;
+    if entries.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, format!("The {} config lists no services", source)));
+    }
+    let (mut errors, mut valid) = (Vec::new(), Vec::new());
+    for (index, (name, link, row)) in entries.into_iter().enumerate() {
+        match (name, link) {
+            (Some(_), Some(_)) => valid.push((index + 1, row)),
+            (name, _) => errors.push(RowError::new(index + 1, name, "name and link are required".into())),
+        }
+    }
+
+    let valid = csv_import::check_hooks(hooks, actor, valid, &mut errors).await;
+    let (imported, created_categories) =
+        services.import(valid.clone(), query.force, query.dry_run, &mut errors).await?;
+    errors.sort_by_key(RowError::row);
+    if !query.dry_run {
+        csv_import::saved_hooks(hooks, actor, &valid, &errors);
+        let detail = format!("{} services, {} entries failed", imported, errors.len());
+        audit.record(actor, &format!("service.import_{}", source), None, Some(detail)).await;
+    }
+    Ok(Json(ImportReport { dry_run: query.dry_run, imported, created_categories, errors }))
+}
+
+// POST /services/import/homer
+// Body: Homer's config.yml.
+pub async fn import_homer<R: ServiceRepository>(
+    State(services): State<R>,
+    State(audit): State<Audit>,
+    State(hooks): State<Hooks>,
+    actor: Actor,
+    Query(query): Query<ImportQuery>,
+    body: String,
+) -> Result<Json<ImportReport>, (StatusCode, String)> {
+    import(&services, &audit, &hooks, &actor, &query, "homer", homer(&body)).await
+}
+
+// POST /services/import/heimdall
+// Body: the JSON from Heimdall's export, a list of items.
+pub async fn import_heimdall<R: ServiceRepository>(
+    State(services): State<R>,
+    State(audit): State<Audit>,
+    State(hooks): State<Hooks>,
+    actor: Actor,
+    Query(query): Query<ImportQuery>,
+    body: String,
+) -> Result<Json<ImportReport>, (StatusCode, String)> {
+    import(&services, &audit, &hooks, &actor, &query, "heimdall", heimdall(&body)).await
+}
+
+// POST /services/import/homepage
+// Body: gethomepage's services.yaml.
+pub async fn import_homepage<R: ServiceRepository>(
+    State(services): State<R>,
+    State(audit): State<Audit>,
+    State(hooks): State<Hooks>,
+    actor: Actor,
+    Query(query): Query<ImportQuery>,
+    body: String,
+) -> Result<Json<ImportReport>, (StatusCode, String)> {
+    import(&services, &audit, &hooks, &actor, &query, "homepage", homepage(&body)).await
+}
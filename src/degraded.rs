@@ -0,0 +1,148 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use chrono::{DateTime, SecondsFormat, Utc};
+use http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use sqlx::PgPool;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use crate::auth::ClientCert;
+
+// Keeps the dashboard up while Postgres is unreachable. A background probe
+// notices the outage; until it recovers, GET /services is answered from the
+// last response each caller got, marked with STALE_HEADER, and everything
+// else gets a 503 straight away instead of waiting on the pool.
+const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+const RETRY_AFTER_SECS: u64 = 10;
+// Distinct (caller, query) responses kept; the oldest is dropped first.
+const MAX_CACHED: usize = 256;
+// When the served copy was cached, as RFC 3339.
+pub const STALE_HEADER: &str = "x-indexpage-stale-since";
+
+struct Cached {
+    at: DateTime<Utc>,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+#[derive(Clone)]
+pub struct Degraded {
+    down: Arc<AtomicBool>,
+    cache: Arc<Mutex<HashMap<[u8; 32], Cached>>>,
+}
+
+impl Degraded {
+    // Starts the probe.
+    pub fn start(pool: PgPool) -> Self {
+        let degraded = Self { down: Arc::default(), cache: Arc::default() };
+        let down = degraded.down.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(PROBE_INTERVAL).await;
+                let probe = tokio::time::timeout(PROBE_TIMEOUT, sqlx::query!("SELECT 1 AS one").fetch_one(&pool)).await;
+                let reachable = matches!(probe, Ok(Ok(_)));
+                if down.swap(!reachable, Ordering::Relaxed) == reachable {
+                    match reachable {
+                        true => tracing::info!("database is reachable again; leaving degraded mode"),
+                        false => tracing::warn!("database is unreachable; serving cached services until it is back"),
+                    }
+                }
+            }
+        });
+        degraded
+    }
+
+    fn lookup(&self, key: &[u8; 32]) -> Option<Response> {
+        let cache = self.cache.lock().unwrap();
+        let cached = cache.get(key)?;
+        let mut response = Response::new(Body::from(cached.body.clone()));
+        *response.headers_mut() = cached.headers.clone();
+        let since = cached.at.to_rfc3339_opts(SecondsFormat::Secs, true);
+        response.headers_mut().insert(STALE_HEADER, HeaderValue::from_str(&since).ok()?);
+        Some(response)
+    }
+
+    fn store(&self, key: [u8; 32], headers: HeaderMap, body: Bytes) {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED && !cache.contains_key(&key) {
+            let oldest = cache.iter().min_by_key(|(_, cached)| cached.at).map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(key, Cached { at: Utc::now(), headers, body });
+    }
+}
+
+// Responses depend on who is asking (visibility) and on the query, so they
+// are cached per credentials and query string.
+fn cache_key(request: &Request) -> [u8; 32] {
+    let mut key = openssl::sha::Sha256::new();
+    key.update(request.uri().query().unwrap_or("").as_bytes());
+    for name in [header::AUTHORIZATION.as_str(), "x-api-key", header::COOKIE.as_str()] {
+        for value in request.headers().get_all(name) {
+            key.update(b"\0");
+            key.update(value.as_bytes());
+        }
+    }
+    if let Some(cert) = request.extensions().get::<ClientCert>() {
+        for name in &cert.names {
+            key.update(b"\0");
+            key.update(name.as_bytes());
+        }
+    }
+    key.finish()
+}
+
+fn unavailable() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, RETRY_AFTER_SECS.to_string())],
+        "The database is unreachable; try again shortly",
+    )
+        .into_response()
+}
+
+pub async fn guard(State(degraded): State<Degraded>, request: Request, next: Next) -> Response {
+    let cacheable = request.method() == Method::GET && request.uri().path() == "/services";
+    if request.method() == Method::OPTIONS {
+        return next.run(request).await;
+    }
+    if degraded.down.load(Ordering::Relaxed) {
+        let cached = cacheable.then(|| degraded.lookup(&cache_key(&request))).flatten();
+        return cached.unwrap_or_else(unavailable);
+    }
+    if !cacheable {
+        return next.run(request).await;
+    }
+
+    let key = cache_key(&request);
+    let response = next.run(request).await;
+    if response.status().is_server_error() {
+        // The probe may not have noticed yet.
+        return degraded.lookup(&key).unwrap_or(response);
+    }
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => {
+            degraded.store(key, parts.headers.clone(), body.clone());
+            Response::from_parts(parts, Body::from(body))
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
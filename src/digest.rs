@@ -0,0 +1,140 @@
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    config::{DigestConfig, DigestPeriod},
+    notify::{Notification, Notifier},
+    scheduler::{Schedule, Scheduler},
+};
+
+// Sent at 08:00 in the instance timezone (the scheduler's), which the report
+// is also written in.
+pub fn register(
+    scheduler: &Scheduler,
+    pool: PgPool,
+    config: DigestConfig,
+    tz: Tz,
+    notifier: Notifier,
+) -> anyhow::Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    if let Some(recipients) = &config.recipients {
+        notifier.check_recipients(recipients, "digest.recipients")?;
+    }
+    let schedule = Schedule::parse(match config.period {
+        DigestPeriod::Daily => "0 8 * * *",
+        DigestPeriod::Weekly => "0 8 * * 1",
+    })?;
+    scheduler.register("digest", schedule, move || {
+        let (pool, config, notifier) = (pool.clone(), config.clone(), notifier.clone());
+        async move {
+            let notification = build(&pool, config.period, Utc::now(), tz).await?;
+            match &config.recipients {
+                Some(recipients) => notifier.send_to(notification, recipients).await,
+                None => notifier.send(notification).await,
+            }
+            Ok(())
+        }
+    })
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct Availability {
+    name: String,
+    total: i64,
+    ok: i64,
+    incidents: i64,
+}
+
+// Raw results and hourly rollups never overlap (retention moves one into
+// the other), so adding both covers the whole period. Incidents are counted
+// from raw results only: each run of failed checks is one.
+const AVAILABILITY: &str = r#"
+    WITH checks AS (
+        SELECT service_id, count(*) AS total, count(*) FILTER (WHERE ok) AS ok
+        FROM check_results WHERE checked_at >= $1 GROUP BY service_id
+        UNION ALL
+        SELECT service_id, total_checks, ok_checks
+        FROM check_rollups WHERE period = 'hour' AND bucket >= $1
+    ), totals AS (
+        SELECT service_id, sum(total)::bigint AS total, sum(ok)::bigint AS ok FROM checks GROUP BY service_id
+    ), incidents AS (
+        SELECT service_id, count(*) AS incidents FROM (
+            SELECT service_id, ok, lag(ok) OVER (PARTITION BY service_id ORDER BY checked_at) AS previous
+            FROM check_results WHERE checked_at >= $1
+        ) r
+        WHERE NOT ok AND previous IS DISTINCT FROM false
+        GROUP BY service_id
+    )
+    SELECT s.name, t.total, t.ok, coalesce(i.incidents, 0) AS incidents
+    FROM services s
+    JOIN totals t ON t.service_id = s.id
+    LEFT JOIN incidents i ON i.service_id = s.id
+    WHERE s.deleted_at IS NULL AND s.archived_at IS NULL AND t.total > 0
+    ORDER BY t.ok::float8 / t.total, lower(s.name)
+"#;
+
+fn percent(ok: i64, total: i64) -> f64 {
+    ok as f64 * 100.0 / total as f64
+}
+
+// The report for the period ending at `until`.
+async fn build(pool: &PgPool, period: DigestPeriod, until: DateTime<Utc>, tz: Tz) -> sqlx::Result<Notification> {
+    let (label, since) = match period {
+        DigestPeriod::Daily => ("daily", until - Duration::days(1)),
+        DigestPeriod::Weekly => ("weekly", until - Duration::weeks(1)),
+    };
+    let services = sqlx::query_as::<_, Availability>(AVAILABILITY)
+        .bind(since)
+        .fetch_all(pool)
+        .await?;
+    let added = sqlx::query_scalar!(
+        "SELECT name FROM services
+         WHERE deleted_at IS NULL AND archived_at IS NULL AND created_at >= $1 ORDER BY created_at",
+        since,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut message = format!(
+        "Availability from {} to {}\n\n",
+        since.with_timezone(&tz).format("%Y-%m-%d %H:%M"),
+        until.with_timezone(&tz).format("%Y-%m-%d %H:%M %Z")
+    );
+    let (total, ok) = services.iter().fold((0, 0), |(total, ok), service| (total + service.total, ok + service.ok));
+    let incidents: i64 = services.iter().map(|service| service.incidents).sum();
+    if total == 0 {
+        message.push_str("No checks ran.\n");
+    } else {
+        let _ = writeln!(
+            message,
+            "Overall: {:.2}% of {} checks passed, {} incidents across {} services.",
+            percent(ok, total),
+            total,
+            incidents,
+            services.len()
+        );
+        let affected: Vec<_> = services.iter().filter(|service| service.ok < service.total).collect();
+        if affected.is_empty() {
+            message.push_str("Every service was up for the whole period.\n");
+        } else {
+            message.push_str("\nServices with failed checks:\n");
+            for service in affected {
+                let _ = writeln!(
+                    message,
+                    "  {}: {:.2}% up, {} incidents",
+                    service.name,
+                    percent(service.ok, service.total),
+                    service.incidents
+                );
+            }
+        }
+    }
+    if !added.is_empty() {
+        let _ = writeln!(message, "\nNew services ({}): {}", added.len(), added.join(", "));
+    }
+    Ok(Notification { event: "digest", service: label.to_string(), message })
+}
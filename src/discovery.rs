@@ -0,0 +1,313 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::{net::UdpSocket, time::Instant};
+
+use crate::{
+    config::DiscoveryConfig,
+    duplicates,
+    scheduler::{Schedule, Scheduler},
+};
+
+// Optional LAN discovery (`[discovery] enabled`): every `interval_secs`, as
+// the "discovery" job, the server browses mDNS for _http._tcp and
+// _https._tcp services and sends an SSDP search, and keeps what answers
+// within `listen_secs` in memory.
+// GET /discovery/found lists those devices that are not on the dashboard
+// yet, with a name and link ready for POST /services. mDNS needs the
+// `discovery` feature; SSDP devices are listed by their presentation URL,
+// so ones without a web UI are left out.
+const SSDP_ADDR: ([u8; 4], u16) = ([239, 255, 255, 250], 1900);
+// Scans a device may miss before it is dropped.
+const MISSED_SCANS: u32 = 3;
+const DESCRIPTION_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Device {
+    name: String,
+    link: String,
+    // "mdns" or "ssdp".
+    source: &'static str,
+    address: IpAddr,
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+pub struct Discovery {
+    enabled: bool,
+    // By link.
+    found: Arc<RwLock<HashMap<String, Device>>>,
+}
+
+impl Discovery {
+    pub fn new(config: &DiscoveryConfig) -> Self {
+        Self { enabled: config.enabled, found: Arc::default() }
+    }
+}
+
+// The scan, as the "discovery" job.
+pub fn register(scheduler: &Scheduler, discovery: Discovery, config: &DiscoveryConfig) -> anyhow::Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let interval = Duration::from_secs(config.interval_secs.max(60));
+    let listen = Duration::from_secs(config.listen_secs.clamp(1, 30));
+    scheduler.register("discovery", Schedule::Every(interval), move || {
+        let found = discovery.found.clone();
+        async move {
+            let (mdns, ssdp) = tokio::join!(mdns::browse(listen), ssdp(listen));
+            record(&found, [("mdns", mdns), ("ssdp", ssdp)], interval * MISSED_SCANS);
+            Ok(())
+        }
+    })
+}
+
+// A device answering the search: its name, link and address.
+type Seen = (String, String, IpAddr);
+
+// Merges a scan's results into `found` and drops devices unseen for `expiry`.
+fn record(
+    found: &RwLock<HashMap<String, Device>>,
+    results: [(&'static str, std::io::Result<Vec<Seen>>); 2],
+    expiry: Duration,
+) {
+    let now = Utc::now();
+    let mut found = found.write().unwrap();
+    for (source, seen) in results {
+        let seen = seen.unwrap_or_else(|e| {
+            tracing::warn!("{} discovery failed: {}", source, e);
+            Vec::new()
+        });
+        for (name, link, address) in seen {
+            let device = found.entry(link.clone()).or_insert_with(|| Device {
+                name: name.clone(),
+                link,
+                source,
+                address,
+                first_seen: now,
+                last_seen: now,
+            });
+            (device.name, device.address, device.last_seen) = (name, address, now);
+        }
+    }
+    let expired = now - chrono::Duration::from_std(expiry).unwrap_or_default();
+    found.retain(|_, device| device.last_seen > expired);
+}
+
+// Sends `query` to the multicast `group` and hands every answer to `answer`
+// until `listen` is up.
+async fn multicast(
+    group: SocketAddr,
+    query: &[u8],
+    listen: Duration,
+    mut answer: impl FnMut(&[u8], SocketAddr),
+) -> std::io::Result<()> {
+    let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], 0))).await?;
+    socket.set_multicast_ttl_v4(2)?;
+    socket.send_to(query, group).await?;
+    let deadline = Instant::now() + listen;
+    let mut buffer = vec![0; 9000];
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+        let (length, from) = received?;
+        answer(&buffer[..length], from);
+    }
+    Ok(())
+}
+
+async fn ssdp(listen: Duration) -> std::io::Result<Vec<Seen>> {
+    let mx = listen.as_secs().clamp(1, 5);
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: {}\r\nST: ssdp:all\r\n\r\n",
+        mx
+    );
+    // Devices answer once per service they offer; their description is
+    // fetched once.
+    let mut locations: HashMap<String, IpAddr> = HashMap::new();
+    multicast(SocketAddr::from(SSDP_ADDR), search.as_bytes(), listen, |answer, from| {
+        let answer = String::from_utf8_lossy(answer);
+        let location = answer.lines().find_map(|line| {
+            let (header, value) = line.split_once(':')?;
+            header.trim().eq_ignore_ascii_case("location").then(|| value.trim().to_string())
+        });
+        if let Some(location) = location {
+            locations.entry(location).or_insert(from.ip());
+        }
+    })
+    .await?;
+
+    let http = reqwest::Client::builder().timeout(DESCRIPTION_TIMEOUT).build().map_err(std::io::Error::other)?;
+    let mut seen = Vec::new();
+    for (location, address) in locations {
+        let Ok(response) = http.get(&location).send().await else { continue };
+        let Ok(description) = response.text().await else { continue };
+        let Some(presentation) = element(&description, "presentationURL") else { continue };
+        let Some(link) = url::Url::parse(&location).and_then(|base| base.join(&presentation)).ok() else {
+            continue;
+        };
+        if !matches!(link.scheme(), "http" | "https") {
+            continue;
+        }
+        let name = element(&description, "friendlyName").unwrap_or_else(|| address.to_string());
+        seen.push((name, link.to_string(), address));
+    }
+    Ok(seen)
+}
+
+// The text of the first <name> element in a UPnP device description.
+fn element(xml: &str, name: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    let text = xml[start..end].trim().replace("&amp;", "&");
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(feature = "discovery")]
+mod mdns {
+    use hickory_proto::{
+        op::{Message, Query},
+        rr::{Name, RData, RecordType},
+    };
+    use std::{
+        collections::HashMap,
+        net::{IpAddr, SocketAddr},
+        time::Duration,
+    };
+
+    use super::{multicast, Seen};
+
+    const MDNS_ADDR: ([u8; 4], u16) = ([224, 0, 0, 251], 5353);
+    const SERVICE_TYPES: [(&str, &str); 2] = [("_http._tcp.local.", "http"), ("_https._tcp.local.", "https")];
+
+    // Asks from an ephemeral port, so responders answer by unicast with
+    // the SRV, TXT and address records alongside (RFC 6762, 6.7).
+    pub async fn browse(listen: Duration) -> std::io::Result<Vec<Seen>> {
+        let mut query = Message::query();
+        for (service_type, _) in SERVICE_TYPES {
+            let name = Name::from_ascii(service_type).map_err(std::io::Error::other)?;
+            query.add_query(Query::query(name, RecordType::PTR));
+        }
+        let query = query.to_vec().map_err(std::io::Error::other)?;
+
+        // By instance: its scheme and name as announced, (host, port), path
+        // and who answered; by host: its address.
+        let mut instances: HashMap<Name, (&str, Name)> = HashMap::new();
+        let mut targets: HashMap<Name, (Name, u16)> = HashMap::new();
+        let mut paths: HashMap<Name, String> = HashMap::new();
+        let mut addresses: HashMap<Name, IpAddr> = HashMap::new();
+        let mut senders: HashMap<Name, IpAddr> = HashMap::new();
+        multicast(SocketAddr::from(MDNS_ADDR), &query, listen, |answer, from| {
+            let Ok(message) = Message::from_vec(answer) else { return };
+            for record in message.answers.iter().chain(&message.additionals) {
+                let name = record.name.to_lowercase();
+                match &record.data {
+                    RData::PTR(instance) => {
+                        let browsed = SERVICE_TYPES.iter().find(|(service_type, _)| name.to_ascii() == *service_type);
+                        if let Some((_, scheme)) = browsed {
+                            instances.insert(instance.0.to_lowercase(), (scheme, instance.0.clone()));
+                            senders.insert(instance.0.to_lowercase(), from.ip());
+                        }
+                    }
+                    RData::SRV(srv) => {
+                        targets.insert(name, (srv.target.to_lowercase(), srv.port));
+                    }
+                    RData::TXT(txt) => {
+                        let path = txt.txt_data.iter().find_map(|entry| {
+                            let entry = String::from_utf8_lossy(entry);
+                            entry.strip_prefix("path=").map(str::to_string)
+                        });
+                        if let Some(path) = path {
+                            paths.insert(name, path);
+                        }
+                    }
+                    RData::A(a) => {
+                        addresses.insert(name, IpAddr::V4(a.0));
+                    }
+                    _ => {}
+                }
+            }
+        })
+        .await?;
+
+        let mut seen = Vec::new();
+        for (instance, (scheme, announced)) in instances {
+            let Some((host, port)) = targets.get(&instance) else { continue };
+            let Some(address) = addresses.get(host).or(senders.get(&instance)) else { continue };
+            let path = paths.get(&instance).map(String::as_str).unwrap_or("/");
+            let path = if path.starts_with('/') { path.to_string() } else { format!("/{}", path) };
+            let default_port = matches!((scheme, port), ("http", 80) | ("https", 443));
+            let link = match default_port {
+                true => format!("{}://{}{}", scheme, address, path),
+                false => format!("{}://{}:{}{}", scheme, address, port, path),
+            };
+            // "Office Printer._http._tcp.local." -> "Office Printer"
+            let name = announced.iter().next().map(|label| String::from_utf8_lossy(label).into_owned());
+            seen.push((name.unwrap_or_else(|| address.to_string()), link, *address));
+        }
+        Ok(seen)
+    }
+}
+
+#[cfg(not(feature = "discovery"))]
+mod mdns {
+    use std::time::Duration;
+
+    use super::Seen;
+
+    // Without the `discovery` feature only SSDP is searched.
+    pub async fn browse(_listen: Duration) -> std::io::Result<Vec<Seen>> {
+        Ok(Vec::new())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FoundQuery {
+    // Also list devices already on the dashboard.
+    #[serde(default)]
+    all: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Found {
+    #[serde(flatten)]
+    device: Device,
+    // The service already linking there, with ?all=true.
+    listed_as: Option<String>,
+}
+
+// GET /discovery/found
+// Sorted by name.
+pub async fn found(
+    State(pool): State<PgPool>,
+    State(discovery): State<Discovery>,
+    Query(query): Query<FoundQuery>,
+) -> Result<Json<Vec<Found>>, (StatusCode, String)> {
+    if !discovery.enabled {
+        return Err((StatusCode::NOT_FOUND, "Discovery is disabled".into()));
+    }
+    let mut devices: Vec<Device> = discovery.found.read().unwrap().values().cloned().collect();
+    devices.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()).then_with(|| a.link.cmp(&b.link)));
+
+    let mut conn = pool.acquire().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut found = Vec::new();
+    for device in devices {
+        let listed = duplicates::find(&mut conn, &device.link)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        if listed.is_none() || query.all {
+            found.push(Found { device, listed_as: listed.map(|service| service.name) });
+        }
+    }
+    Ok(Json(found))
+}
@@ -0,0 +1,221 @@
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+use crate::{
+    config::Config,
+    email,
+    notify::Notifier,
+    schema,
+    secrets::Secrets,
+    storage::{ObjectStore, Storage},
+};
+
+// `indexpage doctor`
+// Goes through what a first start needs and prints one line per finding:
+// the database and its schema, the listen address, writing to `[storage]`,
+// one round of checks (not recorded) and a test message to every
+// notification receiver. Exits
+// non-zero if anything failed. Unlike check-config it contacts everything.
+const CONNECT_TIMEOUT_SECS: u64 = 5;
+
+#[derive(Default)]
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn ok(&mut self, what: &str, detail: impl std::fmt::Display) {
+        println!("[ ok ] {}: {}", what, detail);
+    }
+
+    fn warn(&mut self, what: &str, detail: impl std::fmt::Display) {
+        println!("[warn] {}: {}", what, detail);
+    }
+
+    fn fail(&mut self, what: &str, detail: impl std::fmt::Display) {
+        self.failures += 1;
+        println!("[FAIL] {}: {}", what, detail);
+    }
+}
+
+// Returns the process exit code.
+pub async fn run(args: &[String]) -> i32 {
+    if !args.is_empty() {
+        eprintln!("usage: indexpage doctor");
+        return 2;
+    }
+    let mut report = Report::default();
+    let config = match Config::load() {
+        Ok(config) => {
+            report.ok("config", "loaded");
+            config
+        }
+        Err(e) => {
+            report.fail("config", e);
+            return 1;
+        }
+    };
+    let secrets = match Secrets::load(&config.vault).await {
+        Ok(secrets) => secrets,
+        Err(e) => {
+            report.fail("vault", e);
+            return 1;
+        }
+    };
+
+    let pool = database(&mut report, &secrets).await;
+    if let Some(pool) = &pool {
+        match schema::missing(pool).await {
+            Ok(missing) if missing.is_empty() => report.ok("schema", "up to date"),
+            Ok(missing) => report.warn(
+                "schema",
+                format!("missing {}; they are created on the next start", missing.join(", ")),
+            ),
+            Err(e) => report.fail("schema", e),
+        }
+    }
+    listener(&mut report, &config).await;
+    storage(&mut report, &config, &secrets).await;
+
+    let mailer = secrets
+        .get("INDEXPAGE_SMTP_PASSWORD")
+        .and_then(|password| email::Mailer::load(&config.email, password));
+    let notifier = match mailer.and_then(|mailer| Notifier::new(&config.notifications, mailer)) {
+        Ok(notifier) => notifier,
+        Err(e) => {
+            report.fail("notifications", e);
+            return 1;
+        }
+    };
+    if let Some(pool) = &pool {
+        checks(&mut report, &config, &secrets, pool, notifier.clone()).await;
+    }
+    let results = notifier.test().await;
+    if results.is_empty() {
+        report.warn("notifications", "no receivers configured");
+    }
+    for (receiver, result) in results {
+        match result {
+            Ok(()) => report.ok(&format!("notify {}", receiver), "test message sent"),
+            Err(e) => report.fail(&format!("notify {}", receiver), e),
+        }
+    }
+
+    match report.failures {
+        0 => {
+            println!("\nAll good.");
+            0
+        }
+        failures => {
+            println!("\n{} problem(s) found.", failures);
+            1
+        }
+    }
+}
+
+async fn database(report: &mut Report, secrets: &Secrets) -> Option<PgPool> {
+    let url = match secrets.get("DATABASE_URL") {
+        Ok(Some(url)) => url,
+        Ok(None) => {
+            report.fail("database", "DATABASE_URL is not set");
+            return None;
+        }
+        Err(e) => {
+            report.fail("database", e);
+            return None;
+        }
+    };
+    let connected = PgPoolOptions::new()
+        .max_connections(2)
+        .acquire_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+        .connect(&url)
+        .await;
+    let pool = match connected {
+        Ok(pool) => pool,
+        Err(e) => {
+            report.fail("database", e);
+            return None;
+        }
+    };
+    match sqlx::query_scalar!(r#"SELECT current_setting('server_version') AS "version!""#).fetch_one(&pool).await {
+        Ok(version) => report.ok("database", format!("connected to PostgreSQL {}", version)),
+        Err(e) => report.fail("database", e),
+    }
+    Some(pool)
+}
+
+async fn listener(report: &mut Report, config: &Config) {
+    let listeners = [("listen", Some(config.server.listen)), ("admin_listen", config.server.admin_listen)];
+    for (name, listen) in listeners {
+        let Some(listen) = listen else { continue };
+        match TcpListener::bind(listen).await {
+            Ok(_) => report.ok(name, format!("{} is free", listen)),
+            // Most likely indexpage itself is running.
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+                report.warn(name, format!("{} is already in use (is indexpage running?)", listen))
+            }
+            Err(e) => report.fail(name, format!("cannot bind {}: {}", listen, e)),
+        }
+    }
+}
+
+// Writes, reads back and deletes an object.
+async fn storage(report: &mut Report, config: &Config, secrets: &Secrets) {
+    let storage = match Storage::new(&config.storage, secrets) {
+        Ok(storage) => storage,
+        Err(e) => return report.fail("storage", e),
+    };
+    let Some(description) = storage.describe() else { return };
+    let key = "doctor/probe";
+    let round_trip = async {
+        storage.put(key, "indexpage doctor".into(), "text/plain").await?;
+        let read = storage.get(key).await?;
+        storage.delete(key).await?;
+        anyhow::ensure!(read.is_some(), "the object written could not be read back");
+        Ok(())
+    };
+    match round_trip.await {
+        Ok(()) => report.ok("storage", format!("{} is writable", description)),
+        Err(e) => report.fail("storage", format!("{}: {}", description, e)),
+    }
+}
+
+#[cfg(feature = "checks")]
+async fn checks(report: &mut Report, config: &Config, secrets: &Secrets, pool: &PgPool, notifier: Notifier) {
+    if !config.checks.enabled {
+        return report.warn("checks", "disabled in [checks]");
+    }
+    let cipher = match secrets.get("INDEXPAGE_SECRET_KEY").and_then(|key| crate::crypto::Cipher::load(key, &config.secrets)) {
+        Ok(cipher) => cipher,
+        Err(e) => return report.fail("checks", e),
+    };
+    let plugins = match crate::plugins::Plugins::load(&config.plugins) {
+        Ok(plugins) => plugins,
+        Err(e) => return report.fail("checks", e),
+    };
+    let checker = match crate::checker::Checker::new(&config.checks, notifier, cipher, plugins) {
+        Ok(checker) => checker,
+        Err(e) => return report.fail("checks", e),
+    };
+    let links = crate::links::Links::new(&config.links, config.server.tls.is_some()).for_checks();
+    let results = match crate::checker::dry_run(pool, checker, &links).await {
+        Ok(results) => results,
+        Err(e) => return report.fail("checks", e),
+    };
+    if results.is_empty() {
+        report.warn("checks", "no services to check");
+    }
+    for (name, latency_ms, error) in results {
+        match error {
+            None => report.ok(&format!("check {}", name), format!("up ({} ms)", latency_ms)),
+            // A service being down is what checks are for, not a setup problem.
+            Some(error) => report.warn(&format!("check {}", name), format!("down: {}", error)),
+        }
+    }
+}
+
+#[cfg(not(feature = "checks"))]
+async fn checks(report: &mut Report, _config: &Config, _secrets: &Secrets, _pool: &PgPool, _notifier: Notifier) {
+    report.warn("checks", "this build lacks the `checks` feature");
+}
@@ -0,0 +1,177 @@
+use axum::{
+    response::{IntoResponse, Response},
+    Json,
+};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use url::Url;
+
+use crate::{services::Service, tags};
+
+// Links are stored as given. A new link that matches an existing one, or
+// only differs from it in ways that don't change the page (http vs https,
+// a "www." prefix, a trailing slash, tracking or reordered query
+// parameters), is refused with a 409 carrying the existing service, unless
+// the request says ?force=true. Links that aren't URLs (e.g. with
+// {variables} in the host) are only compared exactly.
+const TRACKING_PARAMS: [&str; 9] = ["fbclid", "gclid", "dclid", "msclkid", "yclid", "igshid", "mc_cid", "mc_eid", "_ga"];
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Force {
+    #[serde(default)]
+    pub force: bool,
+}
+
+fn is_tracking(param: &str) -> bool {
+    param.starts_with("utm_") || TRACKING_PARAMS.contains(&param)
+}
+
+// Lowercase scheme and host, no default port (both done by Url) and no
+// tracking parameters. Only for comparing; links are stored as given.
+fn normalize(link: &str) -> String {
+    let link = link.trim();
+    let Ok(mut url) = Url::parse(link) else { return link.to_string() };
+    if url.query().is_some() {
+        let kept: Vec<(String, String)> =
+            url.query_pairs().filter(|(key, _)| !is_tracking(key)).map(|(key, value)| (key.into(), value.into())).collect();
+        if kept.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(kept);
+        }
+    }
+    url.to_string()
+}
+
+// What two links must share to count as the same: host without "www.",
+// port, path without a trailing slash, sorted query and fragment.
+fn key(url: &Url) -> String {
+    let host = url.host_str().unwrap_or_default();
+    let mut query: Vec<(String, String)> =
+        url.query_pairs().filter(|(key, _)| !is_tracking(key)).map(|(key, value)| (key.into(), value.into())).collect();
+    query.sort();
+    format!(
+        "{}:{:?}{}?{:?}#{}",
+        host.strip_prefix("www.").unwrap_or(host),
+        url.port(),
+        url.path().trim_end_matches('/'),
+        query,
+        url.fragment().unwrap_or_default()
+    )
+}
+
+// A live service whose link is `link` or a near-duplicate of it.
+pub async fn find(conn: &mut PgConnection, link: &str) -> sqlx::Result<Option<Service>> {
+    let Some(url) = Url::parse(link.trim()).ok().filter(|url| url.host_str().is_some()) else {
+        return sqlx::query_as::<_, Service>(&format!(
+            "SELECT services.*, {} FROM services WHERE deleted_at IS NULL AND link = $1 ORDER BY id",
+            tags::TAGS_COLUMN
+        ))
+        .bind(link)
+        .fetch_optional(conn)
+        .await;
+    };
+    // Narrowed down to links mentioning the host first.
+    let host = url.host_str().unwrap_or_default();
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    let candidates = sqlx::query_as::<_, Service>(&format!(
+        "SELECT services.*, {} FROM services WHERE deleted_at IS NULL AND strpos(lower(link), $1) > 0 ORDER BY id",
+        tags::TAGS_COLUMN
+    ))
+    .bind(host)
+    .fetch_all(conn)
+    .await?;
+    Ok(candidates.into_iter().find(|service| same(&service.link, link)))
+}
+
+// Whether `existing` is `link` or a near-duplicate of it.
+pub fn same(existing: &str, link: &str) -> bool {
+    let parse = |link: &str| Url::parse(link.trim()).ok().filter(|url| url.host_str().is_some());
+    existing == link || matches!((parse(existing), parse(link)), (Some(a), Some(b)) if key(&a) == key(&b))
+}
+
+// The answer to a duplicate link: 409 with the service that has it.
+#[derive(Debug, Serialize)]
+pub struct Duplicate {
+    pub message: String,
+    pub service: Service,
+}
+
+impl IntoResponse for Duplicate {
+    fn into_response(self) -> Response {
+        (StatusCode::CONFLICT, Json(self)).into_response()
+    }
+}
+
+// The live service that `link` duplicates, if any; with `force` only one
+// with the same link (up to normalize) counts.
+pub async fn check(
+    conn: &mut PgConnection,
+    link: &str,
+    force: bool,
+) -> Result<Option<Duplicate>, (StatusCode, String)> {
+    let existing = find(conn, link)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(verdict(existing, link, force))
+}
+
+// The duplicate to answer with, given the service `find` returned.
+pub fn verdict(existing: Option<Service>, link: &str, force: bool) -> Option<Duplicate> {
+    match existing {
+        Some(service) if normalize(&service.link) == normalize(link) => {
+            Some(Duplicate { message: format!("'{}' already links to {}", service.name, service.link), service })
+        }
+        Some(service) if !force => Some(Duplicate {
+            message: format!("'{}' already links to {} (add ?force=true to add it anyway)", service.name, service.link),
+            service,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(name: &str, link: &str) -> Service {
+        serde_json::from_value(serde_json::json!({
+            "id": 1, "name": name, "link": link, "check_type": "http", "public": false, "tags": [],
+            "created_at": "2026-01-01T00:00:00Z", "updated_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn normalize_only_drops_tracking_parameters() {
+        let tracked = "HTTPS://Wiki.Example.com:443/Docs?utm_source=x&b=1&fbclid=y";
+        assert_eq!(normalize(tracked), "https://wiki.example.com/Docs?b=1");
+        assert_eq!(normalize("https://example.com/?utm_medium=mail"), "https://example.com/");
+        assert_eq!(normalize("https://example.com/a/?b=2&a=1"), "https://example.com/a/?b=2&a=1");
+        assert_eq!(normalize("  {base}/x "), "{base}/x");
+    }
+
+    #[test]
+    fn near_duplicates_differ_only_in_what_does_not_change_the_page() {
+        assert!(same("https://example.com/wiki/", "http://www.example.com/wiki?utm_source=x"));
+        assert!(same("https://example.com/?a=1&b=2", "https://example.com?b=2&a=1"));
+        assert!(!same("https://example.com/wiki", "https://example.com/docs"));
+        assert!(!same("https://example.com/", "https://example.com:8443/"));
+        assert!(!same("https://example.com/#a", "https://example.com/#b"));
+        assert!(same("{base}/a", "{base}/a"));
+        assert!(!same("{base}/a", "{base}/a/"));
+    }
+
+    #[test]
+    fn force_only_lets_near_duplicates_through() {
+        let wiki = || Some(service("Wiki", "https://wiki.example.com/"));
+        let identical = verdict(wiki(), "https://Wiki.example.com?utm_source=x", true).unwrap();
+        assert_eq!(identical.message, "'Wiki' already links to https://wiki.example.com/");
+        assert_eq!(identical.service.name, "Wiki");
+        let near = verdict(wiki(), "http://www.wiki.example.com", false).unwrap();
+        assert!(near.message.contains("?force=true"));
+        assert!(verdict(wiki(), "http://www.wiki.example.com", true).is_none());
+        assert!(verdict(None, "https://wiki.example.com", false).is_none());
+    }
+}
@@ -0,0 +1,71 @@
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    Address, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use std::{sync::Arc, time::Duration};
+
+use crate::config::{EmailConfig, SmtpSecurity};
+
+// Plain-text mail over SMTP. Absent (None in the app state) without
+// `email.smtp_host`; features that need it say so.
+#[derive(Clone)]
+pub struct Mailer {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    base_url: Option<String>,
+}
+
+impl Mailer {
+    pub fn load(config: &EmailConfig, password: Option<String>) -> anyhow::Result<Option<Self>> {
+        let Some(host) = &config.smtp_host else { return Ok(None) };
+        let (builder, port) = match config.security {
+            SmtpSecurity::Tls => (AsyncSmtpTransport::<Tokio1Executor>::relay(host)?, 465),
+            SmtpSecurity::Starttls => (AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?, 587),
+            SmtpSecurity::None => (AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host), 25),
+        };
+        let mut builder = builder
+            .port(config.smtp_port.unwrap_or(port))
+            .timeout(Some(Duration::from_secs(10)));
+        if let Some(username) = &config.username {
+            let password = password
+                .ok_or_else(|| anyhow::anyhow!("email.username is set but INDEXPAGE_SMTP_PASSWORD is not"))?;
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+        let from = config
+            .from
+            .parse()
+            .map_err(|e| anyhow::anyhow!("invalid email.from '{}': {}", config.from, e))?;
+        Ok(Some(Self {
+            inner: Arc::new(Inner {
+                transport: builder.build(),
+                from,
+                base_url: config.base_url.as_ref().map(|url| url.trim_end_matches('/').to_string()),
+            }),
+        }))
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> anyhow::Result<()> {
+        let message = Message::builder()
+            .from(self.inner.from.clone())
+            .to(to.parse()?)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)?;
+        self.inner.transport.send(message).await?;
+        Ok(())
+    }
+
+    // An absolute URL for `path` when `email.base_url` is set.
+    pub fn link(&self, path: &str) -> Option<String> {
+        self.inner.base_url.as_ref().map(|base| format!("{}{}", base, path))
+    }
+}
+
+pub fn is_valid_address(address: &str) -> bool {
+    address.parse::<Address>().is_ok()
+}
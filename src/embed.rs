@@ -0,0 +1,220 @@
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use http::{header, StatusCode};
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+use crate::{
+    audit::{Actor, Audit},
+    checks,
+    health::HEALTH_COLUMN,
+    links::LinkContext,
+    status_page::escape_html,
+};
+
+// Service lists for embedding in wikis and other dashboards: an admin signs
+// a token scoped to one category or tag with POST /embed/tokens, and
+// GET /embed?token= serves that list as bare HTML, without a session or API
+// key. Tokens are HMAC-SHA256 signed with INDEXPAGE_EMBED_KEY and carry
+// their expiry; nothing is stored, so changing the key revokes them all.
+// Without a key embedding is off.
+const DEFAULT_DAYS: i64 = 365;
+const MAX_DAYS: i64 = 3650;
+
+#[derive(Clone)]
+pub struct Embed {
+    key: Option<Arc<Vec<u8>>>,
+}
+
+// What a token grants: the services of one category or with one tag.
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    category: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    // Unix seconds.
+    exp: i64,
+}
+
+impl Embed {
+    pub fn new(key: Option<String>) -> Self {
+        Self { key: key.filter(|key| !key.is_empty()).map(|key| Arc::new(key.into_bytes())) }
+    }
+
+    fn mac(key: &[u8], payload: &str) -> Option<Vec<u8>> {
+        let pkey = PKey::hmac(key).ok()?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &pkey).ok()?;
+        signer.sign_oneshot_to_vec(payload.as_bytes()).ok()
+    }
+
+    // "<base64url(claims)>.<base64url(mac)>"
+    fn sign(&self, claims: &Claims) -> Option<String> {
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).ok()?);
+        let mac = Self::mac(self.key.as_ref()?, &payload)?;
+        Some(format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(mac)))
+    }
+
+    // The token's claims if it is signed with our key and not expired.
+    fn verify(&self, token: &str) -> Option<Claims> {
+        let (payload, mac) = token.split_once('.')?;
+        let expected = Self::mac(self.key.as_ref()?, payload)?;
+        let mac = URL_SAFE_NO_PAD.decode(mac).ok()?;
+        if mac.len() != expected.len() || !openssl::memcmp::eq(&mac, &expected) {
+            return None;
+        }
+        let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        (claims.exp > Utc::now().timestamp()).then_some(claims)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateToken {
+    #[serde(default)]
+    category_id: Option<i32>,
+    #[serde(default)]
+    tag: Option<String>,
+    #[serde(default)]
+    expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbedToken {
+    token: String,
+    // Relative; for an iframe's src.
+    path: String,
+    expires_at: DateTime<Utc>,
+}
+
+// POST /embed/tokens
+// Body: {"category_id": 3} or {"tag": "media"}, optionally with
+// "expires_in_days" (365 by default).
+pub async fn create_token(
+    State(pool): State<PgPool>,
+    State(embed): State<Embed>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Json(payload): Json<CreateToken>,
+) -> Result<Json<EmbedToken>, (StatusCode, String)> {
+    if embed.key.is_none() {
+        return Err((StatusCode::NOT_FOUND, "Embedding is off; set INDEXPAGE_EMBED_KEY".into()));
+    }
+    let internal = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let tag = payload.tag.as_deref().map(str::trim).filter(|tag| !tag.is_empty());
+    let scope = match (payload.category_id, tag) {
+        (Some(id), None) => sqlx::query_scalar::<_, String>("SELECT name FROM categories WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&pool)
+            .await
+            .map_err(internal)?
+            .map(|name| format!("category {}", name))
+            .ok_or((StatusCode::NOT_FOUND, "Category not found".into()))?,
+        (None, Some(tag)) => sqlx::query_scalar::<_, String>("SELECT name FROM tags WHERE name = $1")
+            .bind(tag)
+            .fetch_optional(&pool)
+            .await
+            .map_err(internal)?
+            .map(|name| format!("tag {}", name))
+            .ok_or((StatusCode::NOT_FOUND, format!("Tag '{}' not found", tag)))?,
+        _ => return Err((StatusCode::BAD_REQUEST, "Set either category_id or tag".into())),
+    };
+    let days = payload.expires_in_days.unwrap_or(DEFAULT_DAYS);
+    if !(1..=MAX_DAYS).contains(&days) {
+        return Err((StatusCode::BAD_REQUEST, format!("expires_in_days must be 1 to {}", MAX_DAYS)));
+    }
+    let expires_at = Utc::now() + Duration::days(days);
+    let claims = Claims { category: payload.category_id, tag: tag.map(str::to_string), exp: expires_at.timestamp() };
+    let token = embed
+        .sign(&claims)
+        .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "Signing the token failed".into()))?;
+    let detail = format!("{} until {}", scope, expires_at.to_rfc3339());
+    audit.record(&actor, "embed.token", None, Some(detail)).await;
+    Ok(Json(EmbedToken { path: format!("/embed?token={}", token), token, expires_at }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbedQuery {
+    token: String,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct Entry {
+    name: String,
+    link: String,
+    internal_link: Option<String>,
+    color: Option<String>,
+    ok: Option<bool>,
+    degraded: Option<bool>,
+    alert_down: Option<bool>,
+    health: Option<String>,
+}
+
+// GET /embed?token=
+// A bare list for an iframe. The page and its links send no referrer, so
+// the token doesn't leak to the services it lists.
+pub async fn embed(
+    State(pool): State<PgPool>,
+    State(embed): State<Embed>,
+    links: LinkContext,
+    Query(query): Query<EmbedQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let claims = embed
+        .verify(&query.token)
+        .ok_or((StatusCode::FORBIDDEN, "Invalid or expired embed token".into()))?;
+    let entries = sqlx::query_as::<_, Entry>(&format!(
+        "SELECT s.name, s.link, s.internal_link, coalesce(s.color, c.color) AS color, r.ok, r.degraded, {}, {} \
+         FROM services s LEFT JOIN categories c ON c.id = s.category_id \
+         LEFT JOIN LATERAL (SELECT ok, degraded FROM check_results \
+         WHERE service_id = s.id ORDER BY checked_at DESC LIMIT 1) r ON true \
+         WHERE s.deleted_at IS NULL AND s.archived_at IS NULL \
+         AND ($1::int IS NULL OR s.category_id = $1) \
+         AND ($2::text IS NULL OR EXISTS (SELECT 1 FROM service_tags st JOIN tags t ON t.id = st.tag_id \
+         WHERE st.service_id = s.id AND t.name = $2)) \
+         ORDER BY lower(s.name)",
+        checks::ALERT_DOWN_COLUMN,
+        HEALTH_COLUMN
+    ))
+    .bind(claims.category)
+    .bind(&claims.tag)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut body = String::new();
+    for entry in &entries {
+        let state = checks::state_of(entry.health.as_deref(), entry.ok, entry.degraded, entry.alert_down);
+        let color = entry.color.as_deref().map(|color| format!(" style=\"border-left-color: {}\"", escape_html(color)));
+        body.push_str(&format!(
+            "<li class=\"{}\"{}><a href=\"{}\" target=\"_blank\" rel=\"noopener noreferrer\">{}</a></li>",
+            state,
+            color.unwrap_or_default(),
+            escape_html(&links.choose(&entry.link, entry.internal_link.as_deref())),
+            escape_html(&entry.name)
+        ));
+    }
+    let html = format!(
+        r#"<!doctype html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width">
+<meta name="referrer" content="no-referrer">
+<style>
+body {{ font-family: system-ui, sans-serif; margin: 0; padding: .5rem; background: transparent; }}
+ul {{ list-style: none; margin: 0; padding: 0; }}
+li {{ padding: .3rem .5rem; border-left: 4px solid transparent; }}
+li::before {{ content: "\25cf"; margin-right: .4rem; color: #757575; }}
+.up::before {{ color: #2e7d32; }} .degraded::before, .flapping::before {{ color: #ef6c00; }}
+.down::before {{ color: #c62828; }}
+a {{ color: inherit; text-decoration: none; }} a:hover {{ text-decoration: underline; }}
+</style></head>
+<body><ul>{}</ul></body></html>"#,
+        body
+    );
+    let headers = [(header::CACHE_CONTROL, "no-store"), (header::REFERRER_POLICY, "no-referrer")];
+    Ok((headers, Html(html)).into_response())
+}
@@ -0,0 +1,114 @@
+use axum::{
+    body::{Body, HttpBody},
+    extract::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use http::{header, HeaderMap, HeaderValue};
+use serde_json::{json, Map, Value};
+
+// One shape for every response, for clients that ask for it with
+// `Accept: application/vnd.indexpage+json`: {"data": ..., "meta": {...}}
+// on success and {"errors": [{"status": ..., "message": ...}]} on failure.
+// Lists get their count and the next page (from the Link and X-Next-Cursor
+// headers, which stay) in meta; plain-text answers become meta.message.
+// Without the header responses are unchanged. Streams, HTML and images
+// are passed through as they are.
+pub const MEDIA_TYPE: &str = "application/vnd.indexpage+json";
+// Bodies of unknown or larger size aren't wrapped.
+const MAX_WRAPPED_BYTES: u64 = 16 * 1024 * 1024;
+
+fn wants_envelope(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains(MEDIA_TYPE))
+}
+
+enum Kind {
+    Json,
+    Text,
+}
+
+fn kind_of(headers: &HeaderMap) -> Option<Kind> {
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or("");
+    if content_type.starts_with("application/json") {
+        Some(Kind::Json)
+    } else if content_type.starts_with("text/plain") {
+        Some(Kind::Text)
+    } else {
+        None
+    }
+}
+
+// The URL in `Link: <url>; rel="next"`.
+fn next_link(headers: &HeaderMap) -> Option<&str> {
+    let link = headers.get(header::LINK)?.to_str().ok()?;
+    link.split(',')
+        .find(|part| part.contains("rel=\"next\""))
+        .and_then(|part| part.trim().strip_prefix('<')?.split_once('>'))
+        .map(|(url, _)| url)
+}
+
+pub async fn wrap(request: Request, next: Next) -> Response {
+    if !wants_envelope(request.headers()) {
+        return next.run(request).await;
+    }
+    let response = next.run(request).await;
+    let Some(kind) = kind_of(response.headers()) else { return response };
+    if response.body().size_hint().exact().is_none_or(|len| len > MAX_WRAPPED_BYTES) {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return (parts.status, e.to_string()).into_response(),
+    };
+    let value = match kind {
+        Kind::Json => serde_json::from_slice(&body).ok(),
+        Kind::Text => None,
+    };
+    let text = String::from_utf8_lossy(&body).into_owned();
+
+    let status = parts.status;
+    let wrapped = if status.is_client_error() || status.is_server_error() {
+        let error = match value {
+            Some(Value::Object(mut fields)) => {
+                fields.insert("status".into(), status.as_u16().into());
+                Value::Object(fields)
+            }
+            Some(value) => json!({ "status": status.as_u16(), "detail": value }),
+            None => json!({ "status": status.as_u16(), "message": text }),
+        };
+        json!({ "errors": [error] })
+    } else {
+        let mut meta = Map::new();
+        let data = match (kind, value) {
+            (_, Some(Value::Array(items))) => {
+                meta.insert("count".into(), items.len().into());
+                meta.insert("next".into(), next_link(&parts.headers).into());
+                let cursor = parts.headers.get("x-next-cursor").and_then(|value| value.to_str().ok());
+                meta.insert("next_cursor".into(), cursor.into());
+                Value::Array(items)
+            }
+            (Kind::Json, Some(value)) => value,
+            // Malformed JSON is left alone.
+            (Kind::Json, None) => return Response::from_parts(parts, Body::from(body)),
+            (Kind::Text, _) => {
+                if !text.is_empty() {
+                    meta.insert("message".into(), text.into());
+                }
+                Value::Null
+            }
+        };
+        json!({ "data": data, "meta": meta })
+    };
+
+    let mut response = (status, Json(wrapped)).into_response();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(MEDIA_TYPE));
+    parts.headers.append(header::VARY, HeaderValue::from_static("accept"));
+    std::mem::swap(response.headers_mut(), &mut parts.headers);
+    response
+}
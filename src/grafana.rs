@@ -0,0 +1,197 @@
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use crate::categories::Visibility;
+
+// Endpoints for Grafana's JSON datasource plugin (simpod-json-datasource),
+// with this instance's /grafana as the datasource URL. Every service has two
+// metrics, "<name>:uptime" (percent of passing checks) and "<name>:latency"
+// (mean milliseconds), bucketed to the panel's interval.
+
+// Buckets smaller than this would mostly be empty at the default check interval.
+const MIN_INTERVAL_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    Uptime,
+    Latency,
+}
+
+impl Metric {
+    fn parse(target: &str) -> Option<(&str, Self)> {
+        let (service, metric) = target.rsplit_once(':')?;
+        match metric {
+            "uptime" => Some((service, Self::Uptime)),
+            "latency" => Some((service, Self::Latency)),
+            _ => None,
+        }
+    }
+}
+
+// GET /grafana
+// The datasource's "Save & test" only needs a 200.
+pub async fn health() -> StatusCode {
+    StatusCode::OK
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Search {
+    #[serde(default)]
+    target: String,
+}
+
+// POST /grafana/search, and /grafana/metrics for newer plugin versions
+// Metric names of visible services containing `target`.
+pub async fn search(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    Json(search): Json<Search>,
+) -> Result<Json<Vec<String>>, (StatusCode, String)> {
+    let mut sql = QueryBuilder::<Postgres>::new(
+        "SELECT services.name FROM services LEFT JOIN categories ON categories.id = services.category_id \
+         WHERE services.deleted_at IS NULL AND services.archived_at IS NULL",
+    );
+    visibility.restrict_services(&mut sql);
+    sql.push(" ORDER BY lower(services.name)");
+    let names = sql
+        .build_query_scalar::<String>()
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let needle = search.target.to_lowercase();
+    Ok(Json(
+        names
+            .iter()
+            .flat_map(|name| [format!("{}:uptime", name), format!("{}:latency", name)])
+            .filter(|metric| metric.to_lowercase().contains(&needle))
+            .collect(),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryRange {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryTarget {
+    target: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsQuery {
+    range: QueryRange,
+    #[serde(default)]
+    interval_ms: Option<i64>,
+    targets: Vec<QueryTarget>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Series {
+    target: String,
+    // [value, unix milliseconds] pairs, as the plugin expects.
+    datapoints: Vec<(f64, i64)>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct Bucket {
+    bucket: DateTime<Utc>,
+    total: i64,
+    ok: i64,
+    latency_ms: Option<f64>,
+}
+
+// Raw results and rollups never overlap (retention moves one into the next),
+// so their union covers the whole range. Rollups are weighted by their check
+// count so re-bucketing keeps the averages exact.
+const BUCKETS: &str = r#"
+    WITH points AS (
+        SELECT checked_at AS at, 1::bigint AS total, ok::int::bigint AS ok,
+               latency_ms::float8 AS latency_sum, (latency_ms IS NOT NULL)::int::bigint AS latency_n
+        FROM check_results WHERE service_id = $1 AND checked_at >= $2 AND checked_at < $3
+        UNION ALL
+        SELECT bucket, total_checks, ok_checks, avg_latency_ms * total_checks, total_checks
+        FROM check_rollups WHERE service_id = $1 AND bucket >= $2 AND bucket < $3
+    )
+    SELECT to_timestamp(floor(extract(epoch FROM at) / $4) * $4) AS bucket,
+           sum(total)::bigint AS total, sum(ok)::bigint AS ok,
+           sum(latency_sum) / nullif(sum(latency_n), 0) AS latency_ms
+    FROM points
+    GROUP BY 1
+    ORDER BY 1
+"#;
+
+// POST /grafana/query
+// Unknown targets and services the caller can't see come back as empty series.
+pub async fn query(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    Json(query): Json<MetricsQuery>,
+) -> Result<Json<Vec<Series>>, (StatusCode, String)> {
+    if query.range.to <= query.range.from {
+        return Err((StatusCode::BAD_REQUEST, "range.to must be after range.from".into()));
+    }
+    let interval = (query.interval_ms.unwrap_or(0) / 1000).max(MIN_INTERVAL_SECS);
+    let mut series = Vec::with_capacity(query.targets.len());
+    for QueryTarget { target } in query.targets {
+        let datapoints = match Metric::parse(&target) {
+            Some((service, metric)) => datapoints(&pool, &visibility, service, metric, &query.range, interval).await?,
+            None => Vec::new(),
+        };
+        series.push(Series { target, datapoints });
+    }
+    Ok(Json(series))
+}
+
+async fn datapoints(
+    pool: &PgPool,
+    visibility: &Visibility,
+    service: &str,
+    metric: Metric,
+    range: &QueryRange,
+    interval: i64,
+) -> Result<Vec<(f64, i64)>, (StatusCode, String)> {
+    match visibility.check_service(pool, service).await {
+        Ok(()) => {}
+        Err((StatusCode::NOT_FOUND, _)) => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    }
+    let internal = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let Some(id) = sqlx::query_scalar::<_, i32>(&format!(
+        "SELECT id FROM services WHERE id = {}",
+        crate::services::SERVICE_ID_BY_NAME
+    ))
+    .bind(service)
+    .fetch_optional(pool)
+    .await
+    .map_err(internal)?
+    else {
+        return Ok(Vec::new());
+    };
+
+    let buckets = sqlx::query_as::<_, Bucket>(BUCKETS)
+        .bind(id)
+        .bind(range.from)
+        .bind(range.to)
+        .bind(interval as f64)
+        .fetch_all(pool)
+        .await
+        .map_err(internal)?;
+    Ok(buckets
+        .into_iter()
+        .filter_map(|bucket| {
+            let value = match metric {
+                Metric::Uptime if bucket.total > 0 => 100.0 * bucket.ok as f64 / bucket.total as f64,
+                Metric::Uptime => return None,
+                Metric::Latency => bucket.latency_ms?,
+            };
+            Some((value, bucket.bucket.timestamp_millis()))
+        })
+        .collect())
+}
@@ -0,0 +1,287 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{
+    audit::{Actor, Audit},
+    categories::Visibility,
+    config::ChecksConfig,
+    repository::ServiceRepository,
+};
+
+// A service's health as a state machine rather than its latest check:
+// `failures_before_down` failed checks in a row take it down and
+// `successes_before_up` successful ones bring it back, so a single blip
+// changes nothing. When the last `flap_window` results switch between up
+// and down at least `flap_threshold` times it is flapping, until they
+// switch at most half as often. During a maintenance window it is in
+// maintenance: still checked, but not alerted on and without incidents.
+// The state is kept per service in service_health, so it survives restarts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    Up,
+    Down,
+    Degraded,
+    Flapping,
+    Maintenance,
+    Unknown,
+}
+
+impl Health {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::Degraded => "degraded",
+            Self::Flapping => "flapping",
+            Self::Maintenance => "maintenance",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "up" => Some(Self::Up),
+            "down" => Some(Self::Down),
+            "degraded" => Some(Self::Degraded),
+            "flapping" => Some(Self::Flapping),
+            "maintenance" => Some(Self::Maintenance),
+            "unknown" => Some(Self::Unknown),
+            _ => None,
+        }
+    }
+}
+
+// Selects a service's stored health, or "maintenance" during a window even
+// before the next check; used inside service queries on `s`.
+pub const HEALTH_COLUMN: &str = "CASE WHEN s.maintenance_until > now() THEN 'maintenance' \
+     ELSE (SELECT state FROM service_health WHERE service_id = s.id) END AS health";
+
+#[cfg_attr(not(feature = "checks"), allow(dead_code))]
+#[derive(Debug, Clone, Copy)]
+pub struct Rules {
+    failures_before_down: u32,
+    successes_before_up: u32,
+    flap_window: usize,
+    // 0 turns flap detection off.
+    flap_threshold: usize,
+}
+
+#[cfg_attr(not(feature = "checks"), allow(dead_code))]
+impl Rules {
+    pub fn new(config: &ChecksConfig) -> Self {
+        Self {
+            failures_before_down: config.failures_before_down.max(1),
+            successes_before_up: config.successes_before_up.max(1),
+            flap_window: config.flap_window.max(2),
+            flap_threshold: config.flap_threshold,
+        }
+    }
+}
+
+// What service_health holds for a service.
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct Record {
+    state: String,
+    failures: i32,
+    successes: i32,
+    // The last `flap_window` results, oldest first.
+    recent: Vec<bool>,
+}
+
+#[cfg_attr(not(feature = "checks"), allow(dead_code))]
+impl Record {
+    fn unknown() -> Self {
+        Self { state: Health::Unknown.as_str().into(), failures: 0, successes: 0, recent: Vec::new() }
+    }
+
+    // Adds a check result and returns the state it leads to.
+    fn step(&mut self, ok: bool, degraded: bool, maintenance: bool, rules: &Rules) -> Health {
+        if ok {
+            (self.successes, self.failures) = (self.successes.saturating_add(1), 0);
+        } else {
+            (self.failures, self.successes) = (self.failures.saturating_add(1), 0);
+        }
+        self.recent.push(ok);
+        let excess = self.recent.len().saturating_sub(rules.flap_window);
+        self.recent.drain(..excess);
+
+        let current = Health::parse(&self.state).unwrap_or(Health::Unknown);
+        if maintenance {
+            return Health::Maintenance;
+        }
+        let flips = self.recent.windows(2).filter(|pair| pair[0] != pair[1]).count();
+        let flapping = match current {
+            Health::Flapping => flips * 2 > rules.flap_threshold,
+            _ => flips >= rules.flap_threshold,
+        };
+        let up = if degraded { Health::Degraded } else { Health::Up };
+        if rules.flap_threshold > 0 && flapping {
+            Health::Flapping
+        } else if self.failures >= rules.failures_before_down as i32 {
+            Health::Down
+        } else if self.successes >= rules.successes_before_up as i32 {
+            up
+        } else {
+            match current {
+                // Still up despite a failure below the threshold; degraded
+                // follows the latency SLO.
+                Health::Up | Health::Degraded if ok => up,
+                Health::Maintenance => Health::Unknown,
+                state => state,
+            }
+        }
+    }
+}
+
+// A change of state, for notifications and incidents.
+#[cfg_attr(not(feature = "checks"), allow(dead_code))]
+pub struct Transition {
+    pub from: Health,
+    pub to: Health,
+}
+
+// Feeds one check result into the service's state machine; returns the
+// state, and the transition if it changed.
+#[cfg_attr(not(feature = "checks"), allow(dead_code))]
+pub async fn observe(
+    pool: &PgPool,
+    service_id: i32,
+    ok: bool,
+    degraded: bool,
+    maintenance: bool,
+    rules: &Rules,
+) -> sqlx::Result<(Health, Option<Transition>)> {
+    let mut record = sqlx::query_as::<_, Record>(
+        "SELECT state, failures, successes, recent FROM service_health WHERE service_id = $1",
+    )
+    .bind(service_id)
+    .fetch_optional(pool)
+    .await?
+    .unwrap_or_else(Record::unknown);
+    let from = Health::parse(&record.state).unwrap_or(Health::Unknown);
+    let to = record.step(ok, degraded, maintenance, rules);
+    sqlx::query(
+        "INSERT INTO service_health (service_id, state, failures, successes, recent) VALUES ($1, $2, $3, $4, $5) \
+         ON CONFLICT (service_id) DO UPDATE SET state = EXCLUDED.state, failures = EXCLUDED.failures, \
+         successes = EXCLUDED.successes, recent = EXCLUDED.recent, \
+         since = CASE WHEN service_health.state = EXCLUDED.state THEN service_health.since ELSE now() END",
+    )
+    .bind(service_id)
+    .bind(to.as_str())
+    .bind(record.failures)
+    .bind(record.successes)
+    .bind(&record.recent)
+    .execute(pool)
+    .await?;
+    Ok((to, (from != to).then_some(Transition { from, to })))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Maintenance {
+    // None: not in maintenance.
+    until: Option<DateTime<Utc>>,
+}
+
+// POST /services/:name/maintenance
+// Body: {"until": RFC 3339}; replaces any current window.
+pub async fn start_maintenance<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    State(services): State<R>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(name): Path<String>,
+    Json(payload): Json<Maintenance>,
+) -> Result<Json<Maintenance>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let until = payload
+        .until
+        .filter(|until| *until > Utc::now())
+        .ok_or((StatusCode::BAD_REQUEST, "until must be in the future".into()))?;
+    let service = services
+        .set_maintenance(&name, Some(until))
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, "Service not found".into()))?;
+    audit.record(&actor, "service.maintenance", Some(&service), Some(format!("until {}", until.to_rfc3339()))).await;
+    Ok(Json(Maintenance { until: Some(until) }))
+}
+
+// DELETE /services/:name/maintenance
+// Ends the window early; the state is settled again by the next checks.
+pub async fn end_maintenance<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    State(services): State<R>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(name): Path<String>,
+) -> Result<Json<Maintenance>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let service = services
+        .set_maintenance(&name, None)
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, format!("'{}' is not in maintenance", name)))?;
+    audit.record(&actor, "service.maintenance_end", Some(&service), None).await;
+    Ok(Json(Maintenance { until: None }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(failures_before_down: u32, successes_before_up: u32, flap_threshold: usize) -> Rules {
+        Rules { failures_before_down, successes_before_up, flap_window: 6, flap_threshold }
+    }
+
+    // The states a fresh service goes through, storing each as observe does.
+    fn walk(results: &[bool], rules: &Rules) -> Vec<Health> {
+        let mut record = Record::unknown();
+        results
+            .iter()
+            .map(|ok| {
+                let state = record.step(*ok, false, false, rules);
+                record.state = state.as_str().into();
+                state
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_blip_changes_nothing() {
+        use Health::*;
+        let states = walk(&[true, true, false, false, true, false, false, false, true, true], &rules(3, 2, 0));
+        assert_eq!(states, [Unknown, Up, Up, Up, Up, Up, Up, Down, Down, Up]);
+    }
+
+    #[test]
+    fn flapping_stops_at_half_the_flips_it_started_at() {
+        use Health::*;
+        let states = walk(&[true, false, true, false, true, true, true, true], &rules(1, 1, 4));
+        assert_eq!(states, [Up, Down, Up, Down, Flapping, Flapping, Flapping, Up]);
+        // Without the flapping state behind it, 3 flips is not enough.
+        assert_eq!(walk(&[true, false, true, false], &rules(1, 1, 4)), [Up, Down, Up, Down]);
+        assert!(!walk(&[true, false, true, false, true], &rules(1, 1, 0)).contains(&Flapping));
+    }
+
+    #[test]
+    fn maintenance_and_latency_override_up() {
+        let rules = rules(2, 2, 0);
+        let mut record = Record { state: "up".into(), ..Record::unknown() };
+        assert_eq!(record.step(true, true, false, &rules), Health::Degraded);
+        record.state = "degraded".into();
+        assert_eq!(record.step(true, false, false, &rules), Health::Up);
+
+        assert_eq!(record.step(false, false, true, &rules), Health::Maintenance);
+        record.state = "maintenance".into();
+        // What it was before is no longer known.
+        assert_eq!(record.step(false, false, false, &rules), Health::Down);
+        let mut record = Record { state: "maintenance".into(), ..Record::unknown() };
+        assert_eq!(record.step(false, false, false, &rules), Health::Unknown);
+    }
+}
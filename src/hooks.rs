@@ -0,0 +1,166 @@
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::{process::Stdio, sync::Arc, time::Duration};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{audit::Actor, config::HookConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    PreCreate,
+    PostCreate,
+    PreUpdate,
+    PostUpdate,
+    PreDelete,
+    PostDelete,
+}
+
+impl HookEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::PreCreate => "pre_create",
+            Self::PostCreate => "post_create",
+            Self::PreUpdate => "pre_update",
+            Self::PostUpdate => "post_update",
+            Self::PreDelete => "pre_delete",
+            Self::PostDelete => "post_delete",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: HookEvent,
+    actor: &'a str,
+    service: &'a serde_json::Value,
+}
+
+enum Target {
+    // argv; the payload is written to stdin and INDEXPAGE_EVENT is set.
+    Command(Vec<String>),
+    // Receives the payload as a JSON POST.
+    Url(String),
+}
+
+struct Hook {
+    event: HookEvent,
+    target: Target,
+    timeout: Duration,
+}
+
+// User-configured commands or webhooks run around service mutations. Pre
+// hooks run in order and the first failure (non-zero exit, non-2xx response
+// or timeout) rejects the change; post hooks run in the background and their
+// failures are only logged.
+#[derive(Clone)]
+pub struct Hooks {
+    hooks: Arc<Vec<Hook>>,
+    http: reqwest::Client,
+}
+
+impl Hooks {
+    pub fn new(configs: &[HookConfig]) -> anyhow::Result<Self> {
+        let mut hooks = Vec::new();
+        for (index, config) in configs.iter().enumerate() {
+            let target = match (&config.command, &config.url) {
+                (Some(command), None) if !command.is_empty() => Target::Command(command.clone()),
+                (None, Some(url)) => Target::Url(url.clone()),
+                _ => anyhow::bail!("hooks[{}]: set exactly one of `command` or `url`", index),
+            };
+            hooks.push(Hook {
+                event: config.event,
+                target,
+                timeout: Duration::from_secs(config.timeout_secs.max(1)),
+            });
+        }
+        Ok(Self { hooks: Arc::new(hooks), http: reqwest::Client::new() })
+    }
+
+    pub async fn before(
+        &self,
+        event: HookEvent,
+        actor: &Actor,
+        service: &serde_json::Value,
+    ) -> Result<(), (StatusCode, String)> {
+        for hook in self.hooks.iter().filter(|hook| hook.event == event) {
+            if let Err(reason) = self.run(hook, actor, service).await {
+                tracing::info!("{} hook rejected change: {}", event.as_str(), reason);
+                return Err((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    format!("Rejected by {} hook: {}", event.as_str(), reason),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn after(&self, event: HookEvent, actor: &Actor, service: serde_json::Value) {
+        if !self.hooks.iter().any(|hook| hook.event == event) {
+            return;
+        }
+        let (hooks, actor) = (self.clone(), actor.clone());
+        tokio::spawn(async move {
+            for hook in hooks.hooks.iter().filter(|hook| hook.event == event) {
+                if let Err(reason) = hooks.run(hook, &actor, &service).await {
+                    tracing::warn!("{} hook failed: {}", event.as_str(), reason);
+                }
+            }
+        });
+    }
+
+    async fn run(&self, hook: &Hook, actor: &Actor, service: &serde_json::Value) -> Result<(), String> {
+        let payload = Payload { event: hook.event, actor: &actor.name, service };
+        let body = serde_json::to_vec(&payload).map_err(|e| e.to_string())?;
+        let run = async {
+            match &hook.target {
+                Target::Command(argv) => run_command(argv, hook.event, &body).await,
+                Target::Url(url) => {
+                    let response = self
+                        .http
+                        .post(url)
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .body(body.clone())
+                        .send()
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    let status = response.status();
+                    if status.is_success() {
+                        Ok(())
+                    } else {
+                        let text = response.text().await.unwrap_or_default();
+                        Err(format!("HTTP {} {}", status, text.trim()).trim().to_string())
+                    }
+                }
+            }
+        };
+        tokio::time::timeout(hook.timeout, run)
+            .await
+            .map_err(|_| format!("timed out after {}s", hook.timeout.as_secs()))?
+    }
+}
+
+async fn run_command(argv: &[String], event: HookEvent, body: &[u8]) -> Result<(), String> {
+    let mut child = Command::new(&argv[0])
+        .args(&argv[1..])
+        .env("INDEXPAGE_EVENT", event.as_str())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("{}: {}", argv[0], e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that exits without reading its input is not an error.
+        let _ = stdin.write_all(body).await;
+    }
+    let output = child.wait_with_output().await.map_err(|e| e.to_string())?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(match stderr.trim() {
+        "" => format!("{} exited with {}", argv[0], output.status),
+        message => message.to_string(),
+    })
+}
@@ -0,0 +1,346 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::{Redirect, Response},
+    Json,
+};
+use bytes::Bytes;
+use chrono::Utc;
+use http::{HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    caching::{self, Version},
+    categories::Visibility,
+    config::IconsConfig,
+    storage::{ObjectStore, Storage},
+};
+
+// Logos for services from the dashboard-icons collection, which names each
+// app by a lowercase, dash-separated slug ("home-assistant", "adguard-home")
+// in svg/, png/ and webp/. Names are matched against its tree.json and the
+// icons are proxied, so browsers never talk to the CDN and repeat requests
+// are served from memory. URLs handed out carry the icon's fingerprint once
+// it has been fetched, so browsers can keep it for good (see caching.rs).
+const FORMATS: [(&str, &str); 3] = [("svg", "image/svg+xml"), ("png", "image/png"), ("webp", "image/webp")];
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+// Distinct icons kept; the oldest is dropped first.
+const MAX_CACHED: usize = 512;
+// Hosts' first labels that say nothing about the app.
+const GENERIC_HOST_LABELS: [&str; 4] = ["www", "app", "apps", "web"];
+
+struct Index {
+    fetched: Instant,
+    // Slugs available in each of FORMATS, in that order.
+    slugs: [HashSet<String>; 3],
+}
+
+#[derive(Clone)]
+pub struct Icons {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    enabled: bool,
+    base_url: String,
+    ttl: Duration,
+    http: reqwest::Client,
+    storage: Storage,
+    index: tokio::sync::Mutex<Option<Index>>,
+    cache: Mutex<HashMap<String, Cached>>,
+}
+
+struct Cached {
+    fetched: Instant,
+    body: Bytes,
+    fingerprint: String,
+}
+
+impl Icons {
+    pub fn new(config: &IconsConfig, storage: Storage) -> anyhow::Result<Self> {
+        let http = reqwest::Client::builder().timeout(FETCH_TIMEOUT).build()?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                enabled: config.enabled,
+                base_url: config.base_url.trim_end_matches('/').to_string(),
+                ttl: Duration::from_secs(config.cache_ttl_secs),
+                http,
+                storage,
+                index: tokio::sync::Mutex::new(None),
+                cache: Mutex::default(),
+            }),
+        })
+    }
+
+    fn check_enabled(&self) -> Result<(), (StatusCode, String)> {
+        match self.inner.enabled {
+            true => Ok(()),
+            false => Err((StatusCode::NOT_FOUND, "Icons are disabled".into())),
+        }
+    }
+
+    // The first candidate the collection has, as (slug, extension).
+    async fn resolve(&self, candidates: Vec<String>) -> Result<Option<(String, &'static str)>, (StatusCode, String)> {
+        Ok(self.resolve_all(&[candidates]).await?.pop().flatten())
+    }
+
+    // As `resolve`, for many services against one copy of the list. Refetches
+    // tree.json once it is older than the TTL; if that fails, the previous
+    // copy is used.
+    async fn resolve_all(
+        &self,
+        services: &[Vec<String>],
+    ) -> Result<Vec<Option<(String, &'static str)>>, (StatusCode, String)> {
+        let mut index = self.inner.index.lock().await;
+        if index.as_ref().is_none_or(|index| index.fetched.elapsed() > self.inner.ttl) {
+            match self.fetch_index().await {
+                Ok(fetched) => *index = Some(fetched),
+                Err(e) if index.is_some() => tracing::warn!("refreshing the dashboard-icons list failed: {}", e),
+                Err(e) => return Err((StatusCode::BAD_GATEWAY, format!("Fetching the icon list failed: {}", e))),
+            }
+        }
+        let Some(index) = index.as_ref() else { return Ok(vec![None; services.len()]) };
+        let matching = |candidates: &Vec<String>| {
+            candidates.iter().find_map(|slug| {
+                let at = index.slugs.iter().position(|slugs| slugs.contains(slug))?;
+                Some((slug.clone(), FORMATS[at].0))
+            })
+        };
+        Ok(services.iter().map(matching).collect())
+    }
+
+    // /icons URLs for (name, link, icon) services; all None when icons are
+    // disabled or the list can't be fetched.
+    pub async fn urls(&self, services: &[(&str, &str, Option<&str>)]) -> Vec<Option<String>> {
+        let candidates: Vec<Vec<String>> =
+            services.iter().map(|(name, link, icon)| candidates(*icon, name, Some(link))).collect();
+        let resolved = match self.inner.enabled {
+            true => self.resolve_all(&candidates).await.unwrap_or_else(|(_, e)| {
+                tracing::debug!("{}", e);
+                vec![None; services.len()]
+            }),
+            false => vec![None; services.len()],
+        };
+        resolved.into_iter().map(|icon| icon.map(|(slug, format)| self.url(&slug, format))).collect()
+    }
+
+    // /icons/{slug}.{format}, fingerprinted if the icon is in memory.
+    fn url(&self, slug: &str, format: &str) -> String {
+        let file = format!("{}.{}", slug, format);
+        let path = format!("/icons/{}", file);
+        match self.inner.cache.lock().unwrap().get(&file) {
+            Some(cached) => caching::versioned(&path, &cached.fingerprint),
+            None => path,
+        }
+    }
+
+    async fn fetch_index(&self) -> reqwest::Result<Index> {
+        let url = format!("{}/tree.json", self.inner.base_url);
+        let tree: HashMap<String, Vec<String>> =
+            self.inner.http.get(url).send().await?.error_for_status()?.json().await?;
+        let slugs = FORMATS.map(|(extension, _)| {
+            let suffix = format!(".{}", extension);
+            let files = tree.get(extension).map(Vec::as_slice).unwrap_or_default();
+            files.iter().filter_map(|file| file.strip_suffix(&suffix)).map(str::to_string).collect()
+        });
+        Ok(Index { fetched: Instant::now(), slugs })
+    }
+
+    // The icon and its fingerprint. Behind the memory cache is `[storage]`,
+    // which keeps icons across restarts and serves them while the mirror
+    // can't be reached.
+    async fn fetch(
+        &self,
+        file: &str,
+        extension: &str,
+        content_type: &str,
+    ) -> Result<(Bytes, String), (StatusCode, String)> {
+        if let Some(cached) = self.inner.cache.lock().unwrap().get(file)
+            && cached.fetched.elapsed() <= self.inner.ttl
+        {
+            return Ok((cached.body.clone(), cached.fingerprint.clone()));
+        }
+        let key = format!("icons/{}", file);
+        let stored = match self.inner.storage.get(&key).await {
+            Ok(stored) => stored,
+            Err(e) => {
+                tracing::warn!("reading {} from storage: {}", key, e);
+                None
+            }
+        };
+        if let Some(stored) = &stored {
+            let age = (Utc::now() - stored.modified).to_std().unwrap_or_default();
+            if age <= self.inner.ttl {
+                let fetched = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+                return Ok(self.remember(file, stored.body.clone(), fetched));
+            }
+        }
+        match self.download(file, extension).await {
+            Ok(body) => {
+                let storage = self.inner.storage.clone();
+                let (body_to_store, content_type) = (body.clone(), content_type.to_string());
+                tokio::spawn(async move {
+                    if let Err(e) = storage.put(&key, body_to_store, &content_type).await {
+                        tracing::warn!("writing {} to storage: {}", key, e);
+                    }
+                });
+                Ok(self.remember(file, body, Instant::now()))
+            }
+            Err((status, e)) => match stored {
+                Some(stored) if status != StatusCode::NOT_FOUND => {
+                    tracing::warn!("{}; serving the stored copy of {}", e, file);
+                    Ok(self.remember(file, stored.body, Instant::now()))
+                }
+                _ => Err((status, e)),
+            },
+        }
+    }
+
+    async fn download(&self, file: &str, extension: &str) -> Result<Bytes, (StatusCode, String)> {
+        let url = format!("{}/{}/{}", self.inner.base_url, extension, file);
+        let response = self
+            .inner
+            .http
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Fetching the icon failed: {}", e)))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err((StatusCode::NOT_FOUND, format!("No icon named '{}'", file)));
+        }
+        let body = response
+            .error_for_status()
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Fetching the icon failed: {}", e)))?
+            .bytes()
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Fetching the icon failed: {}", e)))?;
+        Ok(body)
+    }
+
+    // Keeps the icon in memory; returns it and its fingerprint.
+    fn remember(&self, file: &str, body: Bytes, fetched: Instant) -> (Bytes, String) {
+        let mut cache = self.inner.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED && !cache.contains_key(file) {
+            let oldest = cache.iter().min_by_key(|(_, cached)| cached.fetched).map(|(file, _)| file.clone());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+        let fingerprint = caching::fingerprint(&body);
+        let cached = Cached { fetched, body: body.clone(), fingerprint: fingerprint.clone() };
+        cache.insert(file.to_string(), cached);
+        (body, fingerprint)
+    }
+}
+
+// Lowercase words of `text`, split on anything but letters and digits.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect()
+}
+
+// Slugs to try for a service, best first: its `icon` column, if set (e.g.
+// by an import from another dashboard), its name with fewer and fewer
+// trailing words ("Grafana prod" -> grafana-prod, grafanaprod, grafana),
+// then the first label of the link's host.
+fn candidates(icon: Option<&str>, name: &str, link: Option<&str>) -> Vec<String> {
+    let mut candidates = Vec::new();
+    let mut push = |slug: String| {
+        if !slug.is_empty() && !candidates.contains(&slug) {
+            candidates.push(slug);
+        }
+    };
+    if let Some(icon) = icon {
+        push(icon.to_string());
+    }
+    let name = words(name);
+    for len in (1..=name.len()).rev() {
+        push(name[..len].join("-"));
+        push(name[..len].concat());
+    }
+    let host = link.and_then(|link| url::Url::parse(link).ok()).and_then(|url| url.host_str().map(str::to_string));
+    if let Some(host) = host {
+        let label = host.split('.').find(|label| !GENERIC_HOST_LABELS.contains(label)).unwrap_or_default();
+        push(words(label).join("-"));
+    }
+    candidates
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SuggestQuery {
+    name: String,
+    #[serde(default)]
+    link: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Suggestion {
+    slug: String,
+    format: &'static str,
+    // Served by GET /icons/{file}.
+    url: String,
+}
+
+// GET /icons/suggest?name=...&link=...
+pub async fn suggest(
+    State(icons): State<Icons>,
+    Query(query): Query<SuggestQuery>,
+) -> Result<Json<Suggestion>, (StatusCode, String)> {
+    icons.check_enabled()?;
+    let (slug, format) = icons
+        .resolve(candidates(None, &query.name, query.link.as_deref()))
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, format!("No icon matches '{}'", query.name)))?;
+    let url = icons.url(&slug, format);
+    Ok(Json(Suggestion { slug, format, url }))
+}
+
+// GET /icons/:file, e.g. /icons/grafana.svg or /icons/grafana.svg?v=...
+pub async fn icon(
+    State(icons): State<Icons>,
+    Path(file): Path<String>,
+    Query(version): Query<Version>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    icons.check_enabled()?;
+    let not_found = || (StatusCode::NOT_FOUND, format!("No icon named '{}'", file));
+    let (slug, extension) = file.rsplit_once('.').ok_or_else(not_found)?;
+    let &(extension, content_type) = FORMATS.iter().find(|(known, _)| *known == extension).ok_or_else(not_found)?;
+    if slug.is_empty() || !slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        return Err(not_found());
+    }
+    let (body, fingerprint) = icons.fetch(&file, extension, content_type).await?;
+    Ok(caching::serve(&headers, &version, icons.inner.ttl, content_type, &fingerprint, body))
+}
+
+// GET /services/:name/icon
+// Redirects to the matching icon.
+pub async fn service_icon(
+    State(pool): State<PgPool>,
+    State(icons): State<Icons>,
+    visibility: Visibility,
+    Path(name): Path<String>,
+) -> Result<Redirect, (StatusCode, String)> {
+    icons.check_enabled()?;
+    visibility.check_service(&pool, &name).await?;
+    let service = sqlx::query!(
+        "SELECT name, link, icon FROM services WHERE lower(name) = lower($1) AND deleted_at IS NULL",
+        name,
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Service not found".into()))?;
+    let (slug, format) = icons
+        .resolve(candidates(service.icon.as_deref(), &service.name, Some(&service.link)))
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, format!("No icon matches '{}'", service.name)))?;
+    Ok(Redirect::temporary(&icons.url(&slug, format)))
+}
@@ -0,0 +1,196 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::{header, HeaderValue, Method, StatusCode};
+use sqlx::PgPool;
+use std::time::Duration;
+
+use crate::{
+    audit::Actor,
+    scheduler::{Schedule, Scheduler},
+};
+
+// Idempotency-Key for POST /services and the /services/import/* endpoints,
+// so flaky clients and provisioning scripts can retry without creating
+// duplicates. The first request with a key is carried out and its response
+// stored; a retry with the same key and request gets that response again,
+// marked Idempotency-Replayed. The same key with a different request is
+// refused with 422, and one arriving while the first is still running with
+// 409. Keys are per user and kept for a day. Server errors aren't stored, so
+// a retry after one is carried out anew.
+const KEY_HEADER: &str = "idempotency-key";
+pub const REPLAYED_HEADER: &str = "idempotency-replayed";
+const MAX_KEY_CHARS: usize = 255;
+const TTL_HOURS: i32 = 24;
+// A request that never finished (timed out, client gone) frees its key
+// after this.
+const ABANDONED_MINS: i32 = 5;
+// Larger responses are passed on but not kept.
+const MAX_STORED_BYTES: usize = 1024 * 1024;
+const PRUNE_INTERVAL_SECS: u64 = 3600;
+
+fn covered(method: &Method, path: &str) -> bool {
+    method == Method::POST && (path == "/services" || path.starts_with("/services/import/"))
+}
+
+fn internal(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+// Tells a retry from a different request under the same key.
+fn fingerprint(parts: &http::request::Parts, body: &[u8]) -> String {
+    let mut hash = openssl::sha::Sha256::new();
+    let path = parts.uri.path_and_query().map_or_else(|| parts.uri.path(), |path| path.as_str());
+    let content_type = parts.headers.get(header::CONTENT_TYPE).map_or(&[][..], HeaderValue::as_bytes);
+    for part in [parts.method.as_str().as_bytes(), path.as_bytes(), content_type] {
+        hash.update(part);
+        hash.update(b"\0");
+    }
+    hash.update(body);
+    hash.finish().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct Stored {
+    fingerprint: String,
+    // None while the first request is running.
+    status: Option<i16>,
+    content_type: Option<String>,
+    body: Option<Vec<u8>>,
+}
+
+fn replay(stored: Stored) -> Response {
+    let status = u16::try_from(stored.status.unwrap_or_default())
+        .ok()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .unwrap_or(StatusCode::OK);
+    let mut response = (status, stored.body.unwrap_or_default()).into_response();
+    let headers = response.headers_mut();
+    headers.remove(header::CONTENT_TYPE);
+    if let Some(content_type) = stored.content_type.and_then(|value| HeaderValue::from_str(&value).ok()) {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    headers.insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+// Inside auth::enforce, so keys belong to the caller, and outside the body
+// limit, which it honors when reading the body.
+pub async fn enforce(
+    State(pool): State<PgPool>,
+    actor: Actor,
+    request: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(key) = request.headers().get(KEY_HEADER).filter(|_| covered(request.method(), request.uri().path()))
+    else {
+        return Ok(next.run(request).await);
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_CHARS)
+        .ok_or((StatusCode::BAD_REQUEST, format!("Idempotency-Key must be 1 to {} characters", MAX_KEY_CHARS)))?
+        .to_string();
+    let (parts, body) = request.into_parts();
+    let body = Bytes::from_request(Request::from_parts(parts.clone(), body), &())
+        .await
+        .map_err(|e| (e.status(), e.body_text()))?;
+    let fingerprint = fingerprint(&parts, &body);
+
+    let claimed = sqlx::query(
+        "INSERT INTO idempotency_keys (principal, key, fingerprint) VALUES ($1, $2, $3) \
+         ON CONFLICT (principal, key) DO UPDATE SET fingerprint = EXCLUDED.fingerprint, status = NULL, \
+         content_type = NULL, body = NULL, created_at = now() \
+         WHERE idempotency_keys.created_at < now() - make_interval(hours => $4) \
+         OR (idempotency_keys.status IS NULL AND idempotency_keys.created_at < now() - make_interval(mins => $5))",
+    )
+    .bind(&actor.name)
+    .bind(&key)
+    .bind(&fingerprint)
+    .bind(TTL_HOURS)
+    .bind(ABANDONED_MINS)
+    .execute(&pool)
+    .await
+    .map_err(internal)?
+    .rows_affected();
+    if claimed == 0 {
+        let stored = sqlx::query_as::<_, Stored>(
+            "SELECT fingerprint, status, content_type, body FROM idempotency_keys WHERE principal = $1 AND key = $2",
+        )
+        .bind(&actor.name)
+        .bind(&key)
+        .fetch_optional(&pool)
+        .await
+        .map_err(internal)?;
+        return match stored {
+            Some(stored) if stored.fingerprint != fingerprint => Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "This Idempotency-Key was already used for a different request".into(),
+            )),
+            Some(stored) if stored.status.is_some() => Ok(replay(stored)),
+            // Running, or just failed and given up; either way try again later.
+            _ => Err((StatusCode::CONFLICT, "A request with this Idempotency-Key is in progress".into())),
+        };
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    let (parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            release(&pool, &actor.name, &key).await;
+            return Ok((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response());
+        }
+    };
+    if parts.status.is_server_error() || body.len() > MAX_STORED_BYTES {
+        release(&pool, &actor.name, &key).await;
+    } else {
+        let content_type = parts.headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+        let stored = sqlx::query(
+            "UPDATE idempotency_keys SET status = $3, content_type = $4, body = $5 WHERE principal = $1 AND key = $2",
+        )
+        .bind(&actor.name)
+        .bind(&key)
+        .bind(parts.status.as_u16() as i16)
+        .bind(content_type)
+        .bind(body.as_ref())
+        .execute(&pool)
+        .await;
+        if let Err(e) = stored {
+            tracing::warn!("storing the response for Idempotency-Key '{}': {}", key, e);
+        }
+    }
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+// Frees a key whose response isn't kept.
+async fn release(pool: &PgPool, principal: &str, key: &str) {
+    let released = sqlx::query("DELETE FROM idempotency_keys WHERE principal = $1 AND key = $2")
+        .bind(principal)
+        .bind(key)
+        .execute(pool)
+        .await;
+    if let Err(e) = released {
+        tracing::warn!("releasing Idempotency-Key '{}': {}", key, e);
+    }
+}
+
+// Deletes keys past their day.
+pub fn register(scheduler: &Scheduler, pool: PgPool) -> anyhow::Result<()> {
+    let interval = Schedule::Every(Duration::from_secs(PRUNE_INTERVAL_SECS));
+    scheduler.register("idempotency", interval, move || {
+        let pool = pool.clone();
+        async move {
+            sqlx::query("DELETE FROM idempotency_keys WHERE created_at < now() - make_interval(hours => $1)")
+                .bind(TTL_HOURS)
+                .execute(&pool)
+                .await?;
+            Ok(())
+        }
+    })
+}
@@ -0,0 +1,224 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use std::collections::HashMap;
+
+use crate::{
+    audit::{Actor, Audit},
+    categories::Visibility,
+    health::Health,
+};
+
+// Outages as records: the checker opens an incident when a service goes
+// down (see health.rs) and closes it once it is up again, so each outage is
+// one incident however long it lasts or whether the process restarted in
+// between. People can add notes (cause, fix, follow-ups).
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+const MAX_NOTE_CHARS: usize = 4000;
+
+// Called with every recorded check result and the health it led to.
+// Flapping and maintenance leave an ongoing incident as it is.
+#[cfg_attr(not(feature = "checks"), allow(dead_code))]
+pub async fn track(pool: &PgPool, service_id: i32, health: Health, ok: bool, error: Option<&str>) -> sqlx::Result<()> {
+    if matches!(health, Health::Up | Health::Degraded) {
+        sqlx::query!("UPDATE incidents SET resolved_at = now() WHERE service_id = $1 AND resolved_at IS NULL", service_id)
+            .execute(pool)
+            .await?;
+    } else if health == Health::Down && !ok {
+        sqlx::query!(
+            "INSERT INTO incidents (service_id, cause) VALUES ($1, $2) \
+             ON CONFLICT (service_id) WHERE resolved_at IS NULL \
+             DO UPDATE SET failed_checks = incidents.failed_checks + 1",
+            service_id,
+            error,
+        )
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Incident {
+    id: i32,
+    service: String,
+    started_at: DateTime<Utc>,
+    // None while ongoing.
+    resolved_at: Option<DateTime<Utc>>,
+    // Until now for ongoing incidents.
+    duration_secs: i64,
+    // The error of the first failed check.
+    cause: Option<String>,
+    failed_checks: i32,
+    #[sqlx(skip)]
+    notes: Vec<Note>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Note {
+    id: i32,
+    author: String,
+    at: DateTime<Utc>,
+    text: String,
+}
+
+#[derive(Debug, Default, Serialize, sqlx::FromRow)]
+pub struct Stats {
+    count: i64,
+    open: i64,
+    total_downtime_secs: i64,
+    // Over resolved incidents only.
+    mean_time_to_recovery_secs: Option<i64>,
+    longest_secs: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IncidentList {
+    // Of every matching incident, not just the ones listed.
+    stats: Stats,
+    incidents: Vec<Incident>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IncidentFilter {
+    service: Option<String>,
+    // true for ongoing incidents only, false for resolved ones.
+    open: Option<bool>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+}
+
+const DURATION: &str = "extract(epoch FROM coalesce(incidents.resolved_at, now()) - incidents.started_at)::bigint";
+
+fn filtered<'a>(select: &str, filter: &'a IncidentFilter, visibility: &Visibility) -> QueryBuilder<'a, Postgres> {
+    let mut sql = QueryBuilder::new(format!(
+        "SELECT {} FROM incidents JOIN services ON services.id = incidents.service_id \
+         LEFT JOIN categories ON categories.id = services.category_id WHERE services.deleted_at IS NULL",
+        select
+    ));
+    if let Some(service) = &filter.service {
+        sql.push(" AND lower(services.name) = lower(").push_bind(service).push(")");
+    }
+    match filter.open {
+        Some(true) => sql.push(" AND incidents.resolved_at IS NULL"),
+        Some(false) => sql.push(" AND incidents.resolved_at IS NOT NULL"),
+        None => &mut sql,
+    };
+    // Incidents overlapping the range.
+    if let Some(since) = filter.since {
+        sql.push(" AND coalesce(incidents.resolved_at, now()) >= ").push_bind(since);
+    }
+    if let Some(until) = filter.until {
+        sql.push(" AND incidents.started_at < ").push_bind(until);
+    }
+    visibility.restrict_services(&mut sql);
+    sql
+}
+
+// GET /incidents
+// Newest first, with notes. Filters: ?service=&open=&since=&until= (RFC
+// 3339) and ?limit= (default 100, at most 1000).
+pub async fn get_incidents(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    Query(filter): Query<IncidentFilter>,
+) -> Result<Json<IncidentList>, (StatusCode, String)> {
+    let limit = filter.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err((StatusCode::BAD_REQUEST, format!("limit must be between 1 and {}", MAX_LIMIT)));
+    }
+    let internal = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+    let stats = format!(
+        "count(*) AS count, count(*) FILTER (WHERE incidents.resolved_at IS NULL) AS open, \
+         coalesce(sum({0}), 0)::bigint AS total_downtime_secs, \
+         (avg({0}) FILTER (WHERE incidents.resolved_at IS NOT NULL))::bigint AS mean_time_to_recovery_secs, \
+         max({0}) AS longest_secs",
+        DURATION
+    );
+    let stats = filtered(&stats, &filter, &visibility)
+        .build_query_as::<Stats>()
+        .fetch_one(&pool)
+        .await
+        .map_err(internal)?;
+
+    let columns = format!(
+        "incidents.id, services.name AS service, incidents.started_at, incidents.resolved_at, \
+         {} AS duration_secs, incidents.cause, incidents.failed_checks",
+        DURATION
+    );
+    let mut sql = filtered(&columns, &filter, &visibility);
+    sql.push(" ORDER BY incidents.started_at DESC, incidents.id DESC LIMIT ").push_bind(limit);
+    let mut incidents = sql.build_query_as::<Incident>().fetch_all(&pool).await.map_err(internal)?;
+
+    let ids: Vec<i32> = incidents.iter().map(|incident| incident.id).collect();
+    let notes = sqlx::query!(
+        "SELECT id, incident_id, author, at, text FROM incident_notes WHERE incident_id = ANY($1) ORDER BY at, id",
+        &ids,
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(internal)?;
+    let mut by_incident: HashMap<i32, Vec<Note>> = HashMap::new();
+    for note in notes {
+        let (id, author, at, text) = (note.id, note.author, note.at, note.text);
+        by_incident.entry(note.incident_id).or_default().push(Note { id, author, at, text });
+    }
+    for incident in &mut incidents {
+        incident.notes = by_incident.remove(&incident.id).unwrap_or_default();
+    }
+    Ok(Json(IncidentList { stats, incidents }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddNote {
+    text: String,
+}
+
+// POST /incidents/:id/notes
+pub async fn add_note(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(id): Path<i32>,
+    Json(payload): Json<AddNote>,
+) -> Result<Json<Note>, (StatusCode, String)> {
+    let text = payload.text.trim();
+    if text.is_empty() || text.chars().count() > MAX_NOTE_CHARS {
+        return Err((StatusCode::BAD_REQUEST, format!("text must be 1 to {} characters", MAX_NOTE_CHARS)));
+    }
+    let internal = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let service = sqlx::query_scalar!(
+        "SELECT s.name FROM incidents i JOIN services s ON s.id = i.service_id WHERE i.id = $1 AND s.deleted_at IS NULL",
+        id,
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(internal)?
+    .ok_or((StatusCode::NOT_FOUND, "Incident not found".into()))?;
+    visibility.check_service(&pool, &service).await.map_err(|(status, e)| match status {
+        StatusCode::NOT_FOUND => (status, "Incident not found".into()),
+        _ => (status, e),
+    })?;
+
+    let note = sqlx::query_as!(
+        Note,
+        "INSERT INTO incident_notes (incident_id, author, text) VALUES ($1, $2, $3) RETURNING id, author, at, text",
+        id,
+        actor.name,
+        text,
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(internal)?;
+    audit.record(&actor, "incident.note", Some(&service), Some(format!("incident {}", id))).await;
+    Ok(Json(note))
+}
@@ -0,0 +1,154 @@
+use axum::extract::{ConnectInfo, FromRef, FromRequestParts, Query};
+use http::{header, request::Parts, HeaderMap, StatusCode};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use crate::{access::Access, config::LinksConfig};
+
+#[derive(Clone)]
+pub struct Links {
+    config: Arc<LinksConfig>,
+    tls: bool,
+}
+
+impl Links {
+    pub fn new(config: &LinksConfig, tls: bool) -> Self {
+        Self { config: Arc::new(config.clone()), tls }
+    }
+
+    // Expansion used by health checks.
+    pub fn for_checks(&self) -> LinkContext {
+        LinkContext {
+            variables: self.config.variables.clone(),
+            host: self.config.check_host.clone(),
+            scheme: self.config.check_scheme.clone(),
+            internal: false,
+        }
+    }
+
+    fn is_internal(&self, client: IpAddr) -> bool {
+        self.config.internal_networks.iter().any(|net| net.contains(&client))
+    }
+
+    fn for_request(&self, headers: &HeaderMap, forwarded: bool, internal: bool) -> LinkContext {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .map(|value| value.trim().to_string())
+        };
+        let host = forwarded
+            .then(|| header("x-forwarded-host"))
+            .flatten()
+            .or_else(|| header(header::HOST.as_str()))
+            .map(|host| strip_port(&host).to_string())
+            .unwrap_or_else(|| self.config.check_host.clone());
+        let scheme = forwarded
+            .then(|| header("x-forwarded-proto"))
+            .flatten()
+            .unwrap_or_else(|| if self.tls { "https".into() } else { "http".into() });
+        LinkContext { variables: self.config.variables.clone(), host, scheme, internal }
+    }
+}
+
+fn strip_port(host: &str) -> &str {
+    if let Some(end) = host.strip_prefix('[').and_then(|_| host.find(']')) {
+        return &host[..=end];
+    }
+    host.rsplit_once(':').map_or(host, |(name, _)| name)
+}
+
+// Values for link placeholders. As an extractor it describes the current
+// request: {host} is the Host the client used (X-Forwarded-Host and
+// X-Forwarded-Proto are honoured from trusted proxies) and `internal` says
+// whether the client sits in `internal_networks` or asked for ?view=internal.
+#[derive(Clone)]
+pub struct LinkContext {
+    variables: HashMap<String, String>,
+    host: String,
+    scheme: String,
+    internal: bool,
+}
+
+impl LinkContext {
+    // The link to show this client: the internal one when it has access to it.
+    pub fn choose(&self, link: &str, internal_link: Option<&str>) -> String {
+        match internal_link {
+            Some(internal_link) if self.internal => self.expand(internal_link),
+            _ => self.expand(link),
+        }
+    }
+
+    // Replaces {name} placeholders; unknown names are left as they are.
+    pub fn expand(&self, link: &str) -> String {
+        if !link.contains('{') {
+            return link.to_string();
+        }
+        let mut out = String::with_capacity(link.len());
+        let mut rest = link;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let Some(end) = after.find('}') else {
+                out.push_str(&rest[start..]);
+                return out;
+            };
+            let name = &after[..end];
+            match self.value(name) {
+                Some(value) => out.push_str(value),
+                None => out.push_str(&rest[start..start + end + 2]),
+            }
+            rest = &after[end + 1..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        match name {
+            "host" => Some(&self.host),
+            "scheme" => Some(&self.scheme),
+            _ => self.variables.get(name).map(String::as_str),
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for LinkContext
+where
+    S: Send + Sync,
+    Links: FromRef<S>,
+    Access: FromRef<S>,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let (links, access) = (Links::from_ref(state), Access::from_ref(state));
+        let peer = parts.extensions.get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(peer)| peer.ip());
+        let forwarded = peer.is_some_and(|peer| access.is_trusted_proxy(peer));
+
+        let Query(query) = Query::<ViewQuery>::try_from_uri(&parts.uri)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.body_text()))?;
+        let internal = match query.view.as_deref() {
+            Some("internal") => true,
+            Some("external") => false,
+            Some(other) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Unknown view '{}' (expected internal or external)", other),
+                ))
+            }
+            None => peer.is_some_and(|peer| links.is_internal(access.client_ip(peer, &parts.headers))),
+        };
+        Ok(links.for_request(&parts.headers, forwarded, internal))
+    }
+}
+
+#[derive(Deserialize)]
+struct ViewQuery {
+    view: Option<String>,
+}
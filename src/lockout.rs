@@ -0,0 +1,186 @@
+use axum::response::{IntoResponse, Response};
+use http::{header, StatusCode};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    audit::{Actor, Audit},
+    config::LockoutConfig,
+};
+
+// Entries kept before forgotten ones are swept out.
+const SWEEP_AT: usize = 10_000;
+
+#[derive(Debug)]
+struct Entry {
+    failures: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+// Failed sign-in and authentication attempts per client IP and per account.
+// After `max_attempts` failures a key is locked out, for twice as long on
+// every further failure up to `max_lockout_secs`; a quiet `forget_after_secs`
+// or a successful sign-in starts it over. Password reset requests are
+// counted the same way under keys of their own (see users.rs). Kept in
+// memory, so a restart clears it.
+#[derive(Clone)]
+pub struct Lockout {
+    config: Arc<LockoutConfig>,
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+pub fn ip_key(ip: Option<&str>) -> String {
+    format!("ip:{}", ip.unwrap_or("unknown"))
+}
+
+pub fn account_key(name: &str) -> String {
+    format!("account:{}", name.to_lowercase())
+}
+
+// Kept apart from the sign-in keys, so asking for resets doesn't lock
+// anyone out of signing in.
+pub fn reset_key(key: &str) -> String {
+    format!("reset:{}", key)
+}
+
+impl Lockout {
+    pub fn new(config: &LockoutConfig) -> Self {
+        Self { config: Arc::new(config.clone()), entries: Arc::default() }
+    }
+
+    // How long until the first of `keys` that is locked may try again.
+    pub fn locked(&self, keys: &[&str]) -> Option<Duration> {
+        if !self.config.enabled {
+            return None;
+        }
+        let now = Instant::now();
+        let entries = self.entries.lock().unwrap();
+        keys.iter()
+            .filter_map(|key| entries.get(*key)?.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+            .max()
+    }
+
+    // Counts a failure against each key. Returns the keys that this failure
+    // locked, with the lockout length, for auditing.
+    pub fn fail(&self, keys: &[&str]) -> Vec<(String, Duration)> {
+        if !self.config.enabled {
+            return Vec::new();
+        }
+        let now = Instant::now();
+        let forget = Duration::from_secs(self.config.forget_after_secs);
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() > SWEEP_AT {
+            entries.retain(|_, entry| now - entry.last_failure < forget);
+        }
+        let mut locked = Vec::new();
+        for key in keys {
+            let entry = entries.entry(key.to_string()).or_insert(Entry {
+                failures: 0,
+                last_failure: now,
+                locked_until: None,
+            });
+            if now - entry.last_failure >= forget {
+                entry.failures = 0;
+            }
+            entry.failures += 1;
+            entry.last_failure = now;
+            if let Some(over) = entry.failures.checked_sub(self.config.max_attempts.max(1)) {
+                let secs = self
+                    .config
+                    .base_lockout_secs
+                    .saturating_mul(1u64 << over.min(20))
+                    .min(self.config.max_lockout_secs);
+                let duration = Duration::from_secs(secs);
+                entry.locked_until = Some(now + duration);
+                locked.push((key.to_string(), duration));
+            }
+        }
+        locked
+    }
+
+    // `fail` plus an `auth.failed` audit entry, and an `auth.lockout` entry
+    // for each key that just got locked.
+    pub async fn record_failure(&self, audit: &Audit, actor: &Actor, keys: &[&str], reason: &str) {
+        audit.record(actor, "auth.failed", None, Some(reason.to_string())).await;
+        for (key, duration) in self.fail(keys) {
+            tracing::warn!("locking out {} for {}s after repeated failures", key, duration.as_secs());
+            let detail = format!("{} for {}s", key, duration.as_secs());
+            audit.record(actor, "auth.lockout", None, Some(detail)).await;
+        }
+    }
+
+    pub fn succeed(&self, keys: &[&str]) {
+        let mut entries = self.entries.lock().unwrap();
+        for key in keys {
+            entries.remove(*key);
+        }
+    }
+}
+
+pub fn too_many(wait: Duration) -> Response {
+    let secs = wait.as_secs().max(1);
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, secs.to_string())],
+        format!("Too many failed attempts; try again in {}s", secs),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lockout(max_attempts: u32, forget_after_secs: u64) -> Lockout {
+        Lockout::new(&LockoutConfig {
+            enabled: true,
+            max_attempts,
+            base_lockout_secs: 10,
+            max_lockout_secs: 25,
+            forget_after_secs,
+        })
+    }
+
+    #[test]
+    fn locks_after_max_attempts_for_longer_each_time() {
+        let lockout = lockout(2, 3600);
+        assert!(lockout.fail(&["ip:a"]).is_empty());
+        assert!(lockout.locked(&["ip:a"]).is_none());
+        let lengths: Vec<u64> = (0..3).map(|_| lockout.fail(&["ip:a"])[0].1.as_secs()).collect();
+        assert_eq!(lengths, [10, 20, 25]);
+        let wait = lockout.locked(&["account:b", "ip:a"]).unwrap();
+        assert!(wait > Duration::from_secs(24) && wait <= Duration::from_secs(25));
+        assert!(lockout.locked(&["account:b"]).is_none());
+
+        lockout.succeed(&["ip:a"]);
+        assert!(lockout.locked(&["ip:a"]).is_none());
+        assert!(lockout.fail(&["ip:a"]).is_empty());
+    }
+
+    #[test]
+    fn forgets_quiet_keys_and_can_be_turned_off() {
+        let forgetful = lockout(2, 0);
+        for _ in 0..5 {
+            assert!(forgetful.fail(&["ip:a"]).is_empty());
+        }
+        let disabled = Lockout::new(&LockoutConfig { enabled: false, max_attempts: 1, ..LockoutConfig::default() });
+        assert!(disabled.fail(&["ip:a"]).is_empty());
+        assert!(disabled.locked(&["ip:a"]).is_none());
+    }
+
+    #[test]
+    fn keys_are_kept_apart() {
+        assert_eq!(account_key("Bob"), account_key("bob"));
+        assert_eq!(ip_key(None), "ip:unknown");
+        let lockout = lockout(1, 3600);
+        lockout.fail(&[&reset_key(&ip_key(Some("10.0.0.1")))]);
+        assert!(lockout.locked(&[&ip_key(Some("10.0.0.1"))]).is_none());
+        assert!(lockout.locked(&[&reset_key(&ip_key(Some("10.0.0.1")))]).is_some());
+    }
+}
@@ -1,119 +1,237 @@
-use axum::{extract::{Path, State}, routing::{get, delete}, Json, Router};
-use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgPoolOptions, PgPool};
 use dotenvy::dotenv;
-use std::env;
-use anyhow::Result;
-use axum::response::IntoResponse;
-use http::{Method, StatusCode};
-use tower_http::cors::{Any, CorsLayer};
+use sqlx::postgres::PgPoolOptions;
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
-struct Service {
-    id: i32,
-    name: String,
-    link: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct CreateService {
-    name: String,
-    link: String,
-}
+mod about;
+mod access;
+mod access_log;
+mod acks;
+mod actions;
+mod alertmanager;
+mod audit;
+mod auth;
+mod badges;
+mod bulk;
+mod caching;
+mod categories;
+mod changes;
+mod check_config;
+#[cfg(feature = "checks")]
+mod checker;
+mod checks;
+mod colors;
+mod config;
+mod crypto;
+mod csv_import;
+mod dashboard_export;
+mod dashboard_import;
+mod degraded;
+mod doctor;
+mod duplicates;
+mod digest;
+mod discovery;
+mod email;
+mod embed;
+mod envelope;
+mod grafana;
+mod health;
+mod hooks;
+mod icons;
+mod idempotency;
+mod incidents;
+mod links;
+mod lockout;
+mod notify;
+mod orgs;
+mod page_snapshots;
+mod pagination;
+mod plugins;
+mod preflight;
+mod prometheus;
+mod proposals;
+mod proxy_sync;
+mod pwa;
+mod query;
+mod quick_add;
+mod redirects;
+mod repository;
+mod result_webhooks;
+mod retention;
+mod routes;
+mod routing;
+mod scheduler;
+mod schema;
+mod secrets;
+mod server;
+mod services;
+mod sessions;
+mod sla;
+mod snapshot;
+mod state;
+mod static_links;
+mod status_page;
+mod storage;
+mod streaming;
+mod suggest;
+mod systemd;
+mod tags;
+#[cfg(any(test, feature = "test-support"))]
+#[cfg_attr(not(test), allow(dead_code))]
+mod testing;
+mod timeouts;
+mod tokens;
+mod totp;
+mod trash;
+mod tz;
+mod usage;
+mod users;
+#[cfg(windows)]
+mod winservice;
+mod wol;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv().ok();
-    let database_url = env::var("DATABASE_URL")?;
-
-   let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await?;
-
-    // Ensure table exists
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS services (
-            id SERIAL PRIMARY KEY,
-            name TEXT UNIQUE NOT NULL,
-            link TEXT UNIQUE NOT NULL
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "indexpage=info".into()),
         )
-        "#
-    )
-    .execute(&pool)
-    .await?;
+        .init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("check-config") {
+        std::process::exit(check_config::run(&args[1..]).await);
+    }
+    if args.first().map(String::as_str) == Some("doctor") {
+        std::process::exit(doctor::run(&args[1..]).await);
+    }
+    #[cfg(windows)]
+    if args.first().map(String::as_str) == Some("--service") {
+        return winservice::main(&args[1..]).await;
+    }
+    run().await
+}
 
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods([
-            Method::GET,
-            Method::POST,
-            Method::DELETE,
-            Method::OPTIONS,
-        ])
-        .allow_headers(Any);
+// Loads the configuration and serves until the process ends.
+async fn run() -> anyhow::Result<()> {
+    about::init();
+    let config = config::Config::load()?;
+    let secrets = secrets::Secrets::load(&config.vault).await?;
+    let database_url = secrets
+        .get("DATABASE_URL")?
+        .ok_or_else(|| anyhow::anyhow!("DATABASE_URL is not set"))?;
 
-    let app = Router::new()
-        .route("/services", get(get_services).post(create_service).options(ok_handler))
-        .route("/services/{name}", delete(delete_service).options(ok_handler))
-        .layer(cors)
-        .with_state(pool);
+    let timeouts = timeouts::Timeouts::new(&config.server);
+    let mut pool_options = PgPoolOptions::new().max_connections(5);
+    if timeouts.enabled() {
+        pool_options = pool_options
+            .after_connect(|conn, meta| Box::pin(timeouts::limit_statements(conn, meta)))
+            .before_acquire(|conn, meta| {
+                Box::pin(async move { timeouts::limit_statements(conn, meta).await.map(|()| true) })
+            });
+    }
+    let pool = pool_options.connect(&database_url).await?;
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
-    axum::serve(listener, app).await?;
+    // Ensure tables exist
+    schema::migrate(&pool).await?;
 
-    Ok(())
-}
+    let app = build(&config, &secrets, pool, timeouts).await?;
 
-async fn ok_handler() -> impl IntoResponse {
-    StatusCode::OK
-}
+    server::serve(&config.server, app).await?;
 
-// GET /services
-async fn get_services(State(pool): State<PgPool>) -> Json<Vec<Service>> {
-    let services = sqlx::query_as::<_, Service>("SELECT * FROM services")
-        .fetch_all(&pool)
-        .await
-        .unwrap_or_else(|_| vec![]);
-    Json(services)
+    Ok(())
 }
 
-// POST /services
-async fn create_service(
-    State(pool): State<PgPool>,
-    Json(payload): Json<CreateService>,
-) -> Result<Json<Service>, (axum::http::StatusCode, String)> {
-    let result = sqlx::query_as::<_, Service>(
-        "INSERT INTO services (name, link) VALUES ($1, $2) RETURNING *",
-    )
-    .bind(&payload.name)
-    .bind(&payload.link)
-    .fetch_one(&pool)
-    .await;
+// Everything between the database and the listener: background jobs, the
+// app state and the router. Shared with the test harness (see testing.rs).
+async fn build(
+    config: &config::Config,
+    secrets: &secrets::Secrets,
+    pool: sqlx::PgPool,
+    timeouts: timeouts::Timeouts,
+) -> anyhow::Result<axum::Router> {
+    let cipher = crypto::Cipher::load(secrets.get("INDEXPAGE_SECRET_KEY")?, &config.secrets)?;
+    let mailer = email::Mailer::load(&config.email, secrets.get("INDEXPAGE_SMTP_PASSWORD")?)?;
+    let notification_routes = routing::Routes::new(pool.clone(), config.timezone, mailer.is_some());
+    let notifier =
+        notify::Notifier::new(&config.notifications, mailer.clone())?.with_routes(notification_routes.clone());
+    let plugins = plugins::Plugins::load(&config.plugins)?;
+    let hooks = hooks::Hooks::new(&config.hooks)?;
+    let audit = audit::Audit::new(pool.clone());
+    let trash = trash::Trash::new(pool.clone(), &config.trash);
+    let links = links::Links::new(&config.links, config.server.tls.is_some());
+    let actions = actions::Actions::new(&config.actions, secrets, &links)?;
+    let preflight = preflight::Preflight::new(&config.checks, &links)?;
 
-    match result {
-        Ok(service) => Ok(Json(service)),
-        Err(e) => Err((
-            axum::http::StatusCode::BAD_REQUEST,
-            format!("Failed to insert: {}", e),
-        )),
-    }
-}
+    let scheduler = scheduler::Scheduler::new(&config.jobs, config.timezone);
+    checks::register(
+        &scheduler,
+        pool.clone(),
+        config.checks.clone(),
+        notifier.clone(),
+        cipher.clone(),
+        plugins.clone(),
+        links.for_checks(),
+    )?;
+    retention::register(&scheduler, pool.clone(), config.retention.clone())?;
+    digest::register(&scheduler, pool.clone(), config.digest.clone(), config.timezone, notifier)?;
+    trash::register(&scheduler, trash.clone(), &config.trash, audit.clone())?;
+    access_log::register(&scheduler, pool.clone(), &config.access_log)?;
+    idempotency::register(&scheduler, pool.clone())?;
+    page_snapshots::register(&scheduler, pool.clone(), &config.page_snapshots, links.for_checks())?;
+    let proxy_sync = proxy_sync::ProxySync::new(&config.proxy_sync, secrets)?;
+    proxy_sync::register(
+        &scheduler,
+        pool.clone(),
+        proxy_sync.clone(),
+        &config.proxy_sync,
+        audit.clone(),
+        hooks.clone(),
+    )?;
+    let discovery = discovery::Discovery::new(&config.discovery);
+    discovery::register(&scheduler, discovery.clone(), &config.discovery)?;
+    scheduler.check_overrides()?;
+    let auth = auth::Auth::new(&config.auth, &config.server, secrets.get("INDEXPAGE_API_KEY")?, pool.clone());
+    let access = access::Access::new(&config.access);
+    let alertmanager = alertmanager::Alertmanager::new(&config.alertmanager);
+    let access_log = access_log::AccessLog::start(&config.access_log, pool.clone()).await?;
+    let degraded = degraded::Degraded::start(pool.clone());
+    let storage = storage::Storage::new(&config.storage, secrets)?;
+    let icons = icons::Icons::new(&config.icons, storage.clone())?;
+    let suggestions = suggest::Suggestions::start(pool.clone(), audit.subscribe(), icons.clone());
+    let static_links = static_links::StaticLinks::new(&config.static_links)?;
+    let proposals = proposals::Proposals::new(&config.proposals);
+    let changes = changes::Changes::new(pool.clone(), config.auth.review_changes);
+    let wake_on_lan = wol::WakeOnLan::new(&config.wake_on_lan);
+    let embed = embed::Embed::new(secrets.get("INDEXPAGE_EMBED_KEY")?);
+    let state = state::AppState {
+        pool,
+        trash,
+        cipher,
+        auth,
+        access,
+        audit,
+        scheduler,
+        plugins,
+        hooks,
+        links,
+        mailer,
+        alertmanager,
+        access_log,
+        icons,
+        suggestions,
+        timezone: config.timezone,
+        routes: notification_routes,
+        static_links,
+        proposals,
+        changes,
+        wake_on_lan,
+        actions,
+        discovery,
+        proxy_sync,
+        embed,
+        preflight,
+        storage,
+    };
 
-// DELETE /services/:name
-async fn delete_service(
-    State(pool): State<PgPool>,
-    Path(name): Path<String>,
-) -> Result<String, (axum::http::StatusCode, String)> {
-    let result = sqlx::query("DELETE FROM services WHERE name = $1")
-        .bind(&name)
-        .execute(&pool)
-        .await;
-
-    match result {
-        Ok(r) if r.rows_affected() > 0 => Ok(format!("Deleted '{}'", name)),
-        Ok(_) => Err((axum::http::StatusCode::NOT_FOUND, "Service not found".into())),
-        Err(e) => Err((axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
-    }
+    Ok(routes::router(state, &config.server, degraded, timeouts))
 }
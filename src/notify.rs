@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    config::NotificationsConfig,
+    email::{self, Mailer},
+    routing::Routes,
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub event: &'static str,
+    pub service: String,
+    pub message: String,
+}
+
+impl Notification {
+    // "warning" for problems, "info" for everything else; routing rules can
+    // require a minimum (see routing.rs).
+    pub fn severity(&self) -> &'static str {
+        match self.event {
+            "down" | "flapping" | "degraded" | "cert_expiring" => "warning",
+            _ => "info",
+        }
+    }
+
+    // The problem event this one reports the end of.
+    pub fn resolves(&self) -> Option<&'static str> {
+        match self.event {
+            "latency_recovered" => Some("degraded"),
+            "recovered" => Some("down"),
+            _ => None,
+        }
+    }
+}
+
+// Where a notification goes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Channels {
+    pub webhooks: Vec<String>,
+    pub slack: Vec<String>,
+    pub emails: Vec<String>,
+}
+
+// Fans notifications out to every configured webhook, Slack webhook and
+// email address, or to the channels of the routing rules they match.
+// Delivery failures are logged and otherwise ignored so a broken receiver
+// never stalls the checker.
+#[derive(Clone)]
+pub struct Notifier {
+    http: reqwest::Client,
+    channels: Arc<Channels>,
+    mailer: Option<Mailer>,
+    routes: Option<Routes>,
+}
+
+impl Notifier {
+    pub fn new(config: &NotificationsConfig, mailer: Option<Mailer>) -> anyhow::Result<Self> {
+        check_addresses(&mailer, &config.emails, "notifications.emails")?;
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("indexpage/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        let channels = Channels {
+            webhooks: config.webhooks.clone(),
+            slack: config.slack.clone(),
+            emails: config.emails.clone(),
+        };
+        Ok(Self { http, channels: Arc::new(channels), mailer, routes: None })
+    }
+
+    pub fn with_routes(mut self, routes: Routes) -> Self {
+        self.routes = Some(routes);
+        self
+    }
+
+    // Startup check for other settings that list email recipients.
+    pub fn check_recipients(&self, addresses: &[String], setting: &str) -> anyhow::Result<()> {
+        check_addresses(&self.mailer, addresses, setting)
+    }
+
+    // Through the routing rules the notification matches; to the configured
+    // channels if it matches none.
+    pub async fn send(&self, notification: Notification) {
+        let Some(routes) = &self.routes else { return self.send_via(&notification, &self.channels).await };
+        match routes.matching(&notification).await {
+            Ok(rules) if !rules.is_empty() => routes.dispatch(self, notification, rules).await,
+            Ok(_) => self.send_via(&notification, &self.channels).await,
+            Err(e) => {
+                tracing::warn!("matching notification routes failed: {}", e);
+                self.send_via(&notification, &self.channels).await
+            }
+        }
+    }
+
+    // As `send`, but emailed to `emails` instead of `notifications.emails`,
+    // regardless of routing rules.
+    pub async fn send_to(&self, notification: Notification, emails: &[String]) {
+        let channels = Channels { emails: emails.to_vec(), ..(*self.channels).clone() };
+        self.send_via(&notification, &channels).await
+    }
+
+    pub async fn send_via(&self, notification: &Notification, channels: &Channels) {
+        tracing::info!("{}: {}", notification.event, notification.message);
+        for (receiver, result) in self.deliver(notification, channels).await {
+            if let Err(e) = result {
+                tracing::warn!("notification to {} failed: {}", receiver, e);
+            }
+        }
+    }
+
+    // Sends a test notification to every receiver and reports each outcome,
+    // for `indexpage doctor`.
+    pub async fn test(&self) -> Vec<(String, Result<(), String>)> {
+        let notification = Notification {
+            event: "test",
+            service: "indexpage".into(),
+            message: "Test notification from `indexpage doctor`.".into(),
+        };
+        self.deliver(&notification, &self.channels).await
+    }
+
+    async fn deliver(&self, notification: &Notification, channels: &Channels) -> Vec<(String, Result<(), String>)> {
+        let mut results = Vec::new();
+        for url in &channels.webhooks {
+            results.push((url.clone(), self.post(url, notification).await));
+        }
+        let text = format!("*{}* {}\n{}", notification.event, notification.service, notification.message);
+        for url in &channels.slack {
+            results.push((url.clone(), self.post(url, &json!({ "text": text })).await));
+        }
+        if let Some(mailer) = &self.mailer {
+            let subject = format!("[indexpage] {}: {}", notification.event, notification.service);
+            for address in &channels.emails {
+                let sent = mailer.send(address, &subject, notification.message.clone()).await;
+                results.push((address.clone(), sent.map_err(|e| e.to_string())));
+            }
+        }
+        results
+    }
+
+    async fn post(&self, url: &str, body: &impl Serialize) -> Result<(), String> {
+        self.http
+            .post(url)
+            .json(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(drop)
+            .map_err(|e| e.to_string())
+    }
+}
+
+fn check_addresses(mailer: &Option<Mailer>, addresses: &[String], setting: &str) -> anyhow::Result<()> {
+    if !addresses.is_empty() && mailer.is_none() {
+        anyhow::bail!("{} needs email.smtp_host", setting);
+    }
+    if let Some(address) = addresses.iter().find(|address| !email::is_valid_address(address)) {
+        anyhow::bail!("invalid address '{}' in {}", address, setting);
+    }
+    Ok(())
+}
@@ -0,0 +1,495 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{
+    audit::{Actor, Audit},
+    categories::Visibility,
+    crypto,
+    email::{self, Mailer},
+};
+
+// Organizations let a small team share services and categories that other
+// principals do not see. Members are principal names (certificate
+// identities, token owners, …); owners manage membership and invites.
+// Services and categories without an org stay visible to everyone.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrgRole {
+    Owner,
+    Member,
+}
+
+impl OrgRole {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Owner => "owner",
+            Self::Member => "member",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        if value == "owner" { Self::Owner } else { Self::Member }
+    }
+}
+
+const INVITE_DAYS: i64 = 7;
+
+#[derive(Debug, Serialize)]
+pub struct Org {
+    id: i32,
+    name: String,
+    created_at: DateTime<Utc>,
+    // The caller's role; absent for admins looking at orgs they are not in.
+    role: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Member {
+    member: String,
+    role: String,
+    joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Invite {
+    id: i32,
+    role: String,
+    invited_by: String,
+    // Where the invite was emailed, if anywhere.
+    email: Option<String>,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedInvite {
+    #[serde(flatten)]
+    invite: Invite,
+    // Shown only here; POST /invites/:token/accept redeems it.
+    token: String,
+}
+
+fn internal(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+fn not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Organization not found".into())
+}
+
+async fn role_in(pool: &PgPool, org_id: i32, member: &str) -> sqlx::Result<Option<OrgRole>> {
+    let role = sqlx::query_scalar!("SELECT role FROM org_members WHERE org_id = $1 AND member = $2", org_id, member)
+        .fetch_optional(pool)
+        .await?;
+    Ok(role.as_deref().map(OrgRole::parse))
+}
+
+// Lets members (or only owners) of the org through; admins always pass.
+// Non-members get a 404 so org names and ids do not leak.
+async fn require(
+    pool: &PgPool,
+    visibility: &Visibility,
+    actor: &Actor,
+    org_id: i32,
+    needed: OrgRole,
+) -> Result<(), (StatusCode, String)> {
+    if visibility.is_unrestricted() {
+        let exists = sqlx::query_scalar!("SELECT id FROM orgs WHERE id = $1", org_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(internal)?;
+        return exists.map(|_| ()).ok_or_else(not_found);
+    }
+    match role_in(pool, org_id, &actor.name).await.map_err(internal)? {
+        None => Err(not_found()),
+        Some(OrgRole::Member) if needed == OrgRole::Owner => {
+            Err((StatusCode::FORBIDDEN, "Only organization owners can do this".into()))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+// Used when a service or category is put into an org: the caller must belong to it.
+pub async fn check_member(pool: &PgPool, visibility: &Visibility, org_id: i32) -> Result<(), (StatusCode, String)> {
+    if visibility.is_unrestricted() {
+        return Ok(());
+    }
+    let Some(member) = visibility.member() else {
+        return Err((StatusCode::BAD_REQUEST, format!("Unknown org_id {}", org_id)));
+    };
+    match role_in(pool, org_id, member).await.map_err(internal)? {
+        Some(_) => Ok(()),
+        None => Err((StatusCode::BAD_REQUEST, format!("Unknown org_id {}", org_id))),
+    }
+}
+
+async fn org_name(pool: &PgPool, org_id: i32) -> Result<String, (StatusCode, String)> {
+    sqlx::query_scalar!("SELECT name FROM orgs WHERE id = $1", org_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(internal)?
+        .ok_or_else(not_found)
+}
+
+// GET /orgs
+// The caller's orgs; admins see all of them.
+pub async fn get_orgs(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    actor: Actor,
+) -> Result<Json<Vec<Org>>, (StatusCode, String)> {
+    let orgs = sqlx::query_as!(
+        Org,
+        r#"
+        SELECT orgs.id, orgs.name, orgs.created_at, m.role AS "role?"
+        FROM orgs LEFT JOIN org_members m ON m.org_id = orgs.id AND m.member = $1
+        WHERE $2 OR m.member IS NOT NULL
+        ORDER BY orgs.name
+        "#,
+        actor.name,
+        visibility.is_unrestricted(),
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(internal)?;
+    Ok(Json(orgs))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateOrg {
+    name: String,
+}
+
+// POST /orgs
+// The creator becomes its first owner.
+pub async fn create_org(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Json(payload): Json<CreateOrg>,
+) -> Result<Json<Org>, (StatusCode, String)> {
+    if payload.name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Name cannot be empty".into()));
+    }
+    let mut tx = pool.begin().await.map_err(internal)?;
+    let created = sqlx::query!("INSERT INTO orgs (name) VALUES ($1) RETURNING id, created_at", payload.name)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to insert: {}", e)))?;
+    let (id, created_at) = (created.id, created.created_at);
+    sqlx::query!("INSERT INTO org_members (org_id, member, role) VALUES ($1, $2, 'owner')", id, actor.name)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal)?;
+    tx.commit().await.map_err(internal)?;
+
+    audit.record(&actor, "org.create", None, Some(payload.name.clone())).await;
+    Ok(Json(Org { id, name: payload.name, created_at, role: Some(OrgRole::Owner.as_str().into()) }))
+}
+
+// DELETE /orgs/:id
+// Refused while services or categories (trashed ones included) still belong
+// to it. Returns the deleted org.
+pub async fn delete_org(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<Org>, (StatusCode, String)> {
+    require(&pool, &visibility, &actor, id, OrgRole::Owner).await?;
+    let org = sqlx::query_as!(
+        Org,
+        r#"WITH deleted AS (DELETE FROM orgs WHERE id = $1 RETURNING id, name, created_at)
+           SELECT deleted.id AS "id!", deleted.name AS "name!", deleted.created_at AS "created_at!", m.role AS "role?"
+           FROM deleted LEFT JOIN org_members m ON m.org_id = deleted.id AND m.member = $2"#,
+        id,
+        actor.name,
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_foreign_key_violation() => (
+            StatusCode::CONFLICT,
+            "Organization still has services or categories (including trashed ones)".into(),
+        ),
+        e => internal(e),
+    })?;
+    audit.record(&actor, "org.delete", None, Some(org.name.clone())).await;
+    Ok(Json(org))
+}
+
+// GET /orgs/:id/members
+pub async fn get_members(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<Member>>, (StatusCode, String)> {
+    require(&pool, &visibility, &actor, id, OrgRole::Member).await?;
+    let members = sqlx::query_as!(
+        Member,
+        "SELECT member, role, joined_at FROM org_members WHERE org_id = $1 ORDER BY role = 'owner' DESC, member",
+        id,
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(internal)?;
+    Ok(Json(members))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetRole {
+    role: OrgRole,
+}
+
+// POST /orgs/:id/members/:member
+pub async fn set_member_role(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path((id, member)): Path<(i32, String)>,
+    Json(payload): Json<SetRole>,
+) -> Result<Json<Member>, (StatusCode, String)> {
+    require(&pool, &visibility, &actor, id, OrgRole::Owner).await?;
+    let mut tx = pool.begin().await.map_err(internal)?;
+    let updated = sqlx::query_as!(
+        Member,
+        "UPDATE org_members SET role = $3 WHERE org_id = $1 AND member = $2 RETURNING member, role, joined_at",
+        id,
+        member,
+        payload.role.as_str(),
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal)?
+    .ok_or((StatusCode::NOT_FOUND, "Member not found".into()))?;
+    ensure_owner_left(&mut tx, id).await?;
+    tx.commit().await.map_err(internal)?;
+
+    let detail = format!("{}: {} is now {}", org_name(&pool, id).await?, member, payload.role.as_str());
+    audit.record(&actor, "org.member.role", None, Some(detail)).await;
+    Ok(Json(updated))
+}
+
+// DELETE /orgs/:id/members/:member
+// Owners remove anyone; members can remove themselves (leave). Returns the
+// removed membership.
+pub async fn remove_member(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path((id, member)): Path<(i32, String)>,
+) -> Result<Json<Member>, (StatusCode, String)> {
+    let needed = if member == actor.name { OrgRole::Member } else { OrgRole::Owner };
+    require(&pool, &visibility, &actor, id, needed).await?;
+    let mut tx = pool.begin().await.map_err(internal)?;
+    let removed = sqlx::query_as!(
+        Member,
+        "DELETE FROM org_members WHERE org_id = $1 AND member = $2 RETURNING member, role, joined_at",
+        id,
+        member,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal)?
+    .ok_or((StatusCode::NOT_FOUND, "Member not found".into()))?;
+    ensure_owner_left(&mut tx, id).await?;
+    tx.commit().await.map_err(internal)?;
+
+    let detail = format!("{}: {}", org_name(&pool, id).await?, member);
+    audit.record(&actor, "org.member.remove", None, Some(detail)).await;
+    Ok(Json(removed))
+}
+
+// An org with no owner could never be managed again.
+async fn ensure_owner_left(tx: &mut sqlx::PgConnection, org_id: i32) -> Result<(), (StatusCode, String)> {
+    let orphaned = sqlx::query_scalar!(
+        r#"SELECT NOT EXISTS (SELECT 1 FROM org_members WHERE org_id = $1 AND role = 'owner') AS "orphaned!""#,
+        org_id,
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal)?;
+    if orphaned {
+        return Err((StatusCode::CONFLICT, "An organization must keep at least one owner".into()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInvite {
+    #[serde(default = "default_invite_role")]
+    role: OrgRole,
+    #[serde(default)]
+    expires_in_days: Option<i64>,
+    // Also send the token to this address.
+    #[serde(default)]
+    email: Option<String>,
+}
+
+fn default_invite_role() -> OrgRole {
+    OrgRole::Member
+}
+
+// GET /orgs/:id/invites
+// Pending (unused, unexpired) invites.
+pub async fn get_invites(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<Invite>>, (StatusCode, String)> {
+    require(&pool, &visibility, &actor, id, OrgRole::Owner).await?;
+    let invites = sqlx::query_as!(
+        Invite,
+        "SELECT id, role, invited_by, email, created_at, expires_at FROM org_invites \
+         WHERE org_id = $1 AND accepted_by IS NULL AND expires_at > now() ORDER BY created_at DESC",
+        id,
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(internal)?;
+    Ok(Json(invites))
+}
+
+// POST /orgs/:id/invites
+pub async fn create_invite(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    State(mailer): State<Option<Mailer>>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(id): Path<i32>,
+    Json(payload): Json<CreateInvite>,
+) -> Result<Json<CreatedInvite>, (StatusCode, String)> {
+    require(&pool, &visibility, &actor, id, OrgRole::Owner).await?;
+    let days = payload.expires_in_days.unwrap_or(INVITE_DAYS);
+    if !(1..=365).contains(&days) {
+        return Err((StatusCode::BAD_REQUEST, "expires_in_days must be between 1 and 365".into()));
+    }
+    let mailer = match (&payload.email, mailer) {
+        (None, _) => None,
+        (Some(_), None) => return Err((StatusCode::BAD_REQUEST, "Email is not configured".into())),
+        (Some(address), Some(_)) if !email::is_valid_address(address) => {
+            return Err((StatusCode::BAD_REQUEST, format!("Invalid email address '{}'", address)));
+        }
+        (Some(_), mailer) => mailer,
+    };
+    let token = crypto::random_token();
+    let invite = sqlx::query_as!(
+        Invite,
+        "INSERT INTO org_invites (org_id, token_hash, role, invited_by, email, expires_at) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING id, role, invited_by, email, created_at, expires_at",
+        id,
+        crypto::hash_token(&token),
+        payload.role.as_str(),
+        actor.name,
+        payload.email,
+        Utc::now() + Duration::days(days),
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(internal)?;
+
+    let org = org_name(&pool, id).await?;
+    if let (Some(mailer), Some(address)) = (mailer, invite.email.clone()) {
+        let path = format!("/invites/{}/accept", token);
+        let body = format!(
+            "{} invited you to join '{}' as {}.\n\n\
+             Sign in, then POST to {} to accept. The invite expires on {}.\n",
+            actor.name,
+            org,
+            invite.role,
+            mailer.link(&path).unwrap_or(path),
+            invite.expires_at.format("%Y-%m-%d"),
+        );
+        let subject = format!("[indexpage] Invitation to {}", org);
+        if let Err(e) = mailer.send(&address, &subject, body).await {
+            tracing::warn!("invite email to {} failed: {}", address, e);
+        }
+    }
+    let detail = format!("{}: {} invite", org, invite.role);
+    audit.record(&actor, "org.invite", None, Some(detail)).await;
+    Ok(Json(CreatedInvite { invite, token }))
+}
+
+// DELETE /orgs/:id/invites/:invite_id
+// Returns the revoked invite.
+pub async fn revoke_invite(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    actor: Actor,
+    Path((id, invite_id)): Path<(i32, i32)>,
+) -> Result<Json<Invite>, (StatusCode, String)> {
+    require(&pool, &visibility, &actor, id, OrgRole::Owner).await?;
+    sqlx::query_as!(
+        Invite,
+        "DELETE FROM org_invites WHERE id = $1 AND org_id = $2 AND accepted_by IS NULL \
+         RETURNING id, role, invited_by, email, created_at, expires_at",
+        invite_id,
+        id,
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(internal)?
+    .map(Json)
+    .ok_or((StatusCode::NOT_FOUND, "Invite not found".into()))
+}
+
+// POST /invites/:token/accept
+// Joins the invite's org as the calling principal. Each invite works once.
+pub async fn accept_invite(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(token): Path<String>,
+) -> Result<Json<Org>, (StatusCode, String)> {
+    let mut tx = pool.begin().await.map_err(internal)?;
+    let invite = sqlx::query!(
+        "UPDATE org_invites SET accepted_by = $2, accepted_at = now() \
+         WHERE token_hash = $1 AND accepted_by IS NULL AND expires_at > now() RETURNING org_id, role",
+        crypto::hash_token(&token),
+        actor.name,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal)?
+    .ok_or((StatusCode::NOT_FOUND, "Invite not found or expired".into()))?;
+    let (org_id, role) = (invite.org_id, invite.role);
+    // Accepting never demotes an existing owner.
+    sqlx::query!(
+        "INSERT INTO org_members (org_id, member, role) VALUES ($1, $2, $3) \
+         ON CONFLICT (org_id, member) DO UPDATE SET role = CASE \
+         WHEN org_members.role = 'owner' THEN 'owner' ELSE EXCLUDED.role END",
+        org_id,
+        actor.name,
+        role,
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(internal)?;
+    let org = sqlx::query_as!(
+        Org,
+        r#"SELECT orgs.id, orgs.name, orgs.created_at, m.role AS "role?" FROM orgs
+           JOIN org_members m ON m.org_id = orgs.id AND m.member = $2 WHERE orgs.id = $1"#,
+        org_id,
+        actor.name,
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal)?;
+    tx.commit().await.map_err(internal)?;
+
+    audit.record(&actor, "org.join", None, Some(format!("{} as {}", org.name, role))).await;
+    Ok(Json(org))
+}
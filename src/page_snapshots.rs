@@ -0,0 +1,311 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use serde::Serialize;
+use sqlx::{PgPool, QueryBuilder};
+use std::{
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    audit::{Actor, Audit},
+    categories::Visibility,
+    config::PageSnapshotsConfig,
+    links::LinkContext,
+    quick_add,
+    scheduler::{Schedule, Scheduler},
+    services::SERVICE_ID_BY_NAME,
+};
+
+// What each service's landing page looks like, taken on a schedule: the
+// status code, where redirects end up, the <title> and a hash of the HTML.
+// A snapshot differing from the previous one (a new login page, a redirect
+// to an ISP portal) is flagged until someone reviews it. Unchanged pages
+// only move the latest snapshot's last_seen_at; failed fetches are left to
+// the checks.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_FETCH_BYTES: usize = 1024 * 1024;
+const CONCURRENCY: usize = 8;
+// Per service; older ones are dropped.
+const KEPT: i64 = 50;
+
+static HTTP: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .user_agent(concat!("indexpage/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .unwrap_or_default()
+});
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Snapshot {
+    id: i32,
+    taken_at: DateTime<Utc>,
+    // The last run that saw the page like this.
+    last_seen_at: DateTime<Utc>,
+    status_code: i32,
+    final_url: String,
+    title: Option<String>,
+    content_hash: String,
+    reviewed: bool,
+    // Only selected for GET /page-changes.
+    #[serde(skip)]
+    #[sqlx(default)]
+    service: String,
+    // What differs from the snapshot before it: "status", "url", "title"
+    // and/or "content".
+    #[sqlx(skip)]
+    changes: Vec<&'static str>,
+}
+
+impl Snapshot {
+    fn diff(&self, previous: &Snapshot) -> Vec<&'static str> {
+        let mut changes = Vec::new();
+        if self.status_code != previous.status_code {
+            changes.push("status");
+        }
+        if self.final_url != previous.final_url {
+            changes.push("url");
+        }
+        if self.title != previous.title {
+            changes.push("title");
+        }
+        if self.content_hash != previous.content_hash {
+            changes.push("content");
+        }
+        changes
+    }
+}
+
+pub fn register(
+    scheduler: &Scheduler,
+    pool: PgPool,
+    config: &PageSnapshotsConfig,
+    links: LinkContext,
+) -> anyhow::Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let interval = Schedule::Every(Duration::from_secs(config.interval_secs.max(60)));
+    scheduler.register("page_snapshots", interval, move || {
+        let (pool, links) = (pool.clone(), links.clone());
+        async move { Ok(take_all(&pool, &links).await?) }
+    })
+}
+
+// Hashed without digits and with whitespace collapsed, so clocks, counters
+// and reflowed markup don't count as changes.
+fn content_hash(html: &str) -> String {
+    let normalized = html.split_whitespace().collect::<Vec<_>>().join(" ");
+    let normalized = normalized.replace(|c: char| c.is_ascii_digit(), "");
+    openssl::sha::sha256(normalized.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// (status code, final URL, title, content hash)
+async fn fetch(link: &str) -> reqwest::Result<(i32, String, Option<String>, String)> {
+    let mut response = HTTP.get(link).send().await?;
+    let status = response.status().as_u16().into();
+    let final_url = response.url().to_string();
+    let mut body = Vec::new();
+    while body.len() < MAX_FETCH_BYTES {
+        let Some(chunk) = response.chunk().await? else { break };
+        body.extend_from_slice(&chunk);
+    }
+    let html = String::from_utf8_lossy(&body);
+    Ok((status, final_url, quick_add::parse_title(&html), content_hash(&html)))
+}
+
+async fn take_all(pool: &PgPool, links: &LinkContext) -> sqlx::Result<()> {
+    let services = sqlx::query!("SELECT id, name, link FROM services WHERE deleted_at IS NULL AND archived_at IS NULL")
+        .fetch_all(pool)
+        .await?;
+    let permits = Arc::new(Semaphore::new(CONCURRENCY));
+    let mut running = JoinSet::new();
+    for service in services {
+        let link = links.expand(&service.link);
+        if !link.starts_with("http://") && !link.starts_with("https://") {
+            continue;
+        }
+        let permits = Arc::clone(&permits);
+        running.spawn(async move {
+            let _permit = permits.acquire().await;
+            (service.id, service.name, fetch(&link).await)
+        });
+    }
+
+    while let Some(joined) = running.join_next().await {
+        let Ok((id, name, fetched)) = joined else { continue };
+        match fetched {
+            Ok((status, final_url, title, hash)) => {
+                record(pool, id, status, &final_url, title.as_deref(), &hash).await?
+            }
+            Err(e) => tracing::debug!("snapshot of {} failed: {}", name, e),
+        }
+    }
+    sqlx::query!(
+        "DELETE FROM page_snapshots WHERE id IN (SELECT id FROM (SELECT id, row_number() OVER \
+         (PARTITION BY service_id ORDER BY taken_at DESC, id DESC) AS n FROM page_snapshots) ranked WHERE n > $1)",
+        KEPT,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// A service's first snapshot is the baseline, so it needs no review.
+async fn record(
+    pool: &PgPool,
+    service_id: i32,
+    status: i32,
+    final_url: &str,
+    title: Option<&str>,
+    hash: &str,
+) -> sqlx::Result<()> {
+    let latest = sqlx::query!(
+        "SELECT id, status_code, final_url, title, content_hash FROM page_snapshots WHERE service_id = $1 \
+         ORDER BY taken_at DESC, id DESC LIMIT 1",
+        service_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+    match latest {
+        Some(latest)
+            if latest.status_code == status
+                && latest.final_url == final_url
+                && latest.title.as_deref() == title
+                && latest.content_hash == hash =>
+        {
+            sqlx::query!("UPDATE page_snapshots SET last_seen_at = now() WHERE id = $1", latest.id)
+                .execute(pool)
+                .await?;
+        }
+        latest => {
+            sqlx::query!(
+                "INSERT INTO page_snapshots (service_id, status_code, final_url, title, content_hash, reviewed) \
+                 VALUES ($1, $2, $3, $4, $5, $6)",
+                service_id,
+                status,
+                final_url,
+                title,
+                hash,
+                latest.is_none(),
+            )
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotList {
+    // Whether the latest snapshot changed and awaits review.
+    changed: bool,
+    // Newest first.
+    snapshots: Vec<Snapshot>,
+}
+
+fn with_changes(mut snapshots: Vec<Snapshot>) -> Vec<Snapshot> {
+    for at in 0..snapshots.len().saturating_sub(1) {
+        snapshots[at].changes = snapshots[at].diff(&snapshots[at + 1]);
+    }
+    snapshots
+}
+
+const COLUMNS: &str = "page_snapshots.id, page_snapshots.taken_at, page_snapshots.last_seen_at, \
+     page_snapshots.status_code, page_snapshots.final_url, page_snapshots.title, page_snapshots.content_hash, \
+     page_snapshots.reviewed";
+
+// GET /services/:name/snapshots
+pub async fn get_snapshots(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    Path(name): Path<String>,
+) -> Result<Json<SnapshotList>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let snapshots = sqlx::query_as::<_, Snapshot>(&format!(
+        "SELECT {} FROM page_snapshots WHERE service_id = {} ORDER BY taken_at DESC, id DESC",
+        COLUMNS, SERVICE_ID_BY_NAME
+    ))
+    .bind(&name)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let snapshots = with_changes(snapshots);
+    let changed = snapshots.first().is_some_and(|latest| !latest.reviewed);
+    Ok(Json(SnapshotList { changed, snapshots }))
+}
+
+// POST /services/:name/snapshots/review
+// Accepts the current page as the new normal.
+pub async fn review(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(name): Path<String>,
+) -> Result<String, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let reviewed = sqlx::query_scalar::<_, i32>(&format!(
+        "UPDATE page_snapshots SET reviewed = true WHERE service_id = {} AND NOT reviewed RETURNING id",
+        SERVICE_ID_BY_NAME
+    ))
+    .bind(&name)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if reviewed.is_empty() {
+        return Err((StatusCode::NOT_FOUND, format!("'{}' has no changes to review", name)));
+    }
+    audit.record(&actor, "service.snapshot_review", Some(&name), None).await;
+    Ok(format!("Reviewed {} change(s) of '{}'", reviewed.len(), name))
+}
+
+#[derive(Debug, Serialize)]
+pub struct PageChange {
+    service: String,
+    latest: Snapshot,
+}
+
+// GET /page-changes
+// Services whose latest snapshot changed and awaits review.
+pub async fn get_changes(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+) -> Result<Json<Vec<PageChange>>, (StatusCode, String)> {
+    let mut sql = QueryBuilder::new(format!(
+        "SELECT services.name AS service, {} FROM page_snapshots \
+         JOIN services ON services.id = page_snapshots.service_id LEFT JOIN categories ON categories.id = services.category_id \
+         WHERE services.deleted_at IS NULL AND services.id IN \
+         (SELECT DISTINCT service_id FROM page_snapshots WHERE NOT reviewed)",
+        COLUMNS
+    ));
+    visibility.restrict_services(&mut sql);
+    sql.push(" ORDER BY services.name, page_snapshots.taken_at DESC, page_snapshots.id DESC");
+    let rows = sql
+        .build_query_as::<Snapshot>()
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut by_service: Vec<(String, Vec<Snapshot>)> = Vec::new();
+    for snapshot in rows {
+        match by_service.last_mut() {
+            Some((service, snapshots)) if *service == snapshot.service => snapshots.push(snapshot),
+            _ => by_service.push((snapshot.service.clone(), vec![snapshot])),
+        }
+    }
+    let changes = by_service
+        .into_iter()
+        .filter_map(|(service, snapshots)| {
+            let latest = with_changes(snapshots).into_iter().next()?;
+            (!latest.reviewed).then_some(PageChange { service, latest })
+        })
+        .collect();
+    Ok(Json(changes))
+}
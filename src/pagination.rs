@@ -0,0 +1,186 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use serde::Deserialize;
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 500;
+
+// Query parameters shared by paginated list endpoints. Without `limit` or
+// `cursor` the full list is returned, as before pagination existed.
+#[derive(Debug, Default, Deserialize)]
+pub struct PageQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+pub enum Page {
+    All,
+    Offset { limit: i64, offset: i64 },
+    // `after` is None on the first page of a keyset walk.
+    Keyset { limit: i64, after: Option<Cursor> },
+}
+
+impl PageQuery {
+    pub fn page(&self) -> Result<Page, (StatusCode, String)> {
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        match (&self.cursor, self.offset) {
+            (Some(_), Some(_)) => Err((
+                StatusCode::BAD_REQUEST,
+                "cursor and offset cannot be combined".into(),
+            )),
+            (Some(cursor), None) => Cursor::decode(cursor)
+                .map(|cursor| Page::Keyset { limit, after: Some(cursor) })
+                .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid cursor".into())),
+            (None, Some(offset)) => Ok(Page::Offset { limit, offset: offset.max(0) }),
+            (None, None) if self.limit.is_some() => Ok(Page::Keyset { limit, after: None }),
+            (None, None) => Ok(Page::All),
+        }
+    }
+}
+
+impl Page {
+    // Rows to fetch: one past the limit tells us whether another page exists.
+    pub fn fetch_limit(&self) -> Option<i64> {
+        match self {
+            Page::All => None,
+            Page::Offset { limit, .. } | Page::Keyset { limit, .. } => Some(limit + 1),
+        }
+    }
+
+    pub fn limit(&self) -> Option<i64> {
+        self.fetch_limit().map(|limit| limit - 1)
+    }
+
+    // Starts a fresh keyset walk as an offset walk instead, for orders that
+    // a (created_at, id) cursor cannot express.
+    pub fn without_keyset(self) -> Page {
+        match self {
+            Page::Keyset { limit, after: None } => Page::Offset { limit, offset: 0 },
+            page => page,
+        }
+    }
+}
+
+// Rebuilds the request URL without its paging parameters so next-page links
+// keep any filters the client sent.
+pub fn base_url(path: &str, raw_query: Option<&str>) -> String {
+    let kept: Vec<&str> = raw_query
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or("");
+            !pair.is_empty() && !matches!(key, "limit" | "offset" | "cursor")
+        })
+        .collect();
+    if kept.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}?{}", path, kept.join("&"))
+    }
+}
+
+// Opaque position in a list ordered by (created_at, id).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub created_at: DateTime<Utc>,
+    pub id: i64,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}:{}", self.created_at.timestamp_micros(), self.id))
+    }
+
+    pub fn decode(value: &str) -> Option<Self> {
+        let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(value).ok()?).ok()?;
+        let (micros, id) = raw.split_once(':')?;
+        Some(Self {
+            created_at: DateTime::from_timestamp_micros(micros.parse().ok()?)?,
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+// Trims the extra row fetched by `Page::fetch_limit` and returns headers
+// pointing at the next page (`X-Next-Cursor` plus an RFC 8288 `Link`).
+pub fn paginate<T>(
+    base: &str,
+    page: &Page,
+    rows: &mut Vec<T>,
+    cursor_of: impl Fn(&T) -> Cursor,
+) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let Some(limit) = page.limit() else { return headers };
+    if rows.len() as i64 <= limit {
+        return headers;
+    }
+    rows.truncate(limit as usize);
+
+    let separator = if base.contains('?') { '&' } else { '?' };
+    let next = match page {
+        Page::Offset { offset, .. } => {
+            format!("{}{}limit={}&offset={}", base, separator, limit, offset + limit)
+        }
+        _ => {
+            let cursor = cursor_of(rows.last().unwrap()).encode();
+            if let Ok(value) = HeaderValue::from_str(&cursor) {
+                headers.insert("x-next-cursor", value);
+            }
+            format!("{}{}limit={}&cursor={}", base, separator, limit, cursor)
+        }
+    };
+    if let Ok(value) = HeaderValue::from_str(&format!("<{}>; rel=\"next\"", next)) {
+        headers.insert(header::LINK, value);
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(limit: Option<i64>, offset: Option<i64>, cursor: Option<&str>) -> PageQuery {
+        PageQuery { limit, offset, cursor: cursor.map(str::to_string) }
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursor = Cursor { created_at: DateTime::from_timestamp_micros(1_760_000_000_123_456).unwrap(), id: 42 };
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        assert!(Cursor::decode("not a cursor").is_none());
+        assert!(Cursor::decode(&URL_SAFE_NO_PAD.encode("123")).is_none());
+    }
+
+    #[test]
+    fn picks_the_page_from_the_query() {
+        assert!(matches!(query(None, None, None).page(), Ok(Page::All)));
+        assert!(matches!(query(Some(10_000), None, None).page(), Ok(Page::Keyset { limit: MAX_LIMIT, after: None })));
+        assert!(matches!(query(Some(0), Some(-5), None).page(), Ok(Page::Offset { limit: 1, offset: 0 })));
+        let cursor = Cursor { created_at: Utc::now(), id: 1 }.encode();
+        assert!(matches!(query(None, None, Some(&cursor)).page(), Ok(Page::Keyset { limit: DEFAULT_LIMIT, .. })));
+        assert_eq!(query(None, Some(1), Some(&cursor)).page().err().unwrap().0, StatusCode::BAD_REQUEST);
+        assert_eq!(query(None, None, Some("bad")).page().err().unwrap().0, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn links_the_next_page() {
+        assert_eq!(base_url("/services", Some("limit=2&tag=a&cursor=x&offset=1")), "/services?tag=a");
+        assert_eq!(base_url("/services", Some("limit=2")), "/services");
+
+        let cursor_of = |id: &i64| Cursor { created_at: DateTime::from_timestamp(0, 0).unwrap(), id: *id };
+        let mut rows = vec![1, 2, 3];
+        let headers = paginate("/services?tag=a", &Page::Keyset { limit: 2, after: None }, &mut rows, cursor_of);
+        assert_eq!(rows, [1, 2]);
+        let cursor = headers["x-next-cursor"].to_str().unwrap();
+        assert_eq!(Cursor::decode(cursor).unwrap().id, 2);
+        assert_eq!(headers[header::LINK], format!("</services?tag=a&limit=2&cursor={}>; rel=\"next\"", cursor));
+
+        let mut rows = vec![1, 2, 3];
+        let headers = paginate("/services", &Page::Offset { limit: 2, offset: 4 }, &mut rows, cursor_of);
+        assert_eq!(headers[header::LINK], "</services?limit=2&offset=6>; rel=\"next\"");
+        let mut rows = vec![1, 2];
+        assert!(paginate("/services", &Page::Offset { limit: 2, offset: 0 }, &mut rows, cursor_of).is_empty());
+    }
+}
@@ -0,0 +1,341 @@
+// WASM plugins, declared as `[[plugins]]` in the config and built in with the
+// `plugins` cargo feature.
+//
+// A plugin is a core WebAssembly module (no WASI) exporting `memory`,
+// `indexpage_alloc(len: i32) -> i32` and one or both of:
+//
+//   indexpage_check(ptr: i32, len: i32) -> i64
+//     A health-check type named after the plugin; services use it by setting
+//     check_type to that name. Input {name, link, settings}, output
+//     {ok, error?}. Latency is measured by the host.
+//   indexpage_enrich(ptr: i32, len: i32) -> i64
+//     Runs when a service is created. Input {name, link, settings}, output
+//     {tags?: [..]}; returned tags are added to the service.
+//
+// Inputs and outputs are JSON in guest memory: the host writes the input into
+// a buffer from indexpage_alloc, and the i64 result packs the output as
+// (ptr << 32) | len. Modules may import from "indexpage":
+//
+//   log(ptr: i32, len: i32)                  UTF-8 message to the server log
+//   http(ptr: i32, len: i32) -> i64          request {method?, url, headers?,
+//                                            body?}, response {status?, body?,
+//                                            error?} packed as above
+//
+// Each call gets a fresh instance limited by the plugin's fuel and memory.
+
+#[cfg(feature = "plugins")]
+use serde::Deserialize;
+#[cfg(feature = "plugins")]
+use std::sync::Arc;
+
+use crate::config::PluginConfig;
+
+#[cfg(feature = "plugins")]
+#[derive(Debug, Deserialize)]
+pub struct CheckResult {
+    pub ok: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[cfg(feature = "plugins")]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Enrichment {
+    pub tags: Vec<String>,
+}
+
+#[derive(Clone, Default)]
+pub struct Plugins {
+    #[cfg(feature = "plugins")]
+    host: Option<Arc<host::Host>>,
+}
+
+#[cfg(feature = "plugins")]
+impl Plugins {
+    pub fn load(configs: &[PluginConfig]) -> anyhow::Result<Self> {
+        if configs.is_empty() {
+            return Ok(Self::default());
+        }
+        Ok(Self { host: Some(Arc::new(host::Host::load(configs)?)) })
+    }
+
+    pub fn has_check(&self, name: &str) -> bool {
+        self.host.as_ref().is_some_and(|host| host.has_check(name))
+    }
+
+    // Run by the checker (see checker.rs).
+    #[cfg_attr(not(feature = "checks"), allow(dead_code))]
+    pub async fn check(&self, plugin: &str, name: &str, link: &str) -> Result<(), String> {
+        let Some(host) = self.host.clone() else { return Err(format!("no plugin '{}'", plugin)) };
+        let input = serde_json::json!({ "name": name, "link": link });
+        let plugin = plugin.to_string();
+        let result: CheckResult = tokio::task::spawn_blocking(move || host.call(&plugin, "indexpage_check", input))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| format!("plugin failed: {:#}", e))?;
+        match result {
+            CheckResult { ok: true, .. } => Ok(()),
+            CheckResult { error, .. } => Err(error.unwrap_or_else(|| "plugin reported failure".into())),
+        }
+    }
+
+    // Tags suggested by every enricher; a failing enricher is logged and skipped.
+    pub async fn enrich(&self, name: &str, link: &str) -> Vec<String> {
+        let Some(host) = self.host.clone() else { return Vec::new() };
+        let mut tags = Vec::new();
+        for plugin in host.enrichers() {
+            let host = host.clone();
+            let input = serde_json::json!({ "name": name, "link": link });
+            let plugin_name = plugin.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                host.call::<Enrichment>(&plugin_name, "indexpage_enrich", input)
+            })
+            .await;
+            match result {
+                Ok(Ok(enrichment)) => tags.extend(enrichment.tags),
+                Ok(Err(e)) => tracing::warn!("enricher {} failed for {}: {:#}", plugin, name, e),
+                Err(e) => tracing::warn!("enricher {} panicked for {}: {}", plugin, name, e),
+            }
+        }
+        tags
+    }
+}
+
+#[cfg(not(feature = "plugins"))]
+impl Plugins {
+    pub fn load(configs: &[PluginConfig]) -> anyhow::Result<Self> {
+        if !configs.is_empty() {
+            anyhow::bail!("[[plugins]] is configured but this build lacks the `plugins` feature");
+        }
+        Ok(Self::default())
+    }
+
+    pub fn has_check(&self, _name: &str) -> bool {
+        false
+    }
+
+    #[cfg_attr(not(feature = "checks"), allow(dead_code))]
+    pub async fn check(&self, plugin: &str, _name: &str, _link: &str) -> Result<(), String> {
+        Err(format!("no plugin '{}'", plugin))
+    }
+
+    pub async fn enrich(&self, _name: &str, _link: &str) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+#[cfg(feature = "plugins")]
+mod host {
+    use anyhow::Context;
+    use serde::de::DeserializeOwned;
+    use serde::Deserialize;
+    use std::{collections::HashMap, time::Duration};
+    use wasmtime::{Caller, Config, Engine, Extern, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+    use crate::config::PluginConfig;
+
+    const ABI_EXPORTS: [&str; 2] = ["memory", "indexpage_alloc"];
+
+    struct Plugin {
+        module: Module,
+        settings: serde_json::Value,
+        fuel: u64,
+        max_memory: usize,
+        check: bool,
+        enrich: bool,
+    }
+
+    struct State {
+        limits: StoreLimits,
+        plugin: String,
+        http: reqwest::Client,
+        runtime: tokio::runtime::Handle,
+    }
+
+    pub struct Host {
+        engine: Engine,
+        linker: Linker<State>,
+        plugins: HashMap<String, Plugin>,
+        http: reqwest::Client,
+        runtime: tokio::runtime::Handle,
+    }
+
+    #[derive(Deserialize)]
+    struct HttpRequest {
+        #[serde(default)]
+        method: Option<String>,
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        #[serde(default)]
+        body: Option<String>,
+    }
+
+    impl Host {
+        pub fn load(configs: &[PluginConfig]) -> anyhow::Result<Self> {
+            let mut config = Config::new();
+            config.consume_fuel(true);
+            let engine = Engine::new(&config)?;
+
+            let mut plugins = HashMap::new();
+            for plugin in configs {
+                let module = Module::from_file(&engine, &plugin.path).map_err(|e| {
+                    anyhow::anyhow!("loading plugin {} from {}: {:#}", plugin.name, plugin.path.display(), e)
+                })?;
+                let exports: Vec<&str> = module.exports().map(|export| export.name()).collect();
+                if let Some(missing) = ABI_EXPORTS.iter().find(|name| !exports.contains(name)) {
+                    anyhow::bail!("plugin {} does not export `{}`", plugin.name, missing);
+                }
+                let check = exports.contains(&"indexpage_check");
+                let enrich = exports.contains(&"indexpage_enrich");
+                if !check && !enrich {
+                    anyhow::bail!("plugin {} exports neither indexpage_check nor indexpage_enrich", plugin.name);
+                }
+                if check && crate::checks::CheckType::parse(&plugin.name).is_some() {
+                    anyhow::bail!("plugin {} shadows a built-in check type", plugin.name);
+                }
+                tracing::info!(
+                    "loaded plugin {} (check: {}, enrich: {})",
+                    plugin.name,
+                    check,
+                    enrich
+                );
+                plugins.insert(
+                    plugin.name.clone(),
+                    Plugin {
+                        module,
+                        settings: serde_json::to_value(&plugin.settings)?,
+                        fuel: plugin.fuel,
+                        max_memory: plugin.max_memory_mb * 1024 * 1024,
+                        check,
+                        enrich,
+                    },
+                );
+            }
+
+            let mut linker = Linker::new(&engine);
+            linker.func_wrap("indexpage", "log", |mut caller: Caller<'_, State>, ptr: i32, len: i32| {
+                let message = read(&mut caller, ptr, len)?;
+                tracing::info!("plugin {}: {}", caller.data().plugin, String::from_utf8_lossy(&message));
+                Ok(())
+            })?;
+            linker.func_wrap("indexpage", "http", |mut caller: Caller<'_, State>, ptr: i32, len: i32| {
+                let request = read(&mut caller, ptr, len)?;
+                let response = match serde_json::from_slice::<HttpRequest>(&request) {
+                    Ok(request) => {
+                        let (http, runtime) = (caller.data().http.clone(), caller.data().runtime.clone());
+                        runtime.block_on(fetch(&http, request))
+                    }
+                    Err(e) => serde_json::json!({ "error": format!("invalid request: {}", e) }),
+                };
+                write(&mut caller, &serde_json::to_vec(&response)?)
+            })?;
+
+            let http = reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .user_agent(concat!("indexpage/", env!("CARGO_PKG_VERSION")))
+                .build()?;
+            Ok(Self { engine, linker, plugins, http, runtime: tokio::runtime::Handle::current() })
+        }
+
+        pub fn has_check(&self, name: &str) -> bool {
+            self.plugins.get(name).is_some_and(|plugin| plugin.check)
+        }
+
+        pub fn enrichers(&self) -> Vec<String> {
+            let mut names: Vec<String> = self
+                .plugins
+                .iter()
+                .filter(|(_, plugin)| plugin.enrich)
+                .map(|(name, _)| name.clone())
+                .collect();
+            names.sort();
+            names
+        }
+
+        // Blocking: instantiates the module and runs `export` to completion.
+        pub fn call<T: DeserializeOwned>(
+            &self,
+            name: &str,
+            export: &str,
+            mut input: serde_json::Value,
+        ) -> anyhow::Result<T> {
+            let plugin = self.plugins.get(name).with_context(|| format!("no plugin '{}'", name))?;
+            input["settings"] = plugin.settings.clone();
+            let input = serde_json::to_vec(&input)?;
+
+            let state = State {
+                limits: StoreLimitsBuilder::new().memory_size(plugin.max_memory).build(),
+                plugin: name.to_string(),
+                http: self.http.clone(),
+                runtime: self.runtime.clone(),
+            };
+            let mut store = Store::new(&self.engine, state);
+            store.limiter(|state| &mut state.limits);
+            store.set_fuel(plugin.fuel)?;
+
+            let instance = self.linker.instantiate(&mut store, &plugin.module)?;
+            let alloc = instance.get_typed_func::<i32, i32>(&mut store, "indexpage_alloc")?;
+            let memory = instance.get_memory(&mut store, "memory").context("no memory export")?;
+            let ptr = alloc.call(&mut store, input.len() as i32)?;
+            memory.write(&mut store, ptr as u32 as usize, &input)?;
+
+            let func = instance.get_typed_func::<(i32, i32), i64>(&mut store, export)?;
+            let packed = func.call(&mut store, (ptr, input.len() as i32))?;
+            let output = unpack(&memory, &store, packed)?;
+            serde_json::from_slice(&output).context("plugin returned invalid JSON")
+        }
+    }
+
+    fn unpack(memory: &Memory, store: &Store<State>, packed: i64) -> anyhow::Result<Vec<u8>> {
+        let (ptr, len) = ((packed >> 32) as u32 as usize, packed as u32 as usize);
+        let mut output = vec![0; len];
+        memory.read(store, ptr, &mut output)?;
+        Ok(output)
+    }
+
+    fn memory(caller: &mut Caller<'_, State>) -> wasmtime::Result<Memory> {
+        match caller.get_export("memory") {
+            Some(Extern::Memory(memory)) => Ok(memory),
+            _ => Err(wasmtime::Error::msg("no memory export")),
+        }
+    }
+
+    fn read(caller: &mut Caller<'_, State>, ptr: i32, len: i32) -> wasmtime::Result<Vec<u8>> {
+        let mut buffer = vec![0; len as u32 as usize];
+        memory(caller)?.read(&*caller, ptr as u32 as usize, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    // Copies `data` into a fresh guest buffer and returns it packed.
+    fn write(caller: &mut Caller<'_, State>, data: &[u8]) -> wasmtime::Result<i64> {
+        let alloc = caller
+            .get_export("indexpage_alloc")
+            .and_then(Extern::into_func)
+            .ok_or_else(|| wasmtime::Error::msg("no indexpage_alloc export"))?
+            .typed::<i32, i32>(&*caller)?;
+        let ptr = alloc.call(&mut *caller, data.len() as i32)?;
+        memory(caller)?.write(&mut *caller, ptr as u32 as usize, data)?;
+        Ok(((ptr as u32 as i64) << 32) | data.len() as i64)
+    }
+
+    async fn fetch(http: &reqwest::Client, request: HttpRequest) -> serde_json::Value {
+        let method = request.method.as_deref().unwrap_or("GET").parse().unwrap_or(reqwest::Method::GET);
+        let mut builder = http.request(method, &request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+        match builder.send().await {
+            Ok(response) => {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                serde_json::json!({ "status": status, "body": body })
+            }
+            Err(e) => serde_json::json!({ "error": e.to_string() }),
+        }
+    }
+}
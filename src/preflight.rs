@@ -0,0 +1,92 @@
+use chrono::Utc;
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::{
+    checks::cert_expiry,
+    config::ChecksConfig,
+    links::{LinkContext, Links},
+};
+
+// POST /services?preflight=true probes the new service's link once before
+// answering and lists what looks wrong in the response's "warnings": an
+// unreachable link, an error status, a redirect to another host, or a
+// certificate expiring within `checks.cert_expiry_warn_days`. The service is
+// created either way. Links that aren't http(s) URLs aren't probed.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PreflightQuery {
+    #[serde(default)]
+    pub preflight: bool,
+}
+
+#[derive(Clone)]
+pub struct Preflight {
+    http: reqwest::Client,
+    // Links are expanded as the checks do.
+    links: LinkContext,
+    cert_expiry_warn_days: i64,
+}
+
+impl Preflight {
+    pub fn new(config: &ChecksConfig, links: &Links) -> anyhow::Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(PROBE_TIMEOUT)
+            .user_agent(concat!("indexpage/", env!("CARGO_PKG_VERSION")))
+            .tls_info(true)
+            .build()?;
+        Ok(Self { http, links: links.for_checks(), cert_expiry_warn_days: config.cert_expiry_warn_days })
+    }
+
+    // Sends `token` as a bearer token, like an http check would.
+    pub async fn probe(&self, link: &str, token: Option<&str>) -> Vec<String> {
+        let Some(url) = url::Url::parse(&self.links.expand(link))
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some())
+        else {
+            return Vec::new();
+        };
+        let mut request = self.http.get(url.clone());
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) if e.is_timeout() => {
+                return vec![format!("link did not answer within {}s", PROBE_TIMEOUT.as_secs())];
+            }
+            Err(e) => return vec![format!("link is unreachable: {}", root_cause(&e))],
+        };
+
+        let mut warnings = Vec::new();
+        if response.url().host() != url.host() {
+            warnings.push(format!("link redirects to {}", response.url()));
+        }
+        let status = response.status();
+        if status.is_client_error() || status.is_server_error() {
+            warnings.push(format!("link returned {}", status));
+        }
+        let expires_at = response
+            .extensions()
+            .get::<reqwest::tls::TlsInfo>()
+            .and_then(|info| info.peer_certificate())
+            .and_then(cert_expiry);
+        if let Some(expires_at) = expires_at {
+            let days_left = (expires_at - Utc::now()).num_days();
+            if days_left < self.cert_expiry_warn_days {
+                warnings.push(format!("certificate expires in {} days ({})", days_left, expires_at.date_naive()));
+            }
+        }
+        warnings
+    }
+}
+
+// reqwest's own message only names the URL; the reason is further down.
+fn root_cause(e: &(dyn std::error::Error + 'static)) -> String {
+    let mut cause = e;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    cause.to_string()
+}
@@ -0,0 +1,73 @@
+use axum::{extract::State, Json};
+use http::StatusCode;
+use serde::Serialize;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use std::collections::BTreeMap;
+
+use crate::{categories::Visibility, links::Links, static_links::StaticLinks, tags};
+
+// GET /integrations/prometheus/targets: every http(s) service link as a
+// Prometheus http_sd target list, for blackbox_exporter to probe what the
+// dashboard lists. Links are expanded as for the checks. Each target group
+// is labelled with the service's name, its category and its tags, the
+// latter joined as ",a,b," so a relabel regex can match one with ".*,a,.*".
+// Point it at the exporter with the usual relabelling:
+//
+//   http_sd_configs: [{ url: "https://dash.example/integrations/prometheus/targets" }]
+//   relabel_configs:
+//     - { source_labels: [__address__], target_label: __param_target }
+//     - { source_labels: [__param_target], target_label: instance }
+//     - { target_label: __address__, replacement: "blackbox:9115" }
+
+#[derive(Debug, Serialize)]
+pub struct TargetGroup {
+    targets: Vec<String>,
+    labels: BTreeMap<&'static str, String>,
+}
+
+fn group(link: String, service: &str, category: Option<String>, tags: &[String]) -> TargetGroup {
+    let mut labels = BTreeMap::from([("service", service.to_string())]);
+    if let Some(category) = category {
+        labels.insert("category", category);
+    }
+    if !tags.is_empty() {
+        labels.insert("tags", format!(",{},", tags.join(",")));
+    }
+    TargetGroup { targets: vec![link], labels }
+}
+
+// GET /integrations/prometheus/targets
+pub async fn targets(
+    State(pool): State<PgPool>,
+    State(links): State<Links>,
+    State(static_links): State<StaticLinks>,
+    visibility: Visibility,
+) -> Result<Json<Vec<TargetGroup>>, (StatusCode, String)> {
+    let mut sql = QueryBuilder::<Postgres>::new(format!(
+        "SELECT services.name, services.link, categories.name, {} FROM services \
+         LEFT JOIN categories ON categories.id = services.category_id \
+         WHERE services.deleted_at IS NULL AND services.archived_at IS NULL",
+        tags::TAGS_COLUMN
+    ));
+    visibility.restrict_services(&mut sql);
+    sql.push(" ORDER BY lower(services.name)");
+    let services = sql
+        .build_query_as::<(String, String, Option<String>, Vec<String>)>()
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let links = links.for_checks();
+    let configured = static_links
+        .all()
+        .iter()
+        .map(|service| (service.name.clone(), service.link.clone(), None, service.tags.clone()));
+    Ok(Json(
+        configured
+            .chain(services)
+            .map(|(name, link, category, tags)| (name, links.expand(&link), category, tags))
+            .filter(|(_, link, _, _)| link.starts_with("http://") || link.starts_with("https://"))
+            .map(|(name, link, category, tags)| group(link, &name, category, &tags))
+            .collect(),
+    ))
+}
@@ -0,0 +1,271 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use http::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    audit::{self, Actor, Audit},
+    categories,
+    config::ProposalsConfig,
+    crypto, duplicates, hooks, links, plugins, preflight,
+    repository::ServiceRepository,
+    services::{self, CreateService, Service},
+    static_links::StaticLinks,
+};
+
+// A suggestion box for shared dashboards. With `[proposals] enabled`,
+// anyone, signed in or not, may propose a service with POST /proposals; it
+// waits in service_proposals, unlisted, until an admin approves it with
+// POST /services/:id/approve (it is then created like any other service)
+// or rejects it with DELETE /proposals/:id. Each client IP gets
+// `max_per_hour` proposals; the counts are kept in memory.
+const WINDOW: Duration = Duration::from_secs(3600);
+const MAX_NAME_CHARS: usize = 100;
+const MAX_DESCRIPTION_CHARS: usize = 500;
+// Clients kept before idle ones are swept out.
+const SWEEP_AT: usize = 10_000;
+
+#[derive(Clone)]
+pub struct Proposals {
+    config: Arc<ProposalsConfig>,
+    // Times of each client IP's proposals within the last WINDOW.
+    recent: Arc<Mutex<HashMap<String, VecDeque<Instant>>>>,
+}
+
+impl Proposals {
+    pub fn new(config: &ProposalsConfig) -> Self {
+        Self { config: Arc::new(config.clone()), recent: Arc::default() }
+    }
+
+    // Counts a proposal from `ip`, or says how long until it may send one.
+    fn admit(&self, ip: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() > SWEEP_AT {
+            recent.retain(|_, times| times.back().is_some_and(|last| now - *last < WINDOW));
+        }
+        let times = recent.entry(ip.to_string()).or_default();
+        while times.front().is_some_and(|first| now - *first >= WINDOW) {
+            times.pop_front();
+        }
+        if times.len() >= self.config.max_per_hour.max(1) {
+            return Err(WINDOW - (now - times[0]));
+        }
+        times.push_back(now);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Proposal {
+    id: i32,
+    name: String,
+    link: String,
+    description: Option<String>,
+    proposed_by: String,
+    ip: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProposeService {
+    name: String,
+    link: String,
+    description: Option<String>,
+}
+
+fn internal(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+// POST /proposals
+// Body: {"name": "...", "link": "https://...", "description": "..."}.
+// 202 with the stored proposal.
+pub async fn propose(
+    State(pool): State<PgPool>,
+    State(proposals): State<Proposals>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Json(payload): Json<ProposeService>,
+) -> Result<Response, (StatusCode, String)> {
+    if !proposals.config.enabled {
+        return Err((StatusCode::NOT_FOUND, "Proposals are disabled".into()));
+    }
+    let name = payload.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_CHARS {
+        return Err((StatusCode::BAD_REQUEST, format!("name must be 1 to {} characters", MAX_NAME_CHARS)));
+    }
+    let description = payload.description.as_deref().map(str::trim).filter(|description| !description.is_empty());
+    if description.is_some_and(|description| description.chars().count() > MAX_DESCRIPTION_CHARS) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("description must be at most {} characters", MAX_DESCRIPTION_CHARS),
+        ));
+    }
+    let link = url::Url::parse(payload.link.trim())
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some())
+        .map(|_| payload.link.trim().to_string())
+        .ok_or((StatusCode::BAD_REQUEST, format!("'{}' is not an http(s) URL", payload.link)))?;
+
+    let mut conn = pool.acquire().await.map_err(internal)?;
+    if let Some(existing) = duplicates::find(&mut conn, &link).await.map_err(internal)? {
+        return Err((StatusCode::CONFLICT, format!("Already on the dashboard as '{}'", existing.name)));
+    }
+    let (pending, proposed) = sqlx::query_as::<_, (i64, bool)>(
+        "SELECT count(*), coalesce(bool_or(link = $1), false) FROM service_proposals",
+    )
+    .bind(&link)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(internal)?;
+    if proposed {
+        return Err((StatusCode::CONFLICT, "This link has already been proposed".into()));
+    }
+    if pending >= proposals.config.max_pending {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, "Too many proposals await review; try again later".into()));
+    }
+    if let Err(wait) = proposals.admit(actor.ip.as_deref().unwrap_or("unknown")) {
+        let secs = wait.as_secs().max(1);
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, secs.to_string())],
+            format!("Too many proposals; try again in {}s", secs),
+        )
+            .into_response());
+    }
+
+    let proposal = sqlx::query_as::<_, Proposal>(
+        "INSERT INTO service_proposals (name, link, description, proposed_by, ip) \
+         VALUES ($1, $2, $3, $4, $5) RETURNING *",
+    )
+    .bind(name)
+    .bind(&link)
+    .bind(description)
+    .bind(&actor.name)
+    .bind(&actor.ip)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(internal)?;
+    audit.record(&actor, "proposal.create", Some(&proposal.name), Some(proposal.link.clone())).await;
+    Ok((StatusCode::ACCEPTED, Json(proposal)).into_response())
+}
+
+// GET /proposals
+// Oldest first.
+pub async fn get_proposals(State(pool): State<PgPool>) -> Result<Json<Vec<Proposal>>, (StatusCode, String)> {
+    let proposals = sqlx::query_as::<_, Proposal>("SELECT * FROM service_proposals ORDER BY created_at, id")
+        .fetch_all(&pool)
+        .await
+        .map_err(internal)?;
+    Ok(Json(proposals))
+}
+
+// DELETE /proposals/:id
+// Returns the rejected proposal.
+pub async fn reject(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<Proposal>, (StatusCode, String)> {
+    let proposal = sqlx::query_as::<_, Proposal>("DELETE FROM service_proposals WHERE id = $1 RETURNING *")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "Proposal not found".into()))?;
+    audit.record(&actor, "proposal.reject", Some(&proposal.name), None).await;
+    Ok(Json(proposal))
+}
+
+// What the admin fills in on approval; the proposal only has a name, link
+// and description.
+#[derive(Debug, Default, Deserialize)]
+pub struct Approval {
+    name: Option<String>,
+    category_id: Option<i32>,
+    #[serde(default)]
+    public: bool,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+// POST /services/:id/approve
+// `id` is the proposal's. Body (optional): {"name", "category_id",
+// "public", "tags"}. Creates the service as POST /services would, with its
+// duplicate checks (?force=true), and drops the proposal.
+#[allow(clippy::too_many_arguments)]
+pub async fn approve<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    services: State<R>,
+    static_links: State<StaticLinks>,
+    cipher: State<Option<crypto::Cipher>>,
+    State(audit): State<Audit>,
+    plugins: State<plugins::Plugins>,
+    hooks: State<hooks::Hooks>,
+    preflight: State<preflight::Preflight>,
+    actor: audit::Actor,
+    links: links::LinkContext,
+    visibility: categories::Visibility,
+    Path(id): Path<i32>,
+    force: Query<duplicates::Force>,
+    approval: Option<Json<Approval>>,
+) -> axum::response::Result<Json<Service>> {
+    let Json(approval) = approval.unwrap_or_default();
+    let proposal = sqlx::query_as::<_, Proposal>("SELECT * FROM service_proposals WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "Proposal not found"))?;
+    let payload = CreateService {
+        name: approval.name.filter(|name| !name.trim().is_empty()).unwrap_or(proposal.name),
+        link: proposal.link,
+        internal_link: None,
+        description: proposal.description,
+        check_type: None,
+        expected_ip: None,
+        latency_threshold_ms: None,
+        check_token: None,
+        category_id: approval.category_id,
+        org_id: None,
+        public: approval.public,
+        tags: approval.tags,
+        colors: Default::default(),
+    };
+    let service = services::create_service(
+        State(pool.clone()),
+        services,
+        static_links,
+        cipher,
+        State(audit.clone()),
+        plugins,
+        hooks,
+        preflight,
+        actor.clone(),
+        links,
+        visibility,
+        force,
+        Query(Default::default()),
+        Json(payload),
+    )
+    .await?
+    .0
+    .service;
+
+    sqlx::query!("DELETE FROM service_proposals WHERE id = $1", id).execute(&pool).await.map_err(internal)?;
+    let detail = format!("proposed by {}", proposal.proposed_by);
+    audit.record(&actor, "proposal.approve", Some(&service.name), Some(detail)).await;
+    Ok(Json(service))
+}
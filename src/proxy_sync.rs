@@ -0,0 +1,350 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    audit::{Actor, Audit},
+    config::{ProxyKind, ProxySourceConfig, ProxySyncConfig},
+    csv_import::{self, Row, RowError},
+    duplicates,
+    hooks::{HookEvent, Hooks},
+    repository::{PgServices, ServiceRepository},
+    scheduler::{Schedule, Scheduler},
+    secrets::Secrets,
+};
+
+// Services from a reverse proxy's host list, for setups where the proxy is
+// the source of truth: every `proxy_sync.interval_secs`, and on POST
+// /integrations/proxies/sync, each source's hosts are read (Nginx Proxy
+// Manager's proxy hosts, or the hosts Caddy's HTTP servers match) and those
+// not on the dashboard yet are created as with the CSV import, named after
+// their first label. Services a source created are marked with its name, so
+// with `remove_missing` they go to the trash once their host is gone. Hooks
+// run as for the CSV import and DELETE /services/:name, so a pre_delete
+// hook can keep a service.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+struct Source {
+    config: ProxySourceConfig,
+    password: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct ProxySync {
+    sources: Arc<Vec<Source>>,
+    http: reqwest::Client,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SourceReport {
+    source: String,
+    hosts: usize,
+    excluded: usize,
+    imported: usize,
+    // Moved to the trash.
+    removed: Vec<String>,
+    // Gone from the source but kept, as a pre_delete hook refused.
+    kept: Vec<Kept>,
+    errors: Vec<RowError>,
+    // Why the host list couldn't be read; nothing was synced then.
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Kept {
+    name: String,
+    error: String,
+}
+
+impl ProxySync {
+    pub fn new(config: &ProxySyncConfig, secrets: &Secrets) -> anyhow::Result<Self> {
+        let mut sources = Vec::new();
+        for (index, source) in config.sources.iter().enumerate() {
+            let context = |what: &str| format!("proxy_sync.sources[{}] ({}): {}", index, source.name, what);
+            url::Url::parse(&source.url).map_err(|e| anyhow::anyhow!(context(&e.to_string())))?;
+            if config.sources[..index].iter().any(|other| other.name == source.name) {
+                anyhow::bail!(context("another source has this name"));
+            }
+            let password = match (&source.password_secret, source.kind) {
+                (Some(name), _) => {
+                    Some(secrets.get(name)?.ok_or_else(|| anyhow::anyhow!(context(&format!("{} is not set", name))))?)
+                }
+                (None, ProxyKind::NginxProxyManager) => anyhow::bail!(context("set email and password_secret")),
+                (None, ProxyKind::Caddy) => None,
+            };
+            if source.kind == ProxyKind::NginxProxyManager && source.email.is_none() {
+                anyhow::bail!(context("set email and password_secret"));
+            }
+            sources.push(Source { config: source.clone(), password });
+        }
+        let http = reqwest::Client::builder().timeout(FETCH_TIMEOUT).build()?;
+        Ok(Self { sources: Arc::new(sources), http })
+    }
+
+    // Syncs every source, one after the other.
+    pub async fn sync(
+        &self,
+        pool: &PgPool,
+        audit: &Audit,
+        hooks: &Hooks,
+        actor: &Actor,
+        dry_run: bool,
+    ) -> Vec<SourceReport> {
+        let mut reports = Vec::new();
+        for source in self.sources.iter() {
+            let report = match self.hosts(source).await {
+                Ok(hosts) => sync_source(pool, hooks, actor, source, hosts, dry_run).await,
+                Err(e) => Err(e.to_string()),
+            };
+            let report = report.unwrap_or_else(|error| {
+                tracing::warn!("syncing proxy hosts from {} failed: {}", source.config.name, error);
+                SourceReport {
+                    source: source.config.name.clone(),
+                    hosts: 0,
+                    excluded: 0,
+                    imported: 0,
+                    removed: Vec::new(),
+                    kept: Vec::new(),
+                    errors: Vec::new(),
+                    error: Some(error),
+                }
+            });
+            if !dry_run && (report.imported > 0 || !report.removed.is_empty()) {
+                let mut detail = format!("{}: {} imported", report.source, report.imported);
+                if !report.removed.is_empty() {
+                    detail.push_str(&format!(", removed {}", report.removed.join(", ")));
+                }
+                audit.record(actor, "service.proxy_sync", None, Some(detail)).await;
+            }
+            reports.push(report);
+        }
+        reports
+    }
+
+    // The source's host names, each with whether it is served over HTTPS.
+    async fn hosts(&self, source: &Source) -> anyhow::Result<Vec<(String, bool)>> {
+        let base = source.config.url.trim_end_matches('/');
+        match source.config.kind {
+            ProxyKind::NginxProxyManager => {
+                let login = serde_json::json!({
+                    "identity": source.config.email,
+                    "secret": source.password,
+                });
+                let token: Value = self
+                    .http
+                    .post(format!("{}/api/tokens", base))
+                    .json(&login)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                let token = token["token"].as_str().ok_or_else(|| anyhow::anyhow!("login returned no token"))?;
+                let hosts: Vec<Value> = self
+                    .http
+                    .get(format!("{}/api/nginx/proxy-hosts", base))
+                    .bearer_auth(token)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                Ok(npm_hosts(&hosts))
+            }
+            ProxyKind::Caddy => {
+                let servers: Value = self
+                    .http
+                    .get(format!("{}/config/apps/http/servers", base))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                Ok(caddy_hosts(&servers))
+            }
+        }
+    }
+}
+
+// Enabled proxy hosts; those with a certificate are served over HTTPS.
+fn npm_hosts(hosts: &[Value]) -> Vec<(String, bool)> {
+    let truthy = |value: &Value| value.as_bool().unwrap_or_else(|| value.as_i64().is_some_and(|value| value != 0));
+    hosts
+        .iter()
+        .filter(|host| host.get("enabled").is_none_or(truthy))
+        .flat_map(|host| {
+            let https = host.get("certificate_id").is_some_and(truthy);
+            let names = host["domain_names"].as_array().cloned().unwrap_or_default();
+            names.into_iter().filter_map(move |name| Some((name.as_str()?.to_string(), https)))
+        })
+        .collect()
+}
+
+// The hosts matched by each server's routes; Caddy serves them over HTTPS
+// unless the server only listens on port 80 or has automatic HTTPS off.
+fn caddy_hosts(servers: &Value) -> Vec<(String, bool)> {
+    let mut hosts = Vec::new();
+    for server in servers.as_object().into_iter().flat_map(|servers| servers.values()) {
+        let listen: Vec<&str> = server["listen"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+        let plain = !listen.is_empty() && listen.iter().all(|address| address.ends_with(":80"));
+        let https = !plain && server["automatic_https"]["disable"].as_bool() != Some(true);
+        for route in server["routes"].as_array().into_iter().flatten() {
+            for matcher in route["match"].as_array().into_iter().flatten() {
+                let names = matcher["host"].as_array().into_iter().flatten().filter_map(Value::as_str);
+                hosts.extend(names.map(|name| (name.to_string(), https)));
+            }
+        }
+    }
+    hosts
+}
+
+// `*` matches any run of characters, including none.
+fn glob(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let Some(text) = text.strip_prefix(prefix) else { return false };
+            (0..=text.len()).filter(|at| text.is_char_boundary(*at)).any(|at| glob(rest, &text[at..]))
+        }
+    }
+}
+
+async fn sync_source(
+    pool: &PgPool,
+    hooks: &Hooks,
+    actor: &Actor,
+    source: &Source,
+    hosts: Vec<(String, bool)>,
+    dry_run: bool,
+) -> Result<SourceReport, String> {
+    let config = &source.config;
+    let mut hosts: Vec<(String, bool)> = hosts
+        .into_iter()
+        .map(|(host, https)| (host.trim().to_lowercase(), https))
+        .filter(|(host, _)| !host.is_empty() && !host.contains('*'))
+        .collect();
+    hosts.sort();
+    hosts.dedup_by(|a, b| a.0 == b.0);
+    let total = hosts.len();
+    hosts.retain(|(host, _)| !config.exclude.iter().any(|pattern| glob(&pattern.to_lowercase(), host)));
+    let excluded = total - hosts.len();
+    let links: Vec<String> = hosts
+        .iter()
+        .map(|(host, https)| format!("{}://{}", if *https { "https" } else { "http" }, host))
+        .collect();
+
+    // Hosts already on the dashboard, by hand or from an earlier sync, are
+    // left alone.
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    let mut rows = Vec::new();
+    for (index, ((host, _), link)) in hosts.iter().zip(&links).enumerate() {
+        if duplicates::find(&mut conn, link).await.map_err(|e| e.to_string())?.is_some() {
+            continue;
+        }
+        let name = host.split('.').next().unwrap_or(host).to_string();
+        let row = Row {
+            name,
+            link: link.clone(),
+            category: config.category.clone(),
+            tags: config.tags.clone(),
+            description: Some(format!("{} via {}", host, config.name)),
+            icon: None,
+        };
+        rows.push((index + 1, row));
+    }
+    drop(conn);
+    let mut errors = Vec::new();
+    let rows = csv_import::check_hooks(hooks, actor, rows, &mut errors).await;
+    let new_links: Vec<String> = rows.iter().map(|(_, row)| row.link.clone()).collect();
+    let services = PgServices::new(pool.clone());
+    let (imported, _) = services.import(rows.clone(), false, dry_run, &mut errors).await.map_err(|(_, e)| e)?;
+    errors.sort_by_key(RowError::row);
+
+    let (mut removed, mut kept) = (Vec::new(), Vec::new());
+    if !dry_run {
+        csv_import::saved_hooks(hooks, actor, &rows, &errors);
+        services.mark_synced(&config.name, &new_links).await.map_err(|(_, e)| e)?;
+    }
+    if config.remove_missing {
+        // One at a time, as DELETE /services/:name would.
+        for name in services.synced_missing(&config.name, &links).await.map_err(|(_, e)| e)? {
+            let service = serde_json::json!({ "name": name });
+            if let Err((_, error)) = hooks.before(HookEvent::PreDelete, actor, &service).await {
+                kept.push(Kept { name, error });
+                continue;
+            }
+            if dry_run {
+                removed.push(name);
+            } else if let Some(trashed) = services.trash(&name).await.map_err(|(_, e)| e)? {
+                hooks.after(HookEvent::PostDelete, actor, serde_json::json!({ "name": trashed.name }));
+                removed.push(trashed.name);
+            }
+        }
+        removed.sort();
+        kept.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    Ok(SourceReport {
+        source: config.name.clone(),
+        hosts: total,
+        excluded,
+        imported,
+        removed,
+        kept,
+        errors,
+        error: None,
+    })
+}
+
+pub fn register(
+    scheduler: &Scheduler,
+    pool: PgPool,
+    sync: ProxySync,
+    config: &ProxySyncConfig,
+    audit: Audit,
+    hooks: Hooks,
+) -> anyhow::Result<()> {
+    if sync.sources.is_empty() {
+        return Ok(());
+    }
+    let interval = Schedule::Every(Duration::from_secs(config.interval_secs.max(60)));
+    scheduler.register("proxy_sync", interval, move || {
+        let (pool, sync, audit, hooks) = (pool.clone(), sync.clone(), audit.clone(), hooks.clone());
+        async move {
+            let reports = sync.sync(&pool, &audit, &hooks, &Actor::system(), false).await;
+            let failed: Vec<&str> =
+                reports.iter().filter(|report| report.error.is_some()).map(|report| report.source.as_str()).collect();
+            if !failed.is_empty() {
+                anyhow::bail!("reading hosts from {} failed", failed.join(", "));
+            }
+            Ok(())
+        }
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncQuery {
+    // Reports what would change without changing it.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+// POST /integrations/proxies/sync
+pub async fn sync_now(
+    State(pool): State<PgPool>,
+    State(sync): State<ProxySync>,
+    State(audit): State<Audit>,
+    State(hooks): State<Hooks>,
+    actor: Actor,
+    Query(query): Query<SyncQuery>,
+) -> Result<Json<Vec<SourceReport>>, (StatusCode, String)> {
+    if sync.sources.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "No proxy_sync sources are configured".into()));
+    }
+    Ok(Json(sync.sync(&pool, &audit, &hooks, &actor, query.dry_run).await))
+}
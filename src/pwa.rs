@@ -0,0 +1,117 @@
+use axum::{
+    extract::Query,
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use http::{header, HeaderMap};
+use std::{sync::LazyLock, time::Duration};
+
+use crate::caching::{self, Version};
+
+// Makes the status page installable as an app: a manifest, an icon and a
+// service worker. The worker precaches the page and answers /status from the
+// last copy it saw while the server can't be reached (or answers with a 5xx),
+// so the home screen icon still opens with the last known service list.
+// /status only lists public services, so the copy is safe to keep on a shared
+// device, unlike authenticated API responses.
+const CACHE: &str = "indexpage-v1";
+// For /icon.svg without a fingerprint.
+const ICON_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+const ICON: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 96 96">
+<rect width="96" height="96" rx="20" fill="#2e7d32"/>
+<rect x="22" y="26" width="52" height="10" rx="5" fill="#fff"/>
+<rect x="22" y="43" width="52" height="10" rx="5" fill="#fff"/>
+<rect x="22" y="60" width="34" height="10" rx="5" fill="#fff"/>
+</svg>"##;
+static ICON_FINGERPRINT: LazyLock<String> = LazyLock::new(|| caching::fingerprint(ICON.as_bytes()));
+static ICON_URL: LazyLock<String> = LazyLock::new(|| caching::versioned("/icon.svg", &ICON_FINGERPRINT));
+
+// GET /manifest.webmanifest
+pub async fn manifest() -> Response {
+    let manifest = serde_json::json!({
+        "name": "indexpage",
+        "short_name": "indexpage",
+        "start_url": "/status",
+        "scope": "/",
+        "display": "standalone",
+        "background_color": "#ffffff",
+        "theme_color": "#2e7d32",
+        "icons": [{ "src": *ICON_URL, "sizes": "any", "type": "image/svg+xml", "purpose": "any maskable" }],
+    });
+    ([(header::CONTENT_TYPE, "application/manifest+json")], Json(manifest)).into_response()
+}
+
+// GET /icon.svg
+pub async fn icon(Query(version): Query<Version>, headers: HeaderMap) -> Response {
+    let body = Bytes::from_static(ICON.as_bytes());
+    caching::serve(&headers, &version, ICON_MAX_AGE, "image/svg+xml", &ICON_FINGERPRINT, body)
+}
+
+// GET /sw.js
+// Served from the root so its scope covers every path. Browsers revalidate
+// it on navigation; no-cache keeps a stale worker from lingering.
+pub async fn service_worker() -> Response {
+    let script = format!(
+        r#"const CACHE = "{}";
+const SHELL = ["/status", "/manifest.webmanifest", "{1}"];
+
+self.addEventListener("install", (event) => {{
+  event.waitUntil(caches.open(CACHE).then((cache) => cache.addAll(SHELL)).then(() => self.skipWaiting()));
+}});
+
+self.addEventListener("activate", (event) => {{
+  event.waitUntil(
+    caches.keys()
+      .then((keys) => Promise.all(keys.filter((key) => key !== CACHE).map((key) => caches.delete(key))))
+      .then(() => self.clients.claim())
+  );
+}});
+
+// Network first; the last good copy when the server is unreachable or failing.
+async function networkFirst(request) {{
+  const cache = await caches.open(CACHE);
+  try {{
+    const response = await fetch(request);
+    if (response.ok) {{
+      await cache.put(request, response.clone());
+      return response;
+    }}
+    if (response.status < 500) return response;
+    return (await cache.match(request)) || response;
+  }} catch (error) {{
+    const cached = await cache.match(request, {{ ignoreSearch: request.mode === "navigate" }});
+    if (cached) return cached;
+    throw error;
+  }}
+}}
+
+self.addEventListener("fetch", (event) => {{
+  const url = new URL(event.request.url);
+  if (event.request.method !== "GET" || url.origin !== self.location.origin) return;
+  if (url.pathname === "/status") {{
+    event.respondWith(networkFirst(event.request));
+  }} else if (SHELL.includes(url.pathname + url.search)) {{
+    event.respondWith(caches.match(event.request).then((cached) => cached || fetch(event.request)));
+  }}
+}});
+"#,
+        CACHE, *ICON_URL
+    );
+    (
+        [(header::CONTENT_TYPE, "text/javascript"), (header::CACHE_CONTROL, "no-cache")],
+        script,
+    )
+        .into_response()
+}
+
+// For the <head> of HTML pages.
+pub static HEAD: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        r##"<link rel="manifest" href="/manifest.webmanifest">
+<link rel="icon" href="{}" type="image/svg+xml">
+<meta name="theme-color" content="#2e7d32">
+<script>if ("serviceWorker" in navigator) navigator.serviceWorker.register("/sw.js");</script>"##,
+        *ICON_URL
+    )
+});
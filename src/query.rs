@@ -0,0 +1,168 @@
+use http::StatusCode;
+use serde::Deserialize;
+use sqlx::{Postgres, QueryBuilder};
+
+use crate::{categories::Visibility, checks::STATE_SQL, health::Health, pagination::Page, tags::TAGS_COLUMN};
+
+// Filters and sort order accepted by GET /services. Every value reaches the
+// database as a bind parameter; sort keys are mapped through a fixed list
+// of columns, so nothing from the query string is spliced into SQL.
+#[derive(Debug, Default, Deserialize)]
+pub struct ServiceFilter {
+    pub category: Option<String>,
+    // Comma-separated; a service must carry all of them.
+    pub tag: Option<String>,
+    pub status: Option<String>,
+    pub q: Option<String>,
+    pub visibility: Option<String>,
+    // "active" (the default), "archived" or "all".
+    pub state: Option<String>,
+    // Comma-separated keys, `-` prefix for descending, e.g. `category,-updated_at`.
+    pub sort: Option<String>,
+}
+
+struct SortKey {
+    column: &'static str,
+    descending: bool,
+}
+
+fn sort_column(key: &str) -> Option<&'static str> {
+    match key {
+        "name" => Some("services.name"),
+        "link" => Some("services.link"),
+        "category" => Some("categories.name"),
+        "created_at" => Some("services.created_at"),
+        "updated_at" => Some("services.updated_at"),
+        _ => None,
+    }
+}
+
+fn parse_sort(sort: &str) -> Result<Vec<SortKey>, String> {
+    sort.split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| {
+            let (descending, name) = match key.strip_prefix('-') {
+                Some(name) => (true, name),
+                None => (false, key.strip_prefix('+').unwrap_or(key)),
+            };
+            sort_column(name)
+                .map(|column| SortKey { column, descending })
+                .ok_or_else(|| format!("Unknown sort key '{}'", name))
+        })
+        .collect()
+}
+
+fn bad_request(message: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message)
+}
+
+impl ServiceFilter {
+    pub fn is_sorted(&self) -> bool {
+        self.sort.as_deref().is_some_and(|sort| !sort.trim().is_empty())
+    }
+
+    // Builds the listing query. Keyset cursors only make sense for the
+    // default (created_at, id) order, so custom sorts fall back to offsets.
+    pub fn build<'a>(
+        &'a self,
+        page: &'a Page,
+        visibility: &Visibility,
+    ) -> Result<QueryBuilder<'a, Postgres>, (StatusCode, String)> {
+        let sort = parse_sort(self.sort.as_deref().unwrap_or("")).map_err(bad_request)?;
+
+        let mut sql = QueryBuilder::new("SELECT services.*, ");
+        sql.push(TAGS_COLUMN);
+        sql.push(" FROM services LEFT JOIN categories ON categories.id = services.category_id");
+        if self.status.is_some() {
+            sql.push(
+                " LEFT JOIN LATERAL (SELECT ok, degraded FROM check_results \
+                 WHERE service_id = services.id ORDER BY checked_at DESC LIMIT 1) latest ON true \
+                 LEFT JOIN service_health health ON health.service_id = services.id",
+            );
+        }
+        sql.push(" WHERE services.deleted_at IS NULL");
+        visibility.restrict_services(&mut sql);
+
+        if let Some(category) = &self.category {
+            sql.push(" AND lower(categories.name) = lower(").push_bind(category).push(")");
+        }
+        if let Some(tags) = &self.tag {
+            let tags: Vec<String> = tags
+                .split(',')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect();
+            let count = tags.len() as i64;
+            sql.push(
+                " AND (SELECT count(*) FROM service_tags st JOIN tags t ON t.id = st.tag_id \
+                 WHERE st.service_id = services.id AND t.name = ANY(",
+            )
+            .push_bind(tags)
+            .push(")) = ")
+            .push_bind(count);
+        }
+        if let Some(status) = &self.status {
+            let health = Health::parse(status).ok_or_else(|| bad_request(format!("Unknown status '{}'", status)))?;
+            sql.push(" AND ").push(STATE_SQL).push(" = ").push_bind(health.as_str());
+        }
+        if let Some(q) = &self.q {
+            let pattern = format!(
+                "%{}%",
+                q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+            );
+            sql.push(" AND (services.name ILIKE ")
+                .push_bind(pattern.clone())
+                .push(" OR services.link ILIKE ")
+                .push_bind(pattern.clone())
+                .push(" OR services.internal_link ILIKE ")
+                .push_bind(pattern)
+                .push(")");
+        }
+        if let Some(visibility) = &self.visibility {
+            sql.push(match visibility.as_str() {
+                "public" => " AND services.public",
+                "private" => " AND NOT services.public",
+                other => return Err(bad_request(format!("Unknown visibility '{}'", other))),
+            });
+        }
+
+        sql.push(match self.state.as_deref().unwrap_or("active") {
+            "active" => " AND services.archived_at IS NULL",
+            "archived" => " AND services.archived_at IS NOT NULL",
+            "all" => "",
+            other => return Err(bad_request(format!("Unknown state '{}'", other))),
+        });
+
+        let mut offset = None;
+        match page {
+            Page::Keyset { after: Some(_), .. } if !sort.is_empty() => {
+                return Err(bad_request("cursor pagination cannot be combined with sort".into()));
+            }
+            Page::Keyset { after: Some(cursor), .. } => {
+                sql.push(" AND (services.created_at, services.id) > (")
+                    .push_bind(cursor.created_at)
+                    .push(", ")
+                    .push_bind(cursor.id)
+                    .push(")");
+            }
+            Page::Offset { offset: value, .. } => offset = Some(*value),
+            _ => {}
+        }
+
+        sql.push(" ORDER BY ");
+        for key in &sort {
+            sql.push(key.column)
+                .push(if key.descending { " DESC NULLS LAST, " } else { " ASC NULLS LAST, " });
+        }
+        sql.push("services.created_at, services.id");
+
+        if let Some(limit) = page.fetch_limit() {
+            sql.push(" LIMIT ").push_bind(limit);
+        }
+        if let Some(offset) = offset {
+            sql.push(" OFFSET ").push_bind(offset);
+        }
+        Ok(sql)
+    }
+}
@@ -0,0 +1,168 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use http::{header, HeaderMap, HeaderValue, StatusCode, Uri};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::{sync::LazyLock, time::Duration};
+
+use crate::{
+    audit, auth, categories, crypto, duplicates, hooks, links, plugins, preflight,
+    repository::ServiceRepository,
+    services::{CreateService, CreatedService},
+    sessions,
+    static_links::StaticLinks,
+};
+
+// GET/POST /quick-add?url=...&name=...&token=...
+// One-click adding of the page being viewed, for bookmarklets and share
+// sheets, which can open a URL but not set headers: the personal token comes
+// in the query instead (see `with_query_token`); a session cookie alone
+// isn't accepted. Without a name, the page's <title> is used, falling back
+// to its host name.
+pub const PATH: &str = "/quick-add";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+// The title is in the head; no need to download the whole page.
+const MAX_FETCH_BYTES: usize = 256 * 1024;
+const MAX_NAME_CHARS: usize = 100;
+
+static HTTP: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .user_agent(concat!("indexpage/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .unwrap_or_default()
+});
+
+#[derive(Debug, Deserialize)]
+pub struct QuickAdd {
+    url: String,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+// Copies `token` from the query into X-API-Key so auth::enforce checks it
+// like any other key. Only for this route, so tokens don't end up in URLs
+// elsewhere. A link on any other site can send a signed-in browser here
+// with its session cookie, so the cookie is dropped and a key is required;
+// and as query strings end up in browser history, only personal tokens are
+// taken from it, not the API key or session tokens.
+pub fn with_query_token(uri: &Uri, headers: &mut HeaderMap, api_key: Option<&str>) -> Result<(), (StatusCode, String)> {
+    if uri.path() != PATH {
+        return Ok(());
+    }
+    headers.remove(header::COOKIE);
+    let token = url::form_urlencoded::parse(uri.query().unwrap_or("").as_bytes())
+        .find(|(key, _)| key == "token")
+        .map(|(_, token)| token.into_owned());
+    if let Some(token) = token.filter(|_| !headers.contains_key("x-api-key")) {
+        if token.starts_with(sessions::TOKEN_PREFIX) || api_key.is_some_and(|key| auth::keys_match(key, &token)) {
+            return Err((StatusCode::BAD_REQUEST, "?token= takes a personal token (see /me/tokens)".into()));
+        }
+        if let Ok(token) = HeaderValue::from_str(&token) {
+            headers.insert("x-api-key", token);
+        }
+    }
+    if !headers.contains_key("x-api-key") && !headers.contains_key(header::AUTHORIZATION) {
+        return Err((StatusCode::UNAUTHORIZED, "Quick add needs a personal token (?token=) or an API key".into()));
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn quick_add<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    services: State<R>,
+    static_links: State<StaticLinks>,
+    cipher: State<Option<crypto::Cipher>>,
+    audit: State<audit::Audit>,
+    plugins: State<plugins::Plugins>,
+    hooks: State<hooks::Hooks>,
+    preflight: State<preflight::Preflight>,
+    actor: audit::Actor,
+    links: links::LinkContext,
+    visibility: categories::Visibility,
+    Query(query): Query<QuickAdd>,
+) -> axum::response::Result<Json<CreatedService>> {
+    let url = url::Url::parse(query.url.trim())
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some())
+        .ok_or((StatusCode::BAD_REQUEST, format!("'{}' is not an http(s) URL", query.url)))?;
+    // Checked before fetching the title; create_service checks again.
+    let link = query.url.trim().to_string();
+    let internal = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let existing = duplicates::find(&mut *pool.acquire().await.map_err(internal)?, &link).await.map_err(internal)?;
+    if let Some(mut service) = existing {
+        service.expand_links(&links);
+        let message = format!("Already on the dashboard as '{}'", service.name);
+        return Err(duplicates::Duplicate { message, service }.into());
+    }
+
+    let name = match query.name.as_deref().map(str::trim).filter(|name| !name.is_empty()) {
+        Some(name) => name.to_string(),
+        None => match title(&url).await {
+            Some(title) => title,
+            None => url.host_str().unwrap_or_default().trim_start_matches("www.").to_string(),
+        },
+    };
+    let payload = CreateService {
+        name: name.chars().take(MAX_NAME_CHARS).collect(),
+        link,
+        internal_link: None,
+        description: None,
+        check_type: None,
+        expected_ip: None,
+        latency_threshold_ms: None,
+        check_token: None,
+        category_id: None,
+        org_id: None,
+        public: false,
+        tags: Vec::new(),
+        colors: Default::default(),
+    };
+    let force = Query(duplicates::Force { force: true });
+    crate::services::create_service(
+        State(pool),
+        services,
+        static_links,
+        cipher,
+        audit,
+        plugins,
+        hooks,
+        preflight,
+        actor,
+        links,
+        visibility,
+        force,
+        Query(Default::default()),
+        Json(payload),
+    )
+    .await
+}
+
+// The page's <title>, if it can be fetched in time and has one.
+async fn title(url: &url::Url) -> Option<String> {
+    let mut response = HTTP.get(url.clone()).send().await.ok()?.error_for_status().ok()?;
+    let mut body = Vec::new();
+    while body.len() < MAX_FETCH_BYTES {
+        let Some(chunk) = response.chunk().await.ok()? else { break };
+        body.extend_from_slice(&chunk);
+    }
+    parse_title(&String::from_utf8_lossy(&body))
+}
+
+pub fn parse_title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = html[start..end]
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!title.is_empty()).then_some(title)
+}
@@ -0,0 +1,153 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use http::{header, StatusCode, Uri};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use url::{form_urlencoded, Url};
+
+use crate::{
+    audit::{Actor, Audit},
+    auth::Principal,
+    categories::Visibility,
+    links::LinkContext,
+    repository::ServiceRepository,
+    services::SERVICE_ID_BY_NAME,
+    usage,
+};
+
+// GET /go/:name sends the browser on to a service's link, so dashboards can
+// point there and the click shows up in the access log. Per service it can
+// redirect permanently (301) instead of temporarily (302), pass the query
+// string it was called with on to the target, and add fixed parameters such
+// as utm_source. Services without settings get a plain 302.
+const MAX_PARAMS: usize = 16;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RedirectSettings {
+    #[serde(default)]
+    permanent: bool,
+    // Everything but ?view=, which picks the link (see links.rs).
+    #[serde(default)]
+    pass_query: bool,
+    // Set on the target, replacing parameters of the same name.
+    #[serde(default)]
+    params: BTreeMap<String, String>,
+}
+
+fn encode(params: &BTreeMap<String, String>) -> String {
+    form_urlencoded::Serializer::new(String::new()).extend_pairs(params).finish()
+}
+
+fn decode(params: &str) -> BTreeMap<String, String> {
+    form_urlencoded::parse(params.as_bytes()).into_owned().collect()
+}
+
+// `link` with the settings applied for a request with `query`.
+fn target(link: &str, settings: &RedirectSettings, query: Option<&str>) -> Result<String, (StatusCode, String)> {
+    let passed: Vec<(String, String)> = match (settings.pass_query, query) {
+        (true, Some(query)) => form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .filter(|(name, _)| name != "view" && !settings.params.contains_key(name))
+            .collect(),
+        _ => Vec::new(),
+    };
+    if passed.is_empty() && settings.params.is_empty() {
+        return Ok(link.to_string());
+    }
+    let mut url = Url::parse(link)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, format!("Can't add parameters to '{}': {}", link, e)))?;
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .into_owned()
+        .filter(|(name, _)| !settings.params.contains_key(name))
+        .collect();
+    url.query_pairs_mut().clear().extend_pairs(kept).extend_pairs(passed).extend_pairs(&settings.params);
+    Ok(url.to_string())
+}
+
+type Row = (String, Option<String>, Option<bool>, Option<bool>, Option<String>);
+
+// The service's link, internal link and redirect settings.
+async fn load(pool: &PgPool, name: &str) -> Result<(String, Option<String>, RedirectSettings), (StatusCode, String)> {
+    let row = sqlx::query_as::<_, Row>(&format!(
+        "SELECT s.link, s.internal_link, r.permanent, r.pass_query, r.params FROM services s \
+         LEFT JOIN service_redirects r ON r.service_id = s.id WHERE s.id = {}",
+        SERVICE_ID_BY_NAME
+    ))
+    .bind(name)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let (link, internal_link, permanent, pass_query, params) =
+        row.ok_or((StatusCode::NOT_FOUND, "Service not found".into()))?;
+    let settings = RedirectSettings {
+        permanent: permanent.unwrap_or(false),
+        pass_query: pass_query.unwrap_or(false),
+        params: params.as_deref().map(decode).unwrap_or_default(),
+    };
+    Ok((link, internal_link, settings))
+}
+
+// GET /go/:name
+// Counts as a click for the caller's recent and most used services.
+pub async fn go(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    links: LinkContext,
+    principal: Option<Extension<Principal>>,
+    Path(name): Path<String>,
+    uri: Uri,
+) -> Result<Response, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let (link, internal_link, settings) = load(&pool, &name).await?;
+    let location = target(&links.choose(&link, internal_link.as_deref()), &settings, uri.query())?;
+    usage::record_click(&pool, principal.as_deref(), &name);
+    let status = if settings.permanent { StatusCode::MOVED_PERMANENTLY } else { StatusCode::FOUND };
+    Ok((status, [(header::LOCATION, location)]).into_response())
+}
+
+// GET /services/:name/redirect
+pub async fn get_settings(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    Path(name): Path<String>,
+) -> Result<Json<RedirectSettings>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let (_, _, settings) = load(&pool, &name).await?;
+    Ok(Json(settings))
+}
+
+// PUT /services/:name/redirect
+// Body: {"permanent": bool, "pass_query": bool, "params": {"utm_source": "..."}};
+// omitted fields go back to their defaults.
+pub async fn update_settings<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    State(services): State<R>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(name): Path<String>,
+    Json(settings): Json<RedirectSettings>,
+) -> Result<Json<RedirectSettings>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    if settings.params.len() > MAX_PARAMS {
+        return Err((StatusCode::BAD_REQUEST, format!("At most {} params are allowed", MAX_PARAMS)));
+    }
+    if settings.params.keys().any(|name| name.trim().is_empty()) {
+        return Err((StatusCode::BAD_REQUEST, "Param names must not be empty".into()));
+    }
+    let saved =
+        services.set_redirect(&name, settings.permanent, settings.pass_query, &encode(&settings.params)).await?;
+    let service = saved.ok_or((StatusCode::NOT_FOUND, "Service not found".into()))?;
+    let detail = format!(
+        "{}{}",
+        if settings.permanent { "301" } else { "302" },
+        if settings.pass_query { ", passing the query string" } else { "" }
+    );
+    audit.record(&actor, "service.redirect", Some(&service), Some(detail)).await;
+    Ok(Json(settings))
+}
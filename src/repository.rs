@@ -0,0 +1,1431 @@
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use sqlx::{Acquire, PgConnection, PgPool, Postgres, QueryBuilder, Transaction};
+use std::{collections::HashMap, future::Future};
+
+use crate::{
+    bulk::{BulkChanges, BulkFilter},
+    categories::Visibility,
+    colors::Colors,
+    csv_import::{Row, RowError},
+    duplicates,
+    pagination::Page,
+    query::ServiceFilter,
+    services::{CreateService, Service, SERVICE_ID_BY_NAME},
+    tags,
+};
+
+#[cfg(test)]
+pub use memory::MemoryServices;
+
+// Reads and writes of services for the handlers (/services and the
+// per-service settings, the trash, proxy syncs), so they only deal with
+// requests, hooks and the audit log. Writes that touch
+// more than one table (a service and its tags) run in one transaction:
+// either all of it is saved or none. Errors come back ready to return, with
+// the status the handlers used to pick. The handlers are generic over it:
+// routes.rs gives them PgServices, tests can give them MemoryServices.
+pub trait ServiceRepository {
+    fn list(
+        &self,
+        filter: &ServiceFilter,
+        page: &Page,
+        visibility: &Visibility,
+    ) -> impl Future<Output = Result<Vec<Service>, (StatusCode, String)>> + Send;
+
+    // With its tags; trashed services included.
+    fn get(&self, id: i32) -> impl Future<Output = Result<Option<Service>, (StatusCode, String)>> + Send;
+
+    // `check_token_encrypted` replaces the plain token in `service`.
+    fn create(
+        &self,
+        service: &CreateService,
+        check_type: &str,
+        check_token_encrypted: Option<String>,
+    ) -> impl Future<Output = Result<Service, (StatusCode, String)>> + Send;
+
+    // The renamed service's id; None if there is no live service `name`.
+    fn rename(
+        &self,
+        name: &str,
+        new_name: &str,
+    ) -> impl Future<Output = Result<Option<i32>, (StatusCode, String)>> + Send;
+
+    // Copies `source`'s settings (CLONED_COLUMNS) and tags to a new service;
+    // returns its id.
+    fn clone_service(
+        &self,
+        source: &str,
+        name: &str,
+        link: &str,
+        internal_link: Option<&str>,
+    ) -> impl Future<Output = Result<i32, (StatusCode, String)>> + Send;
+
+    // Moves the service to the trash; returns it, with its tags.
+    fn trash(&self, name: &str) -> impl Future<Output = Result<Option<Service>, (StatusCode, String)>> + Send;
+
+    // Archives the service unless it already is, and closes its ongoing
+    // incident; returns it, with its tags.
+    fn archive(&self, name: &str) -> impl Future<Output = Result<Option<Service>, (StatusCode, String)>> + Send;
+
+    // Brings an archived service back; returns it, with its tags.
+    fn unarchive(&self, name: &str) -> impl Future<Output = Result<Option<Service>, (StatusCode, String)>> + Send;
+
+    // Saves `rows`, numbered for their errors, creating unknown categories;
+    // on a dry run nothing is kept. A bad row (a duplicate link, see
+    // duplicates.rs, or a taken name) is added to `errors` and skipped.
+    // Returns how many rows were saved and the categories created for them.
+    fn import(
+        &self,
+        rows: Vec<(usize, Row)>,
+        force: bool,
+        dry_run: bool,
+        errors: &mut Vec<RowError>,
+    ) -> impl Future<Output = Result<(usize, Vec<String>), (StatusCode, String)>> + Send;
+
+    // Ids and names of the live services matching `filter` that the caller
+    // can see, by id.
+    fn bulk_matches(
+        &self,
+        filter: &BulkFilter,
+        visibility: &Visibility,
+    ) -> impl Future<Output = Result<Vec<(i32, String)>, (StatusCode, String)>> + Send;
+
+    // Applies `changes` to the live services among `ids`, all or none; 400 if
+    // they would move them into a category the caller can't see. Returns the
+    // ids changed.
+    fn bulk_update(
+        &self,
+        ids: &[i32],
+        changes: &BulkChanges,
+        visibility: &Visibility,
+    ) -> impl Future<Output = Result<Vec<i32>, (StatusCode, String)>> + Send;
+
+    // Brings back the most recently trashed service `name`; returns its name.
+    fn restore(&self, name: &str) -> impl Future<Output = Result<Option<String>, (StatusCode, String)>> + Send;
+
+    // Starts a maintenance window until `until`, replacing any current one,
+    // or with None ends the current one (finding nothing if there is none).
+    // Returns the service's name.
+    fn set_maintenance(
+        &self,
+        name: &str,
+        until: Option<DateTime<Utc>>,
+    ) -> impl Future<Output = Result<Option<String>, (StatusCode, String)>> + Send;
+
+    // Returns the service's name.
+    fn set_colors(
+        &self,
+        name: &str,
+        colors: &Colors,
+    ) -> impl Future<Output = Result<Option<String>, (StatusCode, String)>> + Send;
+
+    // Returns the service's name.
+    fn set_mac(
+        &self,
+        name: &str,
+        mac_address: Option<&str>,
+    ) -> impl Future<Output = Result<Option<String>, (StatusCode, String)>> + Send;
+
+    // Replaces the redirect settings, `params` form-encoded (see
+    // redirects.rs); returns the service's name.
+    fn set_redirect(
+        &self,
+        name: &str,
+        permanent: bool,
+        pass_query: bool,
+        params: &str,
+    ) -> impl Future<Output = Result<Option<String>, (StatusCode, String)>> + Send;
+
+    // Replaces the SLA of the service with id `id`; `period` as in sla.rs.
+    fn set_sla(
+        &self,
+        id: i32,
+        target: f64,
+        period: &str,
+    ) -> impl Future<Output = Result<(), (StatusCode, String)>> + Send;
+
+    // Returns the removed target and period.
+    fn remove_sla(&self, id: i32) -> impl Future<Output = Result<Option<(f64, String)>, (StatusCode, String)>> + Send;
+
+    // Marks the live services with these links, unless they already are,
+    // as created by the proxy sync `source` (see proxy_sync.rs).
+    fn mark_synced(
+        &self,
+        source: &str,
+        links: &[String],
+    ) -> impl Future<Output = Result<(), (StatusCode, String)>> + Send;
+
+    // Names of the live services `source` created whose link isn't among
+    // `links`.
+    fn synced_missing(
+        &self,
+        source: &str,
+        links: &[String],
+    ) -> impl Future<Output = Result<Vec<String>, (StatusCode, String)>> + Send;
+}
+
+// Per-service settings copied by POST /services/:name/clone.
+const CLONED_COLUMNS: &str =
+    "description, check_type, expected_ip, latency_threshold_ms, check_token_encrypted, category_id, org_id, public, \
+     icon, color, accent";
+
+fn internal(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+fn conflict(name: &str) -> (StatusCode, String) {
+    (StatusCode::CONFLICT, format!("A service named '{}' already exists", name))
+}
+
+// Names are unique ignoring case (services_live_name_lower), so "grafana"
+// conflicts with "Grafana".
+fn write_failed(action: &str, name: &str, e: sqlx::Error) -> (StatusCode, String) {
+    match e.as_database_error() {
+        Some(db) if db.is_unique_violation() => conflict(name),
+        _ => (StatusCode::BAD_REQUEST, format!("Failed to {}: {}", action, e)),
+    }
+}
+
+// An imported row's error for a taken name.
+const NAME_TAKEN: &str = "a service with this name already exists";
+
+fn row_failed(e: sqlx::Error) -> String {
+    match e.as_database_error() {
+        Some(db) if db.is_unique_violation() => NAME_TAKEN.into(),
+        _ => e.to_string(),
+    }
+}
+
+// Finds the category by name, ignoring case, or creates it.
+async fn category_id(
+    conn: &mut PgConnection,
+    known: &mut HashMap<String, i32>,
+    created: &mut Vec<String>,
+    name: &str,
+) -> sqlx::Result<i32> {
+    if let Some(id) = known.get(&name.to_lowercase()) {
+        return Ok(*id);
+    }
+    let existing =
+        sqlx::query_scalar!("SELECT id FROM categories WHERE lower(name) = lower($1) ORDER BY id LIMIT 1", name)
+            .fetch_optional(&mut *conn)
+            .await?;
+    let id = match existing {
+        Some(id) => id,
+        None => {
+            created.push(name.to_string());
+            sqlx::query_scalar!("INSERT INTO categories (name) VALUES ($1) RETURNING id", name)
+                .fetch_one(&mut *conn)
+                .await?
+        }
+    };
+    known.insert(name.to_lowercase(), id);
+    Ok(id)
+}
+
+async fn insert_row(
+    conn: &mut PgConnection,
+    categories: &mut HashMap<String, i32>,
+    created: &mut Vec<String>,
+    row: &Row,
+) -> sqlx::Result<()> {
+    let category = match &row.category {
+        Some(name) => Some(category_id(conn, categories, created, name).await?),
+        None => None,
+    };
+    let id = sqlx::query_scalar!(
+        "INSERT INTO services (name, link, category_id, description, icon) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        row.name,
+        row.link,
+        category,
+        row.description,
+        row.icon,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    tags::attach(conn, id, &row.tags).await
+}
+
+#[derive(Clone)]
+pub struct PgServices {
+    pool: PgPool,
+}
+
+impl PgServices {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    // Dropping it without a commit rolls everything back.
+    async fn begin(&self) -> Result<Transaction<'static, Postgres>, (StatusCode, String)> {
+        self.pool.begin().await.map_err(internal)
+    }
+}
+
+impl ServiceRepository for PgServices {
+    async fn list(
+        &self,
+        filter: &ServiceFilter,
+        page: &Page,
+        visibility: &Visibility,
+    ) -> Result<Vec<Service>, (StatusCode, String)> {
+        filter
+            .build(page, visibility)?
+            .build_query_as::<Service>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(internal)
+    }
+
+    async fn get(&self, id: i32) -> Result<Option<Service>, (StatusCode, String)> {
+        sqlx::query_as::<_, Service>(&format!("SELECT services.*, {} FROM services WHERE id = $1", tags::TAGS_COLUMN))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(internal)
+    }
+
+    async fn create(
+        &self,
+        service: &CreateService,
+        check_type: &str,
+        check_token_encrypted: Option<String>,
+    ) -> Result<Service, (StatusCode, String)> {
+        let mut tx = self.begin().await?;
+        let mut created = sqlx::query_as::<_, Service>(
+            "INSERT INTO services (name, link, check_type, expected_ip, latency_threshold_ms, category_id, public, \
+             check_token_encrypted, internal_link, org_id, description, color, accent) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13) RETURNING *",
+        )
+        .bind(&service.name)
+        .bind(&service.link)
+        .bind(check_type)
+        .bind(&service.expected_ip)
+        .bind(service.latency_threshold_ms)
+        .bind(service.category_id)
+        .bind(service.public)
+        .bind(check_token_encrypted)
+        .bind(&service.internal_link)
+        .bind(service.org_id)
+        .bind(&service.description)
+        .bind(&service.colors.color)
+        .bind(&service.colors.accent)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| write_failed("insert", &service.name, e))?;
+        tags::attach(&mut tx, created.id, &service.tags)
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to tag: {}", e)))?;
+        tx.commit().await.map_err(internal)?;
+
+        created.tags = service.tags.clone();
+        created.tags.sort();
+        created.tags.dedup();
+        Ok(created)
+    }
+
+    async fn rename(&self, name: &str, new_name: &str) -> Result<Option<i32>, (StatusCode, String)> {
+        sqlx::query_scalar::<_, i32>(&format!(
+            "UPDATE services SET name = $2, updated_at = now() WHERE id = {} RETURNING id",
+            SERVICE_ID_BY_NAME
+        ))
+        .bind(name)
+        .bind(new_name)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| write_failed("rename", new_name, e))
+    }
+
+    async fn clone_service(
+        &self,
+        source: &str,
+        name: &str,
+        link: &str,
+        internal_link: Option<&str>,
+    ) -> Result<i32, (StatusCode, String)> {
+        let mut tx = self.begin().await?;
+        let source = sqlx::query_scalar::<_, i32>(&format!("SELECT id FROM services WHERE id = {}", SERVICE_ID_BY_NAME))
+            .bind(source)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(internal)?
+            .ok_or((StatusCode::NOT_FOUND, "Service not found".into()))?;
+
+        let id = sqlx::query_scalar::<_, i32>(&format!(
+            "INSERT INTO services (name, link, internal_link, {0}) SELECT $2, $3, $4, {0} FROM services WHERE id = $1 \
+             RETURNING id",
+            CLONED_COLUMNS
+        ))
+        .bind(source)
+        .bind(name)
+        .bind(link)
+        .bind(internal_link)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| write_failed("insert", name, e))?;
+
+        sqlx::query!(
+            "INSERT INTO service_tags (service_id, tag_id) SELECT $1, tag_id FROM service_tags WHERE service_id = $2",
+            id,
+            source,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(internal)?;
+        tx.commit().await.map_err(internal)?;
+        Ok(id)
+    }
+
+    async fn trash(&self, name: &str) -> Result<Option<Service>, (StatusCode, String)> {
+        sqlx::query_as::<_, Service>(&format!(
+            "UPDATE services SET deleted_at = now() WHERE id = {} RETURNING *, {}",
+            SERVICE_ID_BY_NAME,
+            tags::TAGS_COLUMN
+        ))
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(internal)
+    }
+
+    async fn archive(&self, name: &str) -> Result<Option<Service>, (StatusCode, String)> {
+        let mut tx = self.begin().await?;
+        let archived = sqlx::query_as::<_, Service>(&format!(
+            "UPDATE services SET archived_at = now() WHERE id = {} AND archived_at IS NULL RETURNING *, {}",
+            SERVICE_ID_BY_NAME,
+            tags::TAGS_COLUMN
+        ))
+        .bind(name)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(internal)?;
+        let Some(archived) = archived else { return Ok(None) };
+        sqlx::query("UPDATE incidents SET resolved_at = now() WHERE service_id = $1 AND resolved_at IS NULL")
+            .bind(archived.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal)?;
+        tx.commit().await.map_err(internal)?;
+        Ok(Some(archived))
+    }
+
+    async fn unarchive(&self, name: &str) -> Result<Option<Service>, (StatusCode, String)> {
+        sqlx::query_as::<_, Service>(&format!(
+            "UPDATE services SET archived_at = NULL WHERE id = {} AND archived_at IS NOT NULL RETURNING *, {}",
+            SERVICE_ID_BY_NAME,
+            tags::TAGS_COLUMN
+        ))
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(internal)
+    }
+
+    // In one transaction, rolled back on a dry run, with a savepoint per row.
+    async fn import(
+        &self,
+        rows: Vec<(usize, Row)>,
+        force: bool,
+        dry_run: bool,
+        errors: &mut Vec<RowError>,
+    ) -> Result<(usize, Vec<String>), (StatusCode, String)> {
+        let mut tx = self.begin().await?;
+        let (mut categories, mut created_categories) = (HashMap::new(), Vec::new());
+        let mut imported = 0;
+        for (number, mut row) in rows {
+            row.tags.sort();
+            row.tags.dedup();
+            let mut fail = |error: String| errors.push(RowError::new(number, Some(row.name.clone()), error));
+            // Sees the rows imported so far, too.
+            match duplicates::check(&mut tx, &row.link, force).await {
+                Ok(None) => {}
+                Ok(Some(duplicate)) => {
+                    fail(duplicate.message);
+                    continue;
+                }
+                Err((_, error)) => {
+                    fail(error);
+                    continue;
+                }
+            }
+
+            // A savepoint per row, so a failed row doesn't abort the others.
+            let mut savepoint = Acquire::begin(&mut *tx).await.map_err(internal)?;
+            let mut new_categories = Vec::new();
+            match insert_row(&mut savepoint, &mut categories, &mut new_categories, &row).await {
+                Ok(()) => {
+                    savepoint.commit().await.map_err(internal)?;
+                    created_categories.append(&mut new_categories);
+                    imported += 1;
+                }
+                Err(e) => {
+                    savepoint.rollback().await.map_err(internal)?;
+                    // Ids looked up inside the rolled-back savepoint may be gone.
+                    categories.retain(|name, _| !new_categories.iter().any(|new| new.to_lowercase() == *name));
+                    fail(row_failed(e));
+                }
+            }
+        }
+
+        if dry_run {
+            tx.rollback().await.map_err(internal)?;
+        } else {
+            tx.commit().await.map_err(internal)?;
+        }
+        Ok((imported, created_categories))
+    }
+
+    async fn bulk_matches(
+        &self,
+        filter: &BulkFilter,
+        visibility: &Visibility,
+    ) -> Result<Vec<(i32, String)>, (StatusCode, String)> {
+        let mut sql = QueryBuilder::new(
+            "SELECT services.id, services.name FROM services \
+             LEFT JOIN categories ON categories.id = services.category_id WHERE services.deleted_at IS NULL",
+        );
+        if let Some(ids) = &filter.ids {
+            sql.push(" AND services.id = ANY(").push_bind(ids.clone()).push(")");
+        }
+        if let Some(tag) = &filter.tag {
+            sql.push(
+                " AND EXISTS (SELECT 1 FROM service_tags st JOIN tags t ON t.id = st.tag_id \
+                 WHERE st.service_id = services.id AND lower(t.name) = lower(",
+            )
+            .push_bind(tag.clone())
+            .push("))");
+        }
+        if let Some(category_id) = filter.category_id {
+            sql.push(" AND services.category_id = ").push_bind(category_id);
+        }
+        visibility.restrict_services(&mut sql);
+        sql.push(" ORDER BY services.id");
+        sql.build_query_as::<(i32, String)>().fetch_all(&self.pool).await.map_err(internal)
+    }
+
+    async fn bulk_update(
+        &self,
+        ids: &[i32],
+        changes: &BulkChanges,
+        visibility: &Visibility,
+    ) -> Result<Vec<i32>, (StatusCode, String)> {
+        let mut tx = self.begin().await?;
+        if let Some(Some(category_id)) = changes.category_id {
+            let mut sql = QueryBuilder::new("SELECT categories.id FROM categories WHERE categories.id = ");
+            sql.push_bind(category_id);
+            visibility.restrict(&mut sql);
+            if sql.build_query_scalar::<i32>().fetch_optional(&mut *tx).await.map_err(internal)?.is_none() {
+                return Err((StatusCode::BAD_REQUEST, format!("Category {} not found", category_id)));
+            }
+        }
+
+        let ids = sqlx::query_scalar!(
+            "UPDATE services SET category_id = CASE WHEN $2 THEN $3 ELSE category_id END, \
+             public = coalesce($4, public), updated_at = now() WHERE id = ANY($1) AND deleted_at IS NULL \
+             RETURNING id",
+            ids,
+            changes.category_id.is_some(),
+            changes.category_id.flatten(),
+            changes.public,
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(internal)?;
+        for id in &ids {
+            tags::attach(&mut tx, *id, &changes.add_tags).await.map_err(internal)?;
+        }
+        if !changes.remove_tags.is_empty() {
+            sqlx::query!(
+                "DELETE FROM service_tags WHERE service_id = ANY($1) \
+                 AND tag_id IN (SELECT id FROM tags WHERE name = ANY($2))",
+                &ids,
+                &changes.remove_tags,
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(internal)?;
+        }
+        tx.commit().await.map_err(internal)?;
+        Ok(ids)
+    }
+
+    async fn restore(&self, name: &str) -> Result<Option<String>, (StatusCode, String)> {
+        sqlx::query_scalar::<_, String>(
+            "UPDATE services SET deleted_at = NULL, updated_at = now() WHERE id = (SELECT id FROM services \
+             WHERE lower(name) = lower($1) AND deleted_at IS NOT NULL ORDER BY deleted_at DESC LIMIT 1) \
+             RETURNING name",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| write_failed("restore", name, e))
+    }
+
+    async fn set_maintenance(
+        &self,
+        name: &str,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Option<String>, (StatusCode, String)> {
+        let sql = match until {
+            Some(_) => "UPDATE services SET maintenance_until = $2 WHERE id = {} RETURNING name",
+            None => "UPDATE services SET maintenance_until = NULL WHERE id = {} AND maintenance_until > now() \
+                     RETURNING name",
+        };
+        sqlx::query_scalar::<_, String>(&sql.replace("{}", SERVICE_ID_BY_NAME))
+            .bind(name)
+            .bind(until)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(internal)
+    }
+
+    async fn set_colors(&self, name: &str, colors: &Colors) -> Result<Option<String>, (StatusCode, String)> {
+        sqlx::query_scalar::<_, String>(&format!(
+            "UPDATE services SET color = $2, accent = $3, updated_at = now() WHERE id = {} RETURNING name",
+            SERVICE_ID_BY_NAME
+        ))
+        .bind(name)
+        .bind(&colors.color)
+        .bind(&colors.accent)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(internal)
+    }
+
+    async fn set_mac(&self, name: &str, mac_address: Option<&str>) -> Result<Option<String>, (StatusCode, String)> {
+        sqlx::query_scalar::<_, String>(&format!(
+            "UPDATE services SET mac_address = $2 WHERE id = {} RETURNING name",
+            SERVICE_ID_BY_NAME
+        ))
+        .bind(name)
+        .bind(mac_address)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(internal)
+    }
+
+    async fn set_redirect(
+        &self,
+        name: &str,
+        permanent: bool,
+        pass_query: bool,
+        params: &str,
+    ) -> Result<Option<String>, (StatusCode, String)> {
+        sqlx::query_scalar::<_, String>(&format!(
+            "INSERT INTO service_redirects (service_id, permanent, pass_query, params) \
+             SELECT s.id, $2, $3, $4 FROM services s WHERE s.id = {} \
+             ON CONFLICT (service_id) DO UPDATE \
+             SET permanent = EXCLUDED.permanent, pass_query = EXCLUDED.pass_query, params = EXCLUDED.params \
+             RETURNING (SELECT name FROM services WHERE id = service_id)",
+            SERVICE_ID_BY_NAME
+        ))
+        .bind(name)
+        .bind(permanent)
+        .bind(pass_query)
+        .bind(params)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(internal)
+    }
+
+    async fn set_sla(&self, id: i32, target: f64, period: &str) -> Result<(), (StatusCode, String)> {
+        sqlx::query(
+            "INSERT INTO service_slas (service_id, target, period) VALUES ($1, $2, $3) \
+             ON CONFLICT (service_id) DO UPDATE SET target = EXCLUDED.target, period = EXCLUDED.period, \
+             updated_at = now()",
+        )
+        .bind(id)
+        .bind(target)
+        .bind(period)
+        .execute(&self.pool)
+        .await
+        .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn remove_sla(&self, id: i32) -> Result<Option<(f64, String)>, (StatusCode, String)> {
+        sqlx::query_as::<_, (f64, String)>("DELETE FROM service_slas WHERE service_id = $1 RETURNING target, period")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(internal)
+    }
+
+    async fn mark_synced(&self, source: &str, links: &[String]) -> Result<(), (StatusCode, String)> {
+        sqlx::query(
+            "UPDATE services SET synced_from = $1 \
+             WHERE deleted_at IS NULL AND synced_from IS NULL AND link = ANY($2)",
+        )
+        .bind(source)
+        .bind(links)
+        .execute(&self.pool)
+        .await
+        .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn synced_missing(&self, source: &str, links: &[String]) -> Result<Vec<String>, (StatusCode, String)> {
+        sqlx::query_scalar::<_, String>(
+            "SELECT name FROM services WHERE synced_from = $1 AND deleted_at IS NULL AND NOT (link = ANY($2))",
+        )
+        .bind(source)
+        .bind(links)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(internal)
+    }
+}
+
+// A ServiceRepository without a database, for tests. Categories are just
+// names, open to everyone; listings can't filter by status or sort. The
+// tests below hold it and PgServices to the same behaviour.
+#[cfg(test)]
+mod memory {
+    use chrono::{DateTime, SubsecRound, Utc};
+    use http::StatusCode;
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex, MutexGuard},
+    };
+
+    use super::{conflict, ServiceRepository, NAME_TAKEN};
+    use crate::{
+        bulk::{BulkChanges, BulkFilter},
+        categories::Visibility,
+        colors::Colors,
+        csv_import::{Row, RowError},
+        duplicates,
+        pagination::Page,
+        query::ServiceFilter,
+        services::{CreateService, Service, Source},
+    };
+
+    #[derive(Clone, Default)]
+    pub struct MemoryServices {
+        state: Arc<Mutex<State>>,
+    }
+
+    #[derive(Clone, Default)]
+    struct State {
+        // Each with whether it is in the trash.
+        services: Vec<(Service, bool)>,
+        // By id, from 1.
+        categories: Vec<String>,
+        // Ids of trashed services, most recently trashed last.
+        trashed: Vec<i32>,
+        // What Service leaves out, by service id.
+        maintenance: HashMap<i32, DateTime<Utc>>,
+        macs: HashMap<i32, String>,
+        redirects: HashMap<i32, (bool, bool, String)>,
+        slas: HashMap<i32, (f64, String)>,
+        synced_from: HashMap<i32, String>,
+    }
+
+    fn blank(name: &str, link: &str) -> Service {
+        // Microseconds, as Postgres keeps them (and cursors encode them).
+        let now = Utc::now().trunc_subsecs(6);
+        Service {
+            id: 0,
+            name: name.to_string(),
+            link: link.to_string(),
+            internal_link: None,
+            description: None,
+            check_type: "http".into(),
+            expected_ip: None,
+            latency_threshold_ms: None,
+            category_id: None,
+            org_id: None,
+            public: false,
+            created_at: now,
+            updated_at: now,
+            archived_at: None,
+            color: None,
+            accent: None,
+            tags: Vec::new(),
+            source: Source::Database,
+        }
+    }
+
+    fn visible(visibility: &Visibility, service: &Service) -> bool {
+        let category = service.category_id.map(|_| (None, &[][..], None));
+        visibility.allows(category, service.org_id, &[])
+    }
+
+    impl State {
+        fn live(&self) -> impl Iterator<Item = &Service> {
+            self.services.iter().filter(|(_, trashed)| !trashed).map(|(service, _)| service)
+        }
+
+        // The live service `name`, as SERVICE_ID_BY_NAME finds it.
+        fn find(&mut self, name: &str) -> Option<&mut Service> {
+            let wanted = name.to_lowercase();
+            self.services
+                .iter_mut()
+                .filter(|(service, trashed)| !trashed && service.name.to_lowercase() == wanted)
+                .map(|(service, _)| service)
+                .min_by_key(|service| (service.name != name, service.id))
+        }
+
+        fn taken(&self, name: &str) -> bool {
+            self.live().any(|service| service.name.to_lowercase() == name.to_lowercase())
+        }
+
+        fn check_category(&self, category_id: Option<i32>) -> Result<(), (StatusCode, String)> {
+            match category_id {
+                Some(id) if id < 1 || id as usize > self.categories.len() => {
+                    Err((StatusCode::BAD_REQUEST, format!("Category {} not found", id)))
+                }
+                _ => Ok(()),
+            }
+        }
+
+        // Finds the category by name, ignoring case, or creates it.
+        fn category_id(&mut self, name: &str, created: &mut Vec<String>) -> i32 {
+            let existing = self.categories.iter().position(|category| category.to_lowercase() == name.to_lowercase());
+            let index = existing.unwrap_or_else(|| {
+                created.push(name.to_string());
+                self.categories.push(name.to_string());
+                self.categories.len() - 1
+            });
+            index as i32 + 1
+        }
+
+        fn insert(&mut self, mut service: Service) -> Service {
+            service.id = self.services.len() as i32 + 1;
+            service.tags.sort();
+            service.tags.dedup();
+            self.services.push((service.clone(), false));
+            service
+        }
+    }
+
+    impl MemoryServices {
+        fn state(&self) -> MutexGuard<'_, State> {
+            self.state.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        pub fn seed_category(&self, name: &str) -> i32 {
+            self.state().category_id(name, &mut Vec::new())
+        }
+    }
+
+    impl ServiceRepository for MemoryServices {
+        async fn list(
+            &self,
+            filter: &ServiceFilter,
+            page: &Page,
+            visibility: &Visibility,
+        ) -> Result<Vec<Service>, (StatusCode, String)> {
+            let bad_request = |message: String| (StatusCode::BAD_REQUEST, message);
+            if filter.status.is_some() || filter.is_sorted() {
+                return Err(bad_request("MemoryServices can't filter by status or sort".into()));
+            }
+            let archived = match filter.state.as_deref().unwrap_or("active") {
+                "active" => Some(false),
+                "archived" => Some(true),
+                "all" => None,
+                other => return Err(bad_request(format!("Unknown state '{}'", other))),
+            };
+            let public = match filter.visibility.as_deref() {
+                None => None,
+                Some("public") => Some(true),
+                Some("private") => Some(false),
+                Some(other) => return Err(bad_request(format!("Unknown visibility '{}'", other))),
+            };
+            let tags: Vec<&str> =
+                filter.tag.as_deref().unwrap_or("").split(',').map(str::trim).filter(|tag| !tag.is_empty()).collect();
+            let q = filter.q.as_deref().map(str::to_lowercase);
+
+            let state = self.state();
+            let in_category = |service: &Service| {
+                filter.category.as_deref().is_none_or(|wanted| {
+                    let name = service.category_id.and_then(|id| state.categories.get(id as usize - 1));
+                    name.is_some_and(|name| name.to_lowercase() == wanted.to_lowercase())
+                })
+            };
+            let mut services: Vec<Service> = state
+                .live()
+                .filter(|service| visible(visibility, service) && in_category(service))
+                .filter(|service| tags.iter().all(|tag| service.tags.iter().any(|own| own == tag)))
+                .filter(|service| {
+                    q.as_deref().is_none_or(|q| {
+                        let texts = [Some(&service.name), Some(&service.link), service.internal_link.as_ref()];
+                        texts.into_iter().flatten().any(|text| text.to_lowercase().contains(q))
+                    })
+                })
+                .filter(|service| public.is_none_or(|public| service.public == public))
+                .filter(|service| archived.is_none_or(|archived| service.archived_at.is_some() == archived))
+                .cloned()
+                .collect();
+            services.sort_by_key(|service| (service.created_at, service.id));
+
+            match page {
+                Page::Keyset { after: Some(cursor), .. } => {
+                    let after = (cursor.created_at, cursor.id);
+                    services.retain(|service| (service.created_at, i64::from(service.id)) > after)
+                }
+                Page::Offset { offset, .. } => {
+                    services.drain(..(*offset as usize).min(services.len()));
+                }
+                _ => {}
+            }
+            if let Some(limit) = page.fetch_limit() {
+                services.truncate(limit as usize);
+            }
+            Ok(services)
+        }
+
+        async fn get(&self, id: i32) -> Result<Option<Service>, (StatusCode, String)> {
+            Ok(self.state().services.iter().find(|(service, _)| service.id == id).map(|(service, _)| service.clone()))
+        }
+
+        async fn create(
+            &self,
+            service: &CreateService,
+            check_type: &str,
+            _check_token_encrypted: Option<String>,
+        ) -> Result<Service, (StatusCode, String)> {
+            let mut state = self.state();
+            state.check_category(service.category_id)?;
+            if state.taken(&service.name) {
+                return Err(conflict(&service.name));
+            }
+            Ok(state.insert(Service {
+                internal_link: service.internal_link.clone(),
+                description: service.description.clone(),
+                check_type: check_type.to_string(),
+                expected_ip: service.expected_ip.clone(),
+                latency_threshold_ms: service.latency_threshold_ms,
+                category_id: service.category_id,
+                org_id: service.org_id,
+                public: service.public,
+                color: service.colors.color.clone(),
+                accent: service.colors.accent.clone(),
+                tags: service.tags.clone(),
+                ..blank(&service.name, &service.link)
+            }))
+        }
+
+        async fn rename(&self, name: &str, new_name: &str) -> Result<Option<i32>, (StatusCode, String)> {
+            let mut state = self.state();
+            let Some(id) = state.find(name).map(|service| service.id) else { return Ok(None) };
+            if state.live().any(|other| other.id != id && other.name.to_lowercase() == new_name.to_lowercase()) {
+                return Err(conflict(new_name));
+            }
+            let service = state.find(name).ok_or((StatusCode::NOT_FOUND, "Service not found".into()))?;
+            service.name = new_name.to_string();
+            service.updated_at = Utc::now();
+            Ok(Some(id))
+        }
+
+        async fn clone_service(
+            &self,
+            source: &str,
+            name: &str,
+            link: &str,
+            internal_link: Option<&str>,
+        ) -> Result<i32, (StatusCode, String)> {
+            let mut state = self.state();
+            let source = state.find(source).ok_or((StatusCode::NOT_FOUND, "Service not found".into()))?.clone();
+            if state.taken(name) {
+                return Err(conflict(name));
+            }
+            let now = Utc::now();
+            let copy = Service {
+                name: name.to_string(),
+                link: link.to_string(),
+                internal_link: internal_link.map(str::to_string),
+                created_at: now,
+                updated_at: now,
+                archived_at: None,
+                ..source
+            };
+            Ok(state.insert(copy).id)
+        }
+
+        async fn trash(&self, name: &str) -> Result<Option<Service>, (StatusCode, String)> {
+            let mut state = self.state();
+            let Some(id) = state.find(name).map(|service| service.id) else { return Ok(None) };
+            state.trashed.push(id);
+            let (service, trashed) = &mut state.services[id as usize - 1];
+            *trashed = true;
+            Ok(Some(service.clone()))
+        }
+
+        async fn archive(&self, name: &str) -> Result<Option<Service>, (StatusCode, String)> {
+            let mut state = self.state();
+            let service = state.find(name).filter(|service| service.archived_at.is_none());
+            Ok(service.map(|service| {
+                service.archived_at = Some(Utc::now());
+                service.clone()
+            }))
+        }
+
+        async fn unarchive(&self, name: &str) -> Result<Option<Service>, (StatusCode, String)> {
+            let mut state = self.state();
+            let service = state.find(name).filter(|service| service.archived_at.is_some());
+            Ok(service.map(|service| {
+                service.archived_at = None;
+                service.clone()
+            }))
+        }
+
+        async fn import(
+            &self,
+            rows: Vec<(usize, Row)>,
+            force: bool,
+            dry_run: bool,
+            errors: &mut Vec<RowError>,
+        ) -> Result<(usize, Vec<String>), (StatusCode, String)> {
+            let mut state = self.state();
+            let before = dry_run.then(|| state.clone());
+            let (mut imported, mut created_categories) = (0, Vec::new());
+            for (number, row) in rows {
+                let mut fail = |error: String| errors.push(RowError::new(number, Some(row.name.clone()), error));
+                let existing = state.live().find(|service| duplicates::same(&service.link, &row.link)).cloned();
+                if let Some(duplicate) = duplicates::verdict(existing, &row.link, force) {
+                    fail(duplicate.message);
+                    continue;
+                }
+                if state.taken(&row.name) {
+                    fail(NAME_TAKEN.into());
+                    continue;
+                }
+                let category_id = row.category.as_deref().map(|name| state.category_id(name, &mut created_categories));
+                state.insert(Service {
+                    category_id,
+                    description: row.description.clone(),
+                    tags: row.tags.clone(),
+                    ..blank(&row.name, &row.link)
+                });
+                imported += 1;
+            }
+            if let Some(before) = before {
+                *state = before;
+            }
+            Ok((imported, created_categories))
+        }
+
+        async fn bulk_matches(
+            &self,
+            filter: &BulkFilter,
+            visibility: &Visibility,
+        ) -> Result<Vec<(i32, String)>, (StatusCode, String)> {
+            let state = self.state();
+            Ok(state
+                .live()
+                .filter(|service| filter.ids.as_ref().is_none_or(|ids| ids.contains(&service.id)))
+                .filter(|service| {
+                    let tag = filter.tag.as_deref().map(str::to_lowercase);
+                    tag.is_none_or(|tag| service.tags.iter().any(|own| own.to_lowercase() == tag))
+                })
+                .filter(|service| filter.category_id.is_none_or(|id| service.category_id == Some(id)))
+                .filter(|service| visible(visibility, service))
+                .map(|service| (service.id, service.name.clone()))
+                .collect())
+        }
+
+        async fn bulk_update(
+            &self,
+            ids: &[i32],
+            changes: &BulkChanges,
+            _visibility: &Visibility,
+        ) -> Result<Vec<i32>, (StatusCode, String)> {
+            let mut state = self.state();
+            state.check_category(changes.category_id.flatten())?;
+            let mut updated = Vec::new();
+            let changing = state.services.iter_mut().filter(|(service, trashed)| !trashed && ids.contains(&service.id));
+            for (service, _) in changing {
+                if let Some(category_id) = changes.category_id {
+                    service.category_id = category_id;
+                }
+                if let Some(public) = changes.public {
+                    service.public = public;
+                }
+                service.tags.extend(changes.add_tags.iter().cloned());
+                service.tags.retain(|tag| !changes.remove_tags.contains(tag));
+                service.tags.sort();
+                service.tags.dedup();
+                service.updated_at = Utc::now();
+                updated.push(service.id);
+            }
+            Ok(updated)
+        }
+
+        async fn restore(&self, name: &str) -> Result<Option<String>, (StatusCode, String)> {
+            let mut state = self.state();
+            let named = |id: &&i32| state.services[**id as usize - 1].0.name.to_lowercase() == name.to_lowercase();
+            let Some(&id) = state.trashed.iter().rev().find(named) else { return Ok(None) };
+            if state.taken(name) {
+                return Err(conflict(name));
+            }
+            state.trashed.retain(|trashed| *trashed != id);
+            let (service, trashed) = &mut state.services[id as usize - 1];
+            *trashed = false;
+            service.updated_at = Utc::now();
+            Ok(Some(service.name.clone()))
+        }
+
+        async fn set_maintenance(
+            &self,
+            name: &str,
+            until: Option<DateTime<Utc>>,
+        ) -> Result<Option<String>, (StatusCode, String)> {
+            let mut state = self.state();
+            let Some((id, name)) = state.find(name).map(|service| (service.id, service.name.clone())) else {
+                return Ok(None);
+            };
+            let found = match until {
+                Some(until) => {
+                    state.maintenance.insert(id, until);
+                    true
+                }
+                None => state.maintenance.remove(&id).is_some_and(|until| until > Utc::now()),
+            };
+            Ok(found.then_some(name))
+        }
+
+        async fn set_colors(&self, name: &str, colors: &Colors) -> Result<Option<String>, (StatusCode, String)> {
+            let mut state = self.state();
+            Ok(state.find(name).map(|service| {
+                service.color = colors.color.clone();
+                service.accent = colors.accent.clone();
+                service.updated_at = Utc::now();
+                service.name.clone()
+            }))
+        }
+
+        async fn set_mac(
+            &self,
+            name: &str,
+            mac_address: Option<&str>,
+        ) -> Result<Option<String>, (StatusCode, String)> {
+            let mut state = self.state();
+            let Some((id, name)) = state.find(name).map(|service| (service.id, service.name.clone())) else {
+                return Ok(None);
+            };
+            match mac_address {
+                Some(mac_address) => state.macs.insert(id, mac_address.to_string()),
+                None => state.macs.remove(&id),
+            };
+            Ok(Some(name))
+        }
+
+        async fn set_redirect(
+            &self,
+            name: &str,
+            permanent: bool,
+            pass_query: bool,
+            params: &str,
+        ) -> Result<Option<String>, (StatusCode, String)> {
+            let mut state = self.state();
+            let Some((id, name)) = state.find(name).map(|service| (service.id, service.name.clone())) else {
+                return Ok(None);
+            };
+            state.redirects.insert(id, (permanent, pass_query, params.to_string()));
+            Ok(Some(name))
+        }
+
+        async fn set_sla(&self, id: i32, target: f64, period: &str) -> Result<(), (StatusCode, String)> {
+            self.state().slas.insert(id, (target, period.to_string()));
+            Ok(())
+        }
+
+        async fn remove_sla(&self, id: i32) -> Result<Option<(f64, String)>, (StatusCode, String)> {
+            Ok(self.state().slas.remove(&id))
+        }
+
+        async fn mark_synced(&self, source: &str, links: &[String]) -> Result<(), (StatusCode, String)> {
+            let mut state = self.state();
+            let ids: Vec<i32> =
+                state.live().filter(|service| links.contains(&service.link)).map(|service| service.id).collect();
+            for id in ids {
+                state.synced_from.entry(id).or_insert_with(|| source.to_string());
+            }
+            Ok(())
+        }
+
+        async fn synced_missing(&self, source: &str, links: &[String]) -> Result<Vec<String>, (StatusCode, String)> {
+            let state = self.state();
+            Ok(state
+                .live()
+                .filter(|service| state.synced_from.get(&service.id).is_some_and(|from| from == source))
+                .filter(|service| !links.contains(&service.link))
+                .map(|service| service.name.clone())
+                .collect())
+        }
+    }
+}
+
+// Each contract runs against MemoryServices and, with TEST_DATABASE_URL,
+// PgServices, with a "Docs" category to file services under.
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{pagination::Cursor, testing::TestApp};
+
+    fn new_service(fields: serde_json::Value) -> CreateService {
+        serde_json::from_value(fields).unwrap()
+    }
+
+    async fn create(services: &impl ServiceRepository, fields: serde_json::Value) -> Service {
+        services.create(&new_service(fields), "http", None).await.unwrap()
+    }
+
+    fn row(name: &str, link: &str, category: Option<&str>, tags: &[&str]) -> Row {
+        Row {
+            name: name.into(),
+            link: link.into(),
+            category: category.map(str::to_string),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            description: None,
+            icon: None,
+        }
+    }
+
+    async fn names(services: &impl ServiceRepository, state: &str) -> Vec<String> {
+        let filter = ServiceFilter { state: Some(state.into()), ..Default::default() };
+        let listed = services.list(&filter, &Page::All, &Visibility::everything()).await.unwrap();
+        listed.into_iter().map(|service| service.name).collect()
+    }
+
+    async fn creates_and_renames(services: impl ServiceRepository, docs: i32) {
+        let wiki = json!({"name": "Wiki", "link": "https://wiki.example.com", "category_id": docs,
+                          "tags": ["b", "a", "a"]});
+        let wiki = create(&services, wiki).await;
+        assert_eq!(wiki.tags, ["a", "b"]);
+        assert_eq!(wiki.category_id, Some(docs));
+        let taken = new_service(json!({"name": "wiki", "link": "https://w.example.com"}));
+        assert_eq!(services.create(&taken, "http", None).await.unwrap_err().0, StatusCode::CONFLICT);
+        create(&services, json!({"name": "Grafana", "link": "https://grafana.example.com"})).await;
+
+        assert_eq!(services.rename("WIKI", "Docs").await.unwrap(), Some(wiki.id));
+        assert_eq!(services.rename("Missing", "Other").await.unwrap(), None);
+        assert_eq!(services.rename("grafana", "docs").await.unwrap_err().0, StatusCode::CONFLICT);
+        assert_eq!(services.get(wiki.id).await.unwrap().unwrap().name, "Docs");
+    }
+
+    async fn archives_and_trashes(services: impl ServiceRepository) {
+        let wiki = create(&services, json!({"name": "Wiki", "link": "https://wiki.example.com"})).await;
+        assert!(services.archive("wiki").await.unwrap().unwrap().archived_at.is_some());
+        assert!(services.archive("wiki").await.unwrap().is_none());
+        assert!(names(&services, "active").await.is_empty());
+        assert_eq!(names(&services, "archived").await, ["Wiki"]);
+
+        assert!(services.unarchive("Wiki").await.unwrap().unwrap().archived_at.is_none());
+        assert!(services.unarchive("Wiki").await.unwrap().is_none());
+        assert_eq!(services.trash("Wiki").await.unwrap().unwrap().id, wiki.id);
+        assert!(services.trash("Wiki").await.unwrap().is_none());
+        assert!(services.get(wiki.id).await.unwrap().is_some());
+        assert!(names(&services, "all").await.is_empty());
+        // The trash doesn't hold on to the name.
+        create(&services, json!({"name": "Wiki", "link": "https://wiki.example.com"})).await;
+    }
+
+    async fn clones(services: impl ServiceRepository, docs: i32) {
+        let wiki = json!({"name": "Wiki", "link": "https://wiki.example.com", "category_id": docs, "public": true,
+                          "tags": ["docs"], "color": "#112233"});
+        create(&services, wiki).await;
+        let id = services.clone_service("wiki", "Wiki 2", "https://wiki2.example.com", None).await.unwrap();
+        let copy = services.get(id).await.unwrap().unwrap();
+        assert_eq!((copy.name.as_str(), copy.link.as_str()), ("Wiki 2", "https://wiki2.example.com"));
+        assert_eq!((copy.category_id, copy.public, copy.color.as_deref()), (Some(docs), true, Some("#112233")));
+        assert_eq!(copy.tags, ["docs"]);
+
+        let missing = services.clone_service("Missing", "Other", "https://other.example.com", None).await;
+        assert_eq!(missing.unwrap_err().0, StatusCode::NOT_FOUND);
+        let taken = services.clone_service("Wiki", "wiki 2", "https://other.example.com", None).await;
+        assert_eq!(taken.unwrap_err().0, StatusCode::CONFLICT);
+    }
+
+    async fn imports(services: impl ServiceRepository, docs: i32) {
+        create(&services, json!({"name": "Wiki", "link": "https://wiki.example.com"})).await;
+        let rows = || {
+            vec![
+                (2, row("Grafana", "https://grafana.example.com", Some("media"), &["x", "x"])),
+                (3, row("Wiki copy", "http://www.wiki.example.com/", None, &[])),
+                (4, row("grafana", "https://other.example.com", None, &[])),
+                (5, row("Plex", "https://plex.example.com", Some("Media"), &[])),
+                (6, row("Manual", "https://manual.example.com", Some("DOCS"), &[])),
+            ]
+        };
+        for dry_run in [true, false] {
+            let mut errors = Vec::new();
+            let (imported, created) = services.import(rows(), false, dry_run, &mut errors).await.unwrap();
+            assert_eq!((imported, created), (3, vec!["media".to_string()]));
+            let failed: Vec<usize> = errors.iter().map(RowError::row).collect();
+            assert_eq!(failed, [3, 4]);
+        }
+        assert_eq!(names(&services, "all").await, ["Wiki", "Grafana", "Plex", "Manual"]);
+        let listed = services.list(&ServiceFilter::default(), &Page::All, &Visibility::everything()).await.unwrap();
+        assert_eq!(listed[1].tags, ["x"]);
+        assert_eq!(listed[1].category_id, listed[2].category_id);
+        assert_eq!(listed[3].category_id, Some(docs));
+
+        // Forced, near-duplicates go in but identical links still don't.
+        let mut errors = Vec::new();
+        let forced = vec![
+            (2, row("Wiki copy", "http://www.wiki.example.com/", None, &[])),
+            (3, row("Wiki again", "https://wiki.example.com", None, &[])),
+        ];
+        assert_eq!(services.import(forced, true, false, &mut errors).await.unwrap().0, 1);
+        assert_eq!(errors.iter().map(RowError::row).collect::<Vec<_>>(), [3]);
+    }
+
+    async fn bulk_updates(services: impl ServiceRepository, docs: i32) {
+        let everything = Visibility::everything();
+        let a = create(&services, json!({"name": "A", "link": "https://a.example.com", "tags": ["media"]})).await;
+        let b = create(&services, json!({"name": "B", "link": "https://b.example.com", "tags": ["Media"]})).await;
+        let c = create(&services, json!({"name": "C", "link": "https://c.example.com"})).await;
+
+        let by_tag: BulkFilter = serde_json::from_value(json!({"tag": "MEDIA"})).unwrap();
+        let matched = services.bulk_matches(&by_tag, &everything).await.unwrap();
+        assert_eq!(matched, [(a.id, "A".to_string()), (b.id, "B".to_string())]);
+        let by_id: BulkFilter = serde_json::from_value(json!({"ids": [c.id, a.id]})).unwrap();
+        assert_eq!(services.bulk_matches(&by_id, &everything).await.unwrap().len(), 2);
+
+        let changes: BulkChanges = serde_json::from_value(
+            json!({"add_tags": ["new"], "remove_tags": ["media"], "category_id": docs, "public": true}),
+        )
+        .unwrap();
+        let mut updated = services.bulk_update(&[a.id, b.id], &changes, &everything).await.unwrap();
+        updated.sort();
+        assert_eq!(updated, [a.id, b.id]);
+        let a = services.get(a.id).await.unwrap().unwrap();
+        assert_eq!((a.tags, a.category_id, a.public), (vec!["new".to_string()], Some(docs), true));
+        assert_eq!(services.get(b.id).await.unwrap().unwrap().tags, ["Media", "new"]);
+
+        let unknown: BulkChanges = serde_json::from_value(json!({"category_id": docs + 100})).unwrap();
+        let refused = services.bulk_update(&[c.id], &unknown, &everything).await;
+        assert_eq!(refused.unwrap_err().0, StatusCode::BAD_REQUEST);
+        let uncategorized: BulkChanges = serde_json::from_value(json!({"category_id": null})).unwrap();
+        services.bulk_update(&[a.id], &uncategorized, &everything).await.unwrap();
+        assert_eq!(services.get(a.id).await.unwrap().unwrap().category_id, None);
+
+        services.trash("C").await.unwrap();
+        assert!(services.bulk_update(&[c.id], &uncategorized, &everything).await.unwrap().is_empty());
+    }
+
+    async fn lists_by_page(services: impl ServiceRepository) {
+        for name in ["Grafana", "Wiki", "Plex"] {
+            create(&services, json!({"name": name, "link": format!("https://{}.example.com", name)})).await;
+        }
+        let everything = Visibility::everything();
+        let filter = ServiceFilter::default();
+        let first = services.list(&filter, &Page::Keyset { limit: 2, after: None }, &everything).await.unwrap();
+        assert_eq!(first.len(), 3);
+        let after = Cursor { created_at: first[0].created_at, id: first[0].id.into() };
+        let rest = services.list(&filter, &Page::Keyset { limit: 2, after: Some(after) }, &everything).await.unwrap();
+        assert_eq!(rest.iter().map(|service| service.name.as_str()).collect::<Vec<_>>(), ["Wiki", "Plex"]);
+        let offset = services.list(&filter, &Page::Offset { limit: 5, offset: 2 }, &everything).await.unwrap();
+        assert_eq!(offset[0].name, "Plex");
+
+        let q = ServiceFilter { q: Some("GRAF".into()), ..Default::default() };
+        assert_eq!(services.list(&q, &Page::All, &everything).await.unwrap()[0].name, "Grafana");
+        let unknown = ServiceFilter { state: Some("gone".into()), ..Default::default() };
+        assert_eq!(services.list(&unknown, &Page::All, &everything).await.unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+
+    async fn restores_and_sets(services: impl ServiceRepository) {
+        create(&services, json!({"name": "Wiki", "link": "https://wiki.example.com"})).await;
+        services.trash("Wiki").await.unwrap();
+        let wiki = create(&services, json!({"name": "wiki", "link": "https://wiki2.example.com"})).await;
+        assert_eq!(services.restore("WIKI").await.unwrap_err().0, StatusCode::CONFLICT);
+        // The most recently trashed one comes back.
+        services.trash("wiki").await.unwrap();
+        assert_eq!(services.restore("WIKI").await.unwrap().as_deref(), Some("wiki"));
+        assert!(services.restore("Missing").await.unwrap().is_none());
+
+        assert!(services.set_maintenance("wiki", None).await.unwrap().is_none());
+        let until = Utc::now() + chrono::Duration::hours(1);
+        assert_eq!(services.set_maintenance("Wiki", Some(until)).await.unwrap().as_deref(), Some("wiki"));
+        assert_eq!(services.set_maintenance("wiki", None).await.unwrap().as_deref(), Some("wiki"));
+        assert!(services.set_maintenance("Missing", Some(until)).await.unwrap().is_none());
+
+        let colors = Colors { color: Some("#112233".into()), accent: None };
+        assert_eq!(services.set_colors("wiki", &colors).await.unwrap().as_deref(), Some("wiki"));
+        assert_eq!(services.get(wiki.id).await.unwrap().unwrap().color.as_deref(), Some("#112233"));
+        assert!(services.set_mac("wiki", Some("aa:bb:cc:dd:ee:ff")).await.unwrap().is_some());
+        assert!(services.set_mac("Missing", None).await.unwrap().is_none());
+        assert!(services.set_redirect("wiki", true, false, "utm_source=x").await.unwrap().is_some());
+        assert!(services.set_redirect("Missing", true, false, "").await.unwrap().is_none());
+
+        assert!(services.remove_sla(wiki.id).await.unwrap().is_none());
+        services.set_sla(wiki.id, 99.0, "week").await.unwrap();
+        services.set_sla(wiki.id, 99.5, "month").await.unwrap();
+        assert_eq!(services.remove_sla(wiki.id).await.unwrap(), Some((99.5, "month".to_string())));
+    }
+
+    async fn tracks_synced_services(services: impl ServiceRepository) {
+        let links: Vec<String> = ["a", "b", "c"].iter().map(|name| format!("https://{}.example.com", name)).collect();
+        for (name, link) in ["A", "B", "C"].iter().zip(&links) {
+            create(&services, json!({"name": name, "link": link})).await;
+        }
+        services.mark_synced("npm", &links[..2]).await.unwrap();
+        services.mark_synced("caddy", &links).await.unwrap();
+        assert_eq!(services.synced_missing("npm", &links[..1]).await.unwrap(), ["B"]);
+        assert_eq!(services.synced_missing("caddy", &[]).await.unwrap(), ["C"]);
+        services.trash("B").await.unwrap();
+        assert!(services.synced_missing("npm", &links[..1]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn memory_services_keep_the_contract() {
+        let memory = || {
+            let services = MemoryServices::default();
+            let docs = services.seed_category("Docs");
+            (services, docs)
+        };
+        let (services, docs) = memory();
+        creates_and_renames(services, docs).await;
+        archives_and_trashes(memory().0).await;
+        let (services, docs) = memory();
+        clones(services, docs).await;
+        let (services, docs) = memory();
+        imports(services, docs).await;
+        let (services, docs) = memory();
+        bulk_updates(services, docs).await;
+        lists_by_page(memory().0).await;
+        restores_and_sets(memory().0).await;
+        tracks_synced_services(memory().0).await;
+    }
+
+    #[tokio::test]
+    async fn pg_services_keep_the_contract() {
+        let postgres = || async {
+            let app = TestApp::spawn().await.unwrap();
+            let docs = app.seed_category("Docs").await;
+            (PgServices::new(app.pool.clone()), docs, app)
+        };
+        if std::env::var("TEST_DATABASE_URL").is_err() {
+            return;
+        }
+        let (services, docs, app) = postgres().await;
+        creates_and_renames(services, docs).await;
+        app.finish().await;
+        let (services, _, app) = postgres().await;
+        archives_and_trashes(services).await;
+        app.finish().await;
+        let (services, docs, app) = postgres().await;
+        clones(services, docs).await;
+        app.finish().await;
+        let (services, docs, app) = postgres().await;
+        imports(services, docs).await;
+        app.finish().await;
+        let (services, docs, app) = postgres().await;
+        bulk_updates(services, docs).await;
+        app.finish().await;
+        let (services, _, app) = postgres().await;
+        lists_by_page(services).await;
+        app.finish().await;
+        let (services, _, app) = postgres().await;
+        restores_and_sets(services).await;
+        app.finish().await;
+        let (services, _, app) = postgres().await;
+        tracks_synced_services(services).await;
+        app.finish().await;
+    }
+}
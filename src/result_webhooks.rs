@@ -0,0 +1,200 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::{sync::LazyLock, time::Duration};
+
+use crate::{
+    audit::{Actor, Audit},
+    services::SERVICE_ID_BY_NAME,
+};
+
+// Webhooks that receive the raw check results, apart from the alerting in
+// notify.rs: every result as it is recorded, one JSON object per POST, or
+// with `batch` each round's results as one JSON array. A webhook gets the
+// results of the services it lists, or of all of them. Deliveries are not
+// retried; failures are only logged.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_WEBHOOKS: i64 = 50;
+
+static HTTP: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .user_agent(concat!("indexpage/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .unwrap_or_default()
+});
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Webhook {
+    id: i32,
+    url: String,
+    // Names; empty for every service.
+    services: Vec<String>,
+    batch: bool,
+    created_by: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhook {
+    url: String,
+    #[serde(default)]
+    services: Vec<String>,
+    #[serde(default)]
+    batch: bool,
+}
+
+const SELECT_WEBHOOKS: &str = "SELECT w.id, w.url, w.batch, w.created_by, w.created_at, \
+     ARRAY(SELECT s.name FROM services s WHERE s.id = ANY(w.service_ids) ORDER BY lower(s.name)) AS services \
+     FROM result_webhooks w";
+
+fn internal(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+// GET /result-webhooks
+pub async fn get_webhooks(State(pool): State<PgPool>) -> Result<Json<Vec<Webhook>>, (StatusCode, String)> {
+    let webhooks = sqlx::query_as::<_, Webhook>(&format!("{} ORDER BY w.id", SELECT_WEBHOOKS))
+        .fetch_all(&pool)
+        .await
+        .map_err(internal)?;
+    Ok(Json(webhooks))
+}
+
+// POST /result-webhooks
+// Body: {"url": "https://...", "services": ["name", ...], "batch": false}.
+pub async fn create_webhook(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Json(payload): Json<CreateWebhook>,
+) -> Result<Json<Webhook>, (StatusCode, String)> {
+    let url = url::Url::parse(payload.url.trim())
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .ok_or((StatusCode::BAD_REQUEST, format!("'{}' is not an http(s) URL", payload.url)))?;
+    let mut service_ids = Vec::new();
+    for name in &payload.services {
+        let id = sqlx::query_scalar::<_, Option<i32>>(&format!("SELECT {}", SERVICE_ID_BY_NAME))
+            .bind(name)
+            .fetch_one(&pool)
+            .await
+            .map_err(internal)?
+            .ok_or((StatusCode::NOT_FOUND, format!("Service '{}' not found", name)))?;
+        service_ids.push(id);
+    }
+    let count = sqlx::query_scalar::<_, i64>("SELECT count(*) FROM result_webhooks")
+        .fetch_one(&pool)
+        .await
+        .map_err(internal)?;
+    if count >= MAX_WEBHOOKS {
+        return Err((StatusCode::CONFLICT, format!("At most {} result webhooks are allowed", MAX_WEBHOOKS)));
+    }
+    let id = sqlx::query_scalar::<_, i32>(
+        "INSERT INTO result_webhooks (url, service_ids, batch, created_by) VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(url.as_str())
+    .bind(&service_ids)
+    .bind(payload.batch)
+    .bind(&actor.name)
+    .fetch_one(&pool)
+    .await
+    .map_err(internal)?;
+    let webhook = sqlx::query_as::<_, Webhook>(&format!("{} WHERE w.id = $1", SELECT_WEBHOOKS))
+        .bind(id)
+        .fetch_one(&pool)
+        .await
+        .map_err(internal)?;
+    audit.record(&actor, "result_webhook.create", None, Some(format!("#{} {}", webhook.id, webhook.url))).await;
+    Ok(Json(webhook))
+}
+
+// DELETE /result-webhooks/:id
+// Returns the deleted webhook.
+pub async fn delete_webhook(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<Webhook>, (StatusCode, String)> {
+    let webhook = sqlx::query_as::<_, Webhook>(&format!(
+        "WITH w AS (DELETE FROM result_webhooks WHERE id = $1 RETURNING *) {}",
+        SELECT_WEBHOOKS.replace("FROM result_webhooks w", "FROM w")
+    ))
+    .bind(id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(internal)?
+    .ok_or((StatusCode::NOT_FOUND, "Result webhook not found".into()))?;
+    audit.record(&actor, "result_webhook.delete", None, Some(format!("#{} {}", webhook.id, webhook.url))).await;
+    Ok(Json(webhook))
+}
+
+// One check result as webhooks receive it.
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub service: String,
+    pub service_id: i32,
+    pub ok: bool,
+    pub latency_ms: i32,
+    pub error: Option<String>,
+    pub degraded: bool,
+    pub cert_expires_at: Option<DateTime<Utc>>,
+    pub checked_at: DateTime<Utc>,
+}
+
+// The webhooks for one round of checks, loaded as it starts.
+#[cfg_attr(not(feature = "checks"), allow(dead_code))]
+pub struct Round {
+    // (url, service ids, results held back for a batch webhook)
+    webhooks: Vec<(String, Vec<i32>, Option<Vec<CheckResult>>)>,
+}
+
+#[cfg_attr(not(feature = "checks"), allow(dead_code))]
+impl Round {
+    pub async fn start(pool: &PgPool) -> sqlx::Result<Self> {
+        let rows = sqlx::query_as::<_, (String, Vec<i32>, bool)>("SELECT url, service_ids, batch FROM result_webhooks")
+            .fetch_all(pool)
+            .await?;
+        let webhooks = rows.into_iter().map(|(url, ids, batch)| (url, ids, batch.then(Vec::new))).collect();
+        Ok(Self { webhooks })
+    }
+
+    // Sends `result` to the webhooks taking results one by one, and holds
+    // it for the batch ones.
+    pub fn deliver(&mut self, result: &CheckResult) {
+        for (url, ids, batch) in &mut self.webhooks {
+            if !ids.is_empty() && !ids.contains(&result.service_id) {
+                continue;
+            }
+            match batch {
+                Some(batch) => batch.push(result.clone()),
+                None => post(url.clone(), serde_json::json!(result)),
+            }
+        }
+    }
+
+    // Sends the batches.
+    pub fn finish(self) {
+        for (url, _, batch) in self.webhooks {
+            if let Some(batch) = batch.filter(|batch| !batch.is_empty()) {
+                post(url, serde_json::json!(batch));
+            }
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "checks"), allow(dead_code))]
+fn post(url: String, body: serde_json::Value) {
+    tokio::spawn(async move {
+        let sent = HTTP.post(&url).json(&body).send().await.and_then(reqwest::Response::error_for_status);
+        if let Err(e) = sent {
+            tracing::warn!("result webhook {} failed: {}", url, e);
+        }
+    });
+}
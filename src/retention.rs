@@ -0,0 +1,103 @@
+use sqlx::PgPool;
+use std::time::Duration;
+
+use crate::{
+    config::RetentionConfig,
+    scheduler::{Schedule, Scheduler},
+};
+
+pub fn register(scheduler: &Scheduler, pool: PgPool, config: RetentionConfig) -> anyhow::Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let interval = Schedule::Every(Duration::from_secs(config.interval_secs.max(60)));
+    scheduler.register("retention", interval, move || {
+        let (pool, config) = (pool.clone(), config.clone());
+        async move { Ok(prune(&pool, &config).await?) }
+    })
+}
+
+// Folds raw check results older than `raw_days` into hourly rollups, hourly
+// rollups older than `hourly_days` into daily ones, and drops daily rollups
+// past `daily_days` (0 keeps them forever). Cutoffs are truncated to whole
+// buckets so a bucket is never split between two runs.
+pub async fn prune(pool: &PgPool, config: &RetentionConfig) -> sqlx::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO check_rollups (service_id, period, bucket, total_checks, ok_checks, avg_latency_ms, max_latency_ms)
+        SELECT service_id, 'hour', date_trunc('hour', checked_at), count(*), count(*) FILTER (WHERE ok),
+               coalesce(avg(latency_ms), 0), coalesce(max(latency_ms), 0)
+        FROM check_results
+        WHERE checked_at < date_trunc('hour', now() - make_interval(days => $1))
+        GROUP BY service_id, date_trunc('hour', checked_at)
+        ON CONFLICT (service_id, period, bucket) DO UPDATE SET
+            avg_latency_ms = (check_rollups.avg_latency_ms * check_rollups.total_checks
+                + excluded.avg_latency_ms * excluded.total_checks)
+                / (check_rollups.total_checks + excluded.total_checks),
+            total_checks = check_rollups.total_checks + excluded.total_checks,
+            ok_checks = check_rollups.ok_checks + excluded.ok_checks,
+            max_latency_ms = greatest(check_rollups.max_latency_ms, excluded.max_latency_ms)
+        "#,
+        config.raw_days.max(1),
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let raw = sqlx::query!(
+        "DELETE FROM check_results WHERE checked_at < date_trunc('hour', now() - make_interval(days => $1))",
+        config.raw_days.max(1),
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO check_rollups (service_id, period, bucket, total_checks, ok_checks, avg_latency_ms, max_latency_ms)
+        SELECT service_id, 'day', date_trunc('day', bucket), sum(total_checks), sum(ok_checks),
+               sum(avg_latency_ms * total_checks) / sum(total_checks), max(max_latency_ms)
+        FROM check_rollups
+        WHERE period = 'hour' AND bucket < date_trunc('day', now() - make_interval(days => $1))
+        GROUP BY service_id, date_trunc('day', bucket)
+        ON CONFLICT (service_id, period, bucket) DO UPDATE SET
+            avg_latency_ms = (check_rollups.avg_latency_ms * check_rollups.total_checks
+                + excluded.avg_latency_ms * excluded.total_checks)
+                / (check_rollups.total_checks + excluded.total_checks),
+            total_checks = check_rollups.total_checks + excluded.total_checks,
+            ok_checks = check_rollups.ok_checks + excluded.ok_checks,
+            max_latency_ms = greatest(check_rollups.max_latency_ms, excluded.max_latency_ms)
+        "#,
+        config.hourly_days.max(1),
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let hourly = sqlx::query!(
+        "DELETE FROM check_rollups WHERE period = 'hour' AND bucket < date_trunc('day', now() - make_interval(days => $1))",
+        config.hourly_days.max(1),
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let daily = if config.daily_days > 0 {
+        sqlx::query!(
+            "DELETE FROM check_rollups WHERE period = 'day' AND bucket < now() - make_interval(days => $1)",
+            config.daily_days,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+    } else {
+        0
+    };
+
+    tx.commit().await?;
+    tracing::info!(
+        "pruned {} raw results, {} hourly and {} daily rollups",
+        raw.rows_affected(),
+        hourly.rows_affected(),
+        daily
+    );
+    Ok(())
+}
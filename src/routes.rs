@@ -0,0 +1,224 @@
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+    Router,
+};
+use http::{Method, StatusCode};
+use std::sync::Arc;
+use tower_http::cors::{Any, CorsLayer};
+
+use crate::{
+    about, access, access_log, acks, actions, alertmanager, audit, auth, badges, bulk, categories, changes, checks,
+    colors,
+    config::ServerConfig,
+    csv_import, dashboard_export, dashboard_import, embed, envelope,
+    degraded::{self, Degraded},
+    discovery, grafana, health, icons, idempotency, incidents, orgs, page_snapshots, prometheus, proposals,
+    proxy_sync, pwa, quick_add, redirects,
+    repository::PgServices,
+    result_webhooks, routing, scheduler, services, sessions, sla, snapshot,
+    state::AppState,
+    status_page, suggest, tags,
+    timeouts::{self, Timeouts},
+    tokens, totp, trash, tz, usage, users, wol,
+};
+
+// Every endpoint and the middleware around them, innermost first:
+// idempotency keys, body limit, review of editors' changes, authentication,
+// the degraded-mode guard, IP access rules, request timeouts, the response
+// envelope, CORS, keeping admin paths off the main listener and the access
+// log.
+pub fn router(state: AppState, server: &ServerConfig, degraded: Degraded, timeouts: Timeouts) -> Router {
+    let cors = CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allow_headers(Any)
+        .expose_headers([
+            http::HeaderName::from_static(degraded::STALE_HEADER),
+            http::HeaderName::from_static(idempotency::REPLAYED_HEADER),
+        ]);
+
+    let admin_paths = Arc::new(if server.admin_listen.is_some() { server.admin_paths.clone() } else { Vec::new() });
+    let changes = state.changes.clone();
+    let app = Router::new()
+        .route(
+            "/services",
+            get(services::get_services::<PgServices>)
+                .post(services::create_service::<PgServices>)
+                .options(ok_handler),
+        )
+        .route("/services/import/csv", post(csv_import::import_csv::<PgServices>).options(ok_handler))
+        .route("/services/import/homer", post(dashboard_import::import_homer::<PgServices>).options(ok_handler))
+        .route("/services/import/heimdall", post(dashboard_import::import_heimdall::<PgServices>).options(ok_handler))
+        .route("/services/import/homepage", post(dashboard_import::import_homepage::<PgServices>).options(ok_handler))
+        .route("/services/export", get(dashboard_export::export))
+        .route("/services/suggest", get(suggest::suggest))
+        .route("/services/bulk/update", post(bulk::bulk_update::<PgServices>).options(ok_handler))
+        .route("/services/{name}", delete(services::delete_service::<PgServices>).options(ok_handler))
+        .route("/services/{name}/status", get(checks::service_status))
+        .route("/services/{name}/history", get(checks::service_history))
+        .route("/services/{name}/rename", post(services::rename_service::<PgServices>).options(ok_handler))
+        .route("/services/{name}/clone", post(services::clone_service::<PgServices>).options(ok_handler))
+        .route(
+            "/services/{name}/archive",
+            post(services::archive_service::<PgServices>)
+                .delete(services::unarchive_service::<PgServices>)
+                .options(ok_handler),
+        )
+        .route("/services/{name}/icon", get(icons::service_icon))
+        .route("/services/{name}/badge", get(badges::badge))
+        .route("/services/{name}/approve", post(proposals::approve::<PgServices>).options(ok_handler))
+        .route("/services/{name}/ack", post(acks::ack).delete(acks::unack).options(ok_handler))
+        .route(
+            "/services/{name}/maintenance",
+            post(health::start_maintenance::<PgServices>)
+                .delete(health::end_maintenance::<PgServices>)
+                .options(ok_handler),
+        )
+        .route(
+            "/services/{name}/sla",
+            get(sla::get_sla)
+                .put(sla::set_sla::<PgServices>)
+                .delete(sla::delete_sla::<PgServices>)
+                .options(ok_handler),
+        )
+        .route("/services/{name}/snapshots", get(page_snapshots::get_snapshots))
+        .route("/services/{name}/snapshots/review", post(page_snapshots::review).options(ok_handler))
+        .route(
+            "/services/{name}/redirect",
+            get(redirects::get_settings).put(redirects::update_settings::<PgServices>).options(ok_handler),
+        )
+        .route(
+            "/services/{name}/wake",
+            get(wol::get_mac).put(wol::set_mac::<PgServices>).post(wol::wake).options(ok_handler),
+        )
+        .route("/services/{name}/colors", put(colors::set_service_colors::<PgServices>).options(ok_handler))
+        .route("/services/{name}/actions", get(actions::get_actions))
+        .route("/services/{name}/actions/{action}", post(actions::run).options(ok_handler))
+        .route("/page-changes", get(page_snapshots::get_changes))
+        .route("/embed", get(embed::embed))
+        .route("/embed/tokens", post(embed::create_token).options(ok_handler))
+        .route("/go/{name}", get(redirects::go))
+        .route("/proposals", get(proposals::get_proposals).post(proposals::propose).options(ok_handler))
+        .route("/proposals/{id}", delete(proposals::reject).options(ok_handler))
+        .route("/icons/suggest", get(icons::suggest))
+        .route("/icons/{file}", get(icons::icon))
+        .route(
+            quick_add::PATH,
+            get(quick_add::quick_add::<PgServices>).post(quick_add::quick_add::<PgServices>).options(ok_handler),
+        )
+        .route("/categories", get(categories::get_categories).post(categories::create_category).options(ok_handler))
+        .route("/categories/{id}", delete(categories::delete_category).options(ok_handler))
+        .route("/categories/{id}/access", post(categories::set_access).options(ok_handler))
+        .route("/categories/{id}/colors", put(colors::set_category_colors).options(ok_handler))
+        .route("/categories/{id}/status", get(categories::category_status))
+        .route("/orgs", get(orgs::get_orgs).post(orgs::create_org).options(ok_handler))
+        .route("/orgs/{id}", delete(orgs::delete_org).options(ok_handler))
+        .route("/orgs/{id}/members", get(orgs::get_members))
+        .route(
+            "/orgs/{id}/members/{member}",
+            post(orgs::set_member_role).delete(orgs::remove_member).options(ok_handler),
+        )
+        .route("/orgs/{id}/invites", get(orgs::get_invites).post(orgs::create_invite).options(ok_handler))
+        .route("/orgs/{id}/invites/{invite_id}", delete(orgs::revoke_invite).options(ok_handler))
+        .route("/invites/{token}/accept", post(orgs::accept_invite).options(ok_handler))
+        .route("/login", post(sessions::login).options(ok_handler))
+        .route("/password-reset", post(users::request_reset).options(ok_handler))
+        .route("/password-reset/{token}", post(users::reset_password).options(ok_handler))
+        .route("/logout", post(sessions::logout).options(ok_handler))
+        .route("/me", get(tokens::me))
+        .route("/me/sessions", get(sessions::get_sessions).delete(sessions::revoke_all).options(ok_handler))
+        .route("/me/sessions/{id}", delete(sessions::revoke_session).options(ok_handler))
+        .route("/me/tokens", get(tokens::get_tokens).post(tokens::create_token).options(ok_handler))
+        .route("/me/tokens/{id}", delete(tokens::revoke_token).options(ok_handler))
+        .route("/me/email", post(users::set_email).options(ok_handler))
+        .route("/me/recent", get(usage::recent))
+        .route("/me/top", get(usage::top))
+        .route("/me/usage", get(usage::get_setting).put(usage::set_setting).options(ok_handler))
+        .route("/me/timezone", get(tz::get_timezone).post(tz::set_timezone).options(ok_handler))
+        .route("/me/totp", post(totp::enroll).delete(totp::disable).options(ok_handler))
+        .route("/me/totp/confirm", post(totp::confirm).options(ok_handler))
+        .route("/me/totp/recovery-codes", post(totp::regenerate).options(ok_handler))
+        .route("/tags", get(tags::get_tags))
+        .route("/grafana", get(grafana::health))
+        .route("/grafana/search", post(grafana::search).options(ok_handler))
+        .route("/grafana/metrics", post(grafana::search).options(ok_handler))
+        .route("/grafana/query", post(grafana::query).options(ok_handler))
+        .route("/integrations/alertmanager", post(alertmanager::ingest).options(ok_handler))
+        .route("/integrations/prometheus/targets", get(prometheus::targets))
+        .route("/integrations/proxies/sync", post(proxy_sync::sync_now).options(ok_handler))
+        .route("/discovery/found", get(discovery::found))
+        .route(
+            "/result-webhooks",
+            get(result_webhooks::get_webhooks).post(result_webhooks::create_webhook).options(ok_handler),
+        )
+        .route("/result-webhooks/{id}", delete(result_webhooks::delete_webhook).options(ok_handler))
+        .route("/changes", get(changes::get_changes))
+        .route("/changes/{id}", get(changes::get_change))
+        .route("/changes/{id}/approve", post(changes::approve).options(ok_handler))
+        .route("/changes/{id}/reject", post(changes::reject).options(ok_handler))
+        .route("/trash", get(trash::get_trash).delete(trash::empty).options(ok_handler))
+        .route("/trash/{name}", delete(trash::purge_one).options(ok_handler))
+        .route("/trash/{name}/restore", post(trash::restore::<PgServices>).options(ok_handler))
+        .route("/status", get(status_page::status_page))
+        .route("/incidents", get(incidents::get_incidents))
+        .route("/incidents/{id}/notes", post(incidents::add_note).options(ok_handler))
+        .route("/manifest.webmanifest", get(pwa::manifest))
+        .route("/icon.svg", get(pwa::icon))
+        .route("/sw.js", get(pwa::service_worker))
+        .route("/about", get(about::about))
+        .route("/audit", get(audit::get_audit))
+        .route("/events/audit", get(audit::events))
+        .route("/admin/jobs", get(scheduler::get_jobs))
+        .route("/admin/users", get(users::get_users).post(users::create_user).options(ok_handler))
+        .route("/admin/users/{name}", delete(users::delete_user).options(ok_handler))
+        .route("/admin/users/{name}/password", post(users::set_password).options(ok_handler))
+        .route("/admin/users/{name}/totp", delete(totp::reset).options(ok_handler))
+        .route(
+            "/admin/notification-routes",
+            get(routing::list_routes).post(routing::create_route).options(ok_handler),
+        )
+        .route(
+            "/admin/notification-routes/{id}",
+            get(routing::get_route).put(routing::update_route).delete(routing::delete_route).options(ok_handler),
+        )
+        .route("/admin/export", get(snapshot::export_handler))
+        .route("/admin/backups", get(snapshot::list_backups_handler).post(snapshot::create_backup_handler))
+        .route(
+            "/admin/backups/{name}",
+            get(snapshot::get_backup_handler).delete(snapshot::delete_backup_handler),
+        )
+        .route(
+            "/admin/import",
+            post(snapshot::import_handler)
+                .layer(DefaultBodyLimit::max(snapshot::MAX_IMPORT_BYTES))
+                .options(ok_handler),
+        )
+        .layer(middleware::from_fn_with_state(state.clone(), idempotency::enforce))
+        .layer(DefaultBodyLimit::max(server.max_body_bytes))
+        .layer(middleware::from_fn_with_state(state.clone(), changes::review))
+        .layer(middleware::from_fn_with_state(state.clone(), auth::enforce))
+        .layer(middleware::from_fn_with_state(degraded, degraded::guard))
+        .layer(middleware::from_fn_with_state(state.clone(), access::enforce))
+        .layer(middleware::from_fn_with_state(timeouts, timeouts::enforce))
+        .layer(middleware::from_fn(envelope::wrap))
+        .layer(cors)
+        .layer(middleware::from_fn_with_state(admin_paths, crate::server::restrict))
+        .layer(middleware::from_fn_with_state(state.clone(), access_log::record))
+        .with_state(state);
+    changes.attach(app.clone());
+    app
+}
+
+async fn ok_handler() -> impl IntoResponse {
+    StatusCode::OK
+}
+
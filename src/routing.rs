@@ -0,0 +1,310 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::AbortHandle;
+
+use crate::{
+    audit::{Actor, Audit},
+    email,
+    notify::{Channels, Notification, Notifier},
+    services::SERVICE_ID_BY_NAME,
+};
+
+// Notification routing rules, kept in the database and managed under
+// /admin/notification-routes. A notification goes to the channels of every
+// enabled rule it matches (by the service's category and tag and by
+// severity), or to `[notifications]` if it matches none. Each rule can have
+// quiet hours, in the instance timezone, during which it delivers nothing,
+// and a minimum duration: a problem is only sent once it has lasted that
+// long, and a recovery that comes sooner cancels both.
+const SEVERITIES: [&str; 2] = ["info", "warning"];
+const MAX_MIN_DURATION_SECS: i32 = 86_400;
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Route {
+    id: i32,
+    name: String,
+    enabled: bool,
+    // Each of these narrows the rule down; None matches everything.
+    category_id: Option<i32>,
+    tag: Option<String>,
+    min_severity: Option<String>,
+    webhooks: Vec<String>,
+    slack: Vec<String>,
+    emails: Vec<String>,
+    // Local times; an end before the start spans midnight.
+    quiet_start: Option<NaiveTime>,
+    quiet_end: Option<NaiveTime>,
+    min_duration_secs: i32,
+    created_at: DateTime<Utc>,
+}
+
+impl Route {
+    fn channels(&self) -> Channels {
+        Channels { webhooks: self.webhooks.clone(), slack: self.slack.clone(), emails: self.emails.clone() }
+    }
+
+    fn is_quiet(&self, now: NaiveTime) -> bool {
+        match (self.quiet_start, self.quiet_end) {
+            (Some(start), Some(end)) if start <= end => (start..end).contains(&now),
+            (Some(start), Some(end)) => now >= start || now < end,
+            _ => false,
+        }
+    }
+}
+
+fn severity_rank(severity: &str) -> Option<usize> {
+    SEVERITIES.iter().position(|known| *known == severity)
+}
+
+// A problem held back by a rule's min duration: (rule, service, event).
+type PendingKey = (i32, String, &'static str);
+
+#[derive(Clone)]
+pub struct Routes {
+    pool: PgPool,
+    timezone: Tz,
+    email: bool,
+    pending: Arc<Mutex<HashMap<PendingKey, AbortHandle>>>,
+}
+
+impl Routes {
+    pub fn new(pool: PgPool, timezone: Tz, email: bool) -> Self {
+        Self { pool, timezone, email, pending: Arc::default() }
+    }
+
+    // Enabled rules matching the notification. Notifications that aren't
+    // about a service (the digest) only match rules without a category or
+    // tag.
+    pub async fn matching(&self, notification: &Notification) -> sqlx::Result<Vec<Route>> {
+        let rules = sqlx::query_as::<_, Route>(&format!(
+            "SELECT r.* FROM notification_routes r WHERE r.enabled \
+             AND (r.category_id IS NULL OR r.category_id = (SELECT category_id FROM services WHERE id = {0})) \
+             AND (r.tag IS NULL OR EXISTS (SELECT 1 FROM service_tags st JOIN tags t ON t.id = st.tag_id \
+                  WHERE st.service_id = {0} AND lower(t.name) = lower(r.tag))) \
+             ORDER BY r.id",
+            SERVICE_ID_BY_NAME
+        ))
+        .bind(&notification.service)
+        .fetch_all(&self.pool)
+        .await?;
+        let severity = severity_rank(notification.severity());
+        Ok(rules
+            .into_iter()
+            .filter(|rule| rule.min_severity.as_deref().is_none_or(|min| severity >= severity_rank(min)))
+            .collect())
+    }
+
+    pub async fn dispatch(&self, notifier: &Notifier, notification: Notification, rules: Vec<Route>) {
+        let notification = Arc::new(notification);
+        for rule in rules {
+            if let Some(problem) = notification.resolves() {
+                let key = (rule.id, notification.service.clone(), problem);
+                if let Some(pending) = self.pending.lock().unwrap().remove(&key) {
+                    pending.abort();
+                    let service = &notification.service;
+                    tracing::info!("{} for {} cleared before rule '{}' sent it", problem, service, rule.name);
+                    continue;
+                }
+            } else if rule.min_duration_secs > 0 {
+                let key = (rule.id, notification.service.clone(), notification.event);
+                let delay = Duration::from_secs(rule.min_duration_secs as u64);
+                let (routes, notifier, notification, pending_key) =
+                    (self.clone(), notifier.clone(), notification.clone(), key.clone());
+                let task = tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    routes.pending.lock().unwrap().remove(&pending_key);
+                    routes.deliver(&notifier, &rule, &notification).await;
+                });
+                if let Some(previous) = self.pending.lock().unwrap().insert(key, task.abort_handle()) {
+                    previous.abort();
+                }
+                continue;
+            }
+            self.deliver(notifier, &rule, &notification).await;
+        }
+    }
+
+    async fn deliver(&self, notifier: &Notifier, rule: &Route, notification: &Notification) {
+        if rule.is_quiet(Utc::now().with_timezone(&self.timezone).time()) {
+            let (event, service) = (notification.event, &notification.service);
+            tracing::info!("{} for {} not sent: quiet hours of rule '{}'", event, service, rule.name);
+            return;
+        }
+        notifier.send_via(notification, &rule.channels()).await;
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RouteInput {
+    name: String,
+    enabled: Option<bool>,
+    category_id: Option<i32>,
+    tag: Option<String>,
+    min_severity: Option<String>,
+    #[serde(flatten)]
+    channels: Channels,
+    quiet_start: Option<NaiveTime>,
+    quiet_end: Option<NaiveTime>,
+    #[serde(default)]
+    min_duration_secs: i32,
+}
+
+impl RouteInput {
+    fn validate(&self, email: bool) -> Result<(), (StatusCode, String)> {
+        let invalid = |message: String| Err((StatusCode::BAD_REQUEST, message));
+        if self.name.trim().is_empty() {
+            return invalid("name cannot be empty".into());
+        }
+        if let Some(severity) = &self.min_severity
+            && severity_rank(severity).is_none()
+        {
+            return invalid(format!("min_severity must be one of {}", SEVERITIES.join(", ")));
+        }
+        let Channels { webhooks, slack, emails } = &self.channels;
+        if webhooks.is_empty() && slack.is_empty() && emails.is_empty() {
+            return invalid("a rule needs at least one of webhooks, slack or emails".into());
+        }
+        let is_http = |url: &String| url::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"));
+        if let Some(url) = webhooks.iter().chain(slack).find(|url| !is_http(url)) {
+            return invalid(format!("'{}' is not an http(s) URL", url));
+        }
+        if !emails.is_empty() && !email {
+            return invalid("emails need email.smtp_host".into());
+        }
+        if let Some(address) = emails.iter().find(|address| !email::is_valid_address(address)) {
+            return invalid(format!("invalid address '{}'", address));
+        }
+        match (self.quiet_start, self.quiet_end) {
+            (Some(start), Some(end)) if start == end => return invalid("quiet hours can't be empty".into()),
+            (Some(_), None) | (None, Some(_)) => return invalid("quiet_start and quiet_end go together".into()),
+            _ => {}
+        }
+        if !(0..=MAX_MIN_DURATION_SECS).contains(&self.min_duration_secs) {
+            return invalid(format!("min_duration_secs must be between 0 and {}", MAX_MIN_DURATION_SECS));
+        }
+        Ok(())
+    }
+}
+
+fn save_error(e: sqlx::Error) -> (StatusCode, String) {
+    match e.as_database_error().and_then(|e| e.constraint()) {
+        Some("notification_routes_category_id_fkey") => (StatusCode::BAD_REQUEST, "Category not found".into()),
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+// GET /admin/notification-routes
+pub async fn list_routes(State(pool): State<PgPool>) -> Result<Json<Vec<Route>>, (StatusCode, String)> {
+    let routes = sqlx::query_as::<_, Route>("SELECT * FROM notification_routes ORDER BY id")
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(routes))
+}
+
+// GET /admin/notification-routes/:id
+pub async fn get_route(State(pool): State<PgPool>, Path(id): Path<i32>) -> Result<Json<Route>, (StatusCode, String)> {
+    sqlx::query_as::<_, Route>("SELECT * FROM notification_routes WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "Route not found".into()))
+}
+
+// POST /admin/notification-routes
+pub async fn create_route(
+    State(pool): State<PgPool>,
+    State(routes): State<Routes>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Json(input): Json<RouteInput>,
+) -> Result<Json<Route>, (StatusCode, String)> {
+    input.validate(routes.email)?;
+    let route = sqlx::query_as::<_, Route>(
+        "INSERT INTO notification_routes (name, enabled, category_id, tag, min_severity, webhooks, slack, emails, \
+         quiet_start, quiet_end, min_duration_secs) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING *",
+    )
+    .bind(input.name.trim())
+    .bind(input.enabled.unwrap_or(true))
+    .bind(input.category_id)
+    .bind(&input.tag)
+    .bind(&input.min_severity)
+    .bind(&input.channels.webhooks)
+    .bind(&input.channels.slack)
+    .bind(&input.channels.emails)
+    .bind(input.quiet_start)
+    .bind(input.quiet_end)
+    .bind(input.min_duration_secs)
+    .fetch_one(&pool)
+    .await
+    .map_err(save_error)?;
+    audit.record(&actor, "notification_route.create", None, Some(route.name.clone())).await;
+    Ok(Json(route))
+}
+
+// PUT /admin/notification-routes/:id
+// Replaces the whole rule.
+pub async fn update_route(
+    State(pool): State<PgPool>,
+    State(routes): State<Routes>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(id): Path<i32>,
+    Json(input): Json<RouteInput>,
+) -> Result<Json<Route>, (StatusCode, String)> {
+    input.validate(routes.email)?;
+    let route = sqlx::query_as::<_, Route>(
+        "UPDATE notification_routes SET name = $2, enabled = $3, category_id = $4, tag = $5, min_severity = $6, \
+         webhooks = $7, slack = $8, emails = $9, quiet_start = $10, quiet_end = $11, min_duration_secs = $12 \
+         WHERE id = $1 RETURNING *",
+    )
+    .bind(id)
+    .bind(input.name.trim())
+    .bind(input.enabled.unwrap_or(true))
+    .bind(input.category_id)
+    .bind(&input.tag)
+    .bind(&input.min_severity)
+    .bind(&input.channels.webhooks)
+    .bind(&input.channels.slack)
+    .bind(&input.channels.emails)
+    .bind(input.quiet_start)
+    .bind(input.quiet_end)
+    .bind(input.min_duration_secs)
+    .fetch_optional(&pool)
+    .await
+    .map_err(save_error)?
+    .ok_or((StatusCode::NOT_FOUND, "Route not found".into()))?;
+    audit.record(&actor, "notification_route.update", None, Some(route.name.clone())).await;
+    Ok(Json(route))
+}
+
+// DELETE /admin/notification-routes/:id
+pub async fn delete_route(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<Route>, (StatusCode, String)> {
+    let route = sqlx::query_as::<_, Route>("DELETE FROM notification_routes WHERE id = $1 RETURNING *")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Route not found".into()))?;
+    audit.record(&actor, "notification_route.delete", None, Some(route.name.clone())).await;
+    Ok(Json(route))
+}
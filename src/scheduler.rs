@@ -0,0 +1,238 @@
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use croner::{parser::{CronParser, Seconds}, Cron};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+type Job = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> + Send + Sync>;
+
+// When a job runs: either a fixed interval ("@every 30s", "@every 5m") or a
+// cron expression with optional seconds field ("0 3 * * *", "@hourly").
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    Every(Duration),
+    Cron(Box<Cron>),
+}
+
+impl Schedule {
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let spec = spec.trim();
+        if let Some(every) = spec.strip_prefix("@every ") {
+            return parse_duration(every.trim())
+                .map(Self::Every)
+                .ok_or_else(|| anyhow::anyhow!("invalid interval '{}' (e.g. 30s, 5m, 2h)", every));
+        }
+        CronParser::builder()
+            .seconds(Seconds::Optional)
+            .build()
+            .parse(spec)
+            .map(|cron| Self::Cron(Box::new(cron)))
+            .map_err(|e| anyhow::anyhow!("invalid schedule '{}': {}", spec, e))
+    }
+
+    // Interval jobs run right away and then every interval after each start;
+    // cron jobs wait for their next matching time in `tz`.
+    fn next(&self, last_started: Option<DateTime<Utc>>, now: DateTime<Utc>, tz: Tz) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Every(interval) => Some(match last_started {
+                Some(started) => started + *interval,
+                None => now,
+            }),
+            Self::Cron(cron) => {
+                cron.find_next_occurrence(&now.with_timezone(&tz), false).ok().map(|next| next.with_timezone(&Utc))
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Every(interval) => format!("@every {}s", interval.as_secs()),
+            Self::Cron(cron) => cron.as_str().to_string(),
+        }
+    }
+}
+
+fn parse_duration(value: &str) -> Option<Duration> {
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount.parse().ok()?;
+    let secs = match unit {
+        "s" => amount,
+        "m" => amount * 60,
+        "h" => amount * 3600,
+        "d" => amount * 86400,
+        _ => return None,
+    };
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    name: &'static str,
+    schedule: String,
+    running: bool,
+    next_run_at: Option<DateTime<Utc>>,
+    last_started_at: Option<DateTime<Utc>>,
+    last_finished_at: Option<DateTime<Utc>>,
+    last_duration_ms: Option<i64>,
+    last_error: Option<String>,
+    last_failed_at: Option<DateTime<Utc>>,
+    runs: u64,
+    failures: u64,
+}
+
+// Runs the background jobs (health checks, history pruning, trash purge, …)
+// on schedules that `[jobs]` in the config can override by name. A job never
+// overlaps itself: a slow run delays the next one.
+#[derive(Clone)]
+pub struct Scheduler {
+    overrides: Arc<HashMap<String, String>>,
+    tz: Tz,
+    jobs: Arc<Mutex<Vec<JobStatus>>>,
+}
+
+impl Scheduler {
+    pub fn new(overrides: &HashMap<String, String>, tz: Tz) -> Self {
+        Self { overrides: Arc::new(overrides.clone()), tz, jobs: Arc::default() }
+    }
+
+    pub fn register<F, Fut>(&self, name: &'static str, default: Schedule, job: F) -> anyhow::Result<()>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let schedule = match self.overrides.get(name) {
+            Some(spec) => Schedule::parse(spec).map_err(|e| anyhow::anyhow!("jobs.{}: {}", name, e))?,
+            None => default,
+        };
+        let job: Job = Arc::new(move || Box::pin(job()));
+
+        let index = {
+            let mut jobs = self.jobs.lock().unwrap();
+            jobs.push(JobStatus {
+                name,
+                schedule: schedule.describe(),
+                running: false,
+                next_run_at: None,
+                last_started_at: None,
+                last_finished_at: None,
+                last_duration_ms: None,
+                last_error: None,
+                last_failed_at: None,
+                runs: 0,
+                failures: 0,
+            });
+            jobs.len() - 1
+        };
+        tokio::spawn(self.clone().run(index, name, schedule, job));
+        Ok(())
+    }
+
+    // Fails on `[jobs]` entries that name no registered job, so a typo does
+    // not silently leave the default schedule in place.
+    pub fn check_overrides(&self) -> anyhow::Result<()> {
+        let jobs = self.jobs.lock().unwrap();
+        for name in self.overrides.keys() {
+            if !jobs.iter().any(|job| job.name == name) {
+                anyhow::bail!("jobs.{}: no such job (or it is disabled)", name);
+            }
+        }
+        Ok(())
+    }
+
+    async fn run(self, index: usize, name: &'static str, schedule: Schedule, job: Job) {
+        let mut last_started = None;
+        loop {
+            let Some(next) = schedule.next(last_started, Utc::now(), self.tz) else {
+                tracing::warn!("job {} has no upcoming run; stopping it", name);
+                return;
+            };
+            self.update(index, |status| status.next_run_at = Some(next));
+            if let Ok(wait) = (next - Utc::now()).to_std() {
+                tokio::time::sleep(wait).await;
+            }
+
+            let started = Utc::now();
+            last_started = Some(started);
+            self.update(index, |status| {
+                status.running = true;
+                status.next_run_at = None;
+                status.last_started_at = Some(started);
+            });
+            let result = job().await;
+            let finished = Utc::now();
+            if let Err(e) = &result {
+                tracing::warn!("job {} failed: {:#}", name, e);
+            }
+            self.update(index, |status| {
+                status.running = false;
+                status.runs += 1;
+                status.last_finished_at = Some(finished);
+                status.last_duration_ms = Some((finished - started).num_milliseconds());
+                match result {
+                    Ok(()) => status.last_error = None,
+                    Err(e) => {
+                        status.failures += 1;
+                        status.last_error = Some(format!("{:#}", e));
+                        status.last_failed_at = Some(finished);
+                    }
+                }
+            });
+        }
+    }
+
+    fn update(&self, index: usize, change: impl FnOnce(&mut JobStatus)) {
+        change(&mut self.jobs.lock().unwrap()[index]);
+    }
+}
+
+// GET /admin/jobs
+pub async fn get_jobs(State(scheduler): State<Scheduler>) -> Json<Vec<JobStatus>> {
+    Json(scheduler.jobs.lock().unwrap().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_intervals() {
+        assert!(matches!(Schedule::parse(" @every 5m "), Ok(Schedule::Every(interval)) if interval.as_secs() == 300));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(86400)));
+        for bad in ["@every 0s", "@every 5", "@every m", "@every 5w"] {
+            assert!(Schedule::parse(bad).is_err(), "{}", bad);
+        }
+        let every = Schedule::parse("@every 30s").unwrap();
+        assert_eq!(every.describe(), "@every 30s");
+        let now = at("2026-01-01T00:00:00Z");
+        assert_eq!(every.next(None, now, Tz::UTC), Some(now));
+        assert_eq!(every.next(Some(now), now, Tz::UTC), Some(at("2026-01-01T00:00:30Z")));
+    }
+
+    #[test]
+    fn parses_cron_in_the_timezone() {
+        let nightly = Schedule::parse("0 3 * * *").unwrap();
+        assert_eq!(nightly.describe(), "0 3 * * *");
+        let now = at("2026-01-01T04:00:00Z");
+        assert_eq!(nightly.next(None, now, Tz::UTC), Some(at("2026-01-02T03:00:00Z")));
+        assert_eq!(nightly.next(None, now, Tz::Europe__Berlin), Some(at("2026-01-02T02:00:00Z")));
+
+        let seconds = Schedule::parse("*/15 * * * * *").unwrap();
+        assert_eq!(seconds.next(None, at("2026-01-01T00:00:01Z"), Tz::UTC), Some(at("2026-01-01T00:00:15Z")));
+        assert!(Schedule::parse("@hourly").is_ok());
+        assert!(Schedule::parse("0 25 * * *").is_err());
+        assert!(Schedule::parse("every day").is_err());
+    }
+}
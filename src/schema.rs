@@ -0,0 +1,459 @@
+use sqlx::PgPool;
+
+// Idempotent DDL run at startup. New columns are added with
+// `ADD COLUMN IF NOT EXISTS` so existing databases upgrade in place.
+const STATEMENTS: &[&str] = &[
+    r#"
+    CREATE TABLE IF NOT EXISTS services (
+        id SERIAL PRIMARY KEY,
+        name TEXT UNIQUE NOT NULL,
+        link TEXT UNIQUE NOT NULL
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS categories (
+        id SERIAL PRIMARY KEY,
+        name TEXT UNIQUE NOT NULL
+    )
+    "#,
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS category_id INTEGER REFERENCES categories(id) ON DELETE SET NULL",
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS public BOOLEAN NOT NULL DEFAULT false",
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now()",
+    "CREATE INDEX IF NOT EXISTS services_created_at_id ON services (created_at, id)",
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT now()",
+    r#"
+    CREATE TABLE IF NOT EXISTS tags (
+        id SERIAL PRIMARY KEY,
+        name TEXT UNIQUE NOT NULL
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS service_tags (
+        service_id INTEGER NOT NULL REFERENCES services(id) ON DELETE CASCADE,
+        tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+        PRIMARY KEY (service_id, tag_id)
+    )
+    "#,
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ",
+    // Trashed services must not block re-creating the same link.
+    "CREATE UNIQUE INDEX IF NOT EXISTS services_live_link ON services (link) WHERE deleted_at IS NULL",
+    "ALTER TABLE services DROP CONSTRAINT IF EXISTS services_link_key",
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS check_type TEXT NOT NULL DEFAULT 'http'",
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS expected_ip TEXT",
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS latency_threshold_ms INTEGER",
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS check_token_encrypted TEXT",
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS internal_link TEXT",
+    "ALTER TABLE categories ADD COLUMN IF NOT EXISTS min_role TEXT",
+    "ALTER TABLE categories ADD COLUMN IF NOT EXISTS groups TEXT[] NOT NULL DEFAULT '{}'",
+    r#"
+    CREATE TABLE IF NOT EXISTS orgs (
+        id SERIAL PRIMARY KEY,
+        name TEXT UNIQUE NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS org_members (
+        org_id INTEGER NOT NULL REFERENCES orgs(id) ON DELETE CASCADE,
+        member TEXT NOT NULL,
+        role TEXT NOT NULL CHECK (role IN ('owner', 'member')),
+        joined_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        PRIMARY KEY (org_id, member)
+    )
+    "#,
+    "CREATE INDEX IF NOT EXISTS org_members_member ON org_members (member)",
+    r#"
+    CREATE TABLE IF NOT EXISTS org_invites (
+        id SERIAL PRIMARY KEY,
+        org_id INTEGER NOT NULL REFERENCES orgs(id) ON DELETE CASCADE,
+        token_hash TEXT UNIQUE NOT NULL,
+        role TEXT NOT NULL CHECK (role IN ('owner', 'member')),
+        invited_by TEXT NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        expires_at TIMESTAMPTZ NOT NULL,
+        accepted_by TEXT,
+        accepted_at TIMESTAMPTZ
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS api_tokens (
+        id SERIAL PRIMARY KEY,
+        owner TEXT NOT NULL,
+        name TEXT NOT NULL,
+        scope TEXT NOT NULL CHECK (scope IN ('read', 'write', 'admin')),
+        prefix TEXT NOT NULL,
+        token_hash TEXT UNIQUE NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        expires_at TIMESTAMPTZ,
+        last_used_at TIMESTAMPTZ,
+        revoked_at TIMESTAMPTZ
+    )
+    "#,
+    "CREATE INDEX IF NOT EXISTS api_tokens_owner ON api_tokens (owner)",
+    r#"
+    CREATE TABLE IF NOT EXISTS users (
+        name TEXT PRIMARY KEY,
+        role TEXT NOT NULL CHECK (role IN ('viewer', 'editor', 'admin')),
+        password_hash TEXT NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS sessions (
+        id SERIAL PRIMARY KEY,
+        user_name TEXT NOT NULL REFERENCES users(name) ON DELETE CASCADE ON UPDATE CASCADE,
+        token_hash TEXT UNIQUE NOT NULL,
+        device TEXT,
+        ip TEXT,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        last_seen_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        expires_at TIMESTAMPTZ NOT NULL,
+        revoked_at TIMESTAMPTZ
+    )
+    "#,
+    "CREATE INDEX IF NOT EXISTS sessions_user ON sessions (user_name)",
+    // Two-factor authentication (see totp.rs). The secret is encrypted and
+    // set on enrollment; it only applies once confirmed with a first code.
+    "ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_secret TEXT",
+    "ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_enabled BOOLEAN NOT NULL DEFAULT false",
+    "ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_last_step BIGINT",
+    "ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_recovery TEXT[] NOT NULL DEFAULT '{}'",
+    // Where password reset links go (see email.rs).
+    "ALTER TABLE users ADD COLUMN IF NOT EXISTS email TEXT",
+    r#"
+    CREATE TABLE IF NOT EXISTS password_resets (
+        id SERIAL PRIMARY KEY,
+        user_name TEXT NOT NULL REFERENCES users(name) ON DELETE CASCADE ON UPDATE CASCADE,
+        token_hash TEXT UNIQUE NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        expires_at TIMESTAMPTZ NOT NULL,
+        used_at TIMESTAMPTZ
+    )
+    "#,
+    "ALTER TABLE org_invites ADD COLUMN IF NOT EXISTS email TEXT",
+    // No ON DELETE: an org cannot be deleted while it still owns things,
+    // which would otherwise become visible to everyone.
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS org_id INTEGER REFERENCES orgs(id)",
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS description TEXT",
+    "ALTER TABLE categories ADD COLUMN IF NOT EXISTS org_id INTEGER REFERENCES orgs(id)",
+    r#"
+    CREATE TABLE IF NOT EXISTS check_results (
+        id BIGSERIAL PRIMARY KEY,
+        service_id INTEGER NOT NULL REFERENCES services(id) ON DELETE CASCADE,
+        checked_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        ok BOOLEAN NOT NULL,
+        latency_ms INTEGER,
+        error TEXT
+    )
+    "#,
+    "ALTER TABLE check_results ADD COLUMN IF NOT EXISTS cert_expires_at TIMESTAMPTZ",
+    "ALTER TABLE check_results ADD COLUMN IF NOT EXISTS degraded BOOLEAN NOT NULL DEFAULT false",
+    "CREATE INDEX IF NOT EXISTS check_results_service_time ON check_results (service_id, checked_at DESC)",
+    "CREATE INDEX IF NOT EXISTS check_results_time ON check_results (checked_at)",
+    r#"
+    CREATE TABLE IF NOT EXISTS check_rollups (
+        service_id INTEGER NOT NULL REFERENCES services(id) ON DELETE CASCADE,
+        period TEXT NOT NULL CHECK (period IN ('hour', 'day')),
+        bucket TIMESTAMPTZ NOT NULL,
+        total_checks BIGINT NOT NULL,
+        ok_checks BIGINT NOT NULL,
+        avg_latency_ms DOUBLE PRECISION NOT NULL,
+        max_latency_ms INTEGER NOT NULL,
+        PRIMARY KEY (service_id, period, bucket)
+    )
+    "#,
+    // Firing alerts from Alertmanager (see alertmanager.rs), removed again
+    // when it reports them resolved.
+    r#"
+    CREATE TABLE IF NOT EXISTS external_alerts (
+        service_id INTEGER NOT NULL REFERENCES services(id) ON DELETE CASCADE,
+        fingerprint TEXT NOT NULL,
+        alertname TEXT,
+        severity TEXT,
+        down BOOLEAN NOT NULL,
+        summary TEXT,
+        starts_at TIMESTAMPTZ NOT NULL,
+        updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        PRIMARY KEY (service_id, fingerprint)
+    )
+    "#,
+    // Service names are stored as text rather than referenced so entries
+    // outlive the services they describe.
+    r#"
+    CREATE TABLE IF NOT EXISTS audit_log (
+        id BIGSERIAL PRIMARY KEY,
+        at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        actor TEXT NOT NULL,
+        ip TEXT,
+        action TEXT NOT NULL,
+        service TEXT,
+        detail TEXT
+    )
+    "#,
+    "CREATE INDEX IF NOT EXISTS audit_log_at_id ON audit_log (at, id)",
+    r#"
+    CREATE TABLE IF NOT EXISTS access_log (
+        id BIGSERIAL PRIMARY KEY,
+        at TIMESTAMPTZ NOT NULL,
+        method TEXT,
+        path TEXT,
+        status INTEGER,
+        latency_ms INTEGER,
+        ip TEXT,
+        user_name TEXT
+    )
+    "#,
+    "CREATE INDEX IF NOT EXISTS access_log_at ON access_log (at)",
+    // An IANA name; NULL uses the instance timezone.
+    "ALTER TABLE users ADD COLUMN IF NOT EXISTS timezone TEXT",
+    r#"
+    CREATE TABLE IF NOT EXISTS incidents (
+        id SERIAL PRIMARY KEY,
+        service_id INTEGER NOT NULL REFERENCES services(id) ON DELETE CASCADE,
+        started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        resolved_at TIMESTAMPTZ,
+        cause TEXT,
+        failed_checks INTEGER NOT NULL DEFAULT 1
+    )
+    "#,
+    // At most one ongoing incident per service.
+    "CREATE UNIQUE INDEX IF NOT EXISTS incidents_open ON incidents (service_id) WHERE resolved_at IS NULL",
+    "CREATE INDEX IF NOT EXISTS incidents_started_at ON incidents (started_at)",
+    r#"
+    CREATE TABLE IF NOT EXISTS incident_notes (
+        id SERIAL PRIMARY KEY,
+        incident_id INTEGER NOT NULL REFERENCES incidents(id) ON DELETE CASCADE,
+        author TEXT NOT NULL,
+        at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        text TEXT NOT NULL
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS alert_acks (
+        service_id INTEGER PRIMARY KEY REFERENCES services(id) ON DELETE CASCADE,
+        acked_by TEXT NOT NULL,
+        acked_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        until TIMESTAMPTZ,
+        note TEXT
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS notification_routes (
+        id SERIAL PRIMARY KEY,
+        name TEXT NOT NULL,
+        enabled BOOLEAN NOT NULL DEFAULT true,
+        category_id INTEGER REFERENCES categories(id) ON DELETE CASCADE,
+        tag TEXT,
+        min_severity TEXT,
+        webhooks TEXT[] NOT NULL DEFAULT '{}',
+        slack TEXT[] NOT NULL DEFAULT '{}',
+        emails TEXT[] NOT NULL DEFAULT '{}',
+        quiet_start TIME,
+        quiet_end TIME,
+        min_duration_secs INTEGER NOT NULL DEFAULT 0,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS page_snapshots (
+        id SERIAL PRIMARY KEY,
+        service_id INTEGER NOT NULL REFERENCES services(id) ON DELETE CASCADE,
+        taken_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        last_seen_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        status_code INTEGER NOT NULL,
+        final_url TEXT NOT NULL,
+        title TEXT,
+        content_hash TEXT NOT NULL,
+        reviewed BOOLEAN NOT NULL DEFAULT false
+    )
+    "#,
+    "CREATE INDEX IF NOT EXISTS page_snapshots_service ON page_snapshots (service_id, taken_at)",
+    r#"
+    CREATE TABLE IF NOT EXISTS service_redirects (
+        service_id INTEGER PRIMARY KEY REFERENCES services(id) ON DELETE CASCADE,
+        permanent BOOLEAN NOT NULL DEFAULT false,
+        pass_query BOOLEAN NOT NULL DEFAULT false,
+        params TEXT NOT NULL DEFAULT ''
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS pending_changes (
+        id SERIAL PRIMARY KEY,
+        method TEXT NOT NULL,
+        path TEXT NOT NULL,
+        content_type TEXT,
+        body TEXT NOT NULL,
+        requested_by TEXT NOT NULL,
+        requested_ip TEXT,
+        requested_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        status TEXT NOT NULL DEFAULT 'pending',
+        decided_by TEXT,
+        decided_at TIMESTAMPTZ,
+        result_status INTEGER,
+        result TEXT
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS service_proposals (
+        id SERIAL PRIMARY KEY,
+        name TEXT NOT NULL,
+        link TEXT NOT NULL,
+        description TEXT,
+        proposed_by TEXT NOT NULL,
+        ip TEXT,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+    )
+    "#,
+    // A dashboard-icons slug to try first; see icons.rs.
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS icon TEXT",
+    // For Wake-on-LAN, as aa:bb:cc:dd:ee:ff; see wol.rs.
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS mac_address TEXT",
+    // The proxy_sync source that created the service; see proxy_sync.rs.
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS synced_from TEXT",
+    r#"
+    CREATE TABLE IF NOT EXISTS result_webhooks (
+        id SERIAL PRIMARY KEY,
+        url TEXT NOT NULL,
+        service_ids INTEGER[] NOT NULL DEFAULT '{}',
+        batch BOOLEAN NOT NULL DEFAULT false,
+        created_by TEXT NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+    )
+    "#,
+    // Set while the service is archived; see services.rs.
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS archived_at TIMESTAMPTZ",
+    // Hex tile colors, as #rrggbb; see colors.rs.
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS color TEXT",
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS accent TEXT",
+    "ALTER TABLE categories ADD COLUMN IF NOT EXISTS color TEXT",
+    "ALTER TABLE categories ADD COLUMN IF NOT EXISTS accent TEXT",
+    // The health state machine; see health.rs.
+    r#"
+    CREATE TABLE IF NOT EXISTS service_health (
+        service_id INTEGER PRIMARY KEY REFERENCES services(id) ON DELETE CASCADE,
+        state TEXT NOT NULL,
+        since TIMESTAMPTZ NOT NULL DEFAULT now(),
+        failures INTEGER NOT NULL DEFAULT 0,
+        successes INTEGER NOT NULL DEFAULT 0,
+        recent BOOLEAN[] NOT NULL DEFAULT '{}'
+    )
+    "#,
+    "ALTER TABLE services ADD COLUMN IF NOT EXISTS maintenance_until TIMESTAMPTZ",
+    // Clicks through GET /go/:name per user; see usage.rs.
+    r#"
+    CREATE TABLE IF NOT EXISTS service_usage (
+        principal TEXT NOT NULL,
+        service_id INTEGER NOT NULL REFERENCES services(id) ON DELETE CASCADE,
+        clicks BIGINT NOT NULL DEFAULT 1,
+        last_used_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        PRIMARY KEY (principal, service_id)
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS usage_opt_outs (
+        principal TEXT PRIMARY KEY,
+        since TIMESTAMPTZ NOT NULL DEFAULT now()
+    )
+    "#,
+    // Idempotency-Key requests and their responses; see idempotency.rs.
+    r#"
+    CREATE TABLE IF NOT EXISTS idempotency_keys (
+        principal TEXT NOT NULL,
+        key TEXT NOT NULL,
+        fingerprint TEXT NOT NULL,
+        status SMALLINT,
+        content_type TEXT,
+        body BYTEA,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        PRIMARY KEY (principal, key)
+    )
+    "#,
+    // Uptime targets; see sla.rs.
+    r#"
+    CREATE TABLE IF NOT EXISTS service_slas (
+        service_id INTEGER PRIMARY KEY REFERENCES services(id) ON DELETE CASCADE,
+        target DOUBLE PRECISION NOT NULL CHECK (target > 0 AND target < 100),
+        period TEXT NOT NULL CHECK (period IN ('week', 'month', 'quarter')),
+        updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+    )
+    "#,
+    // Tokens of local users (see users.rs) stop working with their user and
+    // never grant more than the user's current role; see tokens.rs.
+    "ALTER TABLE api_tokens ADD COLUMN IF NOT EXISTS local_user BOOLEAN NOT NULL DEFAULT false",
+    "UPDATE api_tokens SET local_user = true WHERE NOT local_user AND owner IN (SELECT name FROM users)",
+];
+
+// Tables and added columns the statements above create that the database
+// lacks, as "table" or "table.column", for `indexpage doctor`.
+pub async fn missing(pool: &PgPool) -> sqlx::Result<Vec<String>> {
+    let existing = sqlx::query!(
+        r#"SELECT table_name::text AS "table!", column_name::text AS "column!"
+           FROM information_schema.columns WHERE table_schema = current_schema()"#
+    )
+    .fetch_all(pool)
+    .await?;
+    let has = |table: &str, column: Option<&str>| {
+        existing.iter().any(|row| row.table == table && column.is_none_or(|column| row.column == column))
+    };
+    let mut missing = Vec::new();
+    for statement in STATEMENTS {
+        let words: Vec<&str> = statement.split_whitespace().collect();
+        let after = |phrase: &[&str]| {
+            let at = words.windows(phrase.len()).position(|window| window == phrase)?;
+            words.get(at + phrase.len()).map(|word| word.trim_end_matches('('))
+        };
+        if let Some(table) = after(&["CREATE", "TABLE", "IF", "NOT", "EXISTS"]) {
+            if !has(table, None) {
+                missing.push(table.to_string());
+            }
+        } else if let (Some(table), Some(column)) = (after(&["ALTER", "TABLE"]), after(&["ADD", "COLUMN", "IF", "NOT", "EXISTS"]))
+            && has(table, None)
+            && !has(table, Some(column))
+        {
+            missing.push(format!("{}.{}", table, column));
+        }
+    }
+    Ok(missing)
+}
+
+pub async fn migrate(pool: &PgPool) -> sqlx::Result<()> {
+    for statement in STATEMENTS {
+        sqlx::query(statement).execute(pool).await?;
+    }
+    case_insensitive_names(pool).await
+}
+
+// Replaces the case-sensitive UNIQUE(name) with a unique index on lower(name)
+// over live (not trashed) services. Databases that already hold names
+// differing only in case can't get the index; those are reported and left
+// alone until they're renamed.
+async fn case_insensitive_names(pool: &PgPool) -> sqlx::Result<()> {
+    let conflicts: Vec<(Vec<String>,)> = sqlx::query_as(
+        "SELECT array_agg(name ORDER BY id) FROM services WHERE deleted_at IS NULL \
+         GROUP BY lower(name) HAVING count(*) > 1",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    if !conflicts.is_empty() {
+        for (names,) in &conflicts {
+            tracing::warn!("service names differ only in case: {}", names.join(", "));
+        }
+        tracing::warn!(
+            "case-insensitive name uniqueness not enforced until the {} conflict(s) above are renamed",
+            conflicts.len()
+        );
+        return Ok(());
+    }
+
+    sqlx::query(
+        "CREATE UNIQUE INDEX IF NOT EXISTS services_live_name_lower ON services (lower(name)) WHERE deleted_at IS NULL",
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query("DROP INDEX IF EXISTS services_name_lower").execute(pool).await?;
+    sqlx::query("ALTER TABLE services DROP CONSTRAINT IF EXISTS services_name_key")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use serde_json::Value;
+use std::{collections::HashMap, env, fs, time::Duration};
+
+use crate::config::VaultConfig;
+
+// Resolves secrets such as DATABASE_URL, in order of precedence: the plain
+// environment variable, a file named by `<NAME>_FILE` (the Docker secrets
+// convention), then the key of the same name in the configured Vault secret.
+pub struct Secrets {
+    vault: HashMap<String, String>,
+    // Off for the test harness, whose secrets are fixed.
+    env: bool,
+}
+
+impl Secrets {
+    pub async fn load(config: &VaultConfig) -> anyhow::Result<Self> {
+        let vault = match Vault::from_config(config)? {
+            Some(vault) => {
+                let values = vault.read_secret().await?;
+                if config.renew {
+                    vault.spawn_renewal();
+                }
+                values
+            }
+            None => HashMap::new(),
+        };
+        Ok(Self { vault, env: true })
+    }
+
+    // Just `values`, whatever the environment holds.
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn fixed(values: HashMap<String, String>) -> Self {
+        Self { vault: values, env: false }
+    }
+
+    pub fn get(&self, name: &str) -> anyhow::Result<Option<String>> {
+        if !self.env {
+            return Ok(self.vault.get(name).cloned());
+        }
+        if let Ok(value) = env::var(name) {
+            return Ok(Some(value));
+        }
+        if let Some(value) = from_file(name)? {
+            return Ok(Some(value));
+        }
+        Ok(self.vault.get(name).cloned())
+    }
+}
+
+fn from_file(name: &str) -> anyhow::Result<Option<String>> {
+    let Ok(path) = env::var(format!("{}_FILE", name)) else { return Ok(None) };
+    let value = fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("reading {}_FILE ({}): {}", name, path, e))?;
+    Ok(Some(value.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+struct Vault {
+    http: reqwest::Client,
+    address: String,
+    token: String,
+    path: String,
+}
+
+impl Vault {
+    // Vault is used when an address (VAULT_ADDR or `vault.address`) and a
+    // secret path are configured.
+    fn from_config(config: &VaultConfig) -> anyhow::Result<Option<Self>> {
+        let address = env::var("VAULT_ADDR").ok().or_else(|| config.address.clone());
+        let (Some(address), Some(path)) = (address, config.path.clone()) else {
+            return Ok(None);
+        };
+        let token = match (env::var("VAULT_TOKEN"), from_file("VAULT_TOKEN")?, &config.token_file) {
+            (Ok(token), _, _) => token,
+            (Err(_), Some(token), _) => token,
+            (Err(_), None, Some(file)) => fs::read_to_string(file)
+                .map_err(|e| anyhow::anyhow!("reading {}: {}", file.display(), e))?
+                .trim()
+                .to_string(),
+            (Err(_), None, None) => anyhow::bail!("vault.path is set but no Vault token is configured"),
+        };
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?;
+        Ok(Some(Self {
+            http,
+            address: address.trim_end_matches('/').to_string(),
+            token,
+            path: path.trim_matches('/').to_string(),
+        }))
+    }
+
+    // Reads a KV secret; both KV v2 ({data: {data: {...}}}) and v1
+    // ({data: {...}}) responses are understood. Non-string values are skipped.
+    async fn read_secret(&self) -> anyhow::Result<HashMap<String, String>> {
+        let url = format!("{}/v1/{}", self.address, self.path);
+        let body: Value = self
+            .http
+            .get(&url)
+            .header("X-Vault-Token", &self.token)
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(|e| anyhow::anyhow!("reading Vault secret {}: {}", self.path, e))?
+            .json()
+            .await?;
+
+        let data = &body["data"];
+        let fields = match (&data["data"], &data["metadata"]) {
+            (Value::Object(fields), Value::Object(_)) => fields,
+            _ => data
+                .as_object()
+                .ok_or_else(|| anyhow::anyhow!("Vault secret {} has no data", self.path))?,
+        };
+        Ok(fields
+            .iter()
+            .filter_map(|(key, value)| value.as_str().map(|value| (key.clone(), value.to_string())))
+            .collect())
+    }
+
+    // Renews the token at half its TTL until Vault reports it non-renewable.
+    fn spawn_renewal(self) {
+        tokio::spawn(async move {
+            loop {
+                let delay = match self.renew().await {
+                    Ok(Some(ttl)) => Duration::from_secs((ttl / 2).max(30)),
+                    Ok(None) => {
+                        tracing::info!("Vault token is not renewable; renewal stopped");
+                        return;
+                    }
+                    Err(e) => {
+                        tracing::warn!("Vault token renewal failed: {}", e);
+                        Duration::from_secs(60)
+                    }
+                };
+                tokio::time::sleep(delay).await;
+            }
+        });
+    }
+
+    async fn renew(&self) -> anyhow::Result<Option<u64>> {
+        let body: Value = self
+            .http
+            .post(format!("{}/v1/auth/token/renew-self", self.address))
+            .header("X-Vault-Token", &self.token)
+            .json(&serde_json::json!({}))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let auth = &body["auth"];
+        if auth["renewable"].as_bool() != Some(true) {
+            return Ok(None);
+        }
+        Ok(auth["lease_duration"].as_u64())
+    }
+}
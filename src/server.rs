@@ -0,0 +1,235 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Router,
+};
+use http::StatusCode;
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto::Builder,
+    service::TowerToHyperService,
+};
+use openssl::ssl::SslAcceptor;
+#[cfg(feature = "tls")]
+use openssl::{
+    nid::Nid,
+    ssl::{Ssl, SslFiletype, SslMethod, SslVerifyMode},
+    x509::X509Ref,
+};
+#[cfg(feature = "tls")]
+use std::pin::Pin;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::Semaphore,
+};
+#[cfg(feature = "tls")]
+use tokio_openssl::SslStream;
+use tower::ServiceExt;
+
+use crate::{auth::ClientCert, config::{ServerConfig, TlsConfig}, systemd};
+
+// hyper's smallest accepted read buffer.
+const MIN_HEADER_BYTES: usize = 8192;
+
+// Serves `app` on the configured address, or the socket systemd passed in,
+// over TLS when `server.tls` is set. Handlers can extract
+// ConnectInfo<SocketAddr> either way.
+pub async fn serve(config: &ServerConfig, app: Router) -> anyhow::Result<()> {
+    let listener = match systemd::listener()? {
+        Some(listener) => {
+            tracing::info!("listening on {} (from systemd)", listener.local_addr()?);
+            listener
+        }
+        None => {
+            let listener = TcpListener::bind(config.listen).await?;
+            tracing::info!("listening on {}", config.listen);
+            listener
+        }
+    };
+    let acceptor = config.tls.as_ref().map(acceptor).transpose()?;
+    if let Some(admin_listen) = config.admin_listen {
+        let admin = TcpListener::bind(admin_listen).await?;
+        tracing::info!("admin endpoints on {}", admin_listen);
+        let (acceptor, config, app) = (acceptor.clone(), config.clone(), app.clone().layer(Extension(AdminListener)));
+        tokio::spawn(async move {
+            if let Err(e) = accept(admin, acceptor, &config, app).await {
+                tracing::error!("admin listener stopped: {}", e);
+            }
+        });
+    }
+    systemd::ready();
+    accept(listener, acceptor, config, app).await
+}
+
+// Marks requests that came in on `server.admin_listen`.
+#[derive(Debug, Clone, Copy)]
+pub struct AdminListener;
+
+// With an admin listener, the main one answers its paths with 404 as if
+// they didn't exist. `paths` is empty without one.
+pub async fn restrict(State(paths): State<Arc<Vec<String>>>, request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    let admin_path = paths.iter().any(|prefix| {
+        path.strip_prefix(prefix.trim_end_matches('/')).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    });
+    if admin_path && request.extensions().get::<AdminListener>().is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    next.run(request).await
+}
+
+// Serves `app` on a listener the caller bound, such as the test harness's;
+// `admin_listen` is left closed.
+#[cfg(any(test, feature = "test-support"))]
+#[cfg_attr(not(test), allow(dead_code))]
+pub async fn serve_on(listener: TcpListener, config: &ServerConfig, app: Router) -> anyhow::Result<()> {
+    let acceptor = config.tls.as_ref().map(acceptor).transpose()?;
+    accept(listener, acceptor, config, app).await
+}
+
+async fn accept(
+    listener: TcpListener,
+    acceptor: Option<Arc<SslAcceptor>>,
+    config: &ServerConfig,
+    app: Router,
+) -> anyhow::Result<()> {
+    let builder = Arc::new(builder(config));
+    let connections = config.max_connections.map(|max| Arc::new(Semaphore::new(max.max(1))));
+    loop {
+        // Over the limit, new connections wait in the listen backlog.
+        let permit = match &connections {
+            Some(connections) => Some(connections.clone().acquire_owned().await?),
+            None => None,
+        };
+        let (tcp, remote) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::warn!("accept failed: {}", e);
+                continue;
+            }
+        };
+        let (acceptor, builder, app) = (acceptor.clone(), builder.clone(), app.clone());
+        tokio::spawn(async move {
+            let _permit = permit;
+            match acceptor {
+                #[cfg(feature = "tls")]
+                Some(acceptor) => serve_tls(&builder, &acceptor, tcp, remote, app).await,
+                _ => serve_connection(&builder, tcp, remote, None, app).await,
+            }
+        });
+    }
+}
+
+#[cfg(feature = "tls")]
+async fn serve_tls(
+    builder: &Builder<TokioExecutor>,
+    acceptor: &SslAcceptor,
+    tcp: tokio::net::TcpStream,
+    remote: SocketAddr,
+    app: Router,
+) {
+    let mut stream = match Ssl::new(acceptor.context()).and_then(|ssl| SslStream::new(ssl, tcp)) {
+        Ok(stream) => stream,
+        Err(e) => return tracing::warn!("TLS setup for {} failed: {}", remote, e),
+    };
+    if let Err(e) = Pin::new(&mut stream).accept().await {
+        return tracing::debug!("TLS handshake with {} failed: {}", remote, e);
+    }
+    // The chain was already verified against client_ca_file during
+    // the handshake, so the names can be trusted as-is.
+    let cert = stream.ssl().peer_certificate().map(|cert| identities(&cert));
+    serve_connection(builder, stream, remote, cert, app).await
+}
+
+// HTTP/1 and, unless disabled, HTTP/2 with the configured limits.
+fn builder(config: &ServerConfig) -> Builder<TokioExecutor> {
+    let keep_alive_timeout = Duration::from_secs(config.keep_alive_timeout_secs.max(1));
+    let mut builder = Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(config.keep_alive)
+        .header_read_timeout(keep_alive_timeout)
+        .max_buf_size(config.max_header_bytes.max(MIN_HEADER_BYTES));
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .keep_alive_interval(config.keep_alive.then_some(keep_alive_timeout))
+        .keep_alive_timeout(keep_alive_timeout)
+        .max_header_list_size(config.max_header_bytes.max(MIN_HEADER_BYTES).min(u32::MAX as usize) as u32);
+    if config.http2 { builder } else { builder.http1_only() }
+}
+
+async fn serve_connection<S>(
+    builder: &Builder<TokioExecutor>,
+    stream: S,
+    remote: SocketAddr,
+    cert: Option<ClientCert>,
+    app: Router,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = app.map_request(move |mut request: http::Request<Incoming>| {
+        request.extensions_mut().insert(ConnectInfo(remote));
+        if let Some(cert) = &cert {
+            request.extensions_mut().insert(cert.clone());
+        }
+        request
+    });
+    if let Err(e) = builder
+        .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(service))
+        .await
+    {
+        tracing::debug!("connection from {} closed: {}", remote, e);
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+pub fn acceptor(_tls: &TlsConfig) -> anyhow::Result<Arc<SslAcceptor>> {
+    anyhow::bail!("[server.tls] is configured but this build lacks the `tls` feature")
+}
+
+#[cfg(feature = "tls")]
+pub fn acceptor(tls: &TlsConfig) -> anyhow::Result<Arc<SslAcceptor>> {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())?;
+    builder
+        .set_certificate_chain_file(&tls.cert_file)
+        .map_err(|e| anyhow::anyhow!("loading {}: {}", tls.cert_file.display(), e))?;
+    builder
+        .set_private_key_file(&tls.key_file, SslFiletype::PEM)
+        .map_err(|e| anyhow::anyhow!("loading {}: {}", tls.key_file.display(), e))?;
+    builder.check_private_key()?;
+    if let Some(ca) = &tls.client_ca_file {
+        builder
+            .set_ca_file(ca)
+            .map_err(|e| anyhow::anyhow!("loading {}: {}", ca.display(), e))?;
+        let mut mode = SslVerifyMode::PEER;
+        if tls.require_client_cert {
+            mode |= SslVerifyMode::FAIL_IF_NO_PEER_CERT;
+        }
+        builder.set_verify(mode);
+    }
+    Ok(Arc::new(builder.build()))
+}
+
+#[cfg(feature = "tls")]
+fn identities(cert: &X509Ref) -> ClientCert {
+    let mut names: Vec<String> = cert
+        .subject_name()
+        .entries_by_nid(Nid::COMMONNAME)
+        .filter_map(|entry| entry.data().to_string().ok())
+        .collect();
+    if let Some(sans) = cert.subject_alt_names() {
+        for san in &sans {
+            let name = san.dnsname().or_else(|| san.email()).or_else(|| san.uri());
+            if let Some(name) = name {
+                names.push(name.to_string());
+            }
+        }
+    }
+    ClientCert { names }
+}
@@ -0,0 +1,469 @@
+use axum::{
+    extract::{Path, Query, RawQuery, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{
+    audit, categories, checks, colors, crypto, duplicates, hooks, links, orgs, pagination, plugins, preflight, query,
+    repository::ServiceRepository,
+    static_links::StaticLinks,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Service {
+    pub id: i32,
+    pub name: String,
+    pub link: String,
+    // Shown instead of `link` to clients on the internal networks.
+    pub internal_link: Option<String>,
+    pub description: Option<String>,
+    pub check_type: String,
+    pub expected_ip: Option<String>,
+    pub latency_threshold_ms: Option<i32>,
+    pub category_id: Option<i32>,
+    pub org_id: Option<i32>,
+    pub public: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    // Archived services are kept with their history but hidden and unchecked.
+    #[sqlx(default)]
+    pub archived_at: Option<chrono::DateTime<chrono::Utc>>,
+    // Tile colors; see colors.rs.
+    #[sqlx(default)]
+    pub color: Option<String>,
+    #[sqlx(default)]
+    pub accent: Option<String>,
+    #[sqlx(default)]
+    pub tags: Vec<String>,
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub source: Source,
+}
+
+// Where a service is defined. Config entries (see static_links.rs) have
+// negative ids and are read-only.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    #[default]
+    Database,
+    Config,
+}
+
+impl Service {
+    pub fn expand_links(&mut self, links: &links::LinkContext) {
+        self.link = links.choose(&self.link, self.internal_link.as_deref());
+        self.internal_link = self.internal_link.as_deref().map(|link| links.expand(link));
+    }
+}
+
+// Resolves the live (not trashed) service id for the name bound as $1, ignoring case. An exact
+// match wins if a pre-migration database still has case-only duplicates.
+pub const SERVICE_ID_BY_NAME: &str = "(SELECT id FROM services WHERE lower(name) = lower($1) \
+     AND deleted_at IS NULL ORDER BY name = $1 DESC, id LIMIT 1)";
+
+#[derive(Debug, Deserialize)]
+pub struct CreateService {
+    pub name: String,
+    pub link: String,
+    #[serde(default)]
+    pub internal_link: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub check_type: Option<String>,
+    #[serde(default)]
+    pub expected_ip: Option<String>,
+    #[serde(default)]
+    pub latency_threshold_ms: Option<i32>,
+    // Sent as a bearer token by http checks; stored encrypted, never returned.
+    #[serde(default)]
+    pub check_token: Option<String>,
+    #[serde(default)]
+    pub category_id: Option<i32>,
+    // Limits the service to members of this org.
+    #[serde(default)]
+    pub org_id: Option<i32>,
+    #[serde(default)]
+    pub public: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(flatten)]
+    pub colors: colors::Colors,
+}
+
+// GET /services
+// Filters: ?category=&tag=&status=&q=&visibility=&state=, ordering: ?sort=,
+// paging: ?limit=&offset= or ?limit=&cursor= (keyset on created_at, id).
+pub async fn get_services<R: ServiceRepository>(
+    State(services): State<R>,
+    State(static_links): State<StaticLinks>,
+    Query(query): Query<pagination::PageQuery>,
+    Query(filter): Query<query::ServiceFilter>,
+    RawQuery(raw_query): RawQuery,
+    links: links::LinkContext,
+    visibility: categories::Visibility,
+) -> Result<(http::HeaderMap, Json<Vec<Service>>), (axum::http::StatusCode, String)> {
+    let mut page = query.page()?;
+    if filter.is_sorted() {
+        page = page.without_keyset();
+    }
+
+    let mut services = services.list(&filter, &page, &visibility).await?;
+    let base = pagination::base_url("/services", raw_query.as_deref());
+    let headers = pagination::paginate(&base, &page, &mut services, |service| {
+        pagination::Cursor { created_at: service.created_at, id: service.id.into() }
+    });
+    services.splice(0..0, static_links.matching(&filter, &page));
+    for service in &mut services {
+        service.expand_links(&links);
+    }
+    Ok((headers, Json(services)))
+}
+
+// POST /services's answer; "warnings" only with ?preflight=true.
+#[derive(Debug, Serialize)]
+pub struct CreatedService {
+    #[serde(flatten)]
+    pub service: Service,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warnings: Option<Vec<String>>,
+}
+
+// POST /services
+// 409 with the existing service for a link already on the dashboard, or a
+// near-duplicate of one without ?force=true (see duplicates.rs).
+// ?preflight=true probes the link too (see preflight.rs).
+#[allow(clippy::too_many_arguments)]
+pub async fn create_service<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    State(services): State<R>,
+    State(static_links): State<StaticLinks>,
+    State(cipher): State<Option<crypto::Cipher>>,
+    State(audit): State<audit::Audit>,
+    State(plugins): State<plugins::Plugins>,
+    State(hooks): State<hooks::Hooks>,
+    State(preflight): State<preflight::Preflight>,
+    actor: audit::Actor,
+    links: links::LinkContext,
+    visibility: categories::Visibility,
+    Query(force): Query<duplicates::Force>,
+    Query(probe): Query<preflight::PreflightQuery>,
+    Json(mut payload): Json<CreateService>,
+) -> axum::response::Result<Json<CreatedService>> {
+    static_links.reserve(&payload.name)?;
+    if let Some(org_id) = payload.org_id {
+        orgs::check_member(&pool, &visibility, org_id).await?;
+    }
+    let check_type = payload.check_type.clone().unwrap_or_else(|| "http".into());
+    if checks::CheckType::parse(&check_type).is_none() && !plugins.has_check(&check_type) {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            format!("Unknown check_type '{}' (expected http, dns, none or a plugin check)", check_type),
+        )
+            .into());
+    }
+    if let Some(ip) = &payload.expected_ip
+        && ip.parse::<std::net::IpAddr>().is_err()
+    {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            format!("Invalid expected_ip '{}'", ip),
+        )
+            .into());
+    }
+
+    payload.colors = payload.colors.validated()?;
+
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(|e| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if let Some(mut duplicate) = duplicates::check(&mut conn, &payload.link, force.force).await? {
+        duplicate.service.expand_links(&links);
+        return Err(duplicate.into());
+    }
+    drop(conn);
+
+    let check_token = match (&payload.check_token, &cipher) {
+        (Some(token), Some(cipher)) => Some(cipher.encrypt(token)),
+        (Some(_), None) => {
+            return Err((
+                axum::http::StatusCode::BAD_REQUEST,
+                "check_token requires an encryption key (INDEXPAGE_SECRET_KEY)",
+            )
+                .into())
+        }
+        (None, _) => None,
+    };
+
+    payload.tags.extend(plugins.enrich(&payload.name, &payload.link).await);
+    let proposed = serde_json::json!({
+        "name": payload.name,
+        "link": payload.link,
+        "internal_link": payload.internal_link,
+        "description": payload.description,
+        "check_type": check_type,
+        "expected_ip": payload.expected_ip,
+        "latency_threshold_ms": payload.latency_threshold_ms,
+        "category_id": payload.category_id,
+        "org_id": payload.org_id,
+        "public": payload.public,
+        "tags": payload.tags,
+        "color": payload.colors.color,
+        "accent": payload.colors.accent,
+    });
+    hooks.before(hooks::HookEvent::PreCreate, &actor, &proposed).await?;
+
+    let mut service = services.create(&payload, &check_type, check_token).await?;
+    audit.record(&actor, "service.create", Some(&service.name), Some(service.link.clone())).await;
+    hooks.after(hooks::HookEvent::PostCreate, &actor, serde_json::to_value(&service).unwrap_or_default());
+    let warnings = match probe.preflight {
+        true => Some(preflight.probe(&service.link, payload.check_token.as_deref()).await),
+        false => None,
+    };
+    service.expand_links(&links);
+    Ok(Json(CreatedService { service, warnings }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenameService {
+    name: String,
+}
+
+// POST /services/:name/rename
+// Updates the name in place so the id, and everything keyed by it (check
+// history, tags, category), stays attached.
+#[allow(clippy::too_many_arguments)]
+pub async fn rename_service<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    State(services): State<R>,
+    State(static_links): State<StaticLinks>,
+    State(audit): State<audit::Audit>,
+    State(hooks): State<hooks::Hooks>,
+    actor: audit::Actor,
+    links: links::LinkContext,
+    visibility: categories::Visibility,
+    Path(name): Path<String>,
+    Json(payload): Json<RenameService>,
+) -> Result<Json<Service>, (axum::http::StatusCode, String)> {
+    static_links.reserve(&name)?;
+    static_links.reserve(&payload.name)?;
+    visibility.check_service(&pool, &name).await?;
+    if payload.name.trim().is_empty() {
+        return Err((axum::http::StatusCode::BAD_REQUEST, "Name cannot be empty".into()));
+    }
+    let change = serde_json::json!({ "name": name, "changes": { "name": payload.name } });
+    hooks.before(hooks::HookEvent::PreUpdate, &actor, &change).await?;
+    let id = services
+        .rename(&name, &payload.name)
+        .await?
+        .ok_or((axum::http::StatusCode::NOT_FOUND, "Service not found".into()))?;
+    audit
+        .record(&actor, "service.rename", Some(&payload.name), Some(format!("from '{}'", name)))
+        .await;
+    let mut service = services
+        .get(id)
+        .await?
+        .ok_or((axum::http::StatusCode::NOT_FOUND, "Service not found".into()))?;
+    hooks.after(hooks::HookEvent::PostUpdate, &actor, serde_json::to_value(&service).unwrap_or_default());
+    service.expand_links(&links);
+    Ok(Json(service))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CloneService {
+    name: String,
+    link: String,
+    #[serde(default)]
+    internal_link: Option<String>,
+}
+
+// POST /services/:name/clone
+// Copies check settings, category, visibility and tags; history is not copied.
+// The new link is checked for duplicates as in POST /services.
+#[allow(clippy::too_many_arguments)]
+pub async fn clone_service<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    State(services): State<R>,
+    State(static_links): State<StaticLinks>,
+    State(audit): State<audit::Audit>,
+    State(hooks): State<hooks::Hooks>,
+    actor: audit::Actor,
+    links: links::LinkContext,
+    visibility: categories::Visibility,
+    Path(name): Path<String>,
+    Query(force): Query<duplicates::Force>,
+    Json(payload): Json<CloneService>,
+) -> axum::response::Result<Json<Service>> {
+    static_links.reserve(&payload.name)?;
+    visibility.check_service(&pool, &name).await?;
+    let internal = |e: sqlx::Error| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let mut conn = pool.acquire().await.map_err(internal)?;
+    if let Some(mut duplicate) = duplicates::check(&mut conn, &payload.link, force.force).await? {
+        duplicate.service.expand_links(&links);
+        return Err(duplicate.into());
+    }
+    drop(conn);
+    let proposed = serde_json::json!({
+        "name": payload.name,
+        "link": payload.link,
+        "internal_link": payload.internal_link,
+        "cloned_from": name,
+    });
+    hooks.before(hooks::HookEvent::PreCreate, &actor, &proposed).await?;
+    let id = services
+        .clone_service(&name, &payload.name, &payload.link, payload.internal_link.as_deref())
+        .await?;
+    audit
+        .record(&actor, "service.clone", Some(&payload.name), Some(format!("from '{}'", name)))
+        .await;
+
+    let mut service = services
+        .get(id)
+        .await?
+        .ok_or((axum::http::StatusCode::NOT_FOUND, "Service not found"))?;
+    hooks.after(hooks::HookEvent::PostCreate, &actor, serde_json::to_value(&service).unwrap_or_default());
+    service.expand_links(&links);
+    Ok(Json(service))
+}
+
+// DELETE /services/:name
+// Moves the service to the trash; see trash.rs for restore and purge.
+#[allow(clippy::too_many_arguments)]
+pub async fn delete_service<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    State(services): State<R>,
+    State(static_links): State<StaticLinks>,
+    State(audit): State<audit::Audit>,
+    State(hooks): State<hooks::Hooks>,
+    actor: audit::Actor,
+    visibility: categories::Visibility,
+    links: links::LinkContext,
+    Path(name): Path<String>,
+) -> Result<Json<Service>, (axum::http::StatusCode, String)> {
+    static_links.reserve(&name)?;
+    visibility.check_service(&pool, &name).await?;
+    hooks
+        .before(hooks::HookEvent::PreDelete, &actor, &serde_json::json!({ "name": name }))
+        .await?;
+    let mut deleted = services
+        .trash(&name)
+        .await?
+        .ok_or((axum::http::StatusCode::NOT_FOUND, "Service not found".into()))?;
+    audit.record(&actor, "service.delete", Some(&deleted.name), None).await;
+    hooks.after(hooks::HookEvent::PostDelete, &actor, serde_json::json!({ "name": deleted.name }));
+    deleted.expand_links(&links);
+    Ok(Json(deleted))
+}
+
+// POST /services/:name/archive
+// For services that are offline on purpose, such as old projects: hidden
+// from GET /services unless ?state=archived or ?state=all, and no longer
+// checked or alerted on. Unlike the trash, archived services are never
+// purged; their history stays. An ongoing incident is closed. Returns the
+// archived service.
+#[allow(clippy::too_many_arguments)]
+pub async fn archive_service<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    State(services): State<R>,
+    State(static_links): State<StaticLinks>,
+    State(audit): State<audit::Audit>,
+    actor: audit::Actor,
+    visibility: categories::Visibility,
+    links: links::LinkContext,
+    Path(name): Path<String>,
+) -> Result<Json<Service>, (axum::http::StatusCode, String)> {
+    static_links.reserve(&name)?;
+    visibility.check_service(&pool, &name).await?;
+    let mut archived = services
+        .archive(&name)
+        .await?
+        .ok_or((axum::http::StatusCode::NOT_FOUND, format!("'{}' is not an active service", name)))?;
+    audit.record(&actor, "service.archive", Some(&archived.name), None).await;
+    archived.expand_links(&links);
+    Ok(Json(archived))
+}
+
+// DELETE /services/:name/archive
+// Returns the restored service.
+pub async fn unarchive_service<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    State(services): State<R>,
+    State(audit): State<audit::Audit>,
+    actor: audit::Actor,
+    visibility: categories::Visibility,
+    links: links::LinkContext,
+    Path(name): Path<String>,
+) -> Result<Json<Service>, (axum::http::StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let mut restored = services
+        .unarchive(&name)
+        .await?
+        .ok_or((axum::http::StatusCode::NOT_FOUND, format!("'{}' is not archived", name)))?;
+    audit.record(&actor, "service.unarchive", Some(&restored.name), None).await;
+    restored.expand_links(&links);
+    Ok(Json(restored))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{LinksConfig, StaticLinkConfig},
+        repository::MemoryServices,
+    };
+
+    async fn list(
+        services: &MemoryServices,
+        static_links: &StaticLinks,
+        cursor: Option<String>,
+    ) -> (http::HeaderMap, Vec<String>) {
+        let links = links::Links::new(&LinksConfig::default(), false).for_checks();
+        let page = pagination::PageQuery { limit: Some(1), offset: None, cursor };
+        let (headers, Json(listed)) = get_services(
+            State(services.clone()),
+            State(static_links.clone()),
+            Query(page),
+            Query(query::ServiceFilter::default()),
+            RawQuery(None),
+            links,
+            categories::Visibility::everything(),
+        )
+        .await
+        .unwrap();
+        (headers, listed.into_iter().map(|service| service.name).collect())
+    }
+
+    // GET /services pages through what the repository lists, with the
+    // static links on the first page.
+    #[tokio::test]
+    async fn lists_services_from_the_repository() {
+        let services = MemoryServices::default();
+        for name in ["wiki", "grafana", "plex"] {
+            let link = format!("https://{}.example.com", name);
+            let service = serde_json::json!({"name": name, "link": link});
+            let service: CreateService = serde_json::from_value(service).unwrap();
+            services.create(&service, "http", None).await.unwrap();
+        }
+        services.trash("grafana").await.unwrap();
+        let router = StaticLinkConfig {
+            name: "Router".into(),
+            link: "http://192.168.1.1".into(),
+            internal_link: None,
+            description: None,
+            tags: Vec::new(),
+            public: false,
+        };
+        let static_links = StaticLinks::new(&[router]).unwrap();
+
+        let (headers, names) = list(&services, &static_links, None).await;
+        assert_eq!(names, ["Router", "wiki"]);
+        let cursor = headers["x-next-cursor"].to_str().unwrap().to_string();
+        let (headers, names) = list(&services, &static_links, Some(cursor)).await;
+        assert_eq!(names, ["plex"]);
+        assert!(!headers.contains_key("x-next-cursor"));
+    }
+}
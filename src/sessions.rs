@@ -0,0 +1,255 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use http::{header, HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{
+    audit::{Actor, Audit},
+    auth::{Auth, Principal, Role},
+    crypto::{self, Cipher},
+    lockout, totp, users,
+};
+
+// Browser sign-in for local users. A session is a random token, sent back as
+// an HttpOnly cookie (or usable as a bearer token), of which the database
+// keeps only the hash, so revoking a row signs that device out.
+pub const COOKIE: &str = "indexpage_session";
+pub const TOKEN_PREFIX: &str = "ips_";
+
+#[derive(Debug, Serialize)]
+pub struct Session {
+    id: i32,
+    // The User-Agent the session signed in with.
+    device: Option<String>,
+    ip: Option<String>,
+    created_at: DateTime<Utc>,
+    last_seen_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    current: bool,
+}
+
+fn internal(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+// Resolves a session token to its user, role and session id, refreshing its
+// last-seen time. Expired, idle and revoked sessions resolve to None.
+pub async fn authenticate(pool: &PgPool, token: &str, idle: Duration) -> sqlx::Result<Option<(String, Role, i32)>> {
+    let row = sqlx::query!(
+        "UPDATE sessions SET last_seen_at = now() FROM users \
+         WHERE sessions.token_hash = $1 AND users.name = sessions.user_name AND sessions.revoked_at IS NULL \
+         AND sessions.expires_at > now() AND sessions.last_seen_at > now() - $2::interval \
+         RETURNING users.name, users.role, sessions.id",
+        crypto::hash_token(token),
+        idle as _,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.and_then(|row| Some((row.name, Role::parse(&row.role)?, row.id))))
+}
+
+// The session token from the Cookie header, if any.
+pub fn from_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| pair.trim().strip_prefix(COOKIE)?.strip_prefix('='))
+}
+
+fn cookie(value: &str, max_age: i64, secure: bool) -> String {
+    format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}{}",
+        COOKIE,
+        value,
+        max_age,
+        if secure { "; Secure" } else { "" }
+    )
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Login {
+    name: String,
+    password: String,
+    // The authenticator app or a recovery code, for users with two-factor
+    // authentication; without it such users get a 401 asking for one.
+    #[serde(default)]
+    code: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct LoggedIn {
+    name: String,
+    role: Role,
+    expires_at: DateTime<Utc>,
+    // Also set as a cookie; returned for clients that send bearer tokens.
+    token: String,
+}
+
+// POST /login
+pub async fn login(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    State(auth): State<Auth>,
+    State(cipher): State<Option<Cipher>>,
+    actor: Actor,
+    headers: HeaderMap,
+    Json(payload): Json<Login>,
+) -> Result<Response, (StatusCode, String)> {
+    let ip_key = lockout::ip_key(actor.ip.as_deref());
+    let account_key = lockout::account_key(&payload.name);
+    let keys = [ip_key.as_str(), account_key.as_str()];
+    if let Some(wait) = auth.lockout().locked(&keys) {
+        return Ok(lockout::too_many(wait));
+    }
+
+    let user = sqlx::query!("SELECT name, role, password_hash, totp_enabled FROM users WHERE name = $1", payload.name)
+        .fetch_optional(&pool)
+        .await
+        .map_err(internal)?;
+
+    let hash = user.as_ref().map(|user| user.password_hash.clone());
+    let password = payload.password.clone();
+    let verified = tokio::task::spawn_blocking(move || users::verify_password(&password, hash.as_deref()))
+        .await
+        .unwrap_or(false);
+    let attempted = Actor { name: payload.name.clone(), ip: actor.ip.clone() };
+    let (name, role, totp) = match user {
+        Some(user) if verified => (user.name, Role::parse(&user.role).unwrap_or(Role::Viewer), user.totp_enabled),
+        _ => {
+            auth.lockout().record_failure(&audit, &attempted, &keys, "wrong password").await;
+            return Err((StatusCode::UNAUTHORIZED, "Invalid name or password".into()));
+        }
+    };
+    if totp {
+        let Some(code) = &payload.code else {
+            return Err((StatusCode::UNAUTHORIZED, "Two-factor code required".into()));
+        };
+        if !totp::verify(&pool, &cipher, &name, code).await? {
+            auth.lockout().record_failure(&audit, &attempted, &keys, "wrong two-factor code").await;
+            return Err((StatusCode::UNAUTHORIZED, "Invalid two-factor code".into()));
+        }
+    }
+    auth.lockout().succeed(&keys);
+
+    let token = format!("{}{}", TOKEN_PREFIX, crypto::random_token());
+    let expires_at = Utc::now() + auth.session_ttl();
+    let device = headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.chars().take(256).collect::<String>());
+    sqlx::query!(
+        "INSERT INTO sessions (user_name, token_hash, device, ip, expires_at) VALUES ($1, $2, $3, $4, $5)",
+        name,
+        crypto::hash_token(&token),
+        device,
+        actor.ip,
+        expires_at,
+    )
+    .execute(&pool)
+    .await
+    .map_err(internal)?;
+
+    let actor = Actor { name: name.clone(), ip: actor.ip };
+    audit.record(&actor, "session.login", None, None).await;
+
+    let set_cookie = cookie(&token, auth.session_ttl().num_seconds(), auth.secure_cookies());
+    Ok(([(header::SET_COOKIE, set_cookie)], Json(LoggedIn { name, role, expires_at, token })).into_response())
+}
+
+// POST /logout
+// Ends the session the request was made with and clears the cookie.
+pub async fn logout(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    State(auth): State<Auth>,
+    principal: Option<Extension<Principal>>,
+    actor: Actor,
+) -> Result<Response, (StatusCode, String)> {
+    if let Some(id) = principal.and_then(|Extension(principal)| principal.session_id) {
+        sqlx::query!("UPDATE sessions SET revoked_at = now() WHERE id = $1", id)
+            .execute(&pool)
+            .await
+            .map_err(internal)?;
+        audit.record(&actor, "session.logout", None, None).await;
+    }
+    let clear = cookie("", 0, auth.secure_cookies());
+    Ok(([(header::SET_COOKIE, clear)], "Logged out").into_response())
+}
+
+// GET /me/sessions
+pub async fn get_sessions(
+    State(pool): State<PgPool>,
+    Extension(principal): Extension<Principal>,
+    State(auth): State<Auth>,
+) -> Result<Json<Vec<Session>>, (StatusCode, String)> {
+    let mut sessions = sqlx::query_as!(
+        Session,
+        r#"SELECT id, device, ip, created_at, last_seen_at, expires_at, false AS "current!" FROM sessions
+           WHERE user_name = $1 AND revoked_at IS NULL AND expires_at > now() AND last_seen_at > now() - $2::interval
+           ORDER BY last_seen_at DESC"#,
+        principal.name,
+        auth.session_idle() as _,
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(internal)?;
+    for session in &mut sessions {
+        session.current = principal.session_id == Some(session.id);
+    }
+    Ok(Json(sessions))
+}
+
+// DELETE /me/sessions/:id
+// Returns the revoked session.
+pub async fn revoke_session(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    Extension(principal): Extension<Principal>,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<Session>, (StatusCode, String)> {
+    let revoked = sqlx::query_as!(
+        Session,
+        r#"UPDATE sessions SET revoked_at = now() WHERE id = $1 AND user_name = $2 AND revoked_at IS NULL
+           RETURNING id, device, ip, created_at, last_seen_at, expires_at, coalesce(id = $3, false) AS "current!""#,
+        id,
+        actor.name,
+        principal.session_id,
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(internal)?
+    .ok_or((StatusCode::NOT_FOUND, "Session not found".into()))?;
+    audit.record(&actor, "session.revoke", None, Some(format!("session {}", id))).await;
+    Ok(Json(revoked))
+}
+
+// DELETE /me/sessions
+// Logs out everywhere, including the current session. Returns the revoked
+// sessions.
+pub async fn revoke_all(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    Extension(principal): Extension<Principal>,
+    actor: Actor,
+) -> Result<Json<Vec<Session>>, (StatusCode, String)> {
+    let revoked = sqlx::query_as!(
+        Session,
+        r#"UPDATE sessions SET revoked_at = now() WHERE user_name = $1 AND revoked_at IS NULL
+           RETURNING id, device, ip, created_at, last_seen_at, expires_at, coalesce(id = $2, false) AS "current!""#,
+        actor.name,
+        principal.session_id,
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(internal)?;
+    audit.record(&actor, "session.revoke_all", None, Some(format!("{} sessions", revoked.len()))).await;
+    Ok(Json(revoked))
+}
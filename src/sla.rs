@@ -0,0 +1,274 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, TimeZone, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{
+    audit::{Actor, Audit},
+    categories::Visibility,
+    repository::ServiceRepository,
+    services::SERVICE_ID_BY_NAME,
+};
+
+// Uptime targets for lightweight SLO tracking: a service's target (say 99.5)
+// is the percentage of checks per calendar week, month or quarter (UTC) that
+// should pass. GET /services/:name/sla reports the current period against
+// it, with the error budget left, and how earlier periods did, from raw
+// results and the rollups retention folds them into. Earlier periods are
+// measured against the current target.
+const DEFAULT_PERIODS: u32 = 12;
+const MAX_PERIODS: u32 = 36;
+
+fn internal(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Week,
+    Month,
+    Quarter,
+}
+
+impl Period {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::Quarter => "quarter",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "week" => Some(Self::Week),
+            "month" => Some(Self::Month),
+            "quarter" => Some(Self::Quarter),
+            _ => None,
+        }
+    }
+
+    // The start of the period `at` falls in, as Postgres' date_trunc has it
+    // (weeks start on Monday).
+    fn start(self, at: DateTime<Utc>) -> DateTime<Utc> {
+        let date = at.date_naive();
+        let start = match self {
+            Self::Week => date - Days::new(date.weekday().num_days_from_monday().into()),
+            Self::Month => date.with_day(1).unwrap_or(date),
+            Self::Quarter => NaiveDate::from_ymd_opt(date.year(), (date.month0() / 3) * 3 + 1, 1).unwrap_or(date),
+        };
+        Utc.from_utc_datetime(&start.and_time(Default::default()))
+    }
+
+    // The start of the period `n` periods after the one starting at `start`;
+    // negative `n` goes back.
+    fn shift(self, start: DateTime<Utc>, n: i32) -> DateTime<Utc> {
+        let months = match self {
+            Self::Week => {
+                let days = Days::new(7 * u64::from(n.unsigned_abs()));
+                return if n < 0 { start - days } else { start + days };
+            }
+            Self::Month => Months::new(n.unsigned_abs()),
+            Self::Quarter => Months::new(3 * n.unsigned_abs()),
+        };
+        if n < 0 {
+            start - months
+        } else {
+            start + months
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Sla {
+    // Percent of passing checks, above 0 and below 100.
+    target: f64,
+    period: Period,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct StoredSla {
+    target: f64,
+    period: String,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct Counts {
+    start: DateTime<Utc>,
+    total: i64,
+    ok: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PeriodReport {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    checks: i64,
+    failed_checks: i64,
+    // Percent of passing checks; None without checks.
+    attainment: Option<f64>,
+    breached: bool,
+}
+
+impl PeriodReport {
+    fn new(period: Period, start: DateTime<Utc>, total: i64, ok: i64, target: f64) -> Self {
+        let attainment = (total > 0).then(|| 100.0 * ok as f64 / total as f64);
+        Self {
+            start,
+            end: period.shift(start, 1),
+            checks: total,
+            failed_checks: total - ok,
+            attainment,
+            breached: attainment.is_some_and(|attainment| attainment < target),
+        }
+    }
+}
+
+// How many more checks may fail this period without breaching the target,
+// at the number of checks so far.
+#[derive(Debug, Serialize)]
+pub struct ErrorBudget {
+    allowed_failures: f64,
+    remaining_failures: f64,
+    // Below 0 once breached; None without checks.
+    remaining_percent: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SlaReport {
+    service: String,
+    target: f64,
+    period: Period,
+    current: PeriodReport,
+    error_budget: ErrorBudget,
+    // Earlier periods with checks, latest first.
+    history: Vec<PeriodReport>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SlaQuery {
+    // How many earlier periods to report; 12 by default.
+    periods: Option<u32>,
+}
+
+// Raw results and rollups never overlap (retention moves one into the next),
+// so their union covers the whole range.
+const COUNTS: &str = r#"
+    WITH points AS (
+        SELECT checked_at AS at, 1::bigint AS total, ok::int::bigint AS ok
+        FROM check_results WHERE service_id = $1 AND checked_at >= $2
+        UNION ALL
+        SELECT bucket, total_checks, ok_checks
+        FROM check_rollups WHERE service_id = $1 AND bucket >= $2
+    )
+    SELECT date_trunc($3, at, 'UTC') AS start, sum(total)::bigint AS total, sum(ok)::bigint AS ok
+    FROM points
+    GROUP BY 1
+    ORDER BY 1 DESC
+"#;
+
+async fn service_id(pool: &PgPool, name: &str) -> Result<(i32, String), (StatusCode, String)> {
+    sqlx::query_as::<_, (i32, String)>(&format!("SELECT id, name FROM services WHERE id = {}", SERVICE_ID_BY_NAME))
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "Service not found".into()))
+}
+
+// GET /services/:name/sla?periods=
+pub async fn get_sla(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    Path(name): Path<String>,
+    Query(query): Query<SlaQuery>,
+) -> Result<Json<SlaReport>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let (id, service) = service_id(&pool, &name).await?;
+    let sla = sqlx::query_as::<_, StoredSla>("SELECT target, period FROM service_slas WHERE service_id = $1")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, format!("'{}' has no SLA", service)))?;
+    let period = Period::parse(&sla.period).unwrap_or(Period::Month);
+    let periods = query.periods.unwrap_or(DEFAULT_PERIODS).min(MAX_PERIODS);
+
+    let current_start = period.start(Utc::now());
+    let from = period.shift(current_start, -(periods as i32));
+    let counts = sqlx::query_as::<_, Counts>(COUNTS)
+        .bind(id)
+        .bind(from)
+        .bind(period.as_str())
+        .fetch_all(&pool)
+        .await
+        .map_err(internal)?;
+
+    let (mut current, mut history) = (None, Vec::new());
+    for counts in counts {
+        let report = PeriodReport::new(period, counts.start, counts.total, counts.ok, sla.target);
+        if counts.start >= current_start {
+            current = Some(report);
+        } else {
+            history.push(report);
+        }
+    }
+    let current = current.unwrap_or_else(|| PeriodReport::new(period, current_start, 0, 0, sla.target));
+    let allowed_failures = current.checks as f64 * (100.0 - sla.target) / 100.0;
+    let remaining_failures = allowed_failures - current.failed_checks as f64;
+    let remaining_percent = (allowed_failures > 0.0).then(|| 100.0 * remaining_failures / allowed_failures);
+    Ok(Json(SlaReport {
+        service,
+        target: sla.target,
+        period,
+        current,
+        error_budget: ErrorBudget { allowed_failures, remaining_failures, remaining_percent },
+        history,
+    }))
+}
+
+// PUT /services/:name/sla
+// Body: {"target": 99.5, "period": "month"}; replaces any current target.
+pub async fn set_sla<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    State(services): State<R>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(name): Path<String>,
+    Json(sla): Json<Sla>,
+) -> Result<Json<Sla>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    if !(sla.target > 0.0 && sla.target < 100.0) {
+        return Err((StatusCode::BAD_REQUEST, "target must be a percentage above 0 and below 100".into()));
+    }
+    let (id, service) = service_id(&pool, &name).await?;
+    services.set_sla(id, sla.target, sla.period.as_str()).await?;
+    let detail = format!("{}% per {}", sla.target, sla.period.as_str());
+    audit.record(&actor, "service.sla", Some(&service), Some(detail)).await;
+    Ok(Json(sla))
+}
+
+// DELETE /services/:name/sla
+// Returns the removed target.
+pub async fn delete_sla<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    State(services): State<R>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(name): Path<String>,
+) -> Result<Json<Sla>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let (id, service) = service_id(&pool, &name).await?;
+    let (target, period) =
+        services.remove_sla(id).await?.ok_or((StatusCode::NOT_FOUND, format!("'{}' has no SLA", service)))?;
+    audit.record(&actor, "service.sla_remove", Some(&service), None).await;
+    let period = Period::parse(&period).unwrap_or(Period::Month);
+    Ok(Json(Sla { target, period }))
+}
@@ -0,0 +1,445 @@
+use axum::{
+    body::Body,
+    extract::{FromRequest, Path, Query, Request, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use http::{header, HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+use crate::{
+    audit::{Actor, Audit},
+    storage::{ObjectStore, Storage},
+    streaming::{self, Writer},
+    tags,
+};
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+pub const MAX_IMPORT_BYTES: usize = 64 * 1024 * 1024;
+// Backups are exports kept in `[storage]` under this directory, as NDJSON
+// that POST /admin/import takes back.
+const BACKUPS: &str = "backups";
+
+// Whole-instance state as a single document. Categories and tags are
+// referenced by name so a snapshot can be restored into a fresh database.
+// Check history is not included.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    #[serde(default = "Utc::now")]
+    pub exported_at: DateTime<Utc>,
+    #[serde(default)]
+    pub categories: Vec<SnapshotCategory>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub services: Vec<SnapshotService>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SnapshotCategory {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_role: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    // Organization name; the org must exist on the importing instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SnapshotService {
+    pub name: String,
+    pub link: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub internal_link: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default = "default_check_type")]
+    pub check_type: String,
+    #[serde(default)]
+    pub expected_ip: Option<String>,
+    #[serde(default)]
+    pub latency_threshold_ms: Option<i32>,
+    // Still encrypted with the exporting instance's key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub check_token_encrypted: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub public: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+    // Set for services that were in the trash at export time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+fn default_check_type() -> String {
+    "http".into()
+}
+
+// One line of the NDJSON form of a snapshot: a `snapshot` header line with
+// the version, then `category`, `tag` and `service` lines with the same
+// fields as in the JSON document.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Record {
+    Snapshot {
+        version: u32,
+        #[serde(default = "Utc::now")]
+        exported_at: DateTime<Utc>,
+    },
+    Category(SnapshotCategory),
+    Tag {
+        name: String,
+    },
+    Service(SnapshotService),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    format: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportSummary {
+    categories: usize,
+    tags: usize,
+    services: usize,
+}
+
+// Writes the snapshot as a JSON document, or as NDJSON records. Services are
+// streamed; categories and tags are few enough to read up front.
+async fn export(pool: PgPool, ndjson: bool, mut out: Writer) -> anyhow::Result<()> {
+    let categories = sqlx::query_as::<_, SnapshotCategory>(
+        "SELECT categories.name, min_role, groups, orgs.name AS org FROM categories \
+         LEFT JOIN orgs ON orgs.id = categories.org_id ORDER BY categories.name",
+    )
+    .fetch_all(&pool)
+    .await?;
+    let tags = sqlx::query_scalar!("SELECT name FROM tags ORDER BY name")
+        .fetch_all(&pool)
+        .await?;
+    let exported_at = Utc::now();
+    let services = format!(
+        r#"
+        SELECT services.name, link, internal_link, description, check_type, expected_ip, latency_threshold_ms,
+               check_token_encrypted, categories.name AS category, public, orgs.name AS org, {},
+               services.created_at, deleted_at
+        FROM services LEFT JOIN categories ON categories.id = services.category_id
+        LEFT JOIN orgs ON orgs.id = services.org_id
+        ORDER BY services.created_at, services.id
+        "#,
+        tags::TAGS_COLUMN
+    );
+    let services = sqlx::query_as::<_, SnapshotService>(&services).fetch(&pool);
+
+    if ndjson {
+        out.json_line(&Record::Snapshot { version: SNAPSHOT_VERSION, exported_at }).await?;
+        for category in categories {
+            out.json_line(&Record::Category(category)).await?;
+        }
+        for name in tags {
+            out.json_line(&Record::Tag { name }).await?;
+        }
+        out.json_lines(services.map(|service| service.map(Record::Service))).await?;
+        return out.finish().await;
+    }
+
+    let head = format!(
+        r#"{{"version":{},"exported_at":{},"categories":{},"tags":{},"services":"#,
+        SNAPSHOT_VERSION,
+        serde_json::to_string(&exported_at)?,
+        serde_json::to_string(&categories)?,
+        serde_json::to_string(&tags)?
+    );
+    out.write(head.as_bytes()).await?;
+    out.json_array(services).await?;
+    out.write(b"}").await?;
+    out.finish().await
+}
+
+// Replaces all services, categories and tags with the snapshot's contents in
+// one transaction; on any error the previous state is left untouched.
+pub async fn import(pool: &PgPool, snapshot: &Snapshot) -> Result<ImportSummary, String> {
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(format!(
+            "Unsupported snapshot version {} (expected {})",
+            snapshot.version, SNAPSHOT_VERSION
+        ));
+    }
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    for table in ["services", "categories", "tags"] {
+        sqlx::query(&format!("DELETE FROM {}", table))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    // Orgs and their members are not part of snapshots; scoped entries are
+    // matched to existing orgs by name rather than silently made public.
+    let org_ids: HashMap<String, i32> = sqlx::query_as::<_, (String, i32)>("SELECT name, id FROM orgs")
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .collect();
+    let org_id = |org: &Option<String>| -> Result<Option<i32>, String> {
+        org.as_ref()
+            .map(|name| org_ids.get(name).copied().ok_or_else(|| format!("unknown organization '{}'", name)))
+            .transpose()
+    };
+
+    let mut category_ids = HashMap::new();
+    let mut names: Vec<&str> = snapshot.categories.iter().map(|c| c.name.as_str()).collect();
+    names.extend(snapshot.services.iter().filter_map(|s| s.category.as_deref()));
+    for name in names {
+        if category_ids.contains_key(name) {
+            continue;
+        }
+        let id = sqlx::query_scalar!("INSERT INTO categories (name) VALUES ($1) RETURNING id", name)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| format!("category '{}': {}", name, e))?;
+        category_ids.insert(name, id);
+    }
+    for category in &snapshot.categories {
+        if category.min_role.is_none() && category.groups.is_empty() && category.org.is_none() {
+            continue;
+        }
+        if let Some(role) = &category.min_role
+            && !["viewer", "editor", "admin"].contains(&role.as_str())
+        {
+            return Err(format!("category '{}': unknown min_role '{}'", category.name, role));
+        }
+        let org = org_id(&category.org).map_err(|e| format!("category '{}': {}", category.name, e))?;
+        sqlx::query!(
+            "UPDATE categories SET min_role = $2, groups = $3, org_id = $4 WHERE id = $1",
+            category_ids[category.name.as_str()],
+            category.min_role,
+            &category.groups,
+            org,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("category '{}': {}", category.name, e))?;
+    }
+
+    sqlx::query!("INSERT INTO tags (name) SELECT unnest($1::text[]) ON CONFLICT (name) DO NOTHING", &snapshot.tags)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    for service in &snapshot.services {
+        let org = org_id(&service.org).map_err(|e| format!("service '{}': {}", service.name, e))?;
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO services (name, link, check_type, expected_ip, latency_threshold_ms,
+                                  check_token_encrypted, category_id, public, created_at, deleted_at,
+                                  internal_link, org_id, description)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            RETURNING id
+            "#,
+            service.name,
+            service.link,
+            service.check_type,
+            service.expected_ip,
+            service.latency_threshold_ms,
+            service.check_token_encrypted,
+            service.category.as_deref().and_then(|name| category_ids.get(name)),
+            service.public,
+            service.created_at,
+            service.deleted_at,
+            service.internal_link,
+            org,
+            service.description,
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| format!("service '{}': {}", service.name, e))?;
+        tags::attach(&mut tx, id, &service.tags)
+            .await
+            .map_err(|e| format!("service '{}': {}", service.name, e))?;
+    }
+
+    let tags = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM tags"#)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(ImportSummary {
+        categories: category_ids.len(),
+        tags: tags as usize,
+        services: snapshot.services.len(),
+    })
+}
+
+// GET /admin/export
+// Streamed, so exporting a large instance doesn't hold it all in memory.
+// NDJSON with ?format=ndjson or Accept: application/x-ndjson.
+pub async fn export_handler(
+    State(pool): State<PgPool>,
+    Query(query): Query<ExportQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let ndjson = streaming::wants_ndjson(&headers, query.format.as_deref());
+    let content_type = if ndjson { streaming::NDJSON } else { "application/json" };
+    let body = streaming::body("export", move |out| export(pool, ndjson, out));
+    ([(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+// Collects NDJSON records into a snapshot as the body arrives; the header
+// line must come first.
+async fn read_ndjson(body: Body) -> Result<Snapshot, (StatusCode, String)> {
+    let mut snapshot: Option<Snapshot> = None;
+    streaming::read_lines(body, MAX_IMPORT_BYTES, |line| {
+        let record = serde_json::from_slice::<Record>(line).map_err(|e| e.to_string())?;
+        match (&mut snapshot, record) {
+            (None, Record::Snapshot { version, exported_at }) => {
+                snapshot = Some(Snapshot { version, exported_at, categories: vec![], tags: vec![], services: vec![] });
+            }
+            (None, _) => return Err("the first record must be the snapshot header".into()),
+            (Some(_), Record::Snapshot { .. }) => return Err("duplicate snapshot header".into()),
+            (Some(snapshot), Record::Category(category)) => snapshot.categories.push(category),
+            (Some(snapshot), Record::Tag { name }) => snapshot.tags.push(name),
+            (Some(snapshot), Record::Service(service)) => snapshot.services.push(service),
+        }
+        Ok(())
+    })
+    .await?;
+    snapshot.ok_or((StatusCode::BAD_REQUEST, "Empty snapshot".into()))
+}
+
+// POST /admin/import
+// Takes the JSON document, or NDJSON records with Content-Type
+// application/x-ndjson.
+pub async fn import_handler(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    request: Request,
+) -> Result<Json<ImportSummary>, (StatusCode, String)> {
+    let snapshot = if streaming::is_ndjson(request.headers()) {
+        read_ndjson(request.into_body()).await?
+    } else {
+        let Json(snapshot) =
+            Json::<Snapshot>::from_request(request, &()).await.map_err(|e| (e.status(), e.body_text()))?;
+        snapshot
+    };
+    let summary = import(&pool, &snapshot)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Import failed: {}", e)))?;
+    let detail = format!("{} services, {} categories", summary.services, summary.categories);
+    audit.record(&actor, "snapshot.import", None, Some(detail)).await;
+    Ok(Json(summary))
+}
+
+#[derive(Debug, Serialize)]
+pub struct Backup {
+    pub name: String,
+    pub size: u64,
+    pub taken_at: DateTime<Utc>,
+}
+
+fn backups(storage: &Storage) -> Result<(), (StatusCode, String)> {
+    match storage.is_configured() {
+        true => Ok(()),
+        false => Err((StatusCode::NOT_FOUND, "Storage is not configured".into())),
+    }
+}
+
+fn internal(e: anyhow::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+async fn find_backup(storage: &Storage, name: &str) -> Result<Backup, (StatusCode, String)> {
+    backups(storage)?;
+    list_backups(storage)
+        .await?
+        .into_iter()
+        .find(|backup| backup.name == name)
+        .ok_or((StatusCode::NOT_FOUND, "Backup not found".into()))
+}
+
+// Newest first.
+async fn list_backups(storage: &Storage) -> Result<Vec<Backup>, (StatusCode, String)> {
+    let mut backups = storage
+        .list(BACKUPS)
+        .await
+        .map_err(internal)?
+        .into_iter()
+        .filter_map(|entry| {
+            let name = entry.key.strip_prefix(BACKUPS)?.strip_prefix('/')?.to_string();
+            Some(Backup { name, size: entry.size, taken_at: entry.modified })
+        })
+        .collect::<Vec<_>>();
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(backups)
+}
+
+// POST /admin/backups
+// Takes an export and stores it; names sort by when they were taken.
+pub async fn create_backup_handler(
+    State(pool): State<PgPool>,
+    State(storage): State<Storage>,
+    State(audit): State<Audit>,
+    actor: Actor,
+) -> Result<Json<Backup>, (StatusCode, String)> {
+    backups(&storage)?;
+    let taken_at = Utc::now();
+    let name = taken_at.format("%Y%m%dT%H%M%S%.3fZ.ndjson").to_string();
+    let body = streaming::body("backup", move |out| export(pool, true, out));
+    let body = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Export failed: {}", e)))?;
+    let size = body.len() as u64;
+    storage.put(&format!("{}/{}", BACKUPS, name), body, streaming::NDJSON).await.map_err(internal)?;
+    audit.record(&actor, "snapshot.backup", None, Some(name.clone())).await;
+    Ok(Json(Backup { name, size, taken_at }))
+}
+
+// GET /admin/backups
+pub async fn list_backups_handler(State(storage): State<Storage>) -> Result<Json<Vec<Backup>>, (StatusCode, String)> {
+    backups(&storage)?;
+    Ok(Json(list_backups(&storage).await?))
+}
+
+// GET /admin/backups/{name}
+// The stored NDJSON, ready to POST to /admin/import.
+pub async fn get_backup_handler(
+    State(storage): State<Storage>,
+    Path(name): Path<String>,
+) -> Result<Response, (StatusCode, String)> {
+    let backup = find_backup(&storage, &name).await?;
+    let object = storage
+        .get(&format!("{}/{}", BACKUPS, backup.name))
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "Backup not found".into()))?;
+    Ok(([(header::CONTENT_TYPE, streaming::NDJSON)], object.body).into_response())
+}
+
+// DELETE /admin/backups/{name}
+pub async fn delete_backup_handler(
+    State(storage): State<Storage>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(name): Path<String>,
+) -> Result<Json<Backup>, (StatusCode, String)> {
+    let backup = find_backup(&storage, &name).await?;
+    storage.delete(&format!("{}/{}", BACKUPS, backup.name)).await.map_err(internal)?;
+    audit.record(&actor, "snapshot.backup_delete", None, Some(backup.name.clone())).await;
+    Ok(Json(backup))
+}
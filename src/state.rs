@@ -0,0 +1,209 @@
+use axum::extract::FromRef;
+use sqlx::PgPool;
+
+use crate::{
+    access, access_log, actions, alertmanager, audit, auth, changes, crypto, discovery, email, embed, hooks, icons,
+    links, plugins, preflight, proposals, proxy_sync, repository, routing, scheduler, static_links, storage, suggest,
+    trash, wol,
+};
+
+// What handlers extract with State<T>; each part gets a FromRef impl so
+// handlers only ask for what they use.
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: PgPool,
+    pub trash: trash::Trash,
+    pub cipher: Option<crypto::Cipher>,
+    pub auth: auth::Auth,
+    pub access: access::Access,
+    pub audit: audit::Audit,
+    pub scheduler: scheduler::Scheduler,
+    pub plugins: plugins::Plugins,
+    pub hooks: hooks::Hooks,
+    pub links: links::Links,
+    pub mailer: Option<email::Mailer>,
+    pub alertmanager: alertmanager::Alertmanager,
+    pub access_log: access_log::AccessLog,
+    pub icons: icons::Icons,
+    pub suggestions: suggest::Suggestions,
+    pub timezone: chrono_tz::Tz,
+    pub routes: routing::Routes,
+    pub static_links: static_links::StaticLinks,
+    pub proposals: proposals::Proposals,
+    pub changes: changes::Changes,
+    pub wake_on_lan: wol::WakeOnLan,
+    pub actions: actions::Actions,
+    pub discovery: discovery::Discovery,
+    pub proxy_sync: proxy_sync::ProxySync,
+    pub embed: embed::Embed,
+    pub preflight: preflight::Preflight,
+    pub storage: storage::Storage,
+}
+
+impl FromRef<AppState> for PgPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for repository::PgServices {
+    fn from_ref(state: &AppState) -> Self {
+        repository::PgServices::new(state.pool.clone())
+    }
+}
+
+impl FromRef<AppState> for static_links::StaticLinks {
+    fn from_ref(state: &AppState) -> Self {
+        state.static_links.clone()
+    }
+}
+
+impl FromRef<AppState> for proposals::Proposals {
+    fn from_ref(state: &AppState) -> Self {
+        state.proposals.clone()
+    }
+}
+
+impl FromRef<AppState> for changes::Changes {
+    fn from_ref(state: &AppState) -> Self {
+        state.changes.clone()
+    }
+}
+
+impl FromRef<AppState> for trash::Trash {
+    fn from_ref(state: &AppState) -> Self {
+        state.trash.clone()
+    }
+}
+
+impl FromRef<AppState> for Option<crypto::Cipher> {
+    fn from_ref(state: &AppState) -> Self {
+        state.cipher.clone()
+    }
+}
+
+impl FromRef<AppState> for Option<email::Mailer> {
+    fn from_ref(state: &AppState) -> Self {
+        state.mailer.clone()
+    }
+}
+
+impl FromRef<AppState> for alertmanager::Alertmanager {
+    fn from_ref(state: &AppState) -> Self {
+        state.alertmanager.clone()
+    }
+}
+
+impl FromRef<AppState> for auth::Auth {
+    fn from_ref(state: &AppState) -> Self {
+        state.auth.clone()
+    }
+}
+
+impl FromRef<AppState> for access::Access {
+    fn from_ref(state: &AppState) -> Self {
+        state.access.clone()
+    }
+}
+
+impl FromRef<AppState> for audit::Audit {
+    fn from_ref(state: &AppState) -> Self {
+        state.audit.clone()
+    }
+}
+
+impl FromRef<AppState> for scheduler::Scheduler {
+    fn from_ref(state: &AppState) -> Self {
+        state.scheduler.clone()
+    }
+}
+
+impl FromRef<AppState> for plugins::Plugins {
+    fn from_ref(state: &AppState) -> Self {
+        state.plugins.clone()
+    }
+}
+
+impl FromRef<AppState> for hooks::Hooks {
+    fn from_ref(state: &AppState) -> Self {
+        state.hooks.clone()
+    }
+}
+
+impl FromRef<AppState> for access_log::AccessLog {
+    fn from_ref(state: &AppState) -> Self {
+        state.access_log.clone()
+    }
+}
+
+impl FromRef<AppState> for icons::Icons {
+    fn from_ref(state: &AppState) -> Self {
+        state.icons.clone()
+    }
+}
+
+impl FromRef<AppState> for routing::Routes {
+    fn from_ref(state: &AppState) -> Self {
+        state.routes.clone()
+    }
+}
+
+impl FromRef<AppState> for suggest::Suggestions {
+    fn from_ref(state: &AppState) -> Self {
+        state.suggestions.clone()
+    }
+}
+
+impl FromRef<AppState> for chrono_tz::Tz {
+    fn from_ref(state: &AppState) -> Self {
+        state.timezone
+    }
+}
+
+impl FromRef<AppState> for links::Links {
+    fn from_ref(state: &AppState) -> Self {
+        state.links.clone()
+    }
+}
+
+impl FromRef<AppState> for wol::WakeOnLan {
+    fn from_ref(state: &AppState) -> Self {
+        state.wake_on_lan.clone()
+    }
+}
+
+impl FromRef<AppState> for actions::Actions {
+    fn from_ref(state: &AppState) -> Self {
+        state.actions.clone()
+    }
+}
+
+impl FromRef<AppState> for discovery::Discovery {
+    fn from_ref(state: &AppState) -> Self {
+        state.discovery.clone()
+    }
+}
+
+impl FromRef<AppState> for proxy_sync::ProxySync {
+    fn from_ref(state: &AppState) -> Self {
+        state.proxy_sync.clone()
+    }
+}
+
+impl FromRef<AppState> for embed::Embed {
+    fn from_ref(state: &AppState) -> Self {
+        state.embed.clone()
+    }
+}
+
+impl FromRef<AppState> for preflight::Preflight {
+    fn from_ref(state: &AppState) -> Self {
+        state.preflight.clone()
+    }
+}
+
+impl FromRef<AppState> for storage::Storage {
+    fn from_ref(state: &AppState) -> Self {
+        state.storage.clone()
+    }
+}
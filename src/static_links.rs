@@ -0,0 +1,121 @@
+use chrono::Utc;
+use http::StatusCode;
+use std::sync::Arc;
+
+use crate::{
+    config::StaticLinkConfig,
+    pagination::Page,
+    query::ServiceFilter,
+    services::{Service, Source},
+};
+
+// Services declared with `[[static_links]]` in the config file. They are
+// merged into GET /services ahead of the stored ones, on unpaged listings
+// and the first page of paged ones, and take part in its filters as
+// unchecked, uncategorized services. Their names are reserved: the API
+// refuses to create, rename to or delete them.
+#[derive(Clone)]
+pub struct StaticLinks {
+    services: Arc<Vec<Service>>,
+}
+
+impl StaticLinks {
+    pub fn new(config: &[StaticLinkConfig]) -> anyhow::Result<Self> {
+        let started = Utc::now();
+        let mut services: Vec<Service> = Vec::with_capacity(config.len());
+        for (index, entry) in config.iter().enumerate() {
+            let name = entry.name.trim();
+            if name.is_empty() || entry.link.trim().is_empty() {
+                anyhow::bail!("static_links[{}]: name and link must not be empty", index);
+            }
+            if services.iter().any(|service| service.name.eq_ignore_ascii_case(name)) {
+                anyhow::bail!("static_links[{}]: '{}' is listed twice", index, name);
+            }
+            let mut tags: Vec<String> =
+                entry.tags.iter().map(|tag| tag.trim().to_string()).filter(|tag| !tag.is_empty()).collect();
+            tags.sort();
+            tags.dedup();
+            services.push(Service {
+                id: -(index as i32) - 1,
+                name: name.to_string(),
+                link: entry.link.trim().to_string(),
+                internal_link: entry.internal_link.clone(),
+                description: entry.description.clone(),
+                check_type: "none".into(),
+                expected_ip: None,
+                latency_threshold_ms: None,
+                category_id: None,
+                org_id: None,
+                public: entry.public,
+                created_at: started,
+                updated_at: started,
+                archived_at: None,
+                color: None,
+                accent: None,
+                tags,
+                source: Source::Config,
+            });
+        }
+        Ok(Self { services: Arc::new(services) })
+    }
+
+    pub fn all(&self) -> &[Service] {
+        &self.services
+    }
+
+    // 409 if `name` is one of them.
+    pub fn reserve(&self, name: &str) -> Result<(), (StatusCode, String)> {
+        match self.services.iter().find(|service| service.name.eq_ignore_ascii_case(name.trim())) {
+            Some(service) => Err((
+                StatusCode::CONFLICT,
+                format!("'{}' is defined in the config file and can't be changed through the API", service.name),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // The entries to list ahead of the stored services for this request.
+    pub fn matching(&self, filter: &ServiceFilter, page: &Page) -> Vec<Service> {
+        let first_page = match page {
+            Page::All | Page::Keyset { after: None, .. } => true,
+            Page::Offset { offset, .. } => *offset == 0,
+            Page::Keyset { after: Some(_), .. } => false,
+        };
+        if !first_page {
+            return Vec::new();
+        }
+        // Never checked, so their status is unknown; never in a category or
+        // archived.
+        if filter.category.is_some()
+            || filter.state.as_deref() == Some("archived")
+            || filter.status.as_deref().is_some_and(|status| status != "unknown")
+        {
+            return Vec::new();
+        }
+        let tags: Vec<&str> = filter
+            .tag
+            .as_deref()
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        let q = filter.q.as_deref().map(str::to_lowercase);
+        self.services
+            .iter()
+            .filter(|service| tags.iter().all(|tag| service.tags.iter().any(|own| own == tag)))
+            .filter(|service| {
+                q.as_deref().is_none_or(|q| {
+                    let texts = [Some(&service.name), Some(&service.link), service.internal_link.as_ref()];
+                    texts.into_iter().flatten().any(|text| text.to_lowercase().contains(q))
+                })
+            })
+            .filter(|service| match filter.visibility.as_deref() {
+                Some("public") => service.public,
+                Some("private") => !service.public,
+                _ => true,
+            })
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,282 @@
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use http::{header, HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{checks, health, pwa, tz::Timezone};
+
+const UNCATEGORIZED: &str = "Other";
+
+#[derive(Debug, sqlx::FromRow)]
+struct PublicService {
+    name: String,
+    category: Option<String>,
+    category_color: Option<String>,
+    // The service's colors, else its category's.
+    color: Option<String>,
+    accent: Option<String>,
+    ok: Option<bool>,
+    degraded: Option<bool>,
+    alert_down: Option<bool>,
+    health: Option<String>,
+    ok_checks: i64,
+    total_checks: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusPage {
+    state: &'static str,
+    generated_at: DateTime<Utc>,
+    categories: Vec<CategoryStatus>,
+    incidents: Vec<Incident>,
+}
+
+#[derive(Debug, Serialize)]
+struct CategoryStatus {
+    name: String,
+    color: Option<String>,
+    // Share of successful checks over the last 24 hours, 0-100.
+    availability: Option<f64>,
+    services: Vec<ServiceState>,
+    #[serde(skip)]
+    ok_checks: i64,
+    #[serde(skip)]
+    total_checks: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct ServiceState {
+    name: String,
+    state: &'static str,
+    color: Option<String>,
+    accent: Option<String>,
+}
+
+// An outage of one service, as recorded by the checker (see incidents.rs).
+#[derive(Debug, Serialize)]
+struct Incident {
+    service: String,
+    started_at: DateTime<Utc>,
+    resolved_at: Option<DateTime<Utc>>,
+    failed_checks: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatusQuery {
+    format: Option<String>,
+}
+
+// GET /status
+// Only services marked public are included. Returns JSON for ?format=json or
+// an Accept: application/json request, HTML otherwise.
+pub async fn status_page(
+    State(pool): State<PgPool>,
+    Query(query): Query<StatusQuery>,
+    Timezone(tz): Timezone,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let page = build(&pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let wants_json = match query.format.as_deref() {
+        Some(format) => format == "json",
+        None => headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("application/json")),
+    };
+    if wants_json {
+        Ok(Json(page).into_response())
+    } else {
+        Ok(Html(render(&page, tz)).into_response())
+    }
+}
+
+async fn build(pool: &PgPool) -> sqlx::Result<StatusPage> {
+    let services = sqlx::query_as::<_, PublicService>(&format!(
+        r#"
+        SELECT s.name, c.name AS category, c.color AS category_color,
+               coalesce(s.color, c.color) AS color, coalesce(s.accent, c.accent) AS accent,
+               r.ok, r.degraded, {}, {},
+               h.ok_checks, h.total_checks
+        FROM services s
+        LEFT JOIN categories c ON c.id = s.category_id
+        LEFT JOIN LATERAL (
+            SELECT ok, degraded FROM check_results
+            WHERE service_id = s.id ORDER BY checked_at DESC LIMIT 1
+        ) r ON true
+        CROSS JOIN LATERAL (
+            SELECT count(*) FILTER (WHERE ok) AS ok_checks, count(*) AS total_checks
+            FROM check_results
+            WHERE service_id = s.id AND checked_at > now() - interval '24 hours'
+        ) h
+        WHERE s.public AND s.deleted_at IS NULL AND s.archived_at IS NULL
+        ORDER BY c.name NULLS LAST, s.name
+        "#,
+        checks::ALERT_DOWN_COLUMN,
+        health::HEALTH_COLUMN
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    // Ongoing ones and those that ended within the last week.
+    let incidents = sqlx::query_as!(
+        Incident,
+        r#"
+        SELECT s.name AS service, i.started_at, i.resolved_at, i.failed_checks::bigint AS "failed_checks!"
+        FROM incidents i
+        JOIN services s ON s.id = i.service_id
+        WHERE s.public AND s.deleted_at IS NULL AND s.archived_at IS NULL
+          AND coalesce(i.resolved_at, now()) > now() - interval '7 days'
+        ORDER BY i.started_at DESC
+        LIMIT 20
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut categories: Vec<CategoryStatus> = Vec::new();
+    let (mut down, mut degraded) = (0, 0);
+    let total = services.len();
+    for service in services {
+        let state = checks::state_of(service.health.as_deref(), service.ok, service.degraded, service.alert_down);
+        match state {
+            "down" => down += 1,
+            "degraded" | "flapping" => degraded += 1,
+            _ => {}
+        }
+
+        let name = service.category.unwrap_or_else(|| UNCATEGORIZED.to_string());
+        if categories.last().is_none_or(|category| category.name != name) {
+            categories.push(CategoryStatus {
+                name,
+                color: service.category_color,
+                availability: None,
+                services: Vec::new(),
+                ok_checks: 0,
+                total_checks: 0,
+            });
+        }
+        let category = categories.last_mut().unwrap();
+        category.ok_checks += service.ok_checks;
+        category.total_checks += service.total_checks;
+        category.services.push(ServiceState {
+            name: service.name,
+            state,
+            color: service.color,
+            accent: service.accent,
+        });
+    }
+    for category in &mut categories {
+        if category.total_checks > 0 {
+            category.availability =
+                Some(100.0 * category.ok_checks as f64 / category.total_checks as f64);
+        }
+    }
+
+    let state = if total > 0 && down == total {
+        "major_outage"
+    } else if down > 0 {
+        "partial_outage"
+    } else if degraded > 0 {
+        "degraded"
+    } else {
+        "operational"
+    };
+
+    Ok(StatusPage { state, generated_at: Utc::now(), categories, incidents })
+}
+
+// A style attribute for the colors that are set (see colors.rs), or nothing.
+fn style(colors: &[(&str, Option<&str>)]) -> String {
+    let rules: Vec<String> = colors
+        .iter()
+        .filter_map(|(property, color)| color.map(|color| format!("{}: {}", property, escape_html(color))))
+        .collect();
+    if rules.is_empty() { String::new() } else { format!(" style=\"{}\"", rules.join("; ")) }
+}
+
+// Times are shown in `tz`.
+fn render(page: &StatusPage, tz: Tz) -> String {
+    let time = |at: DateTime<Utc>| at.with_timezone(&tz).format("%Y-%m-%d %H:%M %Z").to_string();
+    let mut body = String::new();
+    for category in &page.categories {
+        let availability = category
+            .availability
+            .map_or_else(|| "no data".to_string(), |a| format!("{:.2}%", a));
+        body.push_str(&format!(
+            "<section><h2{}>{} <small>{}</small></h2><ul>",
+            style(&[("border-bottom-color", category.color.as_deref())]),
+            escape_html(&category.name),
+            availability
+        ));
+        for service in &category.services {
+            let colors = [("border-left-color", service.color.as_deref()), ("background", service.accent.as_deref())];
+            body.push_str(&format!(
+                "<li class=\"{0}\"{2}><span>{1}</span><b>{0}</b></li>",
+                service.state,
+                escape_html(&service.name),
+                style(&colors)
+            ));
+        }
+        body.push_str("</ul></section>");
+    }
+
+    body.push_str("<section><h2>Recent incidents</h2>");
+    if page.incidents.is_empty() {
+        body.push_str("<p>No incidents in the last 7 days.</p>");
+    } else {
+        body.push_str("<ul>");
+        for incident in &page.incidents {
+            let resolved = incident
+                .resolved_at
+                .map_or_else(|| "ongoing".to_string(), |at| format!("resolved {}", time(at)));
+            body.push_str(&format!(
+                "<li><span>{} down since {}</span><b>{}</b></li>",
+                escape_html(&incident.service),
+                time(incident.started_at),
+                resolved
+            ));
+        }
+        body.push_str("</ul>");
+    }
+    body.push_str("</section>");
+
+    format!(
+        r#"<!doctype html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width">
+<title>Status</title>
+{3}
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 46rem; margin: 2rem auto; padding: 0 1rem; }}
+ul {{ list-style: none; padding: 0; }}
+h2 {{ border-bottom: 3px solid transparent; }}
+li {{ display: flex; justify-content: space-between; padding: .4rem .5rem; border-bottom: 1px solid #eee;
+      border-left: 4px solid transparent; }}
+.banner {{ padding: 1rem; border-radius: .4rem; color: #fff; background: #2e7d32; }}
+.banner.degraded, .banner.partial_outage {{ background: #ef6c00; }}
+.banner.major_outage {{ background: #c62828; }}
+.up b {{ color: #2e7d32; }} .degraded b, .flapping b {{ color: #ef6c00; }} .down b {{ color: #c62828; }}
+.unknown b, .maintenance b {{ color: #757575; }}
+</style></head>
+<body><div class="banner {0}">{0}</div>{1}<footer><small>Updated {2}</small></footer></body></html>"#,
+        page.state,
+        body,
+        page.generated_at.with_timezone(&tz).format("%Y-%m-%d %H:%M:%S %Z"),
+        *pwa::HEAD
+    )
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,421 @@
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use std::{future::Future, io::ErrorKind, path::PathBuf, sync::Arc, time::Duration};
+
+use crate::{
+    config::{StorageBackend, StorageConfig},
+    secrets::Secrets,
+};
+
+// Binary assets kept outside the database and across restarts (the icon
+// cache, see icons.rs, and backups, see snapshot.rs): in a local directory,
+// or in a bucket of S3 or a compatible store (MinIO, Garage, R2) for
+// containers without a persistent disk. Chosen with `[storage] backend`;
+// without one nothing is kept. Keys are relative paths such as
+// "icons/grafana.svg".
+const S3_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Object {
+    pub body: Bytes,
+    pub modified: DateTime<Utc>,
+}
+
+// A stored object as listed, without its body.
+pub struct Entry {
+    pub key: String,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+}
+
+pub trait ObjectStore {
+    // None if there is no object under `key`.
+    fn get(&self, key: &str) -> impl Future<Output = anyhow::Result<Option<Object>>> + Send;
+
+    // Replaces any object under `key`.
+    fn put(&self, key: &str, body: Bytes, content_type: &str) -> impl Future<Output = anyhow::Result<()>> + Send;
+
+    // Deleting a missing object is no error.
+    fn delete(&self, key: &str) -> impl Future<Output = anyhow::Result<()>> + Send;
+
+    // The objects directly under `dir` (e.g. "backups"), not those in
+    // directories below it, in no particular order.
+    fn list(&self, dir: &str) -> impl Future<Output = anyhow::Result<Vec<Entry>>> + Send;
+}
+
+// The configured backend.
+#[derive(Clone)]
+pub enum Storage {
+    None,
+    Local(LocalStore),
+    S3(S3Store),
+}
+
+impl Storage {
+    pub fn new(config: &StorageConfig, secrets: &Secrets) -> anyhow::Result<Self> {
+        match config.backend {
+            None => Ok(Self::None),
+            Some(StorageBackend::Local) => Ok(Self::Local(LocalStore { root: Arc::new(config.path.clone()) })),
+            Some(StorageBackend::S3) => S3Store::new(config, secrets).map(Self::S3),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        !matches!(self, Self::None)
+    }
+
+    // For `indexpage doctor`.
+    pub fn describe(&self) -> Option<String> {
+        match self {
+            Self::None => None,
+            Self::Local(local) => Some(format!("directory {}", local.root.display())),
+            Self::S3(s3) => Some(format!("bucket {} at {}", s3.bucket, s3.endpoint)),
+        }
+    }
+}
+
+impl ObjectStore for Storage {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Object>> {
+        match self {
+            Self::None => Ok(None),
+            Self::Local(local) => local.get(key).await,
+            Self::S3(s3) => s3.get(key).await,
+        }
+    }
+
+    async fn put(&self, key: &str, body: Bytes, content_type: &str) -> anyhow::Result<()> {
+        match self {
+            Self::None => Ok(()),
+            Self::Local(local) => local.put(key, body, content_type).await,
+            Self::S3(s3) => s3.put(key, body, content_type).await,
+        }
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        match self {
+            Self::None => Ok(()),
+            Self::Local(local) => local.delete(key).await,
+            Self::S3(s3) => s3.delete(key).await,
+        }
+    }
+
+    async fn list(&self, dir: &str) -> anyhow::Result<Vec<Entry>> {
+        match self {
+            Self::None => Ok(vec![]),
+            Self::Local(local) => local.list(dir).await,
+            Self::S3(s3) => s3.list(dir).await,
+        }
+    }
+}
+
+// Keys never leave the directory or prefix they are stored under.
+fn check_key(key: &str) -> anyhow::Result<()> {
+    let valid = !key.is_empty()
+        && !key.contains('\\')
+        && key.split('/').all(|segment| !matches!(segment, "" | "." | ".."));
+    match valid {
+        true => Ok(()),
+        false => anyhow::bail!("invalid storage key '{}'", key),
+    }
+}
+
+#[derive(Clone)]
+pub struct LocalStore {
+    root: Arc<PathBuf>,
+}
+
+impl LocalStore {
+    fn path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        check_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+impl ObjectStore for LocalStore {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Object>> {
+        let path = self.path(key)?;
+        let body = match tokio::fs::read(&path).await {
+            Ok(body) => body,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(anyhow::anyhow!("reading {}: {}", path.display(), e)),
+        };
+        let modified = tokio::fs::metadata(&path).await?.modified()?;
+        Ok(Some(Object { body: body.into(), modified: modified.into() }))
+    }
+
+    // Written next to its place and renamed, so readers never see half a file.
+    async fn put(&self, key: &str, body: Bytes, _content_type: &str) -> anyhow::Result<()> {
+        let path = self.path(key)?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let mut partial = path.clone().into_os_string();
+        partial.push(".partial");
+        tokio::fs::write(&partial, &body).await.map_err(|e| anyhow::anyhow!("writing {}: {}", path.display(), e))?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    // Files left by an interrupted put are not objects.
+    async fn list(&self, dir: &str) -> anyhow::Result<Vec<Entry>> {
+        let path = self.path(dir)?;
+        let mut entries = match tokio::fs::read_dir(&path).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(anyhow::anyhow!("listing {}: {}", path.display(), e)),
+        };
+        let mut listed = vec![];
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else { continue };
+            if !metadata.is_file() || name.ends_with(".partial") {
+                continue;
+            }
+            let modified = metadata.modified()?.into();
+            listed.push(Entry { key: format!("{}/{}", dir, name), size: metadata.len(), modified });
+        }
+        Ok(listed)
+    }
+}
+
+// Objects addressed by path (endpoint/bucket/key), which every compatible
+// store understands, with requests signed with AWS Signature Version 4.
+// The key pair comes from INDEXPAGE_S3_ACCESS_KEY_ID and
+// INDEXPAGE_S3_SECRET_ACCESS_KEY.
+#[derive(Clone)]
+pub struct S3Store {
+    http: reqwest::Client,
+    endpoint: url::Url,
+    bucket: String,
+    region: String,
+    prefix: String,
+    access_key_id: String,
+    secret_access_key: Arc<String>,
+}
+
+fn hmac(key: &[u8], data: &str) -> anyhow::Result<Vec<u8>> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    Ok(signer.sign_oneshot_to_vec(data.as_bytes())?)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// URI-encodes a path the way Signature Version 4 expects, keeping slashes.
+fn encode_path(path: &str) -> String {
+    path.split('/').map(encode).collect::<Vec<_>>().join("/")
+}
+
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+// The text of each <name> element in an S3 XML response, unescaped. The
+// responses are simple enough not to need an XML parser.
+fn elements(xml: &str, name: &str) -> Vec<String> {
+    let (open, close) = (format!("<{}>", name), format!("</{}>", name));
+    xml.split(open.as_str())
+        .skip(1)
+        .filter_map(|rest| {
+            let text = &rest[..rest.find(close.as_str())?];
+            Some(
+                text.replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&quot;", "\"")
+                    .replace("&apos;", "'")
+                    .replace("&amp;", "&"),
+            )
+        })
+        .collect()
+}
+
+impl S3Store {
+    fn new(config: &StorageConfig, secrets: &Secrets) -> anyhow::Result<Self> {
+        let s3 = &config.s3;
+        let endpoint = url::Url::parse(&s3.endpoint)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some())
+            .ok_or_else(|| anyhow::anyhow!("storage.s3.endpoint: '{}' is not an http(s) URL", s3.endpoint))?;
+        if s3.bucket.is_empty() {
+            anyhow::bail!("storage.s3.bucket: must be set for the s3 backend");
+        }
+        let secret = |name: &str| {
+            secrets
+                .get(name)?
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| anyhow::anyhow!("storage: {} is not set", name))
+        };
+        Ok(Self {
+            http: reqwest::Client::builder().timeout(S3_TIMEOUT).build()?,
+            endpoint,
+            bucket: s3.bucket.clone(),
+            region: s3.region.clone(),
+            prefix: s3.prefix.trim_matches('/').to_string(),
+            access_key_id: secret("INDEXPAGE_S3_ACCESS_KEY_ID")?,
+            secret_access_key: Arc::new(secret("INDEXPAGE_S3_SECRET_ACCESS_KEY")?),
+        })
+    }
+
+    // The bucket's path in the endpoint.
+    fn bucket_path(&self) -> String {
+        format!("{}/{}", self.endpoint.path().trim_end_matches('/'), self.bucket)
+    }
+
+    // The name of `key` in the bucket.
+    fn object(&self, key: &str) -> String {
+        match self.prefix.as_str() {
+            "" => key.to_string(),
+            prefix => format!("{}/{}", prefix, key),
+        }
+    }
+
+    // The request for `key`, signed for `body`.
+    fn request(&self, method: reqwest::Method, key: &str, body: &[u8]) -> anyhow::Result<reqwest::RequestBuilder> {
+        check_key(key)?;
+        let path = encode_path(&format!("{}/{}", self.bucket_path(), self.object(key)));
+        self.signed(method, &path, &[], body)
+    }
+
+    // A request for `path` in the endpoint with the given query parameters,
+    // signed for `body`.
+    fn signed(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: &[u8],
+    ) -> anyhow::Result<reqwest::RequestBuilder> {
+        // Signature Version 4 wants the parameters sorted and encoded alike.
+        let mut query = query.to_vec();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        let mut url = self.endpoint.clone();
+        url.set_path(path);
+        url.set_query(Some(&query).filter(|query| !query.is_empty()).map(String::as_str));
+        let host = &url[url::Position::BeforeHost..url::Position::AfterPort];
+
+        let now = Utc::now();
+        let (date, timestamp) = (now.format("%Y%m%d").to_string(), now.format("%Y%m%dT%H%M%SZ").to_string());
+        let payload_hash = hex(&openssl::sha::sha256(body));
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, path, query, host, payload_hash, timestamp, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex(&openssl::sha::sha256(canonical_request.as_bytes()))
+        );
+        let mut key = hmac(format!("AWS4{}", self.secret_access_key).as_bytes(), &date)?;
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part)?;
+        }
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id,
+            scope,
+            signed_headers,
+            hex(&hmac(&key, &string_to_sign)?)
+        );
+        Ok(self
+            .http
+            .request(method, url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", timestamp)
+            .header(reqwest::header::AUTHORIZATION, authorization))
+    }
+}
+
+impl ObjectStore for S3Store {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Object>> {
+        let response = self.request(reqwest::Method::GET, key, &[])?.send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+        let modified = response
+            .headers()
+            .get(reqwest::header::LAST_MODIFIED)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .map_or_else(Utc::now, |modified| modified.with_timezone(&Utc));
+        Ok(Some(Object { body: response.bytes().await?, modified }))
+    }
+
+    async fn put(&self, key: &str, body: Bytes, content_type: &str) -> anyhow::Result<()> {
+        self.request(reqwest::Method::PUT, key, &body)?
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let response = self.request(reqwest::Method::DELETE, key, &[])?.send().await?;
+        if response.status() != reqwest::StatusCode::NOT_FOUND {
+            response.error_for_status()?;
+        }
+        Ok(())
+    }
+
+    // ListObjectsV2, a page of up to 1000 keys at a time.
+    async fn list(&self, dir: &str) -> anyhow::Result<Vec<Entry>> {
+        check_key(dir)?;
+        let prefix = format!("{}/", self.object(dir));
+        let bucket = encode_path(&self.bucket_path());
+        let mut listed = vec![];
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix.as_str()), ("delimiter", "/")];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token));
+            }
+            let response = self.signed(reqwest::Method::GET, &bucket, &query, &[])?.send().await?.error_for_status()?;
+            let xml = response.text().await?;
+            for contents in elements(&xml, "Contents") {
+                let (Some(key), Some(size), Some(modified)) = (
+                    elements(&contents, "Key").pop(),
+                    elements(&contents, "Size").pop().and_then(|size| size.parse().ok()),
+                    elements(&contents, "LastModified").pop().and_then(|at| at.parse().ok()),
+                ) else {
+                    anyhow::bail!("unexpected object in listing: {}", contents)
+                };
+                let key = match self.prefix.as_str() {
+                    "" => key,
+                    prefix => {
+                        key.strip_prefix(prefix).and_then(|key| key.strip_prefix('/')).unwrap_or(&key).to_string()
+                    }
+                };
+                listed.push(Entry { key, size, modified });
+            }
+            token = elements(&xml, "NextContinuationToken").pop();
+            if token.is_none() {
+                return Ok(listed);
+            }
+        }
+    }
+}
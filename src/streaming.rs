@@ -0,0 +1,166 @@
+use axum::body::Body;
+use bytes::{BufMut, Bytes, BytesMut};
+use futures_util::{Stream, StreamExt};
+use http::{header, HeaderMap, StatusCode};
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use serde::Serialize;
+use std::{future::Future, io};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+// Response bodies written while the rows behind them are still being read,
+// for exports and histories too large to collect first. The channel to the
+// client is small, so a slow reader pauses the query instead of letting rows
+// pile up in memory.
+const CHANNEL_CHUNKS: usize = 4;
+// Rows are batched into chunks of about this size.
+const CHUNK_BYTES: usize = 64 * 1024;
+
+// Newline-delimited JSON, one record per line, for tools like jq that
+// process large documents line by line.
+pub const NDJSON: &str = "application/x-ndjson";
+
+// Whether ?format=ndjson was given or, without a format, the Accept header
+// asks for NDJSON.
+pub fn wants_ndjson(headers: &HeaderMap, format: Option<&str>) -> bool {
+    match format {
+        Some(format) => format == "ndjson",
+        None => headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains(NDJSON)),
+    }
+}
+
+pub fn is_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with(NDJSON))
+}
+
+pub struct Writer {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+    buffer: BytesMut,
+}
+
+impl Writer {
+    pub async fn write(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        self.buffer.extend_from_slice(data);
+        self.flush_full().await
+    }
+
+    // Writes the rows as a JSON array.
+    pub async fn json_array<T, E>(&mut self, rows: impl Stream<Item = Result<T, E>>) -> anyhow::Result<()>
+    where
+        T: Serialize,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let mut rows = std::pin::pin!(rows);
+        let mut first = true;
+        self.buffer.put_u8(b'[');
+        while let Some(row) = rows.next().await {
+            if !std::mem::take(&mut first) {
+                self.buffer.put_u8(b',');
+            }
+            serde_json::to_writer((&mut self.buffer).writer(), &row?)?;
+            self.flush_full().await?;
+        }
+        self.buffer.put_u8(b']');
+        Ok(())
+    }
+
+    // Writes each row as one line of JSON.
+    pub async fn json_lines<T, E>(&mut self, rows: impl Stream<Item = Result<T, E>>) -> anyhow::Result<()>
+    where
+        T: Serialize,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let mut rows = std::pin::pin!(rows);
+        while let Some(row) = rows.next().await {
+            self.json_line(&row?).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn json_line<T: Serialize>(&mut self, value: &T) -> anyhow::Result<()> {
+        serde_json::to_writer((&mut self.buffer).writer(), value)?;
+        self.buffer.put_u8(b'\n');
+        self.flush_full().await
+    }
+
+    // Sends what is left; the body ends when the writer is dropped.
+    pub async fn finish(mut self) -> anyhow::Result<()> {
+        self.send().await
+    }
+
+    async fn flush_full(&mut self) -> anyhow::Result<()> {
+        if self.buffer.len() >= CHUNK_BYTES {
+            self.send().await?;
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self) -> anyhow::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = self.buffer.split().freeze();
+        self.tx.send(Ok(chunk)).await.map_err(|_| anyhow::anyhow!("client went away"))
+    }
+}
+
+// Runs `produce` in the background, streaming what it writes. The status is
+// sent before the first row is read, so a failure part way through can only
+// abort the body, which clients see as a truncated response.
+pub fn body<F, Fut>(what: &'static str, produce: F) -> Body
+where
+    F: FnOnce(Writer) -> Fut,
+    Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(CHANNEL_CHUNKS);
+    let writer = Writer { tx: tx.clone(), buffer: BytesMut::with_capacity(CHUNK_BYTES) };
+    let produced = produce(writer);
+    tokio::spawn(async move {
+        if let Err(e) = produced.await {
+            tracing::warn!("streaming {} failed: {}", what, e);
+            let _ = tx.send(Err(io::Error::other(e.to_string()))).await;
+        }
+    });
+    Body::from_stream(ReceiverStream::new(rx))
+}
+
+// Reads a request body line by line as it arrives, passing each non-empty
+// line to `line`, without holding more than one line's worth of raw input.
+// Errors are reported with the line number; bodies over `limit` bytes are
+// rejected.
+pub async fn read_lines(
+    body: Body,
+    limit: usize,
+    mut line: impl FnMut(&[u8]) -> Result<(), String>,
+) -> Result<(), (StatusCode, String)> {
+    let mut body = std::pin::pin!(Limited::new(body, limit));
+    let mut buffer = BytesMut::new();
+    let mut number = 0;
+    let mut handle = |raw: &[u8]| {
+        number += 1;
+        let raw = raw.trim_ascii();
+        if raw.is_empty() {
+            return Ok(());
+        }
+        line(raw).map_err(|e| (StatusCode::BAD_REQUEST, format!("line {}: {}", number, e)))
+    };
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(|e| match e.is::<LengthLimitError>() {
+            true => (StatusCode::PAYLOAD_TOO_LARGE, format!("Body is larger than {} bytes", limit)),
+            false => (StatusCode::BAD_REQUEST, e.to_string()),
+        })?;
+        let Ok(data) = frame.into_data() else { continue };
+        buffer.extend_from_slice(&data);
+        while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+            let raw = buffer.split_to(end + 1);
+            handle(&raw)?;
+        }
+    }
+    handle(&buffer)
+}
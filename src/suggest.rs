@@ -0,0 +1,183 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{audit::AuditEntry, categories::Visibility, icons::Icons, links::LinkContext};
+
+// Search-as-you-type over service names. Every keystroke is answered from a
+// snapshot in memory; the snapshot is rebuilt in the background after each
+// recorded change (see audit.rs) and every REFRESH_INTERVAL, for changes
+// made around this process.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+// Changes arriving this close together are picked up by one rebuild.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+const DEFAULT_LIMIT: usize = 8;
+const MAX_LIMIT: usize = 25;
+
+struct Entry {
+    name: String,
+    lower: String,
+    link: String,
+    internal_link: Option<String>,
+    icon: Option<String>,
+    // The category's (min_role, groups, org_id), for Visibility::allows.
+    category: Option<(Option<String>, Vec<String>, Option<i32>)>,
+    org_id: Option<i32>,
+}
+
+#[derive(Default)]
+struct Snapshot {
+    entries: Vec<Entry>,
+    orgs: HashMap<String, Vec<i32>>,
+}
+
+#[derive(Clone, Default)]
+pub struct Suggestions {
+    snapshot: Arc<RwLock<Arc<Snapshot>>>,
+}
+
+impl Suggestions {
+    pub fn start(pool: PgPool, mut changes: broadcast::Receiver<AuditEntry>, icons: Icons) -> Self {
+        let suggestions = Self::default();
+        let snapshot = suggestions.snapshot.clone();
+        tokio::spawn(async move {
+            loop {
+                match build(&pool, &icons).await {
+                    Ok(built) => *snapshot.write().unwrap() = Arc::new(built),
+                    Err(e) => tracing::warn!("rebuilding the service suggestions failed: {}", e),
+                }
+                let changed = tokio::time::timeout(REFRESH_INTERVAL, changes.recv()).await;
+                if let Ok(Err(RecvError::Closed)) = changed {
+                    return;
+                }
+                tokio::time::sleep(DEBOUNCE).await;
+                changes = changes.resubscribe();
+            }
+        });
+        suggestions
+    }
+}
+
+async fn build(pool: &PgPool, icons: &Icons) -> sqlx::Result<Snapshot> {
+    let rows = sqlx::query!(
+        r#"SELECT s.name, s.link, s.internal_link, s.org_id, s.icon, c.id AS "category_id?",
+                  c.min_role AS "min_role?", c.groups AS "groups?", c.org_id AS "category_org_id?"
+           FROM services s LEFT JOIN categories c ON c.id = s.category_id
+           WHERE s.deleted_at IS NULL AND s.archived_at IS NULL ORDER BY s.name"#
+    )
+    .fetch_all(pool)
+    .await?;
+    let members = sqlx::query!("SELECT member, org_id FROM org_members").fetch_all(pool).await?;
+
+    let services: Vec<(&str, &str, Option<&str>)> =
+        rows.iter().map(|row| (row.name.as_str(), row.link.as_str(), row.icon.as_deref())).collect();
+    let icons = icons.urls(&services).await;
+    let entries = rows
+        .into_iter()
+        .zip(icons)
+        .map(|(row, icon)| Entry {
+            lower: row.name.to_lowercase(),
+            name: row.name,
+            link: row.link,
+            internal_link: row.internal_link,
+            icon,
+            category: row.category_id.map(|_| (row.min_role, row.groups.unwrap_or_default(), row.category_org_id)),
+            org_id: row.org_id,
+        })
+        .collect();
+    let mut orgs: HashMap<String, Vec<i32>> = HashMap::new();
+    for row in members {
+        orgs.entry(row.member).or_default().push(row.org_id);
+    }
+    Ok(Snapshot { entries, orgs })
+}
+
+// How well `name` (lowercased) matches `query`, lower is better: a prefix of
+// the name, then of one of its words, then anywhere in it, then its letters
+// in order ("gfn" for "Grafana"). None if it doesn't match at all.
+fn rank(name: &str, query: &str) -> Option<(u8, usize)> {
+    if name.starts_with(query) {
+        return Some((0, name.len()));
+    }
+    let word_start = name
+        .char_indices()
+        .filter(|(at, _)| name[..*at].ends_with(|c: char| !c.is_alphanumeric()))
+        .any(|(at, _)| name[at..].starts_with(query));
+    if word_start {
+        return Some((1, name.len()));
+    }
+    if let Some(at) = name.find(query) {
+        return Some((2, at));
+    }
+    let mut letters = name.chars();
+    let fuzzy = query.chars().filter(|c| !c.is_whitespace()).all(|c| letters.any(|letter| letter == c));
+    fuzzy.then_some((3, name.len()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SuggestQuery {
+    #[serde(default)]
+    q: String,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Suggestion {
+    name: String,
+    link: String,
+    // Under /icons, when the dashboard-icons collection has one.
+    icon: Option<String>,
+}
+
+// GET /services/suggest?q=...&limit=
+// Up to `limit` (default 8, at most 25) visible services, best match first.
+pub async fn suggest(
+    State(suggestions): State<Suggestions>,
+    links: LinkContext,
+    visibility: Visibility,
+    Query(query): Query<SuggestQuery>,
+) -> Result<Json<Vec<Suggestion>>, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err((StatusCode::BAD_REQUEST, format!("limit must be between 1 and {}", MAX_LIMIT)));
+    }
+    let q = query.q.trim().to_lowercase();
+    if q.is_empty() {
+        return Ok(Json(Vec::new()));
+    }
+    let snapshot = suggestions.snapshot.read().unwrap().clone();
+    let orgs = visibility.member().and_then(|member| snapshot.orgs.get(member)).map(Vec::as_slice).unwrap_or_default();
+    let mut matches: Vec<_> = snapshot
+        .entries
+        .iter()
+        .filter_map(|entry| Some((rank(&entry.lower, &q)?, entry)))
+        .filter(|(_, entry)| {
+            let category = entry
+                .category
+                .as_ref()
+                .map(|(min_role, groups, org_id)| (min_role.as_deref(), groups.as_slice(), *org_id));
+            visibility.allows(category, entry.org_id, orgs)
+        })
+        .collect();
+    matches.sort_by(|(a, a_entry), (b, b_entry)| a.cmp(b).then_with(|| a_entry.lower.cmp(&b_entry.lower)));
+    let suggestions = matches
+        .into_iter()
+        .take(limit)
+        .map(|(_, entry)| Suggestion {
+            name: entry.name.clone(),
+            link: links.choose(&entry.link, entry.internal_link.as_deref()),
+            icon: entry.icon.clone(),
+        })
+        .collect();
+    Ok(Json(suggestions))
+}
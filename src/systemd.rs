@@ -0,0 +1,92 @@
+use std::{env, time::Duration};
+use tokio::net::TcpListener;
+
+// systemd integration, all driven by the environment systemd sets up, so
+// nothing changes when run any other way:
+// - with a .socket unit (ListenStream=...), the listening socket is
+//   inherited instead of binding `server.listen`;
+// - with Type=notify, readiness is reported once the server is listening;
+// - with WatchdogSec=..., the watchdog is pinged at half that interval, from
+//   the runtime that serves requests, so a wedged process gets restarted.
+// See sd_listen_fds(3) and sd_notify(3).
+#[cfg(unix)]
+const LISTEN_FDS_START: std::os::fd::RawFd = 3;
+
+// The socket passed by socket activation, if any.
+pub fn listener() -> anyhow::Result<Option<TcpListener>> {
+    if !for_us("LISTEN_PID") {
+        return Ok(None);
+    }
+    let count: i32 = match env::var("LISTEN_FDS").ok().and_then(|count| count.parse().ok()) {
+        Some(count) if count > 0 => count,
+        _ => return Ok(None),
+    };
+    if count > 1 {
+        tracing::warn!("systemd passed {} sockets; using the first", count);
+    }
+    inherit().map(Some)
+}
+
+#[cfg(unix)]
+fn inherit() -> anyhow::Result<TcpListener> {
+    use std::os::fd::FromRawFd;
+    // SAFETY: systemd hands this descriptor to the process for it to own,
+    // and nothing else in the process uses it.
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(LISTEN_FDS_START) };
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| anyhow::anyhow!("inherited socket is not a TCP listener: {}", e))?;
+    Ok(TcpListener::from_std(listener)?)
+}
+
+#[cfg(not(unix))]
+fn inherit() -> anyhow::Result<TcpListener> {
+    anyhow::bail!("socket activation is only supported on Unix")
+}
+
+// Tells systemd the server is up and starts the watchdog pings if enabled.
+pub fn ready() {
+    notify("READY=1");
+    let interval = env::var("WATCHDOG_USEC").ok().and_then(|usec| usec.parse::<u64>().ok());
+    if let Some(usec) = interval.filter(|usec| *usec > 0) {
+        if env::var("WATCHDOG_PID").is_ok() && !for_us("WATCHDOG_PID") {
+            return;
+        }
+        let interval = Duration::from_micros(usec / 2);
+        tracing::info!("pinging the systemd watchdog every {:?}", interval);
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                notify("WATCHDOG=1");
+            }
+        });
+    }
+}
+
+// Whether the variable names this process, as systemd sets it for the
+// service's main process only.
+fn for_us(pid_var: &str) -> bool {
+    env::var(pid_var).ok().and_then(|pid| pid.parse::<u32>().ok()) == Some(std::process::id())
+}
+
+#[cfg(unix)]
+fn notify(state: &str) {
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+    let Ok(path) = env::var("NOTIFY_SOCKET") else { return };
+    let sent = (|| {
+        // A leading '@' means the abstract namespace.
+        let addr = match path.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            Some(name) => <SocketAddr as std::os::linux::net::SocketAddrExt>::from_abstract_name(name)?,
+            _ => SocketAddr::from_pathname(&path)?,
+        };
+        UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)
+    })();
+    if let Err(e) = sent {
+        tracing::warn!("sd_notify {} to {} failed: {}", state, path, e);
+    }
+}
+
+#[cfg(not(unix))]
+fn notify(_state: &str) {}
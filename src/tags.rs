@@ -0,0 +1,53 @@
+use axum::{extract::State, Json};
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+
+#[derive(Debug, Serialize)]
+pub struct Tag {
+    name: String,
+    services: i64,
+}
+
+// Selects a service's tag names as a sorted array; used inside service queries.
+pub const TAGS_COLUMN: &str = "ARRAY(SELECT t.name FROM service_tags st JOIN tags t ON t.id = st.tag_id \
+     WHERE st.service_id = services.id ORDER BY t.name) AS tags";
+
+// GET /tags
+pub async fn get_tags(State(pool): State<PgPool>) -> Json<Vec<Tag>> {
+    let tags = sqlx::query_as!(
+        Tag,
+        r#"
+        SELECT t.name, count(s.id) AS "services!"
+        FROM tags t
+        LEFT JOIN service_tags st ON st.tag_id = t.id
+        LEFT JOIN services s ON s.id = st.service_id AND s.deleted_at IS NULL
+        GROUP BY t.name ORDER BY t.name
+        "#,
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap_or_else(|_| vec![]);
+    Json(tags)
+}
+
+// Adds `names` to the service's tags, creating unknown tags on the fly.
+pub async fn attach(conn: &mut PgConnection, service_id: i32, names: &[String]) -> sqlx::Result<()> {
+    if names.is_empty() {
+        return Ok(());
+    }
+    sqlx::query!("INSERT INTO tags (name) SELECT unnest($1::text[]) ON CONFLICT (name) DO NOTHING", names)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO service_tags (service_id, tag_id)
+        SELECT $1, id FROM tags WHERE name = ANY($2)
+        ON CONFLICT DO NOTHING
+        "#,
+        service_id,
+        names,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
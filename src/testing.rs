@@ -0,0 +1,601 @@
+use reqwest::{Method, RequestBuilder};
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::atomic::{AtomicU32, Ordering},
+};
+use tokio::net::TcpListener;
+
+use crate::{config::Config, schema, secrets::Secrets, server, timeouts::Timeouts};
+
+// Black-box HTTP tests (the `test-support` feature). TestApp serves the
+// router as main.rs builds it on a local port, against a schema of its own
+// in the database at TEST_DATABASE_URL that finish() drops again; tests
+// are skipped without one. Requests from request() carry API_KEY, so they
+// act as admin. For example:
+//
+//     let Some(app) = TestApp::spawn().await else { return };
+//     app.seed_service("Wiki", "https://wiki.example.com").await;
+//     let response = app.request(Method::GET, "/services").send().await.unwrap();
+//     assert_eq!(response.status(), 200);
+//     app.finish().await;
+pub const API_KEY: &str = "test-api-key";
+pub const EMBED_KEY: &str = "test-embed-key";
+// 32 zero bytes, base64.
+const SECRET_KEY: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+
+static SCHEMAS: AtomicU32 = AtomicU32::new(0);
+
+pub struct TestApp {
+    // Bound to the app's schema, for seeding and looking behind the API.
+    pub pool: PgPool,
+    pub address: SocketAddr,
+    schema: String,
+    http: reqwest::Client,
+}
+
+// What spawn() runs with: the defaults, minus the jobs that would check or
+// prune behind a test's back.
+pub fn config() -> Config {
+    let mut config = Config::default();
+    config.checks.enabled = false;
+    config.retention.enabled = false;
+    config
+}
+
+impl TestApp {
+    pub async fn spawn() -> Option<Self> {
+        Self::spawn_with(config()).await
+    }
+
+    // None when TEST_DATABASE_URL is unset; panics if the app can't start.
+    pub async fn spawn_with(config: Config) -> Option<Self> {
+        let url = std::env::var("TEST_DATABASE_URL").ok()?;
+        let schema = format!("indexpage_test_{}_{}", std::process::id(), SCHEMAS.fetch_add(1, Ordering::Relaxed));
+        let admin = PgPoolOptions::new()
+            .max_connections(1)
+            .connect(&url)
+            .await
+            .expect("connecting to TEST_DATABASE_URL");
+        sqlx::raw_sql(&format!("DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0}", schema))
+            .execute(&admin)
+            .await
+            .expect("creating the test schema");
+        admin.close().await;
+
+        let search_path = format!("SET search_path TO {}", schema);
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .after_connect(move |conn, _| {
+                let search_path = search_path.clone();
+                Box::pin(async move { sqlx::query(&search_path).execute(conn).await.map(drop) })
+            })
+            .connect(&url)
+            .await
+            .expect("connecting to the test schema");
+        schema::migrate(&pool).await.expect("creating the tables");
+
+        let secrets = Secrets::fixed(HashMap::from([
+            ("INDEXPAGE_API_KEY".to_string(), API_KEY.to_string()),
+            ("INDEXPAGE_EMBED_KEY".to_string(), EMBED_KEY.to_string()),
+            ("INDEXPAGE_SECRET_KEY".to_string(), SECRET_KEY.to_string()),
+        ]));
+        let timeouts = Timeouts::new(&config.server);
+        let app = crate::build(&config, &secrets, pool.clone(), timeouts).await.expect("building the app");
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("binding a port");
+        let address = listener.local_addr().expect("the bound address");
+        tokio::spawn(async move {
+            if let Err(e) = server::serve_on(listener, &config.server, app).await {
+                tracing::error!("test server stopped: {}", e);
+            }
+        });
+        Some(Self { pool, address, schema, http: reqwest::Client::new() })
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.address, path)
+    }
+
+    // As admin; remove the header for anonymous requests.
+    pub fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http.request(method, self.url(path)).header("x-api-key", API_KEY)
+    }
+
+    // With `key` (a session or API token) instead of API_KEY.
+    pub fn request_as(&self, key: &str, method: Method, path: &str) -> RequestBuilder {
+        self.http.request(method, self.url(path)).header("x-api-key", key)
+    }
+
+    // A local user with `password` as its password, signed in; returns the
+    // session token.
+    pub async fn seed_user(&self, name: &str, role: &str, password: &str) -> String {
+        let user = serde_json::json!({"name": name, "role": role, "password": password});
+        let created = self.request(Method::POST, "/admin/users").json(&user).send().await.expect("creating a user");
+        assert_eq!(created.status(), 200, "creating a user");
+        let login = serde_json::json!({"name": name, "password": password});
+        let response = self.http.post(self.url("/login")).json(&login).send().await.expect("signing in");
+        assert_eq!(response.status(), 200, "signing in");
+        let session: serde_json::Value = response.json().await.expect("the session");
+        session["token"].as_str().expect("a session token").to_string()
+    }
+
+    // A personal API token of the user signed in with `session`.
+    pub async fn seed_token(&self, session: &str, scope: &str) -> String {
+        let token = serde_json::json!({"name": "test", "scope": scope});
+        let response =
+            self.request_as(session, Method::POST, "/me/tokens").json(&token).send().await.expect("creating a token");
+        assert_eq!(response.status(), 200, "creating a token");
+        let created: serde_json::Value = response.json().await.expect("the token");
+        created["secret"].as_str().expect("a token secret").to_string()
+    }
+
+    pub async fn seed_category(&self, name: &str) -> i32 {
+        sqlx::query_scalar("INSERT INTO categories (name) VALUES ($1) RETURNING id")
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await
+            .expect("seeding a category")
+    }
+
+    pub async fn seed_service(&self, name: &str, link: &str) -> i32 {
+        sqlx::query_scalar("INSERT INTO services (name, link) VALUES ($1, $2) RETURNING id")
+            .bind(name)
+            .bind(link)
+            .fetch_one(&self.pool)
+            .await
+            .expect("seeding a service")
+    }
+
+    // Puts the service into the category, and tags it with `tags`.
+    pub async fn file_service(&self, service_id: i32, category_id: Option<i32>, tags: &[&str]) {
+        sqlx::query("UPDATE services SET category_id = $2 WHERE id = $1")
+            .bind(service_id)
+            .bind(category_id)
+            .execute(&self.pool)
+            .await
+            .expect("setting the category");
+        sqlx::query(
+            "WITH added AS (INSERT INTO tags (name) SELECT unnest($2::text[]) \
+             ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name RETURNING id) \
+             INSERT INTO service_tags (service_id, tag_id) SELECT $1, id FROM added ON CONFLICT DO NOTHING",
+        )
+        .bind(service_id)
+        .bind(tags)
+        .execute(&self.pool)
+        .await
+        .expect("tagging the service");
+    }
+
+    // Drops the schema. A test that panics before this leaves it behind;
+    // such indexpage_test_* schemas can be dropped by hand.
+    pub async fn finish(self) {
+        let drop_schema = format!("DROP SCHEMA {} CASCADE", self.schema);
+        if let Err(e) = sqlx::query(&drop_schema).execute(&self.pool).await {
+            tracing::warn!("dropping {}: {}", self.schema, e);
+        }
+        self.pool.close().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn serves_seeded_and_created_services() {
+        let Some(app) = TestApp::spawn().await else { return };
+        let category = app.seed_category("Docs").await;
+        let wiki = app.seed_service("Wiki", "https://wiki.example.com").await;
+        app.file_service(wiki, Some(category), &["docs"]).await;
+
+        let grafana = serde_json::json!({"name": "Grafana", "link": "https://grafana.example.com"});
+        let anonymous = app.http.post(app.url("/services")).json(&grafana).send().await.unwrap();
+        assert_eq!(anonymous.status(), 401);
+        let created = app.request(Method::POST, "/services").json(&grafana).send().await.unwrap();
+        assert_eq!(created.status(), 200);
+
+        let services: Vec<serde_json::Value> =
+            app.request(Method::GET, "/services").send().await.unwrap().json().await.unwrap();
+        let names: Vec<&str> = services.iter().filter_map(|service| service["name"].as_str()).collect();
+        assert_eq!(names, ["Wiki", "Grafana"]);
+        assert_eq!(services[0]["tags"], serde_json::json!(["docs"]));
+        app.finish().await;
+    }
+
+    // Sessions revoked with a token, which isn't a session itself, are
+    // never the current one.
+    #[tokio::test]
+    async fn revokes_sessions_with_a_token() {
+        let Some(app) = TestApp::spawn().await else { return };
+        let session = app.seed_user("bob", "editor", "correct horse battery").await;
+        let token = app.seed_token(&session, "write").await;
+
+        let sessions: Vec<serde_json::Value> =
+            app.request_as(&session, Method::GET, "/me/sessions").send().await.unwrap().json().await.unwrap();
+        let id = sessions[0]["id"].as_i64().unwrap();
+        let revoked = app.request_as(&token, Method::DELETE, &format!("/me/sessions/{}", id)).send().await.unwrap();
+        assert_eq!(revoked.status(), 200);
+        let revoked: serde_json::Value = revoked.json().await.unwrap();
+        assert_eq!(revoked["current"], false);
+
+        let login = serde_json::json!({"name": "bob", "password": "correct horse battery"});
+        app.http.post(app.url("/login")).json(&login).send().await.unwrap();
+        let all = app.request_as(&token, Method::DELETE, "/me/sessions").send().await.unwrap();
+        assert_eq!(all.status(), 200);
+        let all: Vec<serde_json::Value> = all.json().await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0]["current"], false);
+        app.finish().await;
+    }
+
+    // A token is worth no more than its owner: demoting the user caps it
+    // and deleting them revokes it.
+    #[tokio::test]
+    async fn tokens_follow_their_owner() {
+        let Some(app) = TestApp::spawn().await else { return };
+        let session = app.seed_user("bob", "editor", "correct horse battery").await;
+        let token = app.seed_token(&session, "write").await;
+        let create = |name: &str| {
+            let service = serde_json::json!({"name": name, "link": format!("https://{}.example.com", name)});
+            app.request_as(&token, Method::POST, "/services").json(&service).send()
+        };
+        assert_eq!(create("wiki").await.unwrap().status(), 200);
+
+        sqlx::query("UPDATE users SET role = 'viewer' WHERE name = 'bob'").execute(&app.pool).await.unwrap();
+        assert_eq!(create("grafana").await.unwrap().status(), 403);
+        assert_eq!(app.request_as(&token, Method::GET, "/services").send().await.unwrap().status(), 200);
+
+        let deleted = app.request(Method::DELETE, "/admin/users/bob").send().await.unwrap();
+        assert_eq!(deleted.status(), 200);
+        assert_eq!(app.request_as(&token, Method::GET, "/me").send().await.unwrap().status(), 401);
+        // Nor does a new user of the same name inherit it.
+        app.seed_user("bob", "editor", "another horse battery").await;
+        assert_eq!(app.request_as(&token, Method::GET, "/me").send().await.unwrap().status(), 401);
+        app.finish().await;
+    }
+
+    // Bad keys lock the client out, but only of trying more bad keys.
+    #[tokio::test]
+    async fn lockout_lets_valid_keys_through() {
+        let mut config = config();
+        config.auth.lockout.max_attempts = 2;
+        let Some(app) = TestApp::spawn_with(config).await else { return };
+        for _ in 0..2 {
+            let refused = app.request_as("wrong", Method::GET, "/services").send().await.unwrap();
+            assert_eq!(refused.status(), 401);
+        }
+        let locked = app.request_as("wrong", Method::GET, "/services").send().await.unwrap();
+        assert_eq!(locked.status(), 429);
+        assert!(locked.headers().contains_key("retry-after"));
+        assert_eq!(app.request(Method::GET, "/services").send().await.unwrap().status(), 200);
+        app.finish().await;
+    }
+
+    // Links are stored as given; a duplicate is refused with the service
+    // that has it.
+    #[tokio::test]
+    async fn refuses_duplicate_links_with_the_existing_service() {
+        let Some(app) = TestApp::spawn().await else { return };
+        let link = "HTTPS://Wiki.Example.com/Docs?utm_source=mail";
+        let wiki = serde_json::json!({"name": "Wiki", "link": link});
+        let created: serde_json::Value =
+            app.request(Method::POST, "/services").json(&wiki).send().await.unwrap().json().await.unwrap();
+        assert_eq!(created["link"], link);
+
+        let again = serde_json::json!({"name": "Docs", "link": "https://wiki.example.com/Docs"});
+        let refused = app.request(Method::POST, "/services?force=true").json(&again).send().await.unwrap();
+        assert_eq!(refused.status(), 409);
+        let refused: serde_json::Value = refused.json().await.unwrap();
+        assert_eq!(refused["service"]["id"], created["id"]);
+        assert_eq!(refused["service"]["link"], link);
+
+        let near = serde_json::json!({"name": "Docs", "link": "http://www.wiki.example.com/Docs/"});
+        let refused = app.request(Method::POST, "/services").json(&near).send().await.unwrap();
+        assert_eq!(refused.status(), 409);
+        let forced = app.request(Method::POST, "/services?force=true").json(&near).send().await.unwrap();
+        assert_eq!(forced.status(), 200);
+        app.finish().await;
+    }
+
+    // A cross-site link carries the session cookie, so quick add wants a
+    // personal token, and never the API key in its URL.
+    #[tokio::test]
+    async fn quick_add_needs_a_personal_token() {
+        let Some(app) = TestApp::spawn().await else { return };
+        let session = app.seed_user("bob", "editor", "correct horse battery").await;
+        let token = app.seed_token(&session, "write").await;
+        let add = |query: String| {
+            app.http.get(app.url(&format!("/quick-add?url=https://wiki.example.com&name=Wiki{}", query)))
+        };
+
+        let cookie = format!("{}={}", crate::sessions::COOKIE, session);
+        let cookie_only = add(String::new()).header("cookie", &cookie).send().await.unwrap();
+        assert_eq!(cookie_only.status(), 401);
+        let garbage = add("&token=garbage".into()).header("cookie", &cookie).send().await.unwrap();
+        assert_eq!(garbage.status(), 401);
+        for key in [API_KEY, session.as_str()] {
+            let in_query = add(format!("&token={}", key)).send().await.unwrap();
+            assert_eq!(in_query.status(), 400);
+        }
+        assert_eq!(add(format!("&token={}", token)).send().await.unwrap().status(), 200);
+        app.finish().await;
+    }
+
+    // Editors can't change categories hidden from them, as if they weren't
+    // there.
+    #[tokio::test]
+    async fn hidden_categories_cannot_be_changed() {
+        let Some(app) = TestApp::spawn().await else { return };
+        let session = app.seed_user("bob", "editor", "correct horse battery").await;
+        let hidden = app.seed_category("Ops").await;
+        let restrict = sqlx::query("UPDATE categories SET min_role = 'admin' WHERE id = $1").bind(hidden);
+        restrict.execute(&app.pool).await.unwrap();
+
+        let colors = serde_json::json!({"color": "#c62828"});
+        let path = format!("/categories/{}/colors", hidden);
+        let recolored = app.request_as(&session, Method::PUT, &path).json(&colors).send().await.unwrap();
+        assert_eq!(recolored.status(), 404);
+        let path = format!("/categories/{}", hidden);
+        assert_eq!(app.request_as(&session, Method::DELETE, &path).send().await.unwrap().status(), 404);
+        assert_eq!(app.request(Method::DELETE, &path).send().await.unwrap().status(), 200);
+
+        let open = app.seed_category("Docs").await;
+        let path = format!("/categories/{}/colors", open);
+        assert_eq!(app.request_as(&session, Method::PUT, &path).json(&colors).send().await.unwrap().status(), 200);
+        app.finish().await;
+    }
+
+    // Archived services drop out of the summaries and exports.
+    #[tokio::test]
+    async fn archived_services_are_left_out() {
+        let Some(app) = TestApp::spawn().await else { return };
+        let category = app.seed_category("Docs").await;
+        for name in ["Wiki", "Old wiki"] {
+            let service = app.seed_service(name, &format!("https://{}.example.com", name.replace(' ', "-"))).await;
+            app.file_service(service, Some(category), &[]).await;
+        }
+        let archived = app.request(Method::POST, "/services/Old%20wiki/archive").send().await.unwrap();
+        assert_eq!(archived.status(), 200);
+
+        let status: serde_json::Value = app
+            .request(Method::GET, &format!("/categories/{}/status", category))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(status["counts"]["total"], 1);
+        let search = serde_json::json!({"target": "wiki"});
+        let metrics: Vec<String> =
+            app.request(Method::POST, "/grafana/search").json(&search).send().await.unwrap().json().await.unwrap();
+        assert_eq!(metrics, ["Wiki:uptime", "Wiki:latency"]);
+        let export = app.request(Method::GET, "/services/export?format=homer").send().await.unwrap();
+        let export = export.text().await.unwrap();
+        assert!(export.contains("Wiki") && !export.contains("Old wiki"), "{}", export);
+        app.finish().await;
+    }
+
+    // A hook running `script` with sh, the payload on its stdin.
+    fn hook(event: crate::hooks::HookEvent, script: &str) -> crate::config::HookConfig {
+        crate::config::HookConfig {
+            event,
+            command: Some(vec!["sh".into(), "-c".into(), script.into()]),
+            url: None,
+            timeout_secs: 5,
+        }
+    }
+
+    // What the post hooks appended to `log` so far, once `lines` are there.
+    async fn hook_log(log: &std::path::Path, lines: usize) -> String {
+        for _ in 0..50 {
+            let written = std::fs::read_to_string(log).unwrap_or_default();
+            if written.lines().count() >= lines {
+                return written;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        panic!("{} has fewer than {} lines", log.display(), lines);
+    }
+
+    // Pre hooks reject single rows of an import; post hooks see the rows
+    // that were saved.
+    #[tokio::test]
+    async fn imports_run_the_hooks() {
+        use crate::hooks::HookEvent;
+        let log = std::env::temp_dir().join(format!("indexpage-hooks-{}-csv", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        let mut config = config();
+        config.hooks = vec![
+            hook(HookEvent::PreCreate, "! grep -q Blocked"),
+            hook(HookEvent::PostCreate, &format!("cat >> {}; echo >> {0}", log.display())),
+        ];
+        let Some(app) = TestApp::spawn_with(config).await else { return };
+
+        let csv = "name,link\nWiki,https://wiki.example.com\nBlocked,https://blocked.example.com\n";
+        let dry_run: serde_json::Value = app
+            .request(Method::POST, "/services/import/csv?dry_run=true")
+            .body(csv)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(dry_run["imported"], 1);
+        let report: serde_json::Value =
+            app.request(Method::POST, "/services/import/csv").body(csv).send().await.unwrap().json().await.unwrap();
+        assert_eq!(report["imported"], 1);
+        assert_eq!(report["errors"][0]["row"], 3);
+        assert!(report["errors"][0]["error"].as_str().unwrap().starts_with("Rejected by pre_create hook"));
+
+        let heimdall = serde_json::json!([
+            {"title": "Grafana", "url": "https://grafana.example.com"},
+            {"title": "Blocked too", "url": "https://blocked2.example.com"},
+        ]);
+        let report: serde_json::Value = app
+            .request(Method::POST, "/services/import/heimdall")
+            .body(heimdall.to_string())
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(report["imported"], 1);
+        assert_eq!(report["errors"][0]["row"], 2);
+
+        let written = hook_log(&log, 2).await;
+        assert_eq!(written.lines().count(), 2, "{}", written);
+        assert!(written.contains("\"name\":\"Wiki\"") && written.contains("\"name\":\"Grafana\""), "{}", written);
+        let _ = std::fs::remove_file(&log);
+        app.finish().await;
+    }
+
+    // A sync creates and removes services one at a time, through the hooks.
+    #[tokio::test]
+    async fn proxy_syncs_run_the_hooks() {
+        use crate::{
+            config::{ProxyKind, ProxySourceConfig},
+            hooks::HookEvent,
+        };
+        use std::sync::{Arc, Mutex};
+
+        let routes = |hosts: &[&str]| serde_json::json!({"srv0": {"routes": [{"match": [{"host": hosts}]}]}});
+        let servers = Arc::new(Mutex::new(routes(&["wiki.lan", "blocked.lan", "keep.lan", "gone.lan"])));
+        let served = servers.clone();
+        let caddy = axum::Router::new().route(
+            "/config/apps/http/servers",
+            axum::routing::get(move || {
+                let servers = served.lock().unwrap().clone();
+                async move { axum::Json(servers) }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let caddy_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, caddy).await });
+
+        let log = std::env::temp_dir().join(format!("indexpage-hooks-{}-sync", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        let mut config = config();
+        let append = format!("cat >> {}; echo >> {0}", log.display());
+        config.hooks = vec![
+            hook(HookEvent::PreCreate, "! grep -q blocked"),
+            hook(HookEvent::PreDelete, "! grep -q keep"),
+            hook(HookEvent::PostCreate, &append),
+            hook(HookEvent::PostDelete, &append),
+        ];
+        config.proxy_sync.sources = vec![ProxySourceConfig {
+            name: "caddy".into(),
+            kind: ProxyKind::Caddy,
+            url: caddy_url,
+            email: None,
+            password_secret: None,
+            exclude: Vec::new(),
+            category: None,
+            tags: Vec::new(),
+            remove_missing: true,
+        }];
+        // Only on request.
+        config.jobs.insert("proxy_sync".into(), "0 0 1 1 *".into());
+        let Some(app) = TestApp::spawn_with(config).await else { return };
+        let sync = || async {
+            let response = app.request(Method::POST, "/integrations/proxies/sync").send().await.unwrap();
+            let reports: Vec<serde_json::Value> = response.json().await.unwrap();
+            reports[0].clone()
+        };
+
+        let report = sync().await;
+        assert_eq!(report["imported"], 3, "{}", report);
+        assert_eq!(report["errors"][0]["name"], "blocked");
+        *servers.lock().unwrap() = routes(&[]);
+        let report = sync().await;
+        assert_eq!(report["removed"], serde_json::json!(["gone", "wiki"]), "{}", report);
+        assert_eq!(report["kept"][0]["name"], "keep");
+
+        let written = hook_log(&log, 5).await;
+        assert_eq!(written.matches("\"event\":\"post_create\"").count(), 3, "{}", written);
+        assert_eq!(written.matches("\"event\":\"post_delete\"").count(), 2, "{}", written);
+        let _ = std::fs::remove_file(&log);
+        app.finish().await;
+    }
+
+    // Backups go through `[storage]` and come back in a form import takes.
+    #[tokio::test]
+    async fn backups_round_trip_through_storage() {
+        let dir = std::env::temp_dir().join(format!("indexpage-storage-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut config = config();
+        config.storage.backend = Some(crate::config::StorageBackend::Local);
+        config.storage.path = dir.clone();
+        let Some(app) = TestApp::spawn_with(config).await else { return };
+        app.seed_service("Wiki", "https://wiki.example.com").await;
+
+        let backup: serde_json::Value =
+            app.request(Method::POST, "/admin/backups").send().await.unwrap().json().await.unwrap();
+        let name = backup["name"].as_str().unwrap().to_string();
+        let listed: serde_json::Value =
+            app.request(Method::GET, "/admin/backups").send().await.unwrap().json().await.unwrap();
+        assert_eq!(listed[0]["name"], name.as_str(), "{}", listed);
+        assert_eq!(listed[0]["size"], backup["size"]);
+
+        let stored = app.request(Method::GET, &format!("/admin/backups/{}", name)).send().await.unwrap();
+        let stored = stored.text().await.unwrap();
+        assert_eq!(stored.len() as u64, backup["size"].as_u64().unwrap());
+        assert!(stored.contains("\"name\":\"Wiki\""), "{}", stored);
+        let response = app
+            .request(Method::POST, "/admin/import")
+            .header(reqwest::header::CONTENT_TYPE, crate::streaming::NDJSON)
+            .body(stored)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        let missing = app.request(Method::GET, "/admin/backups/..%2Fsecrets").send().await.unwrap();
+        assert_eq!(missing.status(), 404);
+        let response = app.request(Method::DELETE, &format!("/admin/backups/{}", name)).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let listed: serde_json::Value =
+            app.request(Method::GET, "/admin/backups").send().await.unwrap().json().await.unwrap();
+        assert_eq!(listed, serde_json::json!([]));
+        let _ = std::fs::remove_dir_all(&dir);
+        app.finish().await;
+    }
+
+    // Runs that fail or time out before an answer are audited as well.
+    #[tokio::test]
+    async fn audits_actions_without_an_answer() {
+        let silent = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let action = |name: &str, addr: SocketAddr| crate::config::ActionConfig {
+            name: name.into(),
+            label: None,
+            services: vec!["wiki".into()],
+            method: "POST".into(),
+            url: format!("http://{}/", addr),
+            headers: HashMap::new(),
+            body: None,
+            role: crate::auth::Role::Viewer,
+            timeout_secs: 1,
+        };
+        let mut config = config();
+        config.actions = vec![action("hang", silent.local_addr().unwrap()), action("refused", closed)];
+        let Some(app) = TestApp::spawn_with(config).await else { return };
+        app.seed_service("Wiki", "https://wiki.example.com").await;
+
+        let hang = app.request(Method::POST, "/services/Wiki/actions/hang").send().await.unwrap();
+        assert_eq!(hang.status(), 504);
+        let refused = app.request(Method::POST, "/services/Wiki/actions/refused").send().await.unwrap();
+        assert_eq!(refused.status(), 502);
+        let audit: serde_json::Value =
+            app.request(Method::GET, "/audit?action=service.action").send().await.unwrap().json().await.unwrap();
+        let details: Vec<&str> =
+            audit.as_array().unwrap().iter().filter_map(|entry| entry["detail"].as_str()).collect();
+        assert_eq!(details.len(), 2, "{}", audit);
+        assert_eq!(details[1], "hang -> timed out");
+        assert!(details[0].starts_with("refused -> failed: "), "{}", details[0]);
+        app.finish().await;
+    }
+}
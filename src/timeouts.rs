@@ -0,0 +1,86 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use http::StatusCode;
+use sqlx::{pool::PoolConnectionMetadata, PgConnection};
+use std::{sync::Arc, time::Duration};
+use tokio::time::Instant;
+
+use crate::config::ServerConfig;
+
+// Request deadlines. A request past its deadline is answered with a 504 and
+// its handler dropped; so that Postgres stops working on it too, every
+// connection the handler acquires gets a statement_timeout of the time the
+// request has left. Connections acquired outside a request (jobs, streamed
+// bodies) get none.
+// Queries are cancelled this long after the deadline, so the client gets the
+// 504 rather than the query's own error.
+const STATEMENT_GRACE: Duration = Duration::from_millis(250);
+
+tokio::task_local! {
+    static DEADLINE: Option<Instant>;
+}
+
+#[derive(Clone, Default)]
+pub struct Timeouts {
+    default: Option<Duration>,
+    // Longest prefix first.
+    routes: Arc<Vec<(String, Option<Duration>)>>,
+}
+
+fn limit(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+impl Timeouts {
+    pub fn new(config: &ServerConfig) -> Self {
+        let mut routes: Vec<_> =
+            config.route_timeouts.iter().map(|(prefix, secs)| (prefix.clone(), limit(*secs))).collect();
+        routes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        Self { default: limit(config.request_timeout_secs), routes: Arc::new(routes) }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.default.is_some() || self.routes.iter().any(|(_, limit)| limit.is_some())
+    }
+
+    fn for_path(&self, path: &str) -> Option<Duration> {
+        let route = self.routes.iter().find(|(prefix, _)| path.starts_with(prefix.as_str()));
+        route.map_or(self.default, |(_, limit)| *limit)
+    }
+}
+
+pub async fn enforce(State(timeouts): State<Timeouts>, request: Request, next: Next) -> Response {
+    let Some(limit) = timeouts.for_path(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let (method, path) = (request.method().clone(), request.uri().path().to_string());
+    let deadline = Instant::now() + limit;
+    match tokio::time::timeout_at(deadline, DEADLINE.scope(Some(deadline), next.run(request))).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!("{} {} timed out after {}s", method, path, limit.as_secs());
+            let body = serde_json::json!({
+                "error": "timeout",
+                "message": format!("The request did not complete within {}s", limit.as_secs()),
+                "timeout_secs": limit.as_secs(),
+            });
+            (StatusCode::GATEWAY_TIMEOUT, Json(body)).into_response()
+        }
+    }
+}
+
+// Pool hook for connections as they are opened or handed out.
+pub async fn limit_statements(conn: &mut PgConnection, _meta: PoolConnectionMetadata) -> sqlx::Result<()> {
+    let remaining = match DEADLINE.try_with(|deadline| *deadline) {
+        Ok(Some(deadline)) => (deadline.saturating_duration_since(Instant::now()) + STATEMENT_GRACE).as_millis(),
+        _ => 0,
+    };
+    sqlx::query!("SELECT set_config('statement_timeout', $1, false)", remaining.to_string())
+        .fetch_one(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{
+    audit::{Actor, Audit},
+    auth::{Principal, Role},
+    crypto,
+};
+
+// Personal API tokens. The secret is shown once at creation; the database
+// only keeps its SHA-256, plus a short prefix so tokens can be told apart.
+const TOKEN_PREFIX: &str = "ipt_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Read,
+    Write,
+    Admin,
+}
+
+impl Scope {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Admin => "admin",
+        }
+    }
+
+    fn role(self) -> Role {
+        match self {
+            Self::Read => Role::Viewer,
+            Self::Write => Role::Editor,
+            Self::Admin => Role::Admin,
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "read" => Some(Self::Read),
+            "write" => Some(Self::Write),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Token {
+    id: i32,
+    name: String,
+    scope: String,
+    // The first characters of the secret, e.g. "ipt_3fQz".
+    prefix: String,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedToken {
+    #[serde(flatten)]
+    token: Token,
+    secret: String,
+}
+
+fn internal(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+// Resolves a presented token to its owner and the role its scope grants,
+// capped at the owner's current role for local users. Returns None for
+// unknown, revoked and expired tokens, and for tokens of deleted users.
+pub async fn authenticate(pool: &PgPool, secret: &str) -> sqlx::Result<Option<(String, Role)>> {
+    if !secret.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+    let row = sqlx::query!(
+        "UPDATE api_tokens SET last_used_at = now() WHERE token_hash = $1 \
+         AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > now()) \
+         AND (NOT local_user OR EXISTS (SELECT 1 FROM users WHERE name = owner)) \
+         RETURNING owner, scope, (SELECT role FROM users WHERE name = owner) AS \"user_role?\"",
+        crypto::hash_token(secret),
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.and_then(|row| {
+        let role = Scope::parse(&row.scope)?.role();
+        let role = match row.user_role.as_deref().map(Role::parse) {
+            Some(user_role) => role.min(user_role?),
+            None => role,
+        };
+        Some((row.owner, role))
+    }))
+}
+
+// GET /me
+pub async fn me(principal: Option<Extension<Principal>>) -> Result<Json<Principal>, (StatusCode, String)> {
+    principal
+        .map(|Extension(principal)| Json(principal))
+        .ok_or((StatusCode::NOT_FOUND, "Authentication is not enabled".into()))
+}
+
+// GET /me/tokens
+// The caller's active tokens.
+pub async fn get_tokens(State(pool): State<PgPool>, actor: Actor) -> Result<Json<Vec<Token>>, (StatusCode, String)> {
+    let tokens = sqlx::query_as!(
+        Token,
+        "SELECT id, name, scope, prefix, created_at, expires_at, last_used_at FROM api_tokens \
+         WHERE owner = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > now()) \
+         ORDER BY created_at DESC",
+        actor.name,
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(internal)?;
+    Ok(Json(tokens))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateToken {
+    name: String,
+    scope: Scope,
+    // Never expires when unset.
+    #[serde(default)]
+    expires_in_days: Option<i64>,
+}
+
+// POST /me/tokens
+// A token cannot be given more than the caller's own role.
+pub async fn create_token(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    principal: Option<Extension<Principal>>,
+    actor: Actor,
+    Json(payload): Json<CreateToken>,
+) -> Result<Json<CreatedToken>, (StatusCode, String)> {
+    if payload.name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Name cannot be empty".into()));
+    }
+    if let Some(Extension(principal)) = &principal
+        && payload.scope.role() > principal.role
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("'{}' cannot create a token with the {} scope", principal.name, payload.scope.as_str()),
+        ));
+    }
+    let expires_at = match payload.expires_in_days {
+        Some(days) if !(1..=3650).contains(&days) => {
+            return Err((StatusCode::BAD_REQUEST, "expires_in_days must be between 1 and 3650".into()))
+        }
+        Some(days) => Some(Utc::now() + Duration::days(days)),
+        None => None,
+    };
+
+    let secret = format!("{}{}", TOKEN_PREFIX, crypto::random_token());
+    let token = sqlx::query_as!(
+        Token,
+        "INSERT INTO api_tokens (owner, name, scope, prefix, token_hash, expires_at, local_user) \
+         VALUES ($1, $2, $3, $4, $5, $6, EXISTS (SELECT 1 FROM users WHERE name = $1)) \
+         RETURNING id, name, scope, prefix, created_at, expires_at, last_used_at",
+        actor.name,
+        payload.name,
+        payload.scope.as_str(),
+        &secret[..TOKEN_PREFIX.len() + 4],
+        crypto::hash_token(&secret),
+        expires_at,
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(internal)?;
+
+    let detail = format!("{} ({})", token.name, token.scope);
+    audit.record(&actor, "token.create", None, Some(detail)).await;
+    Ok(Json(CreatedToken { token, secret }))
+}
+
+// DELETE /me/tokens/:id
+// Returns the revoked token.
+pub async fn revoke_token(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(id): Path<i32>,
+) -> Result<Json<Token>, (StatusCode, String)> {
+    let token = sqlx::query_as!(
+        Token,
+        "UPDATE api_tokens SET revoked_at = now() WHERE id = $1 AND owner = $2 AND revoked_at IS NULL \
+         RETURNING id, name, scope, prefix, created_at, expires_at, last_used_at",
+        id,
+        actor.name,
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(internal)?
+    .ok_or((StatusCode::NOT_FOUND, "Token not found".into()))?;
+
+    audit.record(&actor, "token.revoke", None, Some(token.name.clone())).await;
+    Ok(Json(token))
+}
@@ -0,0 +1,370 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::Utc;
+use http::StatusCode;
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{
+    audit::{Actor, Audit},
+    auth::Auth,
+    crypto::{self, Cipher},
+};
+
+// Time-based one-time passwords (RFC 6238: SHA-1, 6 digits, 30 second steps)
+// as a second factor for POST /login. Enrolling stores an encrypted secret;
+// it takes effect once confirmed with a first code, which also hands out
+// single-use recovery codes for a lost device.
+const STEP_SECS: i64 = 30;
+const DIGITS: u32 = 6;
+// Steps either side of now that are still accepted, for clock drift.
+const SKEW: i64 = 1;
+const RECOVERY_CODES: usize = 10;
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+fn internal(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut buffer = [0u8; 5];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let bits = buffer.iter().fold(0u64, |acc, byte| acc << 8 | u64::from(*byte));
+        for i in 0..(chunk.len() * 8).div_ceil(5) {
+            out.push(BASE32[(bits >> (35 - i * 5) & 31) as usize] as char);
+        }
+    }
+    out
+}
+
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let (mut bits, mut len, mut out) = (0u64, 0, Vec::new());
+    for c in encoded.bytes().filter(|c| *c != b'=') {
+        let value = BASE32.iter().position(|b| *b == c.to_ascii_uppercase())?;
+        bits = bits << 5 | value as u64;
+        len += 5;
+        if len >= 8 {
+            len -= 8;
+            out.push((bits >> len) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn code_at(key: &[u8], step: i64) -> Option<u32> {
+    let pkey = PKey::hmac(key).ok()?;
+    let mut signer = Signer::new(MessageDigest::sha1(), &pkey).ok()?;
+    let mac = signer.sign_oneshot_to_vec(&step.to_be_bytes()).ok()?;
+    let offset = usize::from(mac[mac.len() - 1] & 0x0f);
+    let truncated = u32::from_be_bytes(mac[offset..offset + 4].try_into().ok()?) & 0x7fff_ffff;
+    Some(truncated % 10u32.pow(DIGITS))
+}
+
+// The time step `code` is valid for, if any.
+fn matching_step(secret: &str, code: &str) -> Option<i64> {
+    let code: u32 = code.trim().parse().ok()?;
+    let key = base32_decode(secret)?;
+    let now = Utc::now().timestamp() / STEP_SECS;
+    (now - SKEW..=now + SKEW).find(|step| code_at(&key, *step) == Some(code))
+}
+
+fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let mut uri = url::Url::parse("otpauth://totp/").expect("a valid base URI");
+    uri.path_segments_mut()
+        .expect("otpauth URIs have a path")
+        .push(&format!("{}:{}", issuer, account));
+    uri.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", issuer)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &STEP_SECS.to_string());
+    uri.to_string()
+}
+
+// Recovery codes are compared without dashes or case.
+fn normalize_recovery(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_lowercase()
+}
+
+fn recovery_codes() -> (Vec<String>, Vec<String>) {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            openssl::rand::rand_bytes(&mut bytes).expect("the system RNG is available");
+            let code = base32_encode(&bytes).to_lowercase();
+            let code = format!("{}-{}", &code[..4], &code[4..]);
+            let hash = crypto::hash_token(&normalize_recovery(&code));
+            (code, hash)
+        })
+        .unzip()
+}
+
+fn require_cipher(cipher: &Option<Cipher>) -> Result<&Cipher, (StatusCode, String)> {
+    cipher.as_ref().ok_or((
+        StatusCode::BAD_REQUEST,
+        "Two-factor authentication requires an encryption key (INDEXPAGE_SECRET_KEY)".into(),
+    ))
+}
+
+// Whether the user has confirmed two-factor authentication.
+pub async fn enabled(pool: &PgPool, name: &str) -> Result<bool, (StatusCode, String)> {
+    let enabled = sqlx::query_scalar!("SELECT totp_enabled FROM users WHERE name = $1", name)
+        .fetch_optional(pool)
+        .await
+        .map_err(internal)?;
+    Ok(enabled.unwrap_or(false))
+}
+
+// Checks a code from the authenticator app, or else a recovery code, which
+// is used up. Each app code is only accepted once.
+pub async fn verify(
+    pool: &PgPool,
+    cipher: &Option<Cipher>,
+    name: &str,
+    code: &str,
+) -> Result<bool, (StatusCode, String)> {
+    let Some(stored) = sqlx::query_scalar!("SELECT totp_secret FROM users WHERE name = $1", name)
+        .fetch_optional(pool)
+        .await
+        .map_err(internal)?
+        .flatten()
+    else {
+        return Ok(false);
+    };
+    let secret = require_cipher(cipher)?
+        .decrypt(&stored)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if let Some(step) = matching_step(&secret, code) {
+        let fresh = sqlx::query!(
+            "UPDATE users SET totp_last_step = $2 WHERE name = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)",
+            name,
+            step,
+        )
+        .execute(pool)
+        .await
+        .map_err(internal)?
+        .rows_affected();
+        return Ok(fresh > 0);
+    }
+    let used = sqlx::query!(
+        "UPDATE users SET totp_recovery = array_remove(totp_recovery, $2) WHERE name = $1 AND $2 = ANY(totp_recovery)",
+        name,
+        crypto::hash_token(&normalize_recovery(code)),
+    )
+    .execute(pool)
+    .await
+    .map_err(internal)?
+    .rows_affected();
+    Ok(used > 0)
+}
+
+#[derive(Debug, Serialize)]
+pub struct Enrollment {
+    secret: String,
+    // For a QR code; authenticator apps also accept `secret` typed in.
+    uri: String,
+}
+
+// POST /me/totp
+// Starts (or restarts) enrollment; confirm with POST /me/totp/confirm.
+pub async fn enroll(
+    State(pool): State<PgPool>,
+    State(cipher): State<Option<Cipher>>,
+    State(auth): State<Auth>,
+    actor: Actor,
+) -> Result<Json<Enrollment>, (StatusCode, String)> {
+    let cipher = require_cipher(&cipher)?;
+    let mut key = [0u8; 20];
+    openssl::rand::rand_bytes(&mut key).expect("the system RNG is available");
+    let secret = base32_encode(&key);
+    let updated = sqlx::query!(
+        "UPDATE users SET totp_secret = $2, totp_last_step = NULL WHERE name = $1 AND NOT totp_enabled",
+        actor.name,
+        cipher.encrypt(&secret),
+    )
+    .execute(&pool)
+    .await
+    .map_err(internal)?
+    .rows_affected();
+    if updated == 0 {
+        return Err(match enabled(&pool, &actor.name).await? {
+            true => (StatusCode::CONFLICT, "Two-factor authentication is already enabled".into()),
+            false => (StatusCode::NOT_FOUND, "Only local users can enable two-factor authentication".into()),
+        });
+    }
+    let uri = otpauth_uri(auth.totp_issuer(), &actor.name, &secret);
+    Ok(Json(Enrollment { secret, uri }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Code {
+    code: String,
+}
+
+// What DELETE returns once a user's second factor is gone.
+#[derive(Debug, Serialize)]
+pub struct TotpState {
+    user: String,
+    enabled: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodes {
+    // Shown once; each signs in a single time in place of an app code.
+    recovery_codes: Vec<String>,
+}
+
+async fn replace_recovery_codes(pool: &PgPool, name: &str, enable: bool) -> Result<RecoveryCodes, (StatusCode, String)> {
+    let (codes, hashes) = recovery_codes();
+    sqlx::query!(
+        "UPDATE users SET totp_recovery = $2, totp_enabled = totp_enabled OR $3 WHERE name = $1",
+        name,
+        &hashes,
+        enable,
+    )
+    .execute(pool)
+    .await
+    .map_err(internal)?;
+    Ok(RecoveryCodes { recovery_codes: codes })
+}
+
+// POST /me/totp/confirm
+pub async fn confirm(
+    State(pool): State<PgPool>,
+    State(cipher): State<Option<Cipher>>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Json(payload): Json<Code>,
+) -> Result<Json<RecoveryCodes>, (StatusCode, String)> {
+    if enabled(&pool, &actor.name).await? {
+        return Err((StatusCode::CONFLICT, "Two-factor authentication is already enabled".into()));
+    }
+    if !verify(&pool, &cipher, &actor.name, &payload.code).await? {
+        return Err((StatusCode::BAD_REQUEST, "Invalid two-factor code".into()));
+    }
+    let codes = replace_recovery_codes(&pool, &actor.name, true).await?;
+    audit.record(&actor, "totp.enable", None, None).await;
+    Ok(Json(codes))
+}
+
+// POST /me/totp/recovery-codes
+// Replaces the recovery codes; needs a current code.
+pub async fn regenerate(
+    State(pool): State<PgPool>,
+    State(cipher): State<Option<Cipher>>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Json(payload): Json<Code>,
+) -> Result<Json<RecoveryCodes>, (StatusCode, String)> {
+    if !enabled(&pool, &actor.name).await? {
+        return Err((StatusCode::NOT_FOUND, "Two-factor authentication is not enabled".into()));
+    }
+    if !verify(&pool, &cipher, &actor.name, &payload.code).await? {
+        return Err((StatusCode::BAD_REQUEST, "Invalid two-factor code".into()));
+    }
+    let codes = replace_recovery_codes(&pool, &actor.name, false).await?;
+    audit.record(&actor, "totp.recovery_codes", None, None).await;
+    Ok(Json(codes))
+}
+
+async fn disable_for(pool: &PgPool, name: &str) -> Result<u64, (StatusCode, String)> {
+    Ok(sqlx::query!(
+        "UPDATE users SET totp_secret = NULL, totp_enabled = false, totp_last_step = NULL, totp_recovery = '{}' \
+         WHERE name = $1 AND totp_secret IS NOT NULL",
+        name,
+    )
+    .execute(pool)
+    .await
+    .map_err(internal)?
+    .rows_affected())
+}
+
+// DELETE /me/totp
+// Needs a current code or a recovery code.
+pub async fn disable(
+    State(pool): State<PgPool>,
+    State(cipher): State<Option<Cipher>>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Json(payload): Json<Code>,
+) -> Result<Json<TotpState>, (StatusCode, String)> {
+    if !enabled(&pool, &actor.name).await? {
+        return Err((StatusCode::NOT_FOUND, "Two-factor authentication is not enabled".into()));
+    }
+    if !verify(&pool, &cipher, &actor.name, &payload.code).await? {
+        return Err((StatusCode::BAD_REQUEST, "Invalid two-factor code".into()));
+    }
+    disable_for(&pool, &actor.name).await?;
+    audit.record(&actor, "totp.disable", None, None).await;
+    Ok(Json(TotpState { user: actor.name, enabled: false }))
+}
+
+// DELETE /admin/users/:name/totp
+// For users who lost both their device and their recovery codes.
+pub async fn reset(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(name): Path<String>,
+) -> Result<Json<TotpState>, (StatusCode, String)> {
+    if disable_for(&pool, &name).await? == 0 {
+        return Err((StatusCode::NOT_FOUND, "User not found or two-factor authentication not set up".into()));
+    }
+    audit.record(&actor, "totp.reset", None, Some(name.clone())).await;
+    Ok(Json(TotpState { user: name, enabled: false }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238's SHA-1 seed.
+    const SEED: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn base32_round_trips() {
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_decode("mzxw6ytboi======").unwrap(), b"foobar");
+        assert_eq!(base32_decode(&base32_encode(SEED)).unwrap(), SEED);
+        assert!(base32_decode("MZXW6YTB0I").is_none());
+    }
+
+    #[test]
+    fn codes_match_the_rfc_vectors() {
+        // Times 59, 1111111109 and 1234567890, truncated to 6 digits.
+        assert_eq!(code_at(SEED, 59 / STEP_SECS), Some(287082));
+        assert_eq!(code_at(SEED, 1111111109 / STEP_SECS), Some(81804));
+        assert_eq!(code_at(SEED, 1234567890 / STEP_SECS), Some(5924));
+    }
+
+    #[test]
+    fn accepts_codes_from_neighbouring_steps() {
+        let secret = base32_encode(SEED);
+        let now = Utc::now().timestamp() / STEP_SECS;
+        let code = |step: i64| format!("{:06}", code_at(SEED, step).unwrap());
+        assert_eq!(matching_step(&secret, &code(now)), Some(now));
+        assert_eq!(matching_step(&secret, &format!(" {} ", code(now - 1))), Some(now - 1));
+        assert!(matching_step(&secret, "12345a").is_none());
+    }
+
+    #[test]
+    fn recovery_codes_are_hashed_as_typed_loosely() {
+        assert_eq!(normalize_recovery(" ABCD-efgh "), "abcdefgh");
+        let (codes, hashes) = recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        assert!(codes.iter().all(|code| code.len() == 9 && code.as_bytes()[4] == b'-'));
+        assert_eq!(hashes[0], crypto::hash_token(&normalize_recovery(&codes[0].to_uppercase())));
+    }
+
+    #[test]
+    fn otpauth_uri_names_issuer_and_account() {
+        let uri = otpauth_uri("indexpage", "bob", "MZXW6YTBOI");
+        assert!(uri.starts_with("otpauth://totp/indexpage:bob?secret=MZXW6YTBOI&issuer=indexpage"));
+    }
+}
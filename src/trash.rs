@@ -0,0 +1,146 @@
+use axum::{extract::{Path, State}, Json};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::time::Duration;
+
+use crate::{
+    audit::{Actor, Audit},
+    config::TrashConfig,
+    repository::ServiceRepository,
+    scheduler::{Schedule, Scheduler},
+};
+
+// Resolves the most recently trashed service whose name matches $1.
+const TRASHED_ID_BY_NAME: &str = "(SELECT id FROM services WHERE lower(name) = lower($1) \
+     AND deleted_at IS NOT NULL ORDER BY deleted_at DESC LIMIT 1)";
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TrashedService {
+    id: i32,
+    name: String,
+    link: String,
+    deleted_at: DateTime<Utc>,
+    purge_at: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+pub struct Trash {
+    pool: PgPool,
+    ttl_days: i32,
+}
+
+impl Trash {
+    pub fn new(pool: PgPool, config: &TrashConfig) -> Self {
+        Self { pool, ttl_days: config.ttl_days.max(0) }
+    }
+
+    // Permanently removes services that have been in the trash past the TTL.
+    pub async fn purge_expired(&self) -> sqlx::Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM services WHERE deleted_at < now() - make_interval(days => $1)",
+            self.ttl_days,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+pub fn register(scheduler: &Scheduler, trash: Trash, config: &TrashConfig, audit: Audit) -> anyhow::Result<()> {
+    let interval = Schedule::Every(Duration::from_secs(config.purge_interval_secs.max(60)));
+    scheduler.register("trash_purge", interval, move || {
+        let (trash, audit) = (trash.clone(), audit.clone());
+        async move {
+            let purged = trash.purge_expired().await?;
+            if purged > 0 {
+                tracing::info!("purged {} expired services from trash", purged);
+                audit
+                    .record(&Actor::system(), "trash.expire", None, Some(format!("{} services", purged)))
+                    .await;
+            }
+            Ok(())
+        }
+    })
+}
+
+// GET /trash
+pub async fn get_trash(State(trash): State<Trash>) -> Json<Vec<TrashedService>> {
+    let services = sqlx::query_as!(
+        TrashedService,
+        r#"
+        SELECT id, name, link, deleted_at AS "deleted_at!", deleted_at + make_interval(days => $1) AS "purge_at!"
+        FROM services WHERE deleted_at IS NOT NULL
+        ORDER BY deleted_at DESC
+        "#,
+        trash.ttl_days,
+    )
+    .fetch_all(&trash.pool)
+    .await
+    .unwrap_or_else(|_| vec![]);
+    Json(services)
+}
+
+// POST /trash/:name/restore
+// 409 if a live service has taken the name since.
+pub async fn restore<R: ServiceRepository>(
+    State(services): State<R>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(name): Path<String>,
+) -> Result<String, (StatusCode, String)> {
+    let restored = services.restore(&name).await?.ok_or((StatusCode::NOT_FOUND, "Service not in trash".into()))?;
+    audit.record(&actor, "trash.restore", Some(&restored), None).await;
+    Ok(format!("Restored '{}'", name))
+}
+
+// DELETE /trash/:name
+// Returns the purged service.
+pub async fn purge_one(
+    State(trash): State<Trash>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(name): Path<String>,
+) -> Result<Json<TrashedService>, (StatusCode, String)> {
+    let result = sqlx::query_as::<_, TrashedService>(&format!(
+        "DELETE FROM services WHERE id = {} \
+         RETURNING id, name, link, deleted_at, deleted_at + make_interval(days => $2) AS purge_at",
+        TRASHED_ID_BY_NAME
+    ))
+    .bind(&name)
+    .bind(trash.ttl_days)
+    .fetch_optional(&trash.pool)
+    .await;
+
+    match result {
+        Ok(Some(purged)) => {
+            audit.record(&actor, "trash.purge", Some(&purged.name), None).await;
+            Ok(Json(purged))
+        }
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Service not in trash".into())),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+// DELETE /trash
+// Returns the purged services.
+pub async fn empty(
+    State(trash): State<Trash>,
+    State(audit): State<Audit>,
+    actor: Actor,
+) -> Result<Json<Vec<TrashedService>>, (StatusCode, String)> {
+    let purged = sqlx::query_as!(
+        TrashedService,
+        r#"
+        DELETE FROM services WHERE deleted_at IS NOT NULL
+        RETURNING id, name, link, deleted_at AS "deleted_at!", deleted_at + make_interval(days => $1) AS "purge_at!"
+        "#,
+        trash.ttl_days,
+    )
+    .fetch_all(&trash.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    audit.record(&actor, "trash.empty", None, Some(format!("{} services", purged.len()))).await;
+    Ok(Json(purged))
+}
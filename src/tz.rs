@@ -0,0 +1,112 @@
+use axum::{
+    extract::{FromRef, FromRequestParts, State},
+    Json,
+};
+use chrono_tz::Tz;
+use http::{request::Parts, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::convert::Infallible;
+
+use crate::{
+    audit::{Actor, Audit},
+    auth::Principal,
+};
+
+// Cron schedules and the timestamps formatted for people (the status page,
+// digests, CSV exports) use the instance timezone, `timezone` in the config,
+// UTC by default. Local users may pick their own with POST /me/timezone for
+// what they are shown. JSON timestamps stay RFC 3339 in UTC.
+
+// The timezone to show the caller: its own setting, else the instance's.
+#[derive(Debug, Clone, Copy)]
+pub struct Timezone(pub Tz);
+
+impl<S> FromRequestParts<S> for Timezone
+where
+    S: Send + Sync,
+    PgPool: FromRef<S>,
+    Tz: FromRef<S>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let instance = Tz::from_ref(state);
+        let Some(principal) = parts.extensions.get::<Principal>() else { return Ok(Self(instance)) };
+        let own = user_timezone(&PgPool::from_ref(state), &principal.name).await.unwrap_or_else(|e| {
+            tracing::warn!("reading the timezone of '{}': {}", principal.name, e);
+            None
+        });
+        Ok(Self(own.unwrap_or(instance)))
+    }
+}
+
+// None for users without a setting (or one no longer known) and for names
+// that aren't local users.
+async fn user_timezone(pool: &PgPool, name: &str) -> sqlx::Result<Option<Tz>> {
+    let timezone = sqlx::query_scalar!("SELECT timezone FROM users WHERE name = $1", name)
+        .fetch_optional(pool)
+        .await?
+        .flatten();
+    Ok(timezone.and_then(|timezone| timezone.parse().ok()))
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimezoneSetting {
+    // The caller's own setting, if any.
+    timezone: Option<String>,
+    // What is used for the caller.
+    effective: String,
+}
+
+// GET /me/timezone
+pub async fn get_timezone(
+    State(pool): State<PgPool>,
+    State(instance): State<Tz>,
+    actor: Actor,
+) -> Result<Json<TimezoneSetting>, (StatusCode, String)> {
+    let own = user_timezone(&pool, &actor.name)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(TimezoneSetting {
+        timezone: own.map(|tz| tz.name().to_string()),
+        effective: own.unwrap_or(instance).name().to_string(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetTimezone {
+    // An IANA name such as "Europe/Berlin"; null goes back to the instance's.
+    timezone: Option<String>,
+}
+
+// POST /me/timezone
+pub async fn set_timezone(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    State(instance): State<Tz>,
+    actor: Actor,
+    Json(payload): Json<SetTimezone>,
+) -> Result<Json<TimezoneSetting>, (StatusCode, String)> {
+    let own = match payload.timezone.as_deref().map(str::trim) {
+        Some(name) => Some(
+            name.parse::<Tz>()
+                .map_err(|_| (StatusCode::BAD_REQUEST, format!("Unknown timezone '{}' (e.g. Europe/Berlin)", name)))?,
+        ),
+        None => None,
+    };
+    sqlx::query_scalar!(
+        "UPDATE users SET timezone = $2 WHERE name = $1 RETURNING name",
+        actor.name,
+        own.map(|tz| tz.name()),
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Only local users can set a timezone".into()))?;
+    audit.record(&actor, "user.timezone", None, own.map(|tz| tz.name().to_string())).await;
+    Ok(Json(TimezoneSetting {
+        timezone: own.map(|tz| tz.name().to_string()),
+        effective: own.unwrap_or(instance).name().to_string(),
+    }))
+}
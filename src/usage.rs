@@ -0,0 +1,173 @@
+use axum::{
+    extract::{Query, State},
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use crate::{
+    audit::{Actor, Audit},
+    auth::Principal,
+    categories::Visibility,
+    links::LinkContext,
+    services::{Service, SERVICE_ID_BY_NAME},
+    tags::TAGS_COLUMN,
+};
+
+// Per-user "Recently used" and "Most used" rows: every GET /go/:name by a
+// signed-in user counts a click on the service, one row per user and
+// service with the count and the time of the last one. Users can turn this
+// off with PUT /me/usage, which also forgets what was recorded.
+const DEFAULT_LIMIT: i64 = 10;
+const MAX_LIMIT: i64 = 50;
+
+fn internal(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+// Called by GET /go/:name; in the background, so the redirect isn't held up.
+pub fn record_click(pool: &PgPool, principal: Option<&Principal>, service: &str) {
+    let Some(principal) = principal else { return };
+    let (pool, user, service) = (pool.clone(), principal.name.clone(), service.to_string());
+    tokio::spawn(async move {
+        let recorded = sqlx::query(&format!(
+            "INSERT INTO service_usage (principal, service_id) \
+             SELECT $2, s.id FROM services s WHERE s.id = {} \
+             AND NOT EXISTS (SELECT 1 FROM usage_opt_outs WHERE principal = $2) \
+             ON CONFLICT (principal, service_id) DO UPDATE \
+             SET clicks = service_usage.clicks + 1, last_used_at = now()",
+            SERVICE_ID_BY_NAME
+        ))
+        .bind(&service)
+        .bind(&user)
+        .execute(&pool)
+        .await;
+        if let Err(e) = recorded {
+            tracing::warn!("recording a click on '{}' by '{}': {}", service, user, e);
+        }
+    });
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct UsedService {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    service: Service,
+    clicks: i64,
+    last_used_at: DateTime<Utc>,
+}
+
+async fn used(
+    pool: &PgPool,
+    actor: &Actor,
+    visibility: &Visibility,
+    links: &LinkContext,
+    order: &str,
+    limit: Option<i64>,
+) -> Result<Json<Vec<UsedService>>, (StatusCode, String)> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let mut sql = QueryBuilder::<Postgres>::new("SELECT services.*, ");
+    sql.push(TAGS_COLUMN);
+    sql.push(
+        ", service_usage.clicks, service_usage.last_used_at FROM service_usage \
+         JOIN services ON services.id = service_usage.service_id \
+         LEFT JOIN categories ON categories.id = services.category_id \
+         WHERE services.deleted_at IS NULL AND services.archived_at IS NULL AND service_usage.principal = ",
+    )
+    .push_bind(&actor.name);
+    visibility.restrict_services(&mut sql);
+    sql.push(" ORDER BY ").push(order).push(" LIMIT ").push_bind(limit);
+    let mut services = sql.build_query_as::<UsedService>().fetch_all(pool).await.map_err(internal)?;
+    for used in &mut services {
+        used.service.expand_links(links);
+    }
+    Ok(Json(services))
+}
+
+// GET /me/recent?limit=
+// The caller's services, last used first.
+pub async fn recent(
+    State(pool): State<PgPool>,
+    actor: Actor,
+    visibility: Visibility,
+    links: LinkContext,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<Vec<UsedService>>, (StatusCode, String)> {
+    used(&pool, &actor, &visibility, &links, "service_usage.last_used_at DESC", query.limit).await
+}
+
+// GET /me/top?limit=
+// The caller's services, most clicked first.
+pub async fn top(
+    State(pool): State<PgPool>,
+    actor: Actor,
+    visibility: Visibility,
+    links: LinkContext,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<Vec<UsedService>>, (StatusCode, String)> {
+    let order = "service_usage.clicks DESC, service_usage.last_used_at DESC";
+    used(&pool, &actor, &visibility, &links, order, query.limit).await
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsageSetting {
+    tracking: bool,
+}
+
+// GET /me/usage
+pub async fn get_setting(
+    State(pool): State<PgPool>,
+    actor: Actor,
+) -> Result<Json<UsageSetting>, (StatusCode, String)> {
+    let opted_out = sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM usage_opt_outs WHERE principal = $1)")
+        .bind(&actor.name)
+        .fetch_one(&pool)
+        .await
+        .map_err(internal)?;
+    Ok(Json(UsageSetting { tracking: !opted_out }))
+}
+
+// PUT /me/usage
+// Body: {"tracking": false} stops recording clicks and deletes those
+// recorded so far; {"tracking": true} starts again.
+pub async fn set_setting(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    principal: Option<Extension<Principal>>,
+    Json(setting): Json<UsageSetting>,
+) -> Result<Json<UsageSetting>, (StatusCode, String)> {
+    if principal.is_none() {
+        return Err((StatusCode::NOT_FOUND, "Authentication is not enabled".into()));
+    }
+    let mut tx = pool.begin().await.map_err(internal)?;
+    if setting.tracking {
+        sqlx::query("DELETE FROM usage_opt_outs WHERE principal = $1")
+            .bind(&actor.name)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal)?;
+    } else {
+        sqlx::query("INSERT INTO usage_opt_outs (principal) VALUES ($1) ON CONFLICT DO NOTHING")
+            .bind(&actor.name)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal)?;
+        sqlx::query("DELETE FROM service_usage WHERE principal = $1")
+            .bind(&actor.name)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal)?;
+    }
+    tx.commit().await.map_err(internal)?;
+    let detail = if setting.tracking { "on" } else { "off" };
+    audit.record(&actor, "user.usage_tracking", None, Some(detail.into())).await;
+    Ok(Json(setting))
+}
@@ -0,0 +1,318 @@
+use argon2::{
+    password_hash::{PasswordHasher, PasswordVerifier},
+    Argon2,
+};
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::LazyLock;
+
+use crate::{
+    audit::{Actor, Audit},
+    auth::{Auth, Role},
+    crypto,
+    email::{self, Mailer},
+    lockout,
+};
+
+// Local accounts that sign in with a password (see sessions.rs). Names share
+// one namespace with certificate identities and token owners.
+const MIN_PASSWORD_LEN: usize = 8;
+const RESET_HOURS: i64 = 1;
+
+#[derive(Debug, Serialize)]
+pub struct User {
+    name: String,
+    role: String,
+    email: Option<String>,
+    totp_enabled: bool,
+    created_at: DateTime<Utc>,
+}
+
+fn internal(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+pub fn hash_password(password: &str) -> Result<String, (StatusCode, String)> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Password must be at least {} characters", MIN_PASSWORD_LEN),
+        ));
+    }
+    Argon2::default()
+        .hash_password(password.as_bytes())
+        .map(|hash| hash.to_string())
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+// Checks a password against the stored hash. Unknown users are checked
+// against a throwaway hash so both cases take about as long.
+pub fn verify_password(password: &str, hash: Option<&str>) -> bool {
+    static DUMMY: LazyLock<String> = LazyLock::new(|| {
+        Argon2::default().hash_password(b"dummy password").map(|hash| hash.to_string()).unwrap_or_default()
+    });
+    let verified = Argon2::default().verify_password(password.as_bytes(), hash.unwrap_or(&DUMMY)).is_ok();
+    verified && hash.is_some()
+}
+
+// GET /admin/users
+pub async fn get_users(State(pool): State<PgPool>) -> Result<Json<Vec<User>>, (StatusCode, String)> {
+    let users = sqlx::query_as!(User, "SELECT name, role, email, totp_enabled, created_at FROM users ORDER BY name")
+        .fetch_all(&pool)
+        .await
+        .map_err(internal)?;
+    Ok(Json(users))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUser {
+    name: String,
+    password: String,
+    role: Role,
+    #[serde(default)]
+    email: Option<String>,
+}
+
+fn check_email(address: Option<&str>) -> Result<(), (StatusCode, String)> {
+    match address {
+        Some(address) if !email::is_valid_address(address) => {
+            Err((StatusCode::BAD_REQUEST, format!("Invalid email address '{}'", address)))
+        }
+        _ => Ok(()),
+    }
+}
+
+// POST /admin/users
+pub async fn create_user(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Json(payload): Json<CreateUser>,
+) -> Result<Json<User>, (StatusCode, String)> {
+    if payload.name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Name cannot be empty".into()));
+    }
+    check_email(payload.email.as_deref())?;
+    let hash = hash_password(&payload.password)?;
+    let user = sqlx::query_as!(
+        User,
+        "INSERT INTO users (name, role, password_hash, email) VALUES ($1, $2, $3, $4) \
+         RETURNING name, role, email, totp_enabled, created_at",
+        payload.name,
+        payload.role.as_str(),
+        hash,
+        payload.email,
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to insert: {}", e)))?;
+
+    audit.record(&actor, "user.create", None, Some(format!("{} ({})", user.name, user.role))).await;
+    Ok(Json(user))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetPassword {
+    password: String,
+}
+
+// POST /admin/users/:name/password
+// Also signs the user out everywhere.
+pub async fn set_password(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(name): Path<String>,
+    Json(payload): Json<SetPassword>,
+) -> Result<String, (StatusCode, String)> {
+    let hash = hash_password(&payload.password)?;
+    let mut tx = pool.begin().await.map_err(internal)?;
+    let updated = sqlx::query!("UPDATE users SET password_hash = $2 WHERE name = $1", name, hash)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal)?
+        .rows_affected();
+    if updated == 0 {
+        return Err((StatusCode::NOT_FOUND, "User not found".into()));
+    }
+    sqlx::query!("UPDATE sessions SET revoked_at = now() WHERE user_name = $1 AND revoked_at IS NULL", name)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal)?;
+    tx.commit().await.map_err(internal)?;
+
+    audit.record(&actor, "user.password", None, Some(name.clone())).await;
+    Ok(format!("Changed password for '{}'", name))
+}
+
+// DELETE /admin/users/:name
+// Also revokes the user's API tokens.
+pub async fn delete_user(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(name): Path<String>,
+) -> Result<Json<User>, (StatusCode, String)> {
+    let mut tx = pool.begin().await.map_err(internal)?;
+    let user = sqlx::query_as!(
+        User,
+        "DELETE FROM users WHERE name = $1 RETURNING name, role, email, totp_enabled, created_at",
+        name
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal)?
+    .ok_or((StatusCode::NOT_FOUND, "User not found".into()))?;
+    sqlx::query!("UPDATE api_tokens SET revoked_at = now() WHERE owner = $1 AND revoked_at IS NULL", name)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal)?;
+    tx.commit().await.map_err(internal)?;
+    audit.record(&actor, "user.delete", None, Some(name)).await;
+    Ok(Json(user))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetEmail {
+    email: Option<String>,
+}
+
+// POST /me/email
+// Sets (or with null clears) the caller's address for password resets.
+pub async fn set_email(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Json(payload): Json<SetEmail>,
+) -> Result<Json<User>, (StatusCode, String)> {
+    check_email(payload.email.as_deref())?;
+    let user = sqlx::query_as!(
+        User,
+        "UPDATE users SET email = $2 WHERE name = $1 RETURNING name, role, email, totp_enabled, created_at",
+        actor.name,
+        payload.email,
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(internal)?
+    .ok_or((StatusCode::NOT_FOUND, "Only local users have an email address".into()))?;
+    audit.record(&actor, "user.email", None, None).await;
+    Ok(Json(user))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequestReset {
+    // The user name or email address.
+    name: String,
+}
+
+// POST /password-reset
+// Emails a single-use reset token. Answers the same whether or not the
+// account exists, and sends in the background, so it reveals nothing.
+// Requests count towards a lockout per client IP and per name, as failed
+// sign-ins do, so nobody can flood an inbox.
+pub async fn request_reset(
+    State(pool): State<PgPool>,
+    State(mailer): State<Option<Mailer>>,
+    State(auth): State<Auth>,
+    actor: Actor,
+    Json(payload): Json<RequestReset>,
+) -> Result<Response, (StatusCode, String)> {
+    let mailer = mailer.ok_or((StatusCode::NOT_FOUND, "Email is not configured".into()))?;
+    let ip_key = lockout::reset_key(&lockout::ip_key(actor.ip.as_deref()));
+    let account_key = lockout::reset_key(&lockout::account_key(payload.name.trim()));
+    let keys = [ip_key.as_str(), account_key.as_str()];
+    if let Some(wait) = auth.lockout().locked(&keys) {
+        return Ok(lockout::too_many(wait));
+    }
+    for (key, duration) in auth.lockout().fail(&keys) {
+        tracing::warn!("locking out {} for {}s after repeated password reset requests", key, duration.as_secs());
+    }
+
+    let sent = "If the account has an email address, a reset link was sent to it".into_response();
+    let user = sqlx::query!(
+        r#"SELECT name, email AS "email!" FROM users WHERE email IS NOT NULL AND (name = $1 OR lower(email) = lower($1))"#,
+        payload.name.trim(),
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(internal)?;
+    let Some(user) = user else { return Ok(sent) };
+    let (name, address) = (user.name, user.email);
+
+    // A new request replaces any earlier one.
+    let token = crypto::random_token();
+    let mut tx = pool.begin().await.map_err(internal)?;
+    sqlx::query!("DELETE FROM password_resets WHERE user_name = $1", name)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal)?;
+    sqlx::query!(
+        "INSERT INTO password_resets (user_name, token_hash, expires_at) VALUES ($1, $2, $3)",
+        name,
+        crypto::hash_token(&token),
+        Utc::now() + Duration::hours(RESET_HOURS),
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(internal)?;
+    tx.commit().await.map_err(internal)?;
+
+    let path = format!("/password-reset/{}", token);
+    let body = format!(
+        "A password reset was requested for '{}'.\n\n\
+         To choose a new password, POST {{\"password\": \"...\"}} to {} within {} hour.\n\n\
+         If this wasn't you, ignore this email; your password is unchanged.\n",
+        name,
+        mailer.link(&path).unwrap_or(path),
+        RESET_HOURS
+    );
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(&address, "[indexpage] Password reset", body).await {
+            tracing::warn!("password reset email for {} failed: {}", name, e);
+        }
+    });
+    Ok(sent)
+}
+
+// POST /password-reset/:token
+// Sets the new password and signs the user out everywhere.
+pub async fn reset_password(
+    State(pool): State<PgPool>,
+    State(audit): State<Audit>,
+    actor: Actor,
+    Path(token): Path<String>,
+    Json(payload): Json<SetPassword>,
+) -> Result<String, (StatusCode, String)> {
+    let hash = hash_password(&payload.password)?;
+    let mut tx = pool.begin().await.map_err(internal)?;
+    let name = sqlx::query_scalar!(
+        "UPDATE password_resets SET used_at = now() WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now() \
+         RETURNING user_name",
+        crypto::hash_token(&token),
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal)?
+    .ok_or((StatusCode::NOT_FOUND, "Reset link is invalid or has expired".into()))?;
+    sqlx::query!("UPDATE users SET password_hash = $2 WHERE name = $1", name, hash)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal)?;
+    sqlx::query!("UPDATE sessions SET revoked_at = now() WHERE user_name = $1 AND revoked_at IS NULL", name)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal)?;
+    tx.commit().await.map_err(internal)?;
+
+    let actor = Actor { name: name.clone(), ip: actor.ip };
+    audit.record(&actor, "user.password_reset", None, None).await;
+    Ok(format!("Changed password for '{}'", name))
+}
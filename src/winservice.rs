@@ -0,0 +1,142 @@
+use std::{ffi::OsString, sync::OnceLock, time::Duration};
+use tokio::{runtime::Handle, sync::oneshot};
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode, ServiceInfo,
+        ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult},
+    service_dispatcher,
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+
+// Running as a native Windows service:
+//   indexpage --service install     registers this executable (auto start)
+//   indexpage --service uninstall   stops and removes it
+//   indexpage --service             what the service manager runs
+// The service starts in System32, so it switches to the executable's
+// directory first: indexpage.toml and .env are read from next to it.
+const SERVICE_NAME: &str = "indexpage";
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+// The service manager calls back on a thread of its own; the server runs on
+// the main runtime.
+static RUNTIME: OnceLock<Handle> = OnceLock::new();
+
+define_windows_service!(ffi_service_main, service_main);
+
+pub async fn main(args: &[String]) -> anyhow::Result<()> {
+    match args.first().map(String::as_str) {
+        None => {
+            let _ = RUNTIME.set(Handle::current());
+            // Blocks until the service has stopped.
+            tokio::task::spawn_blocking(|| service_dispatcher::start(SERVICE_NAME, ffi_service_main)).await??;
+            Ok(())
+        }
+        Some("install") => install(),
+        Some("uninstall") => uninstall(),
+        Some(other) => anyhow::bail!("unknown --service command '{}' (expected install or uninstall)", other),
+    }
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        tracing::error!("service failed: {}", e);
+    }
+}
+
+fn run_service() -> anyhow::Result<()> {
+    let (stop_tx, stop_rx) = oneshot::channel();
+    let mut stop_tx = Some(stop_tx);
+    let status = service_control_handler::register(SERVICE_NAME, move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            if let Some(stop_tx) = stop_tx.take() {
+                let _ = stop_tx.send(());
+            }
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+    let report = |current_state, exit_code| {
+        let controls_accepted = match current_state {
+            ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            _ => ServiceControlAccept::empty(),
+        };
+        status.set_service_status(ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state,
+            controls_accepted,
+            exit_code,
+            checkpoint: 0,
+            wait_hint: Duration::from_secs(10),
+            process_id: None,
+        })
+    };
+    report(ServiceState::StartPending, ServiceExitCode::Win32(0))?;
+
+    let result = (|| {
+        let exe = std::env::current_exe()?;
+        if let Some(dir) = exe.parent() {
+            std::env::set_current_dir(dir)?;
+        }
+        dotenvy::dotenv().ok();
+        let runtime = RUNTIME.get().ok_or_else(|| anyhow::anyhow!("not started through `indexpage --service`"))?;
+        report(ServiceState::Running, ServiceExitCode::Win32(0))?;
+        // Stopping drops the server: open connections are closed as the
+        // process exits.
+        runtime.block_on(async {
+            tokio::select! {
+                result = crate::run() => result,
+                _ = stop_rx => Ok(()),
+            }
+        })
+    })();
+
+    report(ServiceState::StopPending, ServiceExitCode::Win32(0))?;
+    let exit_code = match &result {
+        Ok(()) => ServiceExitCode::Win32(0),
+        Err(_) => ServiceExitCode::ServiceSpecific(1),
+    };
+    report(ServiceState::Stopped, exit_code)?;
+    result
+}
+
+fn install() -> anyhow::Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )?;
+    let info = ServiceInfo {
+        name: SERVICE_NAME.into(),
+        display_name: SERVICE_NAME.into(),
+        service_type: SERVICE_TYPE,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: vec!["--service".into()],
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+        .map_err(|e| anyhow::anyhow!("registering service '{}': {}", SERVICE_NAME, e))?;
+    service.set_description("indexpage service dashboard")?;
+    println!("Installed service '{}'; start it with `sc start {}`", SERVICE_NAME, SERVICE_NAME);
+    Ok(())
+}
+
+fn uninstall() -> anyhow::Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service = manager
+        .open_service(SERVICE_NAME, ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE)
+        .map_err(|e| anyhow::anyhow!("opening service '{}': {}", SERVICE_NAME, e))?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+    service.delete()?;
+    println!("Removed service '{}'", SERVICE_NAME);
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+
+use crate::{
+    audit::{Actor, Audit},
+    categories::Visibility,
+    config::WakeOnLanConfig,
+    repository::ServiceRepository,
+    services::SERVICE_ID_BY_NAME,
+};
+
+// Wake-on-LAN, so a tile for a powered-off machine can boot it. A service
+// may have the MAC address of the machine it runs on; with
+// `[wake_on_lan] enabled`, POST /services/:name/wake broadcasts a magic
+// packet for it: six 0xff bytes, then the address sixteen times.
+#[derive(Clone)]
+pub struct WakeOnLan {
+    enabled: bool,
+    broadcast: SocketAddr,
+}
+
+impl WakeOnLan {
+    pub fn new(config: &WakeOnLanConfig) -> Self {
+        Self { enabled: config.enabled, broadcast: config.broadcast }
+    }
+}
+
+// "AA:BB:CC:DD:EE:FF", "aa-bb-cc-dd-ee-ff" or "aabbccddeeff".
+fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let hex: String = mac.trim().chars().filter(|c| *c != ':' && *c != '-').collect();
+    if hex.len() != 12 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut bytes = [0; 6];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn format_mac(bytes: &[u8; 6]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(":")
+}
+
+fn magic_packet(mac: &[u8; 6]) -> Vec<u8> {
+    let mut packet = vec![0xff; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(mac);
+    }
+    packet
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MacAddress {
+    mac_address: Option<String>,
+}
+
+async fn load(pool: &PgPool, name: &str) -> Result<(String, Option<String>), (StatusCode, String)> {
+    sqlx::query_as::<_, (String, Option<String>)>(&format!(
+        "SELECT name, mac_address FROM services WHERE id = {}",
+        SERVICE_ID_BY_NAME
+    ))
+    .bind(name)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Service not found".into()))
+}
+
+// GET /services/:name/wake
+pub async fn get_mac(
+    State(pool): State<PgPool>,
+    visibility: Visibility,
+    Path(name): Path<String>,
+) -> Result<Json<MacAddress>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let (_, mac_address) = load(&pool, &name).await?;
+    Ok(Json(MacAddress { mac_address }))
+}
+
+// PUT /services/:name/wake
+// Body: {"mac_address": "aa:bb:cc:dd:ee:ff"}, or null to remove it.
+pub async fn set_mac<R: ServiceRepository>(
+    State(pool): State<PgPool>,
+    State(services): State<R>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(name): Path<String>,
+    Json(payload): Json<MacAddress>,
+) -> Result<Json<MacAddress>, (StatusCode, String)> {
+    visibility.check_service(&pool, &name).await?;
+    let mac_address = match payload.mac_address.as_deref().map(str::trim).filter(|mac| !mac.is_empty()) {
+        Some(mac) => Some(
+            parse_mac(mac)
+                .map(|bytes| format_mac(&bytes))
+                .ok_or((StatusCode::BAD_REQUEST, format!("'{}' is not a MAC address", mac)))?,
+        ),
+        None => None,
+    };
+    let saved = services.set_mac(&name, mac_address.as_deref()).await?;
+    let service = saved.ok_or((StatusCode::NOT_FOUND, "Service not found".into()))?;
+    audit.record(&actor, "service.mac", Some(&service), mac_address.clone()).await;
+    Ok(Json(MacAddress { mac_address }))
+}
+
+// POST /services/:name/wake
+pub async fn wake(
+    State(pool): State<PgPool>,
+    State(wake_on_lan): State<WakeOnLan>,
+    State(audit): State<Audit>,
+    visibility: Visibility,
+    actor: Actor,
+    Path(name): Path<String>,
+) -> Result<String, (StatusCode, String)> {
+    if !wake_on_lan.enabled {
+        return Err((StatusCode::NOT_FOUND, "Wake-on-LAN is disabled".into()));
+    }
+    visibility.check_service(&pool, &name).await?;
+    let (service, mac_address) = load(&pool, &name).await?;
+    let mac = mac_address
+        .as_deref()
+        .and_then(parse_mac)
+        .ok_or((StatusCode::CONFLICT, format!("'{}' has no MAC address", service)))?;
+
+    let unreachable = |e: std::io::Error| (StatusCode::BAD_GATEWAY, format!("Can't send the magic packet: {}", e));
+    let bind: SocketAddr = if wake_on_lan.broadcast.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0; 16], 0).into() };
+    let socket = UdpSocket::bind(bind).await.map_err(unreachable)?;
+    socket.set_broadcast(true).map_err(unreachable)?;
+    socket.send_to(&magic_packet(&mac), wake_on_lan.broadcast).await.map_err(unreachable)?;
+
+    let detail = format!("{} via {}", format_mac(&mac), wake_on_lan.broadcast);
+    audit.record(&actor, "service.wake", Some(&service), Some(detail)).await;
+    Ok(format!("Sent a magic packet to {}", format_mac(&mac)))
+}